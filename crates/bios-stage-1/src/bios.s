@@ -0,0 +1,81 @@
+.section .text.bios, "ax"
+.global print
+.global read_sectors
+.code16
+
+
+# void print(const char *ptr)
+#
+# Prints a nul terminated string through the BIOS teletype output, and mirrors
+# it to COM1 unless serial output is disabled. Called with the 32-bit C calling convention, so the argument is at esp + 8
+# once si and bx are saved.
+print:
+    push si
+    push bx
+    mov si, [esp + 8]
+
+print_loop:
+    lodsb
+    or al, al
+    jz print_done
+
+.if {serial}
+    # Wait until COM1 can take another byte, a missing port reads as ready
+    mov ah, al
+    mov dx, 0x3fd
+serial_wait:
+    in al, dx
+    test al, 0x20
+    jz serial_wait
+
+    mov al, ah
+    mov dl, 0xf8
+    out dx, al
+.endif
+
+    mov ah, 0x0e
+    mov bh, 0
+    int 0x10
+    jmp print_loop
+
+print_done:
+    pop bx
+    pop si
+    ret
+
+
+# bool read_sectors(u8 drive, u32 lba, u16 sectors, u8 *target)
+#
+# Reads sectors with the BIOS extended disk read, returning whether it succeeded.
+# Called with the 32-bit C calling convention, so arguments start at bp + 8.
+read_sectors:
+    push ebp
+    mov ebp, esp
+    push esi
+
+    # Build the disk address packet on the stack, last field first
+    push dword ptr 0
+    push dword ptr [bp + 12]
+
+    # Split the target into segment:offset
+    mov eax, [bp + 20]
+    shr eax, 4
+    push ax
+    mov ax, [bp + 20]
+    and ax, 0xf
+    push ax
+
+    push word ptr [bp + 16]
+    push word ptr 0x10
+
+    # Read the sectors
+    mov si, sp
+    mov dl, [bp + 8]
+    mov ah, 0x42
+    int 0x13
+    setnc al
+
+    lea sp, [bp - 4]
+    pop esi
+    pop ebp
+    ret
@@ -20,7 +20,25 @@ _start:
     # Setup the stack
     mov sp, 0x7c00
 
-    # Jump to Stage 1
+    # Jump to Stage 1, passing it the drive the BIOS booted us from
+    push edx
+
+.if {serial}
+    # Program COM1 for the configured baud rate, 8N1
+    mov dx, 0x3fb
+    mov al, 0x80
+    out dx, al
+    mov dl, 0xf8
+    mov al, {divisor_low}
+    out dx, al
+    inc dx
+    mov al, {divisor_high}
+    out dx, al
+    mov dl, 0xfb
+    mov al, 0x03
+    out dx, al
+.endif
+
     call _stage_1
 
 spin:
@@ -6,131 +6,139 @@ use core::{
     ffi::{c_char, c_void},
     mem::transmute,
     ptr::{addr_of, addr_of_mut},
+    slice,
+};
+use mrow_common::{
+    handoff::{Handoff, Services, Stage2Entry},
+    header::StageHeader,
+    mbr::{MasterBootRecord, PartitionTable, TableEntry},
+    option_var, serial,
 };
-use mrow_common::mbr::{PartitionTable, TableEntry};
 
-global_asm!(include_str!("./boot.s"));
+global_asm!(
+    include_str!("./boot.s"),
+    serial = const serial::ENABLED as u8,
+    divisor_low = const serial::DIVISOR.to_le_bytes()[0],
+    divisor_high = const serial::DIVISOR.to_le_bytes()[1],
+);
+global_asm!(
+    include_str!("./bios.s"),
+    serial = const serial::ENABLED as u8,
+);
 
 unsafe extern "C" {
     pub static _mbr_start: c_void;
 
     pub static _partition_table: c_void;
     pub static mut _stage_2_start: c_void;
+
+    /// Prints a nul terminated string.
+    ///
+    /// This is exposed to stage 2 through [`Services::print`].
+    pub fn print(ptr: *const c_char);
+
+    /// Reads `sectors` sectors starting at `lba` into `target`.
+    ///
+    /// This is exposed to stage 2 through [`Services::read_sectors`].
+    pub fn read_sectors(drive: u8, lba: u32, sectors: u16, target: *mut u8) -> bool;
 }
 
+/// What we hand to stage 2.
+///
+/// Everything but the boot drive is known at link time, which keeps it out of `.text`.
+pub static mut HANDOFF: Handoff = Handoff::new(
+    0x80,
+    addr_of!(_partition_table).cast::<TableEntry>(),
+    addr_of!(_mbr_start).cast::<MasterBootRecord>(),
+    Services {
+        print,
+        read_sectors,
+    },
+);
+
 #[inline(always)]
 pub unsafe fn partition_table<'a>() -> &'a PartitionTable {
     unsafe { &*addr_of!(_partition_table).cast::<PartitionTable>() }
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn _stage_1() -> ! {
+pub unsafe extern "C" fn _stage_1(boot_drive: u8) -> ! {
     let table = unsafe { partition_table() };
     let stage_2 = &table.entries[0];
 
     if stage_2.is_bootable() & (stage_2.sector_len() != 0)
     // & (stage_2.sector_len() <= (u16::MAX as u32))
     {
-        unsafe { load_stage_2(stage_2) }
+        unsafe { load_stage_2(stage_2, boot_drive) }
     }
 
-    unsafe { print(c"Could not find stage 2".as_ptr()) };
-    loop {}
+    // There's no room for a separate message, a missing stage 2 is just as bad.
+    // A bad header makes `load_stage_2` return here too.
+    bad_stage_2();
 }
 
-type Stage2Fn = unsafe extern "C" fn(print_fn: unsafe extern "C" fn(ptr: *const c_char));
-
-unsafe fn load_stage_2(entry: &TableEntry) {
-    unsafe { print(c"Loading stage 2...\r\n".as_ptr()) };
+/// Loads stage 2 and jumps to it, or returns if it doesn't check out.
+unsafe fn load_stage_2(entry: &TableEntry, drive: u8) {
+    unsafe { print(c"Loading stage 2\r\n".as_ptr()) };
 
     let mut sectors = entry.sector_len() as u16;
     let mut target = addr_of_mut!(_stage_2_start) as *mut u8;
-    let mut lba = entry.start_lba() as u64;
+    let mut lba = entry.start_lba();
 
     while sectors > 0 {
-        let mut dap =
-            DiskAddressPacket::from_lba(lba, 1, target as u16, ((target as u32) >> 16) as u16);
-
-        unsafe {
-            dap.load(0x0080);
+        if !unsafe { read_sectors(drive, lba, 1, target) } {
+            fail();
         }
 
         sectors -= 1;
         target = unsafe { target.add(512) };
         lba += 1;
     }
-    let stage_2 = unsafe { transmute::<_, Stage2Fn>(addr_of!(_stage_2_start)) };
 
-    unsafe { stage_2(print) };
-}
-
-#[repr(C, packed)]
-struct DiskAddressPacket {
-    pub size: u8,
-    pub zero: u8,
-    pub sectors: u16,
-    pub target_offset: u16,
-    pub target_segment: u16,
-    pub start_lba: u64,
-}
+    let header = unsafe { &*addr_of!(_stage_2_start).cast::<StageHeader>() };
+    let start = unsafe { addr_of!(_stage_2_start).cast::<u8>().add(StageHeader::SIZE) };
+    // `target` is where reading stopped.
+    let loaded = target as usize - start as usize;
 
-impl DiskAddressPacket {
-    pub fn from_lba(start_lba: u64, sectors: u16, target_offset: u16, target_segment: u16) -> Self {
-        Self {
-            size: 0x10,
-            zero: 0,
-            sectors,
-            target_offset,
-            target_segment,
-            start_lba,
-        }
+    // The payload is only looked at once it's known to be within what we read.
+    if !(header.is_compatible()
+        && header.payload_len as usize <= loaded
+        && header.verify(unsafe { slice::from_raw_parts(start, header.payload_len as usize) }))
+    {
+        return;
     }
-    pub unsafe fn load(&mut self, disk: u16) {
-        let dap_addr = (self as *mut _) as u16;
-        unsafe {
-            asm!(
-                "mov {1:x}, si",
-                "mov si, {0:x}",
-                "int 0x13",
-                "jc fail",
-                "mov si, {1:x}",
-                in(reg) dap_addr,
-                out(reg) _,
-                in("ax") 0x4200_u16,
-                in("dx") disk,
-            );
-        }
+
+    let stage_2 = unsafe { transmute::<*const u8, Stage2Entry>(start) };
+
+    unsafe {
+        HANDOFF.boot_drive = drive;
+        stage_2(&*addr_of!(HANDOFF))
     }
 }
 
-#[no_mangle]
-unsafe extern "C" fn fail() -> ! {
-    unsafe { print(c"Failed to load stage 2 loader\r\n".as_ptr()) };
+#[inline(always)]
+fn bad_stage_2() -> ! {
+    unsafe { print(c"Bad stage 2\r\n".as_ptr()) };
     loop {}
 }
 
-#[no_mangle]
-unsafe extern "C" fn print(ptr: *const c_char) {
-    unsafe {
-        asm!(
-            "mov si, {0:x}",
-            "2:",
-            "lodsb",
-            "or al, al",
-            "jz 3f",
-
-            "mov ah, 0x0e",
-            "mov bh, 0",
-            "int 0x10",
-            "jmp 2b",
-
-            "3:",
-            in(reg) ptr,
-        );
-    }
+#[inline(always)]
+fn fail() -> ! {
+    unsafe { print(c"Read error\r\n".as_ptr()) };
+    loop {}
 }
 
+/// Whether a panic prints a message, set with `MROW_STAGE_1_PANIC=0` at build
+/// time to save the bytes.
+const PANIC_MESSAGE: bool = option_var!("MROW_STAGE_1_PANIC", u8, 1) != 0;
+
 #[panic_handler]
 pub fn panic(_info: &core::panic::PanicInfo<'_>) -> ! {
-    loop {}
+    if PANIC_MESSAGE {
+        unsafe { print(c"Panic\r\n".as_ptr()) };
+    }
+
+    loop {
+        unsafe { asm!("cli", "hlt", options(nomem, nostack)) };
+    }
 }
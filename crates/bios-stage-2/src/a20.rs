@@ -0,0 +1,190 @@
+use core::{arch::asm, ffi::CStr};
+
+use mrow_common::port::{inb, outb};
+
+/// How many times we poll the keyboard controller or the A20 line before giving up.
+const TIMEOUT: u32 = 0x10000;
+
+/// The way the A20 line ended up enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// The firmware already enabled it.
+    AlreadyEnabled,
+    /// `int 15h, ax=2401h`.
+    Bios,
+    /// Through the output port of the 8042 keyboard controller.
+    KeyboardController,
+    /// Through the fast A20 gate at port `0x92`.
+    FastGate,
+}
+
+impl Method {
+    /// Returns a human readable name of this method.
+    #[inline]
+    #[must_use]
+    pub const fn name(self) -> &'static CStr {
+        match self {
+            Method::AlreadyEnabled => c"already enabled",
+            Method::Bios => c"bios",
+            Method::KeyboardController => c"keyboard controller",
+            Method::FastGate => c"fast a20 gate",
+        }
+    }
+}
+
+/// Enables the A20 line, trying every method we know of in turn.
+///
+/// Returns `None` if the A20 line is still disabled after trying all of them.
+///
+/// # Safety
+///
+/// Must be called in real mode with `ds` set to zero.
+pub unsafe fn enable() -> Option<Method> {
+    if unsafe { is_enabled() } {
+        return Some(Method::AlreadyEnabled);
+    }
+
+    let methods: [(unsafe fn(), Method); 3] = [
+        (enable_bios, Method::Bios),
+        (enable_keyboard_controller, Method::KeyboardController),
+        (enable_fast_gate, Method::FastGate),
+    ];
+
+    for (enable, method) in methods {
+        unsafe { enable() };
+
+        if unsafe { wait_until_enabled() } {
+            return Some(method);
+        }
+    }
+
+    None
+}
+
+/// Returns whether the A20 line is enabled.
+///
+/// This writes different values to `0x0000:0x0500` and `0xffff:0x0510`, which
+/// are the same byte if the address wraps around at 1 MiB.
+///
+/// # Safety
+///
+/// Must be called in real mode with `ds` set to zero.
+pub unsafe fn is_enabled() -> bool {
+    let wrapped: u8;
+
+    unsafe {
+        asm!(
+            "push fs",
+            "mov ax, 0xffff",
+            "mov fs, ax",
+
+            // Save the bytes we're about to clobber.
+            "mov cl, byte ptr [0x0500]",
+            "mov ch, byte ptr fs:[0x0510]",
+
+            "mov byte ptr [0x0500], 0x00",
+            "mov byte ptr fs:[0x0510], 0xff",
+            "cmp byte ptr [0x0500], 0xff",
+            "sete {0}",
+
+            // Restore them, low memory last in case they're the same byte.
+            "mov byte ptr fs:[0x0510], ch",
+            "mov byte ptr [0x0500], cl",
+            "pop fs",
+            out(reg_byte) wrapped,
+            out("ax") _,
+            out("cx") _,
+        );
+    }
+
+    wrapped == 0
+}
+
+/// Polls [`is_enabled`] for a while, as some methods take time to apply.
+unsafe fn wait_until_enabled() -> bool {
+    for _ in 0..TIMEOUT {
+        if unsafe { is_enabled() } {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Asks the BIOS to enable the A20 line.
+unsafe fn enable_bios() {
+    unsafe {
+        asm!(
+            "int 0x15",
+            inout("ax") 0x2401_u16 => _,
+        );
+    }
+}
+
+/// Enables the A20 line through the output port of the keyboard controller.
+unsafe fn enable_keyboard_controller() {
+    const DATA: u16 = 0x60;
+    const COMMAND: u16 = 0x64;
+
+    /// Waits for the controller to accept another byte.
+    unsafe fn wait_input() -> bool {
+        (0..TIMEOUT).any(|_| unsafe { inb(COMMAND) } & 0x02 == 0)
+    }
+
+    /// Waits for the controller to have a byte for us.
+    unsafe fn wait_output() -> bool {
+        (0..TIMEOUT).any(|_| unsafe { inb(COMMAND) } & 0x01 != 0)
+    }
+
+    unsafe {
+        // Disable the keyboard so it doesn't interfere.
+        if !wait_input() {
+            return;
+        }
+        outb(COMMAND, 0xad);
+
+        // Read the output port.
+        if !wait_input() {
+            return;
+        }
+        outb(COMMAND, 0xd0);
+
+        if !wait_output() {
+            return;
+        }
+        let output_port = inb(DATA);
+
+        // Write it back with the A20 bit set.
+        if !wait_input() {
+            return;
+        }
+        outb(COMMAND, 0xd1);
+
+        if !wait_input() {
+            return;
+        }
+        outb(DATA, output_port | 0x02);
+
+        // Enable the keyboard again.
+        if !wait_input() {
+            return;
+        }
+        outb(COMMAND, 0xae);
+
+        wait_input();
+    }
+}
+
+/// Enables the A20 line through the fast A20 gate.
+unsafe fn enable_fast_gate() {
+    const SYSTEM_CONTROL: u16 = 0x92;
+
+    unsafe {
+        let value = inb(SYSTEM_CONTROL);
+
+        if value & 0x02 == 0 {
+            // Bit 0 resets the machine, so make sure it stays clear.
+            outb(SYSTEM_CONTROL, (value | 0x02) & !0x01);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use core::{arch::asm, ffi::CStr, fmt};
+
+use mrow_common::{
+    format::Sink,
+    serial::{self, SerialPort},
+};
+
+/// Where output is mirrored to, unless serial output is disabled at build time.
+const SERIAL: SerialPort = SerialPort::com1();
+
+/// Programs the serial port with the configured baud rate.
+///
+/// Stage 1 already did the bare minimum, this also sets up the FIFOs and
+/// modem control lines.
+///
+/// # Safety
+///
+/// Must be called in real mode.
+pub unsafe fn init() {
+    if serial::ENABLED {
+        unsafe { SERIAL.init(serial::BAUD) };
+    }
+}
+
+/// Prints `s` on screen and mirrors it to serial.
+///
+/// # Safety
+///
+/// Must be called in real mode, before the video mode is changed.
+pub unsafe fn print(s: &CStr) {
+    if serial::ENABLED {
+        unsafe { SERIAL.write_bytes(s.to_bytes()) };
+    }
+
+    unsafe { print_bios(s) };
+}
+
+/// Clears the screen and the serial terminal.
+///
+/// # Safety
+///
+/// Same as [`print`].
+pub unsafe fn clear() {
+    if serial::ENABLED {
+        unsafe { SERIAL.write_bytes(b"\x1b[2J\x1b[H") };
+    }
+
+    // Setting the text mode again clears the screen and homes the cursor.
+    unsafe { asm!("int 0x10", inout("ax") 0x0003_u16 => _) };
+}
+
+/// Returns the next byte received over serial, if serial is enabled and there is one.
+///
+/// # Safety
+///
+/// Must be called after [`init`].
+pub unsafe fn read_serial() -> Option<u8> {
+    if !serial::ENABLED {
+        return None;
+    }
+
+    unsafe { SERIAL.read_byte() }
+}
+
+/// Prints `s`, which unlike [`print`] needs no nul terminator.
+///
+/// # Safety
+///
+/// Same as [`print`].
+pub unsafe fn print_str(s: &str) {
+    Console.write_bytes(s.as_bytes());
+}
+
+/// The screen and serial, as a target for formatting and logging.
+///
+/// The same rules as for [`print`] apply, even though writing is safe.
+pub struct Console;
+
+impl Sink for Console {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if serial::ENABLED {
+            unsafe { SERIAL.write_bytes(bytes) };
+        }
+
+        for &byte in bytes {
+            unsafe { print_byte_bios(byte) };
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+
+        Ok(())
+    }
+}
+
+/// Prints a single byte through the BIOS teletype output.
+unsafe fn print_byte_bios(byte: u8) {
+    unsafe {
+        asm!(
+            "mov ah, 0x0e",
+            "mov bh, 0",
+            "int 0x10",
+            inout("al") byte => _,
+            out("ah") _,
+            out("bh") _,
+        );
+    }
+}
+
+/// Prints a nul terminated string through the BIOS teletype output.
+unsafe fn print_bios(s: &CStr) {
+    unsafe {
+        asm!(
+            "push si",
+            "mov si, {0:x}",
+            "2:",
+            "lodsb",
+            "or al, al",
+            "jz 3f",
+
+            "mov ah, 0x0e",
+            "mov bh, 0",
+            "int 0x10",
+            "jmp 2b",
+
+            "3:",
+            "pop si",
+            in(reg) s.as_ptr(),
+            out("ax") _,
+            out("bx") _,
+        );
+    }
+}
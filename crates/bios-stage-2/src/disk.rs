@@ -0,0 +1,104 @@
+use mrow_common::{
+    fat::{SectorRead, SECTOR_SIZE},
+    handoff::Services,
+};
+
+/// The most sectors we ask the BIOS for at once.
+///
+/// Some firmware can't read more than 127 sectors in one go, and this keeps
+/// every read well within a single segment.
+const CHUNK: u32 = 64;
+
+/// Reads `sectors` sectors starting at `lba` into `target` through stage 1.
+///
+/// Returns whether every read succeeded.
+///
+/// # Safety
+///
+/// `target` must be below 1 MiB and valid for writes of `sectors * 512` bytes.
+pub unsafe fn read(
+    services: &Services,
+    drive: u8,
+    mut lba: u32,
+    mut sectors: u32,
+    mut target: *mut u8,
+) -> bool {
+    while sectors > 0 {
+        let chunk = sectors.min(CHUNK);
+
+        if !unsafe { (services.read_sectors)(drive, lba, chunk as u16, target) } {
+            return false;
+        }
+
+        lba += chunk;
+        sectors -= chunk;
+        target = unsafe { target.add(chunk as usize * 512) };
+    }
+
+    true
+}
+
+/// The error [`BiosDisk`] fails with, the BIOS doesn't tell us much more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadError;
+
+/// A drive read through stage 1, one sector at a time.
+pub struct BiosDisk<'a> {
+    services: &'a Services,
+    drive: u8,
+}
+
+impl<'a> BiosDisk<'a> {
+    /// Creates a disk reading from `drive`.
+    #[inline]
+    #[must_use]
+    pub const fn new(services: &'a Services, drive: u8) -> Self {
+        Self { services, drive }
+    }
+}
+
+impl SectorRead for BiosDisk<'_> {
+    type Error = ReadError;
+
+    fn read_sector(&mut self, lba: u64, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), ReadError> {
+        let target = buffer.as_mut_ptr();
+
+        // The BIOS only addresses sectors with 32 bits and memory below 1 MiB.
+        if (lba > u32::MAX as u64) | (target as usize + SECTOR_SIZE > 0x10_0000) {
+            return Err(ReadError);
+        }
+
+        match unsafe { read(self.services, self.drive, lba as u32, 1, target) } {
+            true => Ok(()),
+            false => Err(ReadError),
+        }
+    }
+
+    fn read_sectors(
+        &mut self,
+        lba: u64,
+        buffer: &mut [[u8; SECTOR_SIZE]],
+    ) -> Result<(), ReadError> {
+        let target = buffer.as_mut_ptr().cast::<u8>();
+        let sectors = buffer.len() as u64;
+
+        if (lba + sectors > u32::MAX as u64)
+            | (target as usize + buffer.len() * SECTOR_SIZE > 0x10_0000)
+        {
+            return Err(ReadError);
+        }
+
+        match unsafe {
+            read(
+                self.services,
+                self.drive,
+                lba as u32,
+                sectors as u32,
+                target,
+            )
+        } {
+            true => Ok(()),
+            false => Err(ReadError),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use core::arch::asm;
+
+/// Returns the scancode and ASCII character of the next key press, without
+/// waiting for one.
+///
+/// # Safety
+///
+/// Must be called in real mode.
+pub unsafe fn read() -> Option<(u8, u8)> {
+    let available: u8;
+
+    unsafe {
+        asm!(
+            "int 0x16",
+            "setnz {available}",
+            available = out(reg_byte) available,
+            inout("ax") 0x0100_u16 => _,
+        );
+    }
+
+    if available == 0 {
+        return None;
+    }
+
+    let key: u16;
+
+    unsafe {
+        asm!(
+            "int 0x16",
+            inout("ax") 0x0000_u16 => key,
+        );
+    }
+
+    Some(((key >> 8) as u8, key as u8))
+}
@@ -1,21 +1,591 @@
 #![no_std]
 #![no_main]
 
-use core::ffi::{c_char, c_void};
+use core::{
+    arch::asm,
+    ffi::{c_void, CStr},
+    fmt::Write,
+    mem::transmute,
+    panic::PanicInfo,
+    ptr::{self, addr_of_mut},
+    slice,
+};
+use mrow_common::{
+    config::{self, Config, Video},
+    cpu, debug, error,
+    fat::{self, File, FileSystem, SectorRead},
+    format::Int,
+    format_to,
+    framebuffer::Framebuffer,
+    handoff::{Handoff, Stage3Entry, Stage3Handoff},
+    header::StageHeader,
+    info,
+    memory_map::{MemoryKind, MemoryMap},
+    menu::Menu,
+    multiboot2::{self, FramebufferTag},
+    option_var,
+    vbe::{Edid, ModeRequest},
+};
+
+use crate::{console::Console, disk::BiosDisk};
+
+mod a20;
+mod console;
+mod disk;
+mod keyboard;
+mod memory;
+mod menu;
+mod protected;
+mod vbe;
 
 unsafe extern "C" {
     pub static _mbr_start: c_void;
     pub static _stage_2_end: c_void;
+    pub static mut _stage_3_start: c_void;
 }
 
+/// How large the configuration file may be, as it's read onto the stack.
+const CONFIG_LEN: usize = 4096;
+
+/// How much of a file we read at once when loading it above 1 MiB.
+const BOUNCE_LEN: usize = 64 * 512;
+
+/// Where files loaded above 1 MiB may start, below is ours and the BIOS's.
+const HIGH_START: u64 = 0x10_0000;
+
+/// Files loaded above 1 MiB have to end below this, as stage 3 doesn't use paging.
+const HIGH_END: u64 = 1 << 32;
+
+/// How much a panic prints, set with `MROW_STAGE_2_PANIC` at build time.
+///
+/// 0 prints nothing, and 1 prints where the panic happened along with messages
+/// that need no formatting. 2 prints formatted messages too, like those of failed
+/// bounds checks, but pulls in the formatting machinery of `core`.
+const PANIC_LEVEL: u8 = option_var!("MROW_STAGE_2_PANIC", u8, 2);
+
+/// Partition kinds of FAT12, FAT16 and FAT32 volumes, with and without LBA.
+const FAT_PARTITION_KINDS: [u8; 6] = [0x01, 0x04, 0x06, 0x0b, 0x0c, 0x0e];
+
+/// The header stage 1 verifies before jumping to [`_start`].
+///
+/// The payload length and checksum are filled in by the host tool.
+#[used]
+#[no_mangle]
+#[link_section = ".header"]
+pub static HEADER: StageHeader = StageHeader::new();
+
+/// The entry point stage 1 calls after verifying [`HEADER`].
+///
+/// # Safety
+///
+/// `handoff` must come from stage 1, with everything it points to still in place.
 #[no_mangle]
 #[link_section = ".start"]
-pub unsafe extern "C" fn _start(print_fn: unsafe extern "C" fn(ptr: *const c_char)) -> ! {
-    unsafe { print_fn(c"Hello from stage 2!\r\n".as_ptr()) };
-    loop {}
+pub unsafe extern "C" fn _start(handoff: &Handoff) -> ! {
+    unsafe { console::init() };
+
+    // We can't trust anything but the version and size until we've checked them,
+    // so there's nothing but our own console to report a mismatch with.
+    if !handoff.is_compatible() {
+        unsafe { console::print(c"Incompatible handoff from stage 1\r\n") };
+        loop {}
+    }
+
+    unsafe { console::print(c"Hello from stage 2!\r\n") };
+
+    match unsafe { a20::enable() } {
+        Some(method) => info!(Console, "A20 line enabled: ", method.name()),
+        None => {
+            unsafe { console::print(c"Failed to enable the A20 line\r\n") };
+            loop {}
+        }
+    }
+
+    // Stage 3 is loaded above 64 KiB and the kernel above 1 MiB, neither of which
+    // we could reach otherwise.
+    unsafe { protected::enter_unreal() };
+
+    let mut memory_map = MemoryMap::<64>::new();
+
+    match unsafe { memory::detect(&mut memory_map) } {
+        Ok(source) => info!(Console, "Memory map from: ", source.name()),
+        Err(memory::Error::Unsupported) => {
+            unsafe { console::print(c"Failed to detect memory\r\n") };
+            loop {}
+        }
+        Err(memory::Error::Full) => {
+            unsafe { console::print(c"Memory map has too many regions\r\n") };
+            loop {}
+        }
+    }
+
+    for region in memory_map.regions() {
+        debug!(
+            Console,
+            Int::hex(region.start).width(16).zeroes(),
+            "-",
+            Int::hex(region.end).width(16).zeroes(),
+            " type ",
+            Int::hex(region.kind as u64),
+        );
+    }
+
+    let stage_3 = unsafe { addr_of_mut!(_stage_3_start).cast::<u8>() };
+
+    let stage_3 = match unsafe { load_image(handoff, &memory_map, 1, stage_3) } {
+        Ok(stage_3) => stage_3,
+        Err(err) => unsafe {
+            print_load_error(c"stage 3", err);
+            loop {}
+        },
+    };
+
+    // Files go above 1 MiB, so they're read through a buffer right after stage 3.
+    let bounce = stage_3.as_ptr_range().end as usize;
+    let bounce = ((bounce + 0xfff) & !0xfff) as *mut u8;
+
+    // Without a boot partition there is no kernel either, which stage 3 reports.
+    let mut fs = match boot_file_system(handoff) {
+        Ok(fs) => Some(fs),
+        Err(LoadError::Missing) => None,
+        Err(err) => unsafe {
+            print_load_error(c"boot partition", err);
+            loop {}
+        },
+    };
+
+    // This stays on the stack for good, as the command line points into it.
+    let mut config_buffer = [0; CONFIG_LEN];
+
+    let text = match read_config(fs.as_mut(), &mut config_buffer) {
+        Ok(text) => text,
+        Err(err) => unsafe {
+            print_load_error(c"config", err);
+            loop {}
+        },
+    };
+
+    let config = match Config::parse(text) {
+        Ok(config) => config,
+        Err(err) => {
+            error!(
+                Console,
+                "Bad config at line ",
+                err.line,
+                ", column ",
+                err.column,
+                ": ",
+                err.kind.message(),
+            );
+            loop {}
+        }
+    };
+
+    // The menu also holds the command line if it gets edited, so it stays on the
+    // stack for good too.
+    let mut boot_menu = Menu::new(config);
+
+    if config.timeout() != 0 {
+        unsafe { menu::run(&mut boot_menu) };
+    }
+
+    let entry = boot_menu.entry();
+
+    info!(Console, "Booting ", entry.name);
+
+    // Stage 3 tells the user if there is no kernel, as it can do so after
+    // checking the CPU can run one at all.
+    let kernel = match unsafe { load_kernel(fs.as_mut(), entry.kernel, &memory_map, bounce) } {
+        Ok(kernel) => kernel,
+        Err(LoadError::Missing) => &[],
+        Err(err) => unsafe {
+            print_load_error(c"kernel", err);
+            loop {}
+        },
+    };
+
+    let initrd = match (fs.as_mut(), entry.initrd) {
+        (Some(fs), Some(path)) => {
+            // Without a kernel the slice is dangling, and its end means nothing.
+            let start = (kernel.as_ptr_range().end as u64).max(HIGH_START);
+            let initrd = fs
+                .open(path)
+                .map_err(LoadError::from)
+                .and_then(|mut file| unsafe {
+                    load_high(fs, &mut file, &memory_map, bounce, start)
+                });
+
+            match initrd {
+                Ok(initrd) => initrd,
+                Err(err) => unsafe {
+                    print_load_error(c"initrd", err);
+                    loop {}
+                },
+            }
+        }
+        _ => &[],
+    };
+
+    let multiboot = multiboot2::Header::find(kernel).and_then(Result::ok);
+
+    // Multiboot2 kernels say for themselves whether they want a framebuffer.
+    let video = match (multiboot, entry.video) {
+        (Some(header), Video::Auto) => multiboot_video(header.framebuffer()),
+        (_, video) => video,
+    };
+
+    // Switching to graphics hides any text output, so don't bother if stage 3
+    // couldn't boot a kernel anyway and should rather tell the user why.
+    let framebuffer = if !kernel.is_empty() && (multiboot.is_some() || cpu::has_long_mode()) {
+        unsafe { set_video_mode(video) }
+    } else {
+        Framebuffer::NONE
+    };
+
+    let stage_3_handoff = Stage3Handoff::new(
+        handoff.boot_drive,
+        boot_partition(handoff).map(|index| index as u8),
+        memory_map.regions(),
+        kernel,
+        boot_menu.cmdline(),
+        initrd,
+        framebuffer,
+    );
+    let stage_3_entry =
+        unsafe { transmute::<*const u8, Stage3Entry>(stage_3.as_ptr().add(StageHeader::SIZE)) };
+
+    unsafe { protected::enter(stage_3_entry, &stage_3_handoff) }
+}
+
+/// Switches to the video mode closest to `video`.
+///
+/// [`Video::Auto`] asks the display for its preferred resolution. Returns
+/// [`Framebuffer::NONE`] if we stayed in text mode.
+unsafe fn set_video_mode(video: Video) -> Framebuffer {
+    let request = match video {
+        Video::Text => return Framebuffer::NONE,
+        Video::Mode(request) => request,
+        Video::Auto => {
+            let mut edid = Edid::new();
+
+            let preferred = if unsafe { vbe::read_edid(&mut edid) } {
+                edid.preferred_resolution()
+            } else {
+                None
+            };
+
+            preferred.map_or(ModeRequest::DEFAULT, |(width, height)| {
+                ModeRequest::new(width, height, ModeRequest::DEFAULT.bpp)
+            })
+        }
+    };
+
+    let Some((mode, framebuffer)) = (unsafe { vbe::select(request) }) else {
+        unsafe { console::print(c"No usable video mode, staying in text mode\r\n") };
+        return Framebuffer::NONE;
+    };
+
+    if !unsafe { vbe::set_mode(mode) } {
+        unsafe { console::print(c"Failed to set video mode, staying in text mode\r\n") };
+        return Framebuffer::NONE;
+    }
+
+    framebuffer
+}
+
+/// Returns the video mode for a Multiboot2 kernel with the framebuffer tag `tag`.
+///
+/// Without the tag, the kernel expects text mode.
+fn multiboot_video(tag: Option<FramebufferTag>) -> Video {
+    let Some(tag) = tag else {
+        return Video::Text;
+    };
+
+    if (tag.width == 0) | (tag.height == 0) {
+        return Video::Auto;
+    }
+
+    let bpp = match tag.depth {
+        0 => ModeRequest::DEFAULT.bpp,
+        depth => depth.min(u8::MAX as u32) as u8,
+    };
+
+    Video::Mode(ModeRequest::new(
+        tag.width.min(u16::MAX as u32) as u16,
+        tag.height.min(u16::MAX as u32) as u16,
+        bpp,
+    ))
+}
+
+/// Errors that can occur while loading an image with [`load_image`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoadError {
+    /// The partition table entry is empty.
+    Missing,
+    /// The image would overlap memory we can't use.
+    TooLarge,
+    /// The BIOS failed to read the image.
+    Read,
+    /// The filesystem the image is on is damaged or not supported.
+    BadFileSystem,
+    /// The header has the wrong magic or version, or claims a payload larger than the image.
+    BadHeader,
+    /// The payload doesn't match the checksum in the header.
+    BadChecksum,
+    /// The file should be text, but isn't valid UTF-8.
+    NotText,
+}
+
+impl<E> From<fat::Error<E>> for LoadError {
+    fn from(err: fat::Error<E>) -> Self {
+        match err {
+            fat::Error::Device(_) => LoadError::Read,
+            fat::Error::NotFound | fat::Error::NotADirectory | fat::Error::IsADirectory => {
+                LoadError::Missing
+            }
+            fat::Error::NotFat | fat::Error::UnsupportedSectorSize | fat::Error::BadCluster => {
+                LoadError::BadFileSystem
+            }
+        }
+    }
+}
+
+impl LoadError {
+    /// Returns a human readable description of this error.
+    #[inline]
+    #[must_use]
+    const fn message(self) -> &'static CStr {
+        match self {
+            LoadError::Missing => c"not found",
+            LoadError::TooLarge => c"does not fit in memory",
+            LoadError::Read => c"read failed",
+            LoadError::BadFileSystem => c"bad filesystem",
+            LoadError::BadHeader => c"bad header",
+            LoadError::BadChecksum => c"bad checksum",
+            LoadError::NotText => c"not valid UTF-8",
+        }
+    }
+}
+
+/// Prints which image failed to load and why.
+unsafe fn print_load_error(name: &CStr, err: LoadError) {
+    error!(Console, "Failed to load ", name, ": ", err.message());
+}
+
+/// Loads the image the partition table entry at `index` points to into `start`
+/// and verifies its header.
+///
+/// Returns the image, header included and padded to whole sectors.
+unsafe fn load_image<'a, const N: usize>(
+    handoff: &Handoff,
+    memory_map: &MemoryMap<N>,
+    index: usize,
+    start: *mut u8,
+) -> Result<&'a [u8], LoadError> {
+    let entry = unsafe { (*handoff.mbr).partition_table.entries[index] };
+    let len = entry.sector_len() as u64 * 512;
+
+    if len == 0 {
+        return Err(LoadError::Missing);
+    }
+
+    // Reads go through the BIOS, so everything has to stay below 1 MiB.
+    if (start as u64 + len > 0x10_0000)
+        | !memory_map.contains(start as u64, start as u64 + len, MemoryKind::Usable)
+    {
+        return Err(LoadError::TooLarge);
+    }
+
+    let read = unsafe {
+        disk::read(
+            &handoff.services,
+            handoff.boot_drive,
+            entry.start_lba(),
+            entry.sector_len(),
+            start,
+        )
+    };
+
+    if !read {
+        return Err(LoadError::Read);
+    }
+
+    let image = unsafe { slice::from_raw_parts(start.cast_const(), len as usize) };
+
+    verify_image(image)?;
+
+    Ok(image)
+}
+
+/// Returns the index of the first FAT partition, which holds the configuration
+/// and everything it refers to.
+fn boot_partition(handoff: &Handoff) -> Option<usize> {
+    let entries = unsafe { (*handoff.mbr).partition_table.entries };
+
+    entries.iter().position(|entry| {
+        FAT_PARTITION_KINDS.contains(&entry.partition_kind) & (entry.sector_len() != 0)
+    })
+}
+
+/// Opens the [boot partition](boot_partition).
+fn boot_file_system(handoff: &Handoff) -> Result<FileSystem<BiosDisk<'_>>, LoadError> {
+    let index = boot_partition(handoff).ok_or(LoadError::Missing)?;
+    let entry = unsafe { (*handoff.mbr).partition_table.entries[index] };
+
+    let disk = BiosDisk::new(&handoff.services, handoff.boot_drive);
+
+    Ok(FileSystem::new(disk, entry.start_lba() as u64)?)
+}
+
+/// Reads the configuration at [`config::PATH`] into `buffer`.
+///
+/// Falls back to [`config::DEFAULT`] if there is no boot partition or no
+/// configuration on it.
+fn read_config<'a, D: SectorRead>(
+    fs: Option<&mut FileSystem<D>>,
+    buffer: &'a mut [u8],
+) -> Result<&'a str, LoadError> {
+    let Some(fs) = fs else {
+        return Ok(config::DEFAULT);
+    };
+
+    let mut file = match fs.open(config::PATH) {
+        Ok(file) => file,
+        Err(fat::Error::NotFound) => return Ok(config::DEFAULT),
+        Err(err) => return Err(err.into()),
+    };
+
+    let len = file.size() as usize;
+    let buffer = buffer.get_mut(..len).ok_or(LoadError::TooLarge)?;
+    let mut done = 0;
+
+    while done < len {
+        let read = fs.read(&mut file, &mut buffer[done..])?;
+
+        if read == 0 {
+            return Err(LoadError::Read);
+        }
+
+        done += read;
+    }
+
+    core::str::from_utf8(buffer).map_err(|_| LoadError::NotText)
+}
+
+/// Loads the kernel at `path` above 1 MiB and verifies its header, unless it's
+/// a Multiboot2 kernel.
+///
+/// See [`load_high`] for `bounce`.
+unsafe fn load_kernel<'a, D: SectorRead, const N: usize>(
+    fs: Option<&mut FileSystem<D>>,
+    path: &str,
+    memory_map: &MemoryMap<N>,
+    bounce: *mut u8,
+) -> Result<&'a [u8], LoadError> {
+    let fs = fs.ok_or(LoadError::Missing)?;
+    let mut file = fs.open(path)?;
+    let image = unsafe { load_high(fs, &mut file, memory_map, bounce, HIGH_START)? };
+
+    // Multiboot2 kernels don't have our header, stage 3 checks theirs instead.
+    if multiboot2::Header::find(image).is_none() {
+        verify_image(image)?;
+    }
+
+    Ok(image)
+}
+
+/// Reads the rest of `file` into the first usable memory at or above `start` that fits it.
+///
+/// The BIOS can't reach that far, so the file is read [`BOUNCE_LEN`] bytes at
+/// a time into `bounce`, and copied up from there. Memory below [`HIGH_START`]
+/// is never used, whatever `start` is.
+///
+/// # Safety
+///
+/// We must be in unreal mode, and `bounce` must be page aligned.
+unsafe fn load_high<'a, D: SectorRead, const N: usize>(
+    fs: &mut FileSystem<D>,
+    file: &mut File,
+    memory_map: &MemoryMap<N>,
+    bounce: *mut u8,
+    start: u64,
+) -> Result<&'a [u8], LoadError> {
+    let bounce_end = bounce as u64 + BOUNCE_LEN as u64;
+
+    if (bounce_end > HIGH_START)
+        | !memory_map.contains(bounce as u64, bounce_end, MemoryKind::Usable)
+    {
+        return Err(LoadError::TooLarge);
+    }
+
+    // Below that are the bounce buffer, stage 3 and our own data.
+    let start = start.max(HIGH_START);
+    let len = (file.size() - file.position()) as u64;
+    let start = memory_map
+        .find(start, HIGH_END, len, 0x1000, MemoryKind::Usable)
+        .ok_or(LoadError::TooLarge)? as usize as *mut u8;
+
+    let bounce = unsafe { slice::from_raw_parts_mut(bounce, BOUNCE_LEN) };
+    let mut done = 0;
+
+    while done < len as usize {
+        let read = fs.read(file, bounce)?;
+
+        if read == 0 {
+            return Err(LoadError::Read);
+        }
+
+        unsafe { ptr::copy_nonoverlapping(bounce.as_ptr(), start.add(done), read) };
+        done += read;
+    }
+
+    Ok(unsafe { slice::from_raw_parts(start.cast_const(), done) })
+}
+
+/// Checks the header at the start of `image` and the payload it covers.
+fn verify_image(image: &[u8]) -> Result<(), LoadError> {
+    let len = image.len() as u64;
+
+    if len < StageHeader::SIZE as u64 {
+        return Err(LoadError::BadHeader);
+    }
+
+    let header = unsafe { &*image.as_ptr().cast::<StageHeader>() };
+
+    if !header.is_compatible() || header.payload_len as u64 > len - StageHeader::SIZE as u64 {
+        return Err(LoadError::BadHeader);
+    }
+
+    let payload = &image[StageHeader::SIZE..][..header.payload_len as usize];
+
+    if !header.verify(payload) {
+        return Err(LoadError::BadChecksum);
+    }
+
+    Ok(())
 }
 
 #[panic_handler]
-pub fn panic(_info: &core::panic::PanicInfo<'_>) -> ! {
-    loop {}
+pub fn panic(info: &PanicInfo<'_>) -> ! {
+    if PANIC_LEVEL >= 1 {
+        format_to!(Console, "Panic");
+
+        if let Some(location) = info.location() {
+            format_to!(Console, " at ", location.file(), ":", location.line());
+        }
+
+        let message = info.message();
+
+        if let Some(message) = message.as_str() {
+            format_to!(Console, ": ", message);
+        } else if PANIC_LEVEL >= 2 {
+            let _ = write!(Console, ": {message}");
+        }
+
+        unsafe { console::print(c"\r\n") };
+    }
+
+    loop {
+        unsafe { asm!("cli", "hlt", options(nomem, nostack)) };
+    }
 }
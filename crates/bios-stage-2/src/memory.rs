@@ -0,0 +1,221 @@
+use core::{arch::asm, ffi::CStr, ptr::addr_of_mut};
+
+use mrow_common::memory_map::{E820Entry, MemoryKind, MemoryMap, MemoryMapFull, MemoryRegion};
+
+/// `"SMAP"`, which E820 expects in `edx` and returns in `eax`.
+const SMAP: u32 = 0x534d_4150;
+
+/// A way of detecting memory, returning whether the firmware supports it.
+type Attempt<const N: usize> = unsafe fn(&mut MemoryMap<N>) -> Result<bool, Error>;
+
+/// Where the memory map came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// `int 15h, eax=e820h`.
+    E820,
+    /// `int 15h, ax=e801h`.
+    E801,
+    /// `int 15h, ah=88h`.
+    Legacy,
+}
+
+impl Source {
+    /// Returns a human readable name of this source.
+    #[inline]
+    #[must_use]
+    pub const fn name(self) -> &'static CStr {
+        match self {
+            Source::E820 => c"e820",
+            Source::E801 => c"e801",
+            Source::Legacy => c"int 15h, ah=88h",
+        }
+    }
+}
+
+/// Errors that can occur while detecting memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// None of the BIOS calls worked.
+    Unsupported,
+    /// The firmware reported more regions than we have room for.
+    Full,
+}
+
+impl From<MemoryMapFull> for Error {
+    #[inline]
+    fn from(_: MemoryMapFull) -> Self {
+        Error::Full
+    }
+}
+
+/// Fills `map` with the memory map of the machine.
+///
+/// Tries E820 first, then falls back to E801 and finally `int 15h, ah=88h`.
+///
+/// # Safety
+///
+/// Must be called in real mode with `es` set to zero and the stack below 64 KiB.
+pub unsafe fn detect<const N: usize>(map: &mut MemoryMap<N>) -> Result<Source, Error> {
+    let attempts: [(Attempt<N>, Source); 3] = [
+        (e820, Source::E820),
+        (e801, Source::E801),
+        (legacy, Source::Legacy),
+    ];
+
+    for (attempt, source) in attempts {
+        map.clear();
+
+        if unsafe { attempt(map)? } {
+            return Ok(source);
+        }
+    }
+
+    map.clear();
+
+    Err(Error::Unsupported)
+}
+
+/// Reads the memory map with E820, returning whether it's supported.
+unsafe fn e820<const N: usize>(map: &mut MemoryMap<N>) -> Result<bool, Error> {
+    let mut continuation = 0_u32;
+    let mut entry = E820Entry::default();
+
+    loop {
+        // Firmware that doesn't know about extended attributes won't touch them,
+        // so default to the entry being valid.
+        entry.attributes = 1;
+
+        let signature: u32;
+        let size: u32;
+
+        unsafe {
+            asm!(
+                "int 0x15",
+                // Clobber the signature on failure.
+                "jnc 2f",
+                "xor eax, eax",
+                "2:",
+                inout("eax") 0xe820_u32 => signature,
+                inout("ebx") continuation,
+                inout("ecx") E820Entry::SIZE => size,
+                inout("edx") SMAP => _,
+                in("edi") addr_of_mut!(entry),
+            );
+        }
+
+        // A failure on anything but the first call just means we're done.
+        if signature != SMAP {
+            return Ok(!map.is_empty());
+        }
+
+        if !(entry.is_empty() | ((size >= E820Entry::SIZE) & entry.is_ignored())) {
+            map.insert(entry.region())?;
+        }
+
+        if continuation == 0 {
+            return Ok(!map.is_empty());
+        }
+    }
+}
+
+/// Reads the amount of memory with E801, returning whether it's supported.
+unsafe fn e801<const N: usize>(map: &mut MemoryMap<N>) -> Result<bool, Error> {
+    let (mut low, mut high, configured_low, configured_high): (u16, u16, u16, u16);
+
+    unsafe {
+        asm!(
+            "int 0x15",
+            // Report no memory at all on failure.
+            "jnc 2f",
+            "xor ax, ax",
+            "xor bx, bx",
+            "xor cx, cx",
+            "xor dx, dx",
+            "2:",
+            inout("ax") 0xe801_u16 => low,
+            out("bx") high,
+            out("cx") configured_low,
+            out("dx") configured_high,
+        );
+    }
+
+    // Some firmware only reports the configured memory in cx and dx.
+    if (configured_low != 0) | (configured_high != 0) {
+        (low, high) = (configured_low, configured_high);
+    }
+
+    if (low == 0) & (high == 0) {
+        return Ok(false);
+    }
+
+    unsafe { insert_conventional(map)? };
+
+    // Memory between 1 MiB and 16 MiB in KiB.
+    insert_usable(map, 0x10_0000, low as u64 * 1024)?;
+    // Memory above 16 MiB in 64 KiB blocks.
+    insert_usable(map, 0x100_0000, high as u64 * 64 * 1024)?;
+
+    Ok(true)
+}
+
+/// Reads the amount of memory above 1 MiB, returning whether it's supported.
+unsafe fn legacy<const N: usize>(map: &mut MemoryMap<N>) -> Result<bool, Error> {
+    let extended: u16;
+
+    unsafe {
+        asm!(
+            "int 0x15",
+            // Report no memory at all on failure.
+            "jnc 2f",
+            "xor ax, ax",
+            "2:",
+            inout("ax") 0x8800_u16 => extended,
+        );
+    }
+
+    if extended == 0 {
+        return Ok(false);
+    }
+
+    unsafe { insert_conventional(map)? };
+
+    // Memory above 1 MiB in KiB.
+    insert_usable(map, 0x10_0000, extended as u64 * 1024)?;
+
+    Ok(true)
+}
+
+/// Adds the memory below 640 KiB as reported by `int 12h`, along with what's
+/// reserved up to 1 MiB.
+unsafe fn insert_conventional<const N: usize>(map: &mut MemoryMap<N>) -> Result<(), Error> {
+    let conventional: u16;
+
+    unsafe {
+        asm!(
+            "int 0x12",
+            out("ax") conventional,
+        );
+    }
+
+    let conventional = conventional as u64 * 1024;
+
+    insert_usable(map, 0, conventional)?;
+    map.insert(MemoryRegion::new(
+        conventional,
+        0x10_0000,
+        MemoryKind::Reserved,
+    ))?;
+
+    Ok(())
+}
+
+/// Adds `len` bytes of usable memory starting at `start`.
+fn insert_usable<const N: usize>(
+    map: &mut MemoryMap<N>,
+    start: u64,
+    len: u64,
+) -> Result<(), Error> {
+    map.insert(MemoryRegion::new(start, start + len, MemoryKind::Usable))?;
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use core::{arch::asm, ptr};
+
+use mrow_common::{
+    format_to,
+    menu::{Action, Key, Menu, SerialDecoder},
+};
+
+use crate::{
+    console::{self, Console},
+    keyboard,
+};
+
+/// The tick counter in the BIOS data area, which the timer interrupt increments
+/// about 18.2 times a second.
+const TICKS: *const u32 = 0x46c as *const u32;
+
+/// Timer ticks per second, close enough for a countdown.
+const TICKS_PER_SECOND: u32 = 18;
+
+/// Shows `menu` until it says to boot, taking input from the keyboard and serial.
+///
+/// # Safety
+///
+/// Must be called in real mode, before the video mode is changed.
+pub unsafe fn run(menu: &mut Menu<'_>) {
+    let mut decoder = SerialDecoder::new();
+    let mut last_tick = unsafe { ticks() };
+    let mut second_start = last_tick;
+
+    unsafe { draw(menu) };
+
+    loop {
+        let action = if let Some(key) = unsafe { next_key(&mut decoder) } {
+            menu.key(key)
+        } else {
+            let now = unsafe { ticks() };
+
+            if now == last_tick {
+                // Stage 1 turned interrupts off, and the timer and keyboard
+                // interrupts have to wake us up again. `sti` only takes effect
+                // after `hlt`, so none can slip in between the check and sleeping.
+                unsafe { asm!("sti", "hlt", "cli", options(nomem, nostack)) };
+                continue;
+            }
+
+            last_tick = now;
+
+            if let Some(key) = decoder.idle() {
+                menu.key(key)
+            } else if now.wrapping_sub(second_start) >= TICKS_PER_SECOND {
+                second_start = now;
+                menu.tick()
+            } else {
+                Action::None
+            }
+        };
+
+        match action {
+            Action::None => {}
+            Action::Redraw => unsafe { draw(menu) },
+            Action::Boot => return,
+        }
+    }
+}
+
+/// Returns the next key from the keyboard or serial, if there is one.
+unsafe fn next_key(decoder: &mut SerialDecoder) -> Option<Key> {
+    if let Some((scancode, ascii)) = unsafe { keyboard::read() } {
+        return Key::from_bios(scancode, ascii);
+    }
+
+    decoder.feed(unsafe { console::read_serial() }?)
+}
+
+/// Returns the BIOS timer tick count.
+unsafe fn ticks() -> u32 {
+    unsafe { ptr::read_volatile(TICKS) }
+}
+
+/// Draws the whole menu from scratch.
+unsafe fn draw(menu: &Menu<'_>) {
+    unsafe {
+        console::clear();
+        console::print(c"mrow boot menu\r\n\r\n");
+
+        for (index, entry) in menu.config().entries().enumerate() {
+            console::print(if index == menu.selected() {
+                c" > "
+            } else {
+                c"   "
+            });
+            console::print_str(entry.name);
+            console::print(c"\r\n");
+        }
+
+        console::print(c"\r\n");
+
+        if menu.is_editing() {
+            console::print(c"Enter boots with this command line, escape goes back:\r\n");
+            console::print_str(menu.cmdline());
+            return;
+        }
+
+        console::print(c"Up and down select, e edits the command line, enter boots.\r\n");
+
+        if let Some(remaining) = menu.remaining() {
+            format_to!(Console, "Booting in ", remaining, " seconds.\r\n");
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use core::{arch::asm, ptr};
+
+use mrow_common::{
+    gdt::{Descriptor, Pointer32},
+    handoff::{Stage3Entry, Stage3Handoff},
+};
+
+/// Selector of the code segment in [`GDT`].
+pub const CODE_SELECTOR: u16 = 0x08;
+/// Selector of the data segment in [`GDT`].
+pub const DATA_SELECTOR: u16 = 0x10;
+/// Selector of the stack segment for unreal mode in [`GDT`].
+pub const UNREAL_STACK_SELECTOR: u16 = 0x18;
+
+/// The flat segments stage 3 runs with, also used for unreal mode.
+static GDT: [Descriptor; 4] = [
+    Descriptor::NULL,
+    Descriptor::CODE_32,
+    Descriptor::DATA_32,
+    Descriptor::DATA_16_FLAT,
+];
+
+/// Switches to unreal mode, lifting the 64 KiB limit of the data and stack segments.
+///
+/// This briefly enters protected mode to load flat segments, and returns to real
+/// mode while the CPU keeps their 4 GiB limit cached. Segment bases go back to zero,
+/// so everything keeps working as before, except that 32-bit offsets now reach
+/// all of memory.
+///
+/// The stack segment needs the same, as the compiler happily uses `ebp` as a
+/// pointer, which goes through `ss`. It keeps using `sp` for pushes and pops though,
+/// so the BIOS doesn't notice.
+///
+/// # Safety
+///
+/// Must be called in real mode with all data segments and `ss` set to zero.
+pub unsafe fn enter_unreal() {
+    let pointer = Pointer32::new(&GDT);
+
+    unsafe {
+        asm!(
+            "pushfl",
+            "cli",
+            "lgdtl (%ebx)",
+
+            "mov %cr0, %eax",
+            "or $1, %eax",
+            "mov %eax, %cr0",
+
+            // cs stays a real mode segment, so we can keep running 16-bit code
+            // and don't need a far jump either way.
+            "mov ${data}, %ax",
+            "mov %ax, %ds",
+            "mov %ax, %es",
+            "mov %ax, %fs",
+            "mov %ax, %gs",
+            "mov ${stack}, %ax",
+            "mov %ax, %ss",
+
+            "mov %cr0, %eax",
+            "and $-2, %eax",
+            "mov %eax, %cr0",
+
+            "xor %ax, %ax",
+            "mov %ax, %ds",
+            "mov %ax, %es",
+            "mov %ax, %fs",
+            "mov %ax, %gs",
+            "mov %ax, %ss",
+            "popfl",
+            data = const DATA_SELECTOR,
+            stack = const UNREAL_STACK_SELECTOR,
+            in("ebx") ptr::from_ref(&pointer),
+            out("eax") _,
+            options(att_syntax),
+        );
+    }
+}
+
+/// Switches to 32-bit protected mode and calls `entry` with `handoff`.
+///
+/// Interrupts stay disabled, as there is no IDT for protected mode yet. The stack
+/// is kept as is, which is fine as real mode segments are all zero.
+///
+/// # Safety
+///
+/// The A20 line must be enabled, and `entry` must be 32-bit code.
+pub unsafe fn enter(entry: Stage3Entry, handoff: &Stage3Handoff) -> ! {
+    let pointer = Pointer32::new(&GDT);
+
+    unsafe {
+        asm!(
+            "cli",
+            "lgdtl (%ebx)",
+
+            "mov %cr0, %eax",
+            "or $1, %eax",
+            "mov %eax, %cr0",
+
+            // Reload cs, which also flushes the prefetch queue.
+            "ljmpl ${code}, $2f",
+
+            ".code32",
+            "2:",
+            "mov ${data}, %ax",
+            "mov %ax, %ds",
+            "mov %ax, %es",
+            "mov %ax, %fs",
+            "mov %ax, %gs",
+            "mov %ax, %ss",
+            // Stage 1 only ever set sp, so don't trust the upper half of esp.
+            "movzwl %sp, %esp",
+
+            "push %edx",
+            "call *%ecx",
+            ".code16",
+            code = const CODE_SELECTOR,
+            data = const DATA_SELECTOR,
+            in("ebx") ptr::from_ref(&pointer),
+            in("ecx") entry,
+            in("edx") handoff,
+            options(att_syntax, noreturn),
+        );
+    }
+}
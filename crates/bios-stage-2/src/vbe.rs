@@ -0,0 +1,139 @@
+use core::{arch::asm, ptr::addr_of_mut};
+
+use mrow_common::{
+    framebuffer::Framebuffer,
+    vbe::{far_to_linear, Edid, ModeInfoBlock, ModeRequest, VbeInfoBlock},
+};
+
+/// What every VBE function returns in `ax` on success.
+const SUCCESS: u16 = 0x004f;
+
+/// Ask for the linear framebuffer when setting a mode.
+const LINEAR_FRAMEBUFFER: u16 = 1 << 14;
+
+/// How many modes we look at before giving up on a list that never ends.
+const MAX_MODES: usize = 512;
+
+/// Fills `info` with information about the VBE controller.
+///
+/// # Safety
+///
+/// Must be called in unreal mode with `es` set to zero.
+pub unsafe fn controller_info(info: &mut VbeInfoBlock) -> bool {
+    let result: u16;
+
+    *info = VbeInfoBlock::new();
+
+    unsafe {
+        asm!(
+            "int 0x10",
+            inout("ax") 0x4f00_u16 => result,
+            in("edi") addr_of_mut!(*info),
+        );
+    }
+
+    (result == SUCCESS) & info.is_valid()
+}
+
+/// Fills `info` with information about `mode`.
+///
+/// # Safety
+///
+/// Must be called in unreal mode with `es` set to zero.
+pub unsafe fn mode_info(mode: u16, info: &mut ModeInfoBlock) -> bool {
+    let result: u16;
+
+    unsafe {
+        asm!(
+            "int 0x10",
+            inout("ax") 0x4f01_u16 => result,
+            in("cx") mode,
+            in("edi") addr_of_mut!(*info),
+        );
+    }
+
+    result == SUCCESS
+}
+
+/// Reads the EDID of the first display, returning whether it's valid.
+///
+/// # Safety
+///
+/// Must be called in unreal mode with `es` set to zero.
+pub unsafe fn read_edid(edid: &mut Edid) -> bool {
+    let result: u16;
+
+    unsafe {
+        asm!(
+            "int 0x10",
+            inout("ax") 0x4f15_u16 => result,
+            // Read EDID of the first controller, block 0.
+            inout("bx") 0x0001_u16 => _,
+            inout("cx") 0_u16 => _,
+            inout("dx") 0_u16 => _,
+            in("edi") addr_of_mut!(*edid),
+        );
+    }
+
+    (result == SUCCESS) & edid.is_valid()
+}
+
+/// Switches to `mode` with its linear framebuffer.
+///
+/// # Safety
+///
+/// Must be called in unreal mode. Text output through the BIOS and VGA memory
+/// stops working afterwards.
+pub unsafe fn set_mode(mode: u16) -> bool {
+    let result: u16;
+
+    unsafe {
+        asm!(
+            "int 0x10",
+            inout("ax") 0x4f02_u16 => result,
+            in("bx") mode | LINEAR_FRAMEBUFFER,
+        );
+    }
+
+    result == SUCCESS
+}
+
+/// Returns the mode that best fulfills `request` and its framebuffer.
+///
+/// # Safety
+///
+/// Must be called in unreal mode with `es` set to zero.
+pub unsafe fn select(request: ModeRequest) -> Option<(u16, Framebuffer)> {
+    let mut controller = VbeInfoBlock::new();
+
+    if !unsafe { controller_info(&mut controller) } {
+        return None;
+    }
+
+    // The list may well live inside `controller`, so keep it around until we're done.
+    let modes = far_to_linear(controller.video_modes()) as usize as *const u16;
+    let mut info = ModeInfoBlock::new();
+    let mut best: Option<(u64, u16, Framebuffer)> = None;
+
+    for i in 0..MAX_MODES {
+        let mode = unsafe { modes.add(i).read_unaligned() };
+
+        if mode == 0xffff {
+            break;
+        }
+
+        if !unsafe { mode_info(mode, &mut info) } {
+            continue;
+        }
+
+        let Some(rank) = request.rank(&info) else {
+            continue;
+        };
+
+        if best.is_none_or(|(best, ..)| rank > best) {
+            best = Some((rank, mode, info.framebuffer(controller.version())));
+        }
+    }
+
+    best.map(|(_, mode, framebuffer)| (mode, framebuffer))
+}
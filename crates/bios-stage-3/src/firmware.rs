@@ -0,0 +1,42 @@
+use core::{ops::Range, slice};
+
+use mrow_common::{
+    acpi::{self, Rsdp},
+    smbios::{self, EntryPoint},
+};
+
+/// Looks for the ACPI RSDP, first in the EBDA and then in the BIOS area.
+pub fn find_rsdp() -> Option<Rsdp<'static>> {
+    areas()
+        .into_iter()
+        .find_map(|area| Some(acpi::find_rsdp(area)?.1))
+}
+
+/// Looks for the SMBIOS entry point, in the same places as [`find_rsdp`].
+pub fn find_smbios() -> Option<EntryPoint<'static>> {
+    areas()
+        .into_iter()
+        .find_map(|area| Some(smbios::find(area)?.1))
+}
+
+/// Returns the memory the firmware leaves its tables in, the EBDA first.
+fn areas() -> [&'static [u8]; 2] {
+    let segment = unsafe { (acpi::EBDA_POINTER as usize as *const u16).read() };
+    let ebda = acpi::ebda_area(segment).map_or(&[][..], |area| unsafe { memory(area) });
+
+    [ebda, unsafe { memory(acpi::BIOS_AREA) }]
+}
+
+/// Returns the physical memory `range` as a slice.
+///
+/// # Safety
+///
+/// Nothing must write to `range` while the slice is alive.
+unsafe fn memory(range: Range<u64>) -> &'static [u8] {
+    unsafe {
+        slice::from_raw_parts(
+            range.start as usize as *const u8,
+            (range.end - range.start) as usize,
+        )
+    }
+}
@@ -0,0 +1,88 @@
+use core::{arch::asm, ptr};
+
+use mrow_common::gdt::{Descriptor, Pointer32};
+
+/// Selector of the code segment in [`GDT`].
+pub const CODE_SELECTOR: u16 = 0x08;
+/// Selector of the data segment in [`GDT`].
+pub const DATA_SELECTOR: u16 = 0x10;
+
+/// The segments the kernel starts with.
+static GDT: [Descriptor; 3] = [Descriptor::NULL, Descriptor::CODE_64, Descriptor::DATA_64];
+
+/// Where the kernel is called, read by the 64-bit code in [`enter`].
+#[repr(C)]
+struct Jump {
+    entry: u64,
+    boot_info: u64,
+}
+
+/// Enables long mode with the page tables at `pml4` and calls `entry` with the
+/// address of the boot information.
+///
+/// # Safety
+///
+/// Long mode must be [supported](mrow_common::cpu::has_long_mode), `pml4` must identity map the code
+/// and stack we run on and map `entry` to 64-bit code.
+pub unsafe fn enter(pml4: u32, entry: u64, boot_info: u64) -> ! {
+    const CR4_PAE: u32 = 1 << 5;
+    const EFER: u32 = 0xc000_0080;
+    const EFER_LME: u32 = 1 << 8;
+    const CR0_PG: u32 = 1 << 31;
+
+    let pointer = Pointer32::new(&GDT);
+    let jump = Jump { entry, boot_info };
+
+    unsafe {
+        asm!(
+            "mov %eax, %cr3",
+
+            "mov %cr4, %eax",
+            "or ${pae}, %eax",
+            "mov %eax, %cr4",
+
+            "mov ${efer}, %ecx",
+            "rdmsr",
+            "or ${lme}, %eax",
+            "wrmsr",
+
+            // This activates long mode, though we're still running 32-bit code
+            // until cs is reloaded.
+            "mov %cr0, %eax",
+            "or ${pg}, %eax",
+            "mov %eax, %cr0",
+
+            "lgdtl (%ebx)",
+            "ljmpl ${code}, $2f",
+
+            ".code64",
+            "2:",
+            "mov ${data}, %ax",
+            "mov %ax, %ds",
+            "mov %ax, %es",
+            "mov %ax, %fs",
+            "mov %ax, %gs",
+            "mov %ax, %ss",
+
+            // The upper halves of the registers are undefined after the switch.
+            "mov %esp, %esp",
+            "and $-16, %rsp",
+            "mov %edi, %edi",
+
+            "mov (%rdi), %rax",
+            "mov 8(%rdi), %rdi",
+            "call *%rax",
+            ".code32",
+            pae = const CR4_PAE,
+            efer = const EFER,
+            lme = const EFER_LME,
+            pg = const CR0_PG,
+            code = const CODE_SELECTOR,
+            data = const DATA_SELECTOR,
+            in("eax") pml4,
+            in("ebx") ptr::from_ref(&pointer),
+            in("edi") ptr::from_ref(&jump),
+            options(att_syntax, noreturn),
+        );
+    }
+}
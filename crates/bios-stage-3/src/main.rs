@@ -0,0 +1,263 @@
+#![no_std]
+#![no_main]
+
+use core::{arch::asm, ffi::c_void, ptr::addr_of, slice};
+use mrow_common::{
+    boot_info::{BootInfoFull, Builder},
+    cpu,
+    handoff::{Stage3Handoff, KERNEL_BASE},
+    header::StageHeader,
+    memory_map::{MemoryKind, MemoryMap, MemoryRegion},
+    multiboot2,
+    paging::PageTable,
+    serial::{self, SerialPort},
+};
+use vga::Console;
+
+mod firmware;
+mod long_mode;
+mod multiboot;
+mod paging;
+mod vga;
+
+/// The first byte of memory that belongs to the loader, right after the BIOS data area.
+///
+/// Everything from here up to the end of stage 3 is our stack and stages.
+const LOADER_START: u64 = 0x500;
+
+/// Where we start looking for memory for the page tables.
+const TABLES_START: u64 = 0x10_0000;
+
+/// How many regions the memory map handed to the kernel can hold.
+///
+/// This is more than stage 2 uses, as marking memory as ours can split regions.
+const MEMORY_MAP_LEN: usize = 128;
+
+/// How much memory we set aside for the boot information.
+const BOOT_INFO_LEN: usize = 0x2000;
+
+/// The name kernels find in the boot information.
+const LOADER_NAME: &str = "mrow";
+
+unsafe extern "C" {
+    pub static _stage_3_end: c_void;
+}
+
+/// The header stage 2 verifies before jumping to [`_start`].
+///
+/// The payload length and checksum are filled in by the host tool.
+#[used]
+#[no_mangle]
+#[link_section = ".header"]
+pub static HEADER: StageHeader = StageHeader::new();
+
+/// The entry point stage 2 calls in protected mode.
+///
+/// # Safety
+///
+/// `handoff` must come from stage 2, with everything it points to still in place.
+#[no_mangle]
+#[link_section = ".start"]
+pub unsafe extern "C" fn _start(handoff: &Stage3Handoff) -> ! {
+    let mut console = unsafe { Console::new() };
+
+    if !handoff.is_compatible() {
+        fail(&mut console, "Incompatible handoff from stage 2\r\n");
+    }
+
+    print(&mut console, "Hello from stage 3!\r\n");
+
+    let kernel = unsafe { handoff.kernel() };
+
+    if kernel.is_empty() {
+        fail(&mut console, "No kernel to boot\r\n");
+    }
+
+    let kernel_start = kernel.as_ptr() as usize as u64;
+    let kernel_end = kernel_start + kernel.len() as u64;
+
+    // The command line lives on the stack of stage 2, which is ours anyway.
+    let cmdline = unsafe { handoff.cmdline() };
+    let initrd = unsafe { handoff.initrd() };
+    let initrd_start = initrd.as_ptr() as usize as u64;
+    let initrd_end = initrd_start + initrd.len() as u64;
+
+    let mut memory_map = MemoryMap::<MEMORY_MAP_LEN>::new();
+
+    for region in unsafe { handoff.memory_map() } {
+        if memory_map.insert(*region).is_err() {
+            fail(&mut console, "Memory map has too many regions\r\n");
+        }
+    }
+
+    let stage_3_end = addr_of!(_stage_3_end) as usize as u64;
+    let loader = MemoryRegion::new(LOADER_START, stage_3_end, MemoryKind::Loader);
+
+    if memory_map.insert(loader).is_err() {
+        fail(&mut console, "Memory map has too many regions\r\n");
+    }
+
+    let rsdp = firmware::find_rsdp();
+    let smbios = firmware::find_smbios();
+
+    match multiboot2::Header::find(kernel) {
+        Some(Ok(header)) => multiboot::boot(&mut console, handoff, &mut memory_map, header, rsdp),
+        Some(Err(_)) => fail(&mut console, "Bad Multiboot2 header\r\n"),
+        None => {}
+    }
+
+    if !cpu::has_long_mode() {
+        fail(
+            &mut console,
+            "This CPU does not support long mode, so it can't run a 64-bit kernel\r\n",
+        );
+    }
+
+    // Claim the kernel and initrd first, so the page tables don't end up on top of them.
+    let loader = [
+        MemoryRegion::new(kernel_start, kernel_end, MemoryKind::Loader),
+        MemoryRegion::new(initrd_start, initrd_end, MemoryKind::Loader),
+    ];
+
+    for region in loader.into_iter().filter(|region| !region.is_empty()) {
+        if memory_map.insert(region).is_err() {
+            fail(&mut console, "Memory map has too many regions\r\n");
+        }
+    }
+
+    let tables_len = paging::tables_len(kernel.len() as u64);
+    let Some(tables) = memory_map.find(
+        TABLES_START,
+        1 << 32,
+        tables_len,
+        paging::PAGE_SIZE,
+        MemoryKind::Usable,
+    ) else {
+        fail(&mut console, "Not enough memory for page tables\r\n");
+    };
+
+    let tables_region = MemoryRegion::new(tables, tables + tables_len, MemoryKind::Loader);
+
+    if memory_map.insert(tables_region).is_err() {
+        fail(&mut console, "Memory map has too many regions\r\n");
+    }
+
+    let pml4 = unsafe {
+        paging::build(
+            tables as usize as *mut PageTable,
+            kernel_start,
+            kernel.len() as u64,
+        )
+    };
+
+    // The boot information contains the memory map, so claim its memory first.
+    let Some(boot_info) = memory_map.find(
+        TABLES_START,
+        1 << 32,
+        BOOT_INFO_LEN as u64,
+        paging::PAGE_SIZE,
+        MemoryKind::Usable,
+    ) else {
+        fail(&mut console, "Not enough memory for boot information\r\n");
+    };
+
+    let boot_info_region = MemoryRegion::new(
+        boot_info,
+        boot_info + BOOT_INFO_LEN as u64,
+        MemoryKind::Loader,
+    );
+
+    if memory_map.insert(boot_info_region).is_err() {
+        fail(&mut console, "Memory map has too many regions\r\n");
+    }
+
+    let buffer = unsafe { slice::from_raw_parts_mut(boot_info as usize as *mut u8, BOOT_INFO_LEN) };
+
+    let tables = [
+        rsdp.map(|rsdp| rsdp.as_bytes()),
+        smbios.map(|smbios| smbios.as_bytes()),
+    ]
+    .map(|table| table.map_or(0, |bytes| bytes.as_ptr() as usize as u64));
+
+    if build_boot_info(
+        buffer,
+        handoff,
+        memory_map.regions(),
+        cmdline,
+        initrd,
+        tables,
+    )
+    .is_err()
+    {
+        fail(&mut console, "Boot information doesn't fit\r\n");
+    }
+
+    print(&mut console, "Entering long mode\r\n");
+
+    unsafe {
+        long_mode::enter(
+            pml4 as u32,
+            KERNEL_BASE + StageHeader::SIZE as u64,
+            boot_info,
+        )
+    }
+}
+
+/// Writes the boot information for a 64-bit kernel into `buffer`.
+///
+/// `rsdp` and `smbios` are the physical addresses of the firmware tables, zero if
+/// we didn't find them.
+fn build_boot_info(
+    buffer: &mut [u8],
+    handoff: &Stage3Handoff,
+    memory_map: &[MemoryRegion],
+    cmdline: &str,
+    initrd: &[u8],
+    [rsdp, smbios]: [u64; 2],
+) -> Result<(), BootInfoFull> {
+    let mut builder = Builder::new(buffer)?;
+    builder.memory_map(memory_map)?;
+    builder.cmdline(cmdline)?;
+
+    if !initrd.is_empty() {
+        let start = initrd.as_ptr() as usize as u64;
+
+        builder.modules(&[(start..start + initrd.len() as u64, "initrd")])?;
+    }
+
+    builder.framebuffer(handoff.framebuffer);
+    builder.rsdp(rsdp);
+    builder.smbios(smbios);
+    builder.boot_device(handoff.boot_drive, handoff.boot_partition().map(u32::from));
+    builder.loader(LOADER_NAME, env!("CARGO_PKG_VERSION"))?;
+    builder.finish()?;
+
+    Ok(())
+}
+
+/// Prints `s` on screen and mirrors it to serial, which stage 2 already set up.
+fn print(console: &mut Console, s: &str) {
+    if serial::ENABLED {
+        unsafe { SerialPort::com1().write_bytes(s.as_bytes()) };
+    }
+
+    console.write_str(s);
+}
+
+/// Prints `message` and stops.
+fn fail(console: &mut Console, message: &str) -> ! {
+    print(console, message);
+    halt();
+}
+
+/// Stops the CPU for good.
+pub fn halt() -> ! {
+    loop {
+        unsafe { asm!("cli", "hlt", options(nomem, nostack)) };
+    }
+}
+
+#[panic_handler]
+pub fn panic(_info: &core::panic::PanicInfo<'_>) -> ! {
+    halt();
+}
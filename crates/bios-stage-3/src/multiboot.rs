@@ -0,0 +1,184 @@
+use core::{arch::asm, ops::Range, slice};
+
+use mrow_common::{
+    acpi::Rsdp,
+    handoff::Stage3Handoff,
+    memory_map::{MemoryKind, MemoryMap, MemoryRegion},
+    multiboot2::{Header, InfoBuilder, InfoFull, BOOTLOADER_MAGIC},
+};
+
+use crate::{fail, print, vga::Console, LOADER_NAME};
+
+/// Where we start looking for memory for the boot information, and for files
+/// that are in the way of the kernel.
+const ALLOCATION_START: u64 = 0x10_0000;
+
+/// How much memory we set aside for the boot information.
+const INFO_LEN: usize = 0x2000;
+
+/// Loads the Multiboot2 kernel `header` belongs to and jumps to it.
+///
+/// Everything of the loader must already be claimed in `memory_map`, except for
+/// the kernel image and initrd.
+pub fn boot<const N: usize>(
+    console: &mut Console,
+    handoff: &Stage3Handoff,
+    memory_map: &mut MemoryMap<N>,
+    header: Header,
+    rsdp: Option<Rsdp<'_>>,
+) -> ! {
+    let kernel = unsafe { handoff.kernel() };
+    let initrd = unsafe { handoff.initrd() };
+
+    let Ok(range) = header.load_range(kernel) else {
+        fail(console, "Bad Multiboot2 kernel\r\n");
+    };
+
+    // The kernel image and initrd aren't claimed yet, as the kernel may well
+    // want to go where stage 2 put them.
+    if !memory_map.contains(range.start, range.end, MemoryKind::Usable) {
+        fail(console, "Multiboot2 kernel doesn't fit in memory\r\n");
+    }
+
+    claim(console, memory_map, range.start, range.end);
+    for file in [kernel, initrd] {
+        let region = region_of(file);
+
+        claim(console, memory_map, region.start, region.end);
+    }
+
+    let kernel = evacuate(console, memory_map, kernel, &range);
+    let initrd = evacuate(console, memory_map, initrd, &range);
+
+    let memory = unsafe {
+        slice::from_raw_parts_mut(
+            range.start as usize as *mut u8,
+            (range.end - range.start) as usize,
+        )
+    };
+
+    let Ok(entry) = header.load(kernel, memory) else {
+        fail(console, "Failed to load Multiboot2 kernel\r\n");
+    };
+
+    let info = allocate(console, memory_map, INFO_LEN as u64);
+    let buffer = unsafe { slice::from_raw_parts_mut(info as usize as *mut u8, INFO_LEN) };
+
+    let Ok(info) = build_info(buffer, handoff, memory_map, initrd, rsdp) else {
+        fail(console, "Multiboot2 boot information doesn't fit\r\n");
+    };
+
+    print(console, "Booting Multiboot2 kernel\r\n");
+
+    unsafe { enter(entry, info.as_ptr() as u32) }
+}
+
+/// Writes the boot information into `buffer`.
+fn build_info<'a, const N: usize>(
+    buffer: &'a mut [u8],
+    handoff: &Stage3Handoff,
+    memory_map: &MemoryMap<N>,
+    initrd: &[u8],
+    rsdp: Option<Rsdp<'_>>,
+) -> Result<&'a mut [u8], InfoFull> {
+    let mut builder = InfoBuilder::new(buffer)?;
+
+    builder.cmdline(unsafe { handoff.cmdline() })?;
+    builder.boot_loader_name(LOADER_NAME)?;
+
+    if !initrd.is_empty() {
+        let start = initrd.as_ptr() as u32;
+
+        builder.module(start..start + initrd.len() as u32, "initrd")?;
+    }
+
+    // The firmware memory map, where nothing is claimed by us yet.
+    builder.basic_meminfo(unsafe { handoff.memory_map() })?;
+    builder.boot_device(
+        handoff.boot_drive as u32,
+        handoff.boot_partition().map_or(u32::MAX, u32::from),
+        u32::MAX,
+    )?;
+    builder.memory_map(memory_map.regions())?;
+    builder.framebuffer(&handoff.framebuffer)?;
+
+    if let Some(rsdp) = rsdp {
+        builder.rsdp(rsdp.as_bytes())?;
+    }
+
+    builder.finish()
+}
+
+/// Moves `file` somewhere else if it overlaps `range`, returning where it is now.
+fn evacuate<'a, const N: usize>(
+    console: &mut Console,
+    memory_map: &mut MemoryMap<N>,
+    file: &'a [u8],
+    range: &Range<u64>,
+) -> &'a [u8] {
+    let region = region_of(file);
+
+    if region.is_empty() | (region.end <= range.start) | (range.end <= region.start) {
+        return file;
+    }
+
+    // This is fresh memory, so it can't overlap the file.
+    let target = allocate(console, memory_map, file.len() as u64) as usize as *mut u8;
+
+    unsafe {
+        target.copy_from_nonoverlapping(file.as_ptr(), file.len());
+        slice::from_raw_parts(target, file.len())
+    }
+}
+
+/// Finds and claims `len` bytes of page aligned memory.
+fn allocate<const N: usize>(console: &mut Console, memory_map: &mut MemoryMap<N>, len: u64) -> u64 {
+    let Some(start) = memory_map.find(ALLOCATION_START, 1 << 32, len, 0x1000, MemoryKind::Usable)
+    else {
+        fail(console, "Not enough memory for Multiboot2 kernel\r\n");
+    };
+
+    claim(console, memory_map, start, start + len);
+
+    start
+}
+
+/// Marks `start..end` as used by the loader.
+fn claim<const N: usize>(
+    console: &mut Console,
+    memory_map: &mut MemoryMap<N>,
+    start: u64,
+    end: u64,
+) {
+    let region = MemoryRegion::new(start, end, MemoryKind::Loader);
+
+    if !region.is_empty() && memory_map.insert(region).is_err() {
+        fail(console, "Memory map has too many regions\r\n");
+    }
+}
+
+/// Returns the memory `bytes` takes up.
+fn region_of(bytes: &[u8]) -> MemoryRegion {
+    let start = bytes.as_ptr() as usize as u64;
+
+    MemoryRegion::new(start, start + bytes.len() as u64, MemoryKind::Loader)
+}
+
+/// Jumps to `entry` with `info` the way Multiboot2 says.
+///
+/// # Safety
+///
+/// `entry` must be the entry point of a loaded Multiboot2 kernel, and `info`
+/// its boot information. We must be in protected mode with flat segments,
+/// paging and interrupts disabled.
+unsafe fn enter(entry: u32, info: u32) -> ! {
+    unsafe {
+        asm!(
+            "jmp *{entry}",
+            entry = in(reg) entry,
+            in("eax") BOOTLOADER_MAGIC,
+            in("ebx") info,
+            options(att_syntax, noreturn),
+        );
+    }
+}
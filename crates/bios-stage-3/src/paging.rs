@@ -0,0 +1,92 @@
+use core::ptr;
+
+use mrow_common::{
+    handoff::KERNEL_BASE,
+    paging::{flags, Entry, Level, PageSize, PageTable, ENTRIES},
+};
+
+pub use mrow_common::paging::PAGE_SIZE;
+
+/// How much of the address space we identity map.
+const IDENTITY_LEN: u64 = 4 << 30;
+/// How many page directories the identity mapping needs.
+const IDENTITY_DIRECTORIES: u64 = IDENTITY_LEN / PageSize::Size1G.bytes();
+
+/// Returns how many bytes of page tables [`build`] needs to map `kernel_len` bytes.
+#[inline]
+#[must_use]
+pub const fn tables_len(kernel_len: u64) -> u64 {
+    let kernel_pages = kernel_len.div_ceil(PAGE_SIZE);
+
+    // One PML4, and a PDPT plus page directories for each half.
+    let tables = 1 + (1 + IDENTITY_DIRECTORIES) + 2 + kernel_pages.div_ceil(ENTRIES as u64);
+
+    tables * PAGE_SIZE
+}
+
+/// Builds page tables that identity map the first 4 GiB with 2 MiB pages and
+/// map the kernel at [`KERNEL_BASE`] with 4 KiB pages.
+///
+/// Returns the physical address of the PML4.
+///
+/// # Safety
+///
+/// `tables` must be page aligned and point to [`tables_len`] bytes we own,
+/// `kernel` must be page aligned and no larger than 1 GiB.
+pub unsafe fn build(tables: *mut PageTable, kernel: u64, kernel_len: u64) -> u64 {
+    let mut next = tables;
+    let mut allocate = || {
+        let table = unsafe { &mut *next };
+        next = unsafe { next.add(1) };
+        table
+    };
+
+    unsafe { ptr::write_bytes(tables.cast::<u8>(), 0, tables_len(kernel_len) as usize) };
+
+    let pml4 = allocate();
+
+    // The identity mapping.
+    let pdpt = allocate();
+    pml4.entries[0] = table_entry(pdpt);
+
+    for directory in 0..IDENTITY_DIRECTORIES {
+        let pd = allocate();
+        pdpt.entries[directory as usize] = table_entry(pd);
+
+        for (page, entry) in pd.entries.iter_mut().enumerate() {
+            let address = (directory * ENTRIES as u64 + page as u64) * PageSize::Size2M.bytes();
+            *entry = Entry::new(address, flags::PRESENT | flags::WRITABLE | flags::HUGE);
+        }
+    }
+
+    // The kernel mapping.
+    let pdpt = allocate();
+    pml4.entries[Level::Pml4.index(KERNEL_BASE)] = table_entry(pdpt);
+
+    let pd = allocate();
+    pdpt.entries[Level::Pdpt.index(KERNEL_BASE)] = table_entry(pd);
+
+    let kernel_pages = kernel_len.div_ceil(PAGE_SIZE);
+
+    for table in 0..kernel_pages.div_ceil(ENTRIES as u64) {
+        let pt = allocate();
+        pd.entries[Level::Pd.index(KERNEL_BASE) + table as usize] = table_entry(pt);
+
+        let pages = (kernel_pages - table * ENTRIES as u64).min(ENTRIES as u64);
+
+        for (page, entry) in pt.entries[..pages as usize].iter_mut().enumerate() {
+            let address = kernel + (table * ENTRIES as u64 + page as u64) * PAGE_SIZE;
+            *entry = Entry::new(address, flags::PRESENT | flags::WRITABLE);
+        }
+    }
+
+    pml4 as *mut PageTable as usize as u64
+}
+
+/// Returns an entry pointing to the next level table.
+fn table_entry(table: &PageTable) -> Entry {
+    Entry::new(
+        table as *const PageTable as usize as u64,
+        flags::PRESENT | flags::WRITABLE,
+    )
+}
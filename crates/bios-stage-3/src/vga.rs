@@ -0,0 +1,78 @@
+use core::ptr::{read_volatile, write_volatile};
+
+/// Address of the VGA text buffer.
+const BUFFER: *mut u16 = 0xb8000 as *mut u16;
+/// Address of the cursor position of page 0 in the BIOS data area.
+const BIOS_CURSOR: *const [u8; 2] = 0x450 as *const [u8; 2];
+
+const WIDTH: usize = 80;
+const HEIGHT: usize = 25;
+
+/// Light grey on black.
+const ATTRIBUTE: u16 = 0x07 << 8;
+
+/// A VGA text mode console that picks up where the BIOS left off.
+pub struct Console {
+    column: usize,
+    row: usize,
+}
+
+impl Console {
+    /// Creates a console at the cursor position the BIOS last used.
+    ///
+    /// # Safety
+    ///
+    /// The display must still be in the 80x25 text mode, and there must only ever
+    /// be one console writing to it at a time.
+    pub unsafe fn new() -> Self {
+        let [column, row] = unsafe { read_volatile(BIOS_CURSOR) };
+
+        Self {
+            column: (column as usize).min(WIDTH - 1),
+            row: (row as usize).min(HEIGHT - 1),
+        }
+    }
+
+    /// Writes a string to the console.
+    pub fn write_str(&mut self, s: &str) {
+        s.bytes().for_each(|byte| self.write_byte(byte));
+    }
+
+    /// Writes a single byte to the console, handling `\r` and `\n`.
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\r' => self.column = 0,
+            b'\n' => self.new_line(),
+            byte => {
+                if self.column >= WIDTH {
+                    self.column = 0;
+                    self.new_line();
+                }
+
+                unsafe { self.write_cell(self.row * WIDTH + self.column, byte as u16) };
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Moves to the next line, scrolling if we're at the bottom.
+    fn new_line(&mut self) {
+        if self.row + 1 < HEIGHT {
+            self.row += 1;
+            return;
+        }
+
+        for cell in WIDTH..WIDTH * HEIGHT {
+            unsafe { write_volatile(BUFFER.add(cell - WIDTH), read_volatile(BUFFER.add(cell))) };
+        }
+
+        for cell in WIDTH * (HEIGHT - 1)..WIDTH * HEIGHT {
+            unsafe { self.write_cell(cell, b' ' as u16) };
+        }
+    }
+
+    /// Writes a character to a cell.
+    unsafe fn write_cell(&mut self, cell: usize, character: u16) {
+        unsafe { write_volatile(BUFFER.add(cell), ATTRIBUTE | character) };
+    }
+}
@@ -0,0 +1,640 @@
+//! Finding and checking ACPI tables, and reading the MADT.
+
+use core::{fmt, ops::Range};
+
+/// The signature the RSDP starts with.
+pub const RSDP_SIGNATURE: [u8; 8] = *b"RSD PTR ";
+
+/// The address of the BIOS data area field holding the segment of the EBDA.
+pub const EBDA_POINTER: u64 = 0x40e;
+
+/// How much of the EBDA the RSDP may be in.
+pub const EBDA_SEARCH_LEN: u64 = 1024;
+
+/// The BIOS read-only memory area, the other place the RSDP may be in.
+pub const BIOS_AREA: Range<u64> = 0xe_0000..0x10_0000;
+
+/// The boundary the RSDP is aligned to.
+pub const RSDP_ALIGN: usize = 16;
+
+/// The length of the RSDP up to revision 0, which is all that is checksummed there.
+pub const RSDP_V1_LEN: usize = 20;
+
+/// The length of the RSDP from revision 2 on.
+pub const RSDP_V2_LEN: usize = 36;
+
+/// Returns the part of the EBDA at `segment` the RSDP may be in.
+///
+/// The segment comes from [`EBDA_POINTER`], and is rejected if it doesn't point
+/// to conventional memory above the BIOS data area.
+#[inline]
+#[must_use]
+pub const fn ebda_area(segment: u16) -> Option<Range<u64>> {
+    let start = (segment as u64) << 4;
+
+    if (start < 0x500) | (start + EBDA_SEARCH_LEN > 0xa_0000) {
+        return None;
+    }
+
+    Some(start..start + EBDA_SEARCH_LEN)
+}
+
+/// The length of the header every system description table starts with.
+pub const SDT_HEADER_LEN: usize = 36;
+
+/// The signature of the RSDT, which lists tables by 32-bit addresses.
+pub const RSDT_SIGNATURE: [u8; 4] = *b"RSDT";
+
+/// The signature of the XSDT, which lists tables by 64-bit addresses.
+pub const XSDT_SIGNATURE: [u8; 4] = *b"XSDT";
+
+/// The signature of the MADT, which lists interrupt controllers.
+pub const MADT_SIGNATURE: [u8; 4] = *b"APIC";
+
+/// Errors that can occur while checking an RSDP or a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error {
+    /// The structure doesn't start with the signature it should.
+    BadSignature,
+    /// The structure is shorter than it claims to be.
+    Truncated,
+    /// The bytes don't add up to zero.
+    BadChecksum,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadSignature => f.write_str("unexpected ACPI signature"),
+            Error::Truncated => f.write_str("ACPI structure is truncated"),
+            Error::BadChecksum => f.write_str("bad ACPI checksum"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// A checked Root System Description Pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rsdp<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Rsdp<'a> {
+    /// Checks the RSDP at the start of `bytes`.
+    ///
+    /// From revision 2 on, the extended checksum over the whole structure must
+    /// match as well.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        if !bytes.starts_with(&RSDP_SIGNATURE) {
+            return Err(Error::BadSignature);
+        }
+
+        let v1 = bytes.get(..RSDP_V1_LEN).ok_or(Error::Truncated)?;
+
+        if !checksum(v1) {
+            return Err(Error::BadChecksum);
+        }
+
+        if v1[15] < 2 {
+            return Ok(Self { bytes: v1 });
+        }
+
+        let len = bytes
+            .get(20..24)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+            .ok_or(Error::Truncated)?;
+
+        if len < RSDP_V2_LEN {
+            return Err(Error::Truncated);
+        }
+
+        let bytes = bytes.get(..len).ok_or(Error::Truncated)?;
+
+        if !checksum(bytes) {
+            return Err(Error::BadChecksum);
+        }
+
+        Ok(Self { bytes })
+    }
+
+    /// Returns the whole structure, as far as it is checksummed.
+    #[inline]
+    #[must_use]
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the ACPI revision, 0 for ACPI 1.0 and 2 or later otherwise.
+    #[inline]
+    #[must_use]
+    pub const fn revision(&self) -> u8 {
+        self.bytes[15]
+    }
+
+    /// Returns the OEM identifier.
+    #[inline]
+    #[must_use]
+    pub fn oem_id(&self) -> &'a [u8] {
+        &self.bytes[9..15]
+    }
+
+    /// Returns the physical address of the RSDT.
+    #[inline]
+    #[must_use]
+    pub fn rsdt_address(&self) -> u32 {
+        u32::from_le_bytes(self.bytes[16..20].try_into().unwrap())
+    }
+
+    /// Returns the physical address of the XSDT, which only exists from revision 2 on.
+    #[inline]
+    #[must_use]
+    pub fn xsdt_address(&self) -> Option<u64> {
+        let address = self.bytes.get(24..32)?;
+
+        Some(u64::from_le_bytes(address.try_into().unwrap())).filter(|&address| address != 0)
+    }
+}
+
+/// A checked system description table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sdt<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Sdt<'a> {
+    /// Returns the length the table starting with `header` claims to have.
+    ///
+    /// This is how much to read before calling [`Sdt::parse`].
+    #[inline]
+    #[must_use]
+    pub fn peek_len(header: &[u8]) -> Option<usize> {
+        let len = header.get(4..8)?;
+
+        Some(u32::from_le_bytes(len.try_into().unwrap()) as usize)
+    }
+
+    /// Checks the table at the start of `bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        let len = Self::peek_len(bytes).ok_or(Error::Truncated)?;
+
+        if len < SDT_HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+
+        let bytes = bytes.get(..len).ok_or(Error::Truncated)?;
+
+        if !checksum(bytes) {
+            return Err(Error::BadChecksum);
+        }
+
+        Ok(Self { bytes })
+    }
+
+    /// Returns the whole table, header included.
+    #[inline]
+    #[must_use]
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the signature saying what table this is.
+    #[inline]
+    #[must_use]
+    pub fn signature(&self) -> [u8; 4] {
+        self.bytes[..4].try_into().unwrap()
+    }
+
+    /// Returns the revision of the table's structure.
+    #[inline]
+    #[must_use]
+    pub const fn revision(&self) -> u8 {
+        self.bytes[8]
+    }
+
+    /// Returns the OEM identifier.
+    #[inline]
+    #[must_use]
+    pub fn oem_id(&self) -> &'a [u8] {
+        &self.bytes[10..16]
+    }
+
+    /// Returns what follows the header.
+    #[inline]
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[SDT_HEADER_LEN..]
+    }
+
+    /// Returns the physical addresses of the tables an RSDT or XSDT lists.
+    ///
+    /// Anything but an XSDT is read as an RSDT.
+    pub fn table_addresses(&self) -> impl Iterator<Item = u64> + 'a {
+        let size = if self.signature() == XSDT_SIGNATURE {
+            8
+        } else {
+            4
+        };
+
+        self.data().chunks_exact(size).map(move |address| {
+            let mut bytes = [0; 8];
+            bytes[..size].copy_from_slice(address);
+
+            u64::from_le_bytes(bytes)
+        })
+    }
+}
+
+/// The Multiple APIC Description Table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Madt<'a> {
+    local_apic_address: u32,
+    flags: u32,
+    entries: &'a [u8],
+}
+
+impl<'a> Madt<'a> {
+    /// Reads the MADT out of a checked table.
+    pub fn parse(sdt: Sdt<'a>) -> Result<Self, Error> {
+        if sdt.signature() != MADT_SIGNATURE {
+            return Err(Error::BadSignature);
+        }
+
+        let data = sdt.data();
+
+        if data.len() < 8 {
+            return Err(Error::Truncated);
+        }
+
+        Ok(Self {
+            local_apic_address: u32::from_le_bytes(data[..4].try_into().unwrap()),
+            flags: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+            entries: &data[8..],
+        })
+    }
+
+    /// Returns the physical address of the local APIC, taking overrides into account.
+    #[must_use]
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::LocalApicAddress(address) => Some(address),
+                _ => None,
+            })
+            .unwrap_or(self.local_apic_address as u64)
+    }
+
+    /// Returns whether there are 8259 PICs as well, which need masking.
+    #[inline]
+    #[must_use]
+    pub const fn has_legacy_pics(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    /// Returns the entries describing processors and interrupt controllers.
+    #[inline]
+    #[must_use]
+    pub const fn entries(&self) -> MadtEntries<'a> {
+        MadtEntries {
+            bytes: self.entries,
+        }
+    }
+}
+
+/// A processor's local APIC is usable.
+pub const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+
+/// An entry of the [`Madt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MadtEntry {
+    /// A processor and its local APIC.
+    LocalApic {
+        /// The ACPI processor UID.
+        processor: u8,
+        /// The ID of the local APIC.
+        apic_id: u8,
+        /// Whether it's [enabled](LOCAL_APIC_ENABLED).
+        flags: u32,
+    },
+    /// An I/O APIC.
+    IoApic {
+        /// The ID of the I/O APIC.
+        id: u8,
+        /// The physical address of its registers.
+        address: u32,
+        /// The first global system interrupt it handles.
+        gsi_base: u32,
+    },
+    /// An ISA IRQ that isn't identity mapped to a global system interrupt.
+    InterruptOverride(InterruptOverride),
+    /// A local APIC input wired to NMI.
+    LocalApicNmi {
+        /// The ACPI processor UID, or `0xff` for all of them.
+        processor: u8,
+        /// The polarity and trigger mode, see [`InterruptOverride::flags`].
+        flags: u16,
+        /// The local interrupt input, 0 for LINT0 and 1 for LINT1.
+        lint: u8,
+    },
+    /// A 64-bit address of the local APIC, overriding the one in the header.
+    LocalApicAddress(u64),
+    /// An entry of a kind we don't care about.
+    Other(u8),
+}
+
+/// A MADT interrupt source override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InterruptOverride {
+    /// The bus, always 0 for ISA.
+    pub bus: u8,
+    /// The ISA IRQ.
+    pub source: u8,
+    /// The global system interrupt it's connected to.
+    pub gsi: u32,
+    /// The polarity in bits 0-1, and the trigger mode in bits 2-3.
+    pub flags: u16,
+}
+
+/// An iterator over the entries of a [`Madt`].
+///
+/// Stops at the first entry that doesn't fit.
+#[derive(Debug, Clone)]
+pub struct MadtEntries<'a> {
+    bytes: &'a [u8],
+}
+
+impl Iterator for MadtEntries<'_> {
+    type Item = MadtEntry;
+
+    fn next(&mut self) -> Option<MadtEntry> {
+        let (&kind, &len) = (self.bytes.first()?, self.bytes.get(1)?);
+        let entry = self.bytes.get(..(len as usize).max(2))?;
+
+        self.bytes = &self.bytes[entry.len()..];
+
+        let u16_at = |offset: usize| {
+            Some(u16::from_le_bytes(
+                entry.get(offset..offset + 2)?.try_into().unwrap(),
+            ))
+        };
+        let u32_at = |offset: usize| {
+            Some(u32::from_le_bytes(
+                entry.get(offset..offset + 4)?.try_into().unwrap(),
+            ))
+        };
+
+        let parsed = match kind {
+            0 => MadtEntry::LocalApic {
+                processor: *entry.get(2)?,
+                apic_id: *entry.get(3)?,
+                flags: u32_at(4)?,
+            },
+            1 => MadtEntry::IoApic {
+                id: *entry.get(2)?,
+                address: u32_at(4)?,
+                gsi_base: u32_at(8)?,
+            },
+            2 => MadtEntry::InterruptOverride(InterruptOverride {
+                bus: *entry.get(2)?,
+                source: *entry.get(3)?,
+                gsi: u32_at(4)?,
+                flags: u16_at(8)?,
+            }),
+            4 => MadtEntry::LocalApicNmi {
+                processor: *entry.get(2)?,
+                flags: u16_at(3)?,
+                lint: *entry.get(5)?,
+            },
+            5 => MadtEntry::LocalApicAddress(u64::from_le_bytes(
+                entry.get(4..12)?.try_into().unwrap(),
+            )),
+            kind => MadtEntry::Other(kind),
+        };
+
+        Some(parsed)
+    }
+}
+
+/// Looks for a valid RSDP in `area`, which must start on a [`RSDP_ALIGN`] boundary.
+///
+/// Returns the offset of the RSDP within `area`. Copies with a bad checksum are
+/// skipped, as some firmware leaves stale ones around.
+#[must_use]
+pub fn find_rsdp(area: &[u8]) -> Option<(usize, Rsdp<'_>)> {
+    (0..area.len())
+        .step_by(RSDP_ALIGN)
+        .find_map(|offset| Some((offset, Rsdp::parse(&area[offset..]).ok()?)))
+}
+
+/// Returns whether `bytes` add up to zero.
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    /// Builds an RSDP of `revision` with a valid checksum.
+    fn rsdp(revision: u8) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&RSDP_SIGNATURE);
+        bytes.push(0);
+        bytes.extend_from_slice(b"BOCHS ");
+        bytes.push(revision);
+        bytes.extend_from_slice(&0x07fe_14d2_u32.to_le_bytes());
+        bytes[8] = fix(&bytes);
+
+        if revision >= 2 {
+            bytes.extend_from_slice(&(RSDP_V2_LEN as u32).to_le_bytes());
+            bytes.extend_from_slice(&0x07fe_1500_u64.to_le_bytes());
+            bytes.extend_from_slice(&[0; 4]);
+            bytes[32] = fix(&bytes);
+        }
+
+        bytes
+    }
+
+    /// Returns the byte that makes `bytes` add up to zero.
+    fn fix(bytes: &[u8]) -> u8 {
+        0_u8.wrapping_sub(bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)))
+    }
+
+    #[test]
+    fn parses_v1() {
+        let bytes = rsdp(0);
+        let rsdp = Rsdp::parse(&bytes).unwrap();
+
+        assert_eq!(rsdp.revision(), 0);
+        assert_eq!(rsdp.oem_id(), b"BOCHS ");
+        assert_eq!(rsdp.rsdt_address(), 0x07fe_14d2);
+        assert_eq!(rsdp.xsdt_address(), None);
+        assert_eq!(rsdp.as_bytes().len(), RSDP_V1_LEN);
+    }
+
+    #[test]
+    fn parses_v2() {
+        let bytes = rsdp(2);
+        let rsdp = Rsdp::parse(&bytes).unwrap();
+
+        assert_eq!(rsdp.revision(), 2);
+        assert_eq!(rsdp.xsdt_address(), Some(0x07fe_1500));
+        assert_eq!(rsdp.as_bytes().len(), RSDP_V2_LEN);
+
+        let mut bad = bytes.clone();
+        bad[28] ^= 1;
+        assert_eq!(Rsdp::parse(&bad), Err(Error::BadChecksum));
+
+        assert_eq!(Rsdp::parse(&bytes[..30]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut bytes = rsdp(0);
+        bytes[10] ^= 1;
+
+        assert_eq!(Rsdp::parse(&bytes), Err(Error::BadChecksum));
+        assert_eq!(Rsdp::parse(b"RSD PTX "), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn scans_bios_area() {
+        let mut area = vec![0xff; (BIOS_AREA.end - BIOS_AREA.start) as usize];
+
+        // A stale copy with a broken checksum, one off the boundary, and the real one.
+        let mut stale = rsdp(2);
+        stale[16] ^= 1;
+        area[0x1_0000..0x1_0000 + RSDP_V2_LEN].copy_from_slice(&stale);
+        area[0x1_2008..0x1_2008 + RSDP_V2_LEN].copy_from_slice(&rsdp(2));
+        area[0x1_6a20..0x1_6a20 + RSDP_V2_LEN].copy_from_slice(&rsdp(2));
+
+        let (offset, rsdp) = find_rsdp(&area).unwrap();
+
+        assert_eq!(offset, 0x1_6a20);
+        assert_eq!(rsdp.revision(), 2);
+        assert!(find_rsdp(&area[..0x1_6a20]).is_none());
+    }
+
+    /// Builds a table with a valid checksum.
+    fn table(signature: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(signature);
+        bytes.extend_from_slice(&((SDT_HEADER_LEN + data.len()) as u32).to_le_bytes());
+        bytes.push(1);
+        bytes.push(0);
+        bytes.extend_from_slice(b"BOCHS BXPC    ");
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend_from_slice(data);
+        bytes[9] = fix(&bytes);
+
+        bytes
+    }
+
+    #[test]
+    fn parses_tables() {
+        let rsdt = table(
+            &RSDT_SIGNATURE,
+            &[0x00, 0x20, 0xfe, 0x07, 0x80, 0x21, 0xfe, 0x07],
+        );
+        let sdt = Sdt::parse(&rsdt).unwrap();
+
+        assert_eq!(Sdt::peek_len(&rsdt[..8]), Some(44));
+        assert_eq!(sdt.signature(), RSDT_SIGNATURE);
+        assert_eq!(sdt.oem_id(), b"BOCHS ");
+        assert_eq!(
+            sdt.table_addresses().collect::<Vec<_>>(),
+            [0x07fe_2000, 0x07fe_2180]
+        );
+
+        let xsdt = table(&XSDT_SIGNATURE, &0x1_0000_2000_u64.to_le_bytes());
+        assert_eq!(
+            Sdt::parse(&xsdt)
+                .unwrap()
+                .table_addresses()
+                .collect::<Vec<_>>(),
+            [0x1_0000_2000]
+        );
+
+        let mut bad = rsdt.clone();
+        bad[40] ^= 1;
+        assert_eq!(Sdt::parse(&bad), Err(Error::BadChecksum));
+        assert_eq!(Sdt::parse(&rsdt[..40]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn parses_madt() {
+        // What QEMU's q35 machine has with one processor, cut short at the end.
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xfee0_0000_u32.to_le_bytes());
+        data.extend_from_slice(&1_u32.to_le_bytes());
+        data.extend_from_slice(&[0, 8, 0, 0, 1, 0, 0, 0]);
+        data.extend_from_slice(&[1, 12, 0, 0, 0x00, 0x00, 0xc0, 0xfe, 0, 0, 0, 0]);
+        data.extend_from_slice(&[2, 10, 0, 0, 2, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[2, 10, 0, 9, 9, 0, 0, 0, 0x0d, 0]);
+        data.extend_from_slice(&[4, 6, 0xff, 0, 0, 1]);
+        data.extend_from_slice(&[0x7f, 3, 0]);
+        data.extend_from_slice(&[1, 12, 0]);
+
+        let bytes = table(&MADT_SIGNATURE, &data);
+        let madt = Madt::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+
+        assert_eq!(madt.local_apic_address(), 0xfee0_0000);
+        assert!(madt.has_legacy_pics());
+        assert_eq!(
+            madt.entries().collect::<Vec<_>>(),
+            [
+                MadtEntry::LocalApic {
+                    processor: 0,
+                    apic_id: 0,
+                    flags: LOCAL_APIC_ENABLED,
+                },
+                MadtEntry::IoApic {
+                    id: 0,
+                    address: 0xfec0_0000,
+                    gsi_base: 0,
+                },
+                MadtEntry::InterruptOverride(InterruptOverride {
+                    bus: 0,
+                    source: 0,
+                    gsi: 2,
+                    flags: 0,
+                }),
+                MadtEntry::InterruptOverride(InterruptOverride {
+                    bus: 0,
+                    source: 9,
+                    gsi: 9,
+                    flags: 0x0d,
+                }),
+                MadtEntry::LocalApicNmi {
+                    processor: 0xff,
+                    flags: 0,
+                    lint: 1,
+                },
+                MadtEntry::Other(0x7f),
+            ]
+        );
+
+        let mut data = data[..8].to_vec();
+        data.extend_from_slice(&[5, 12, 0, 0]);
+        data.extend_from_slice(&0x1_fee0_0000_u64.to_le_bytes());
+
+        let bytes = table(&MADT_SIGNATURE, &data);
+        let madt = Madt::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+
+        assert_eq!(madt.local_apic_address(), 0x1_fee0_0000);
+        assert_eq!(
+            Madt::parse(Sdt::parse(&table(b"FACP", &data)).unwrap()),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn checks_ebda_segment() {
+        assert_eq!(ebda_area(0x9fc0), Some(0x9_fc00..0xa_0000));
+        assert_eq!(ebda_area(0), None);
+        assert_eq!(ebda_area(0xa000), None);
+        assert_eq!(ebda_area(0xffff), None);
+    }
+}
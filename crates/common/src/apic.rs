@@ -0,0 +1,267 @@
+//! Local APIC and I/O APIC registers, and routing ISA IRQs through overrides.
+
+use crate::acpi::InterruptOverride;
+
+/// Registers of the local APIC, as offsets from its base.
+pub mod local {
+    /// The ID of the local APIC, in bits 24-31.
+    pub const ID: usize = 0x20;
+    /// The version, and how many LVT entries there are.
+    pub const VERSION: usize = 0x30;
+    /// The task priority, interrupts at or below it are held back.
+    pub const TASK_PRIORITY: usize = 0x80;
+    /// Written to when an interrupt has been handled.
+    pub const END_OF_INTERRUPT: usize = 0xb0;
+    /// The spurious interrupt vector, and the software enable bit.
+    pub const SPURIOUS: usize = 0xf0;
+    /// The LVT entry of the timer.
+    pub const LVT_TIMER: usize = 0x320;
+    /// The LVT entry of the LINT0 input.
+    pub const LVT_LINT0: usize = 0x350;
+    /// The LVT entry of the LINT1 input.
+    pub const LVT_LINT1: usize = 0x360;
+    /// The LVT entry of internal errors.
+    pub const LVT_ERROR: usize = 0x370;
+    /// The count the timer starts from, writing it starts the timer.
+    pub const TIMER_INITIAL: usize = 0x380;
+    /// The count the timer is at.
+    pub const TIMER_CURRENT: usize = 0x390;
+    /// What the bus clock is divided by for the timer.
+    pub const TIMER_DIVIDE: usize = 0x3e0;
+
+    /// The bit of [`SPURIOUS`] that turns the local APIC on.
+    pub const SOFTWARE_ENABLE: u32 = 1 << 8;
+
+    /// The value of [`TIMER_DIVIDE`] dividing by 16.
+    pub const DIVIDE_BY_16: u32 = 0b0011;
+}
+
+/// Bits of a local vector table entry, next to the vector in bits 0-7.
+pub mod lvt {
+    /// Deliver a non-maskable interrupt, ignoring the vector.
+    pub const NMI: u32 = 0b100 << 8;
+    /// The input is active low.
+    pub const ACTIVE_LOW: u32 = 1 << 13;
+    /// The input is level triggered.
+    pub const LEVEL: u32 = 1 << 15;
+    /// The interrupt is masked.
+    pub const MASKED: u32 = 1 << 16;
+    /// The timer restarts when it reaches zero.
+    pub const PERIODIC: u32 = 1 << 17;
+}
+
+/// Registers of an I/O APIC.
+pub mod io {
+    /// The offset of the register selecting what [`WINDOW`] accesses.
+    pub const SELECT: usize = 0x00;
+    /// The offset of the window into the selected register.
+    pub const WINDOW: usize = 0x10;
+
+    /// The ID of the I/O APIC.
+    pub const ID: u32 = 0x00;
+    /// The version, and the index of the last redirection entry in bits 16-23.
+    pub const VERSION: u32 = 0x01;
+    /// The first redirection entry, each takes up two registers.
+    pub const REDIRECTION: u32 = 0x10;
+}
+
+/// Returns how many redirection entries an I/O APIC with `version` has.
+#[inline]
+#[must_use]
+pub const fn redirection_entries(version: u32) -> u32 {
+    ((version >> 16) & 0xff) + 1
+}
+
+/// When an interrupt input is active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Polarity {
+    /// The line is active while it's high.
+    High,
+    /// The line is active while it's low.
+    Low,
+}
+
+/// What makes an interrupt input fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Trigger {
+    /// The line becoming active.
+    Edge,
+    /// The line being active.
+    Level,
+}
+
+/// Where an interrupt comes in, and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Route {
+    /// The global system interrupt.
+    pub gsi: u32,
+    /// When it's active.
+    pub polarity: Polarity,
+    /// What makes it fire.
+    pub trigger: Trigger,
+}
+
+impl Route {
+    /// Returns where ISA IRQ `irq` comes in, given the MADT's `overrides`.
+    ///
+    /// Without an override, ISA IRQs are identity mapped, edge triggered and
+    /// active high. Overrides can leave either of those to the bus, which means
+    /// the ISA default too.
+    #[must_use]
+    pub fn isa(irq: u8, overrides: impl IntoIterator<Item = InterruptOverride>) -> Self {
+        overrides
+            .into_iter()
+            .find(|found| (found.bus == 0) & (found.source == irq))
+            .map_or(Self::with_flags(irq as u32, 0), |found| {
+                Self::with_flags(found.gsi, found.flags)
+            })
+    }
+
+    /// Returns a route to `gsi` with MADT `flags`, see [`InterruptOverride::flags`].
+    ///
+    /// Whatever the flags leave to the bus gets the ISA default.
+    #[inline]
+    #[must_use]
+    pub const fn with_flags(gsi: u32, flags: u16) -> Self {
+        Self {
+            gsi,
+            polarity: match flags & 0b11 {
+                0b11 => Polarity::Low,
+                _ => Polarity::High,
+            },
+            trigger: match (flags >> 2) & 0b11 {
+                0b11 => Trigger::Level,
+                _ => Trigger::Edge,
+            },
+        }
+    }
+
+    /// Returns the LVT bits for an input with this polarity and trigger mode.
+    #[inline]
+    #[must_use]
+    pub const fn lvt_bits(self) -> u32 {
+        let polarity = match self.polarity {
+            Polarity::High => 0,
+            Polarity::Low => lvt::ACTIVE_LOW,
+        };
+
+        let trigger = match self.trigger {
+            Trigger::Edge => 0,
+            Trigger::Level => lvt::LEVEL,
+        };
+
+        polarity | trigger
+    }
+}
+
+/// An entry of an I/O APIC's redirection table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct RedirectionEntry(pub u64);
+
+impl RedirectionEntry {
+    /// The bit that masks the input.
+    pub const MASKED: u64 = 1 << 16;
+
+    /// Creates an entry delivering `route` as `vector` to the local APIC `destination`.
+    #[inline]
+    #[must_use]
+    pub const fn new(route: Route, vector: u8, destination: u8) -> Self {
+        // The polarity and trigger bits are in the same place as in the LVT.
+        Self(vector as u64 | route.lvt_bits() as u64 | ((destination as u64) << 56))
+    }
+
+    /// Returns the entry with the input masked.
+    #[inline]
+    #[must_use]
+    pub const fn masked(self) -> Self {
+        Self(self.0 | Self::MASKED)
+    }
+
+    /// Returns whether the input is masked.
+    #[inline]
+    #[must_use]
+    pub const fn is_masked(self) -> bool {
+        self.0 & Self::MASKED != 0
+    }
+
+    /// Returns the vector the input is delivered as.
+    #[inline]
+    #[must_use]
+    pub const fn vector(self) -> u8 {
+        self.0 as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERRIDES: [InterruptOverride; 3] = [
+        InterruptOverride {
+            bus: 0,
+            source: 0,
+            gsi: 2,
+            flags: 0,
+        },
+        InterruptOverride {
+            bus: 0,
+            source: 9,
+            gsi: 9,
+            flags: 0b1101,
+        },
+        InterruptOverride {
+            bus: 1,
+            source: 1,
+            gsi: 20,
+            flags: 0b1111,
+        },
+    ];
+
+    #[test]
+    fn routes_isa_irqs() {
+        assert_eq!(
+            Route::isa(0, OVERRIDES),
+            Route {
+                gsi: 2,
+                polarity: Polarity::High,
+                trigger: Trigger::Edge,
+            }
+        );
+        assert_eq!(
+            Route::isa(9, OVERRIDES),
+            Route {
+                gsi: 9,
+                polarity: Polarity::High,
+                trigger: Trigger::Level,
+            }
+        );
+
+        // Overrides for other buses don't count.
+        assert_eq!(
+            Route::isa(1, OVERRIDES),
+            Route {
+                gsi: 1,
+                polarity: Polarity::High,
+                trigger: Trigger::Edge,
+            }
+        );
+    }
+
+    #[test]
+    fn redirection_entries() {
+        let route = Route {
+            gsi: 9,
+            polarity: Polarity::Low,
+            trigger: Trigger::Level,
+        };
+        let entry = RedirectionEntry::new(route, 0x39, 3);
+
+        assert_eq!(entry.0, 0x0300_0000_0000_a039);
+        assert_eq!(entry.vector(), 0x39);
+        assert!(!entry.is_masked());
+        assert!(entry.masked().is_masked());
+
+        assert_eq!(super::redirection_entries(0x0017_0020), 24);
+    }
+}
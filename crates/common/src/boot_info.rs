@@ -0,0 +1,774 @@
+//! The boot information the loader hands to the kernel.
+//!
+//! Everything lives in one contiguous blob that starts with a [`BootInfo`]
+//! header. Data inside the blob is referred to by [`Span`]s, offsets from the
+//! start of the header, so the blob stays valid wherever it ends up. Only things
+//! outside of it, like modules and the framebuffer, use physical addresses.
+//!
+//! The loader writes the blob with a [`Builder`], the kernel checks and reads it
+//! with a [`Reader`].
+
+use core::{
+    fmt,
+    mem::{align_of, size_of},
+    ops::Range,
+    ptr, slice, str,
+};
+
+use crate::{framebuffer::Framebuffer, memory_map::MemoryRegion};
+
+/// Identifies the boot information, `b"mrowboot"` in memory.
+pub const MAGIC: u64 = u64::from_le_bytes(*b"mrowboot");
+
+/// The current version of the [`BootInfo`] layout.
+///
+/// Bump this whenever the layout of [`BootInfo`], [`Module`] or anything a
+/// [`Span`] points to changes.
+pub const VERSION: u16 = 1;
+
+/// The alignment of the blob, and of everything a [`Span`] points to.
+pub const ALIGN: usize = 8;
+
+/// The value of [`BootInfo::boot_partition`] if we didn't boot from a partition.
+pub const NO_PARTITION: u32 = u32::MAX;
+
+/// A range within the boot information, relative to the start of the [`BootInfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct Span {
+    /// The offset of the first byte.
+    pub offset: u32,
+    /// The amount of items, bytes for strings.
+    pub len: u32,
+}
+
+impl Span {
+    /// An empty span.
+    pub const EMPTY: Self = Self { offset: 0, len: 0 };
+
+    /// Returns the byte range of `len` items of `size` bytes each.
+    fn bytes(self, size: usize) -> Option<Range<usize>> {
+        let start = self.offset as usize;
+        let end = (self.len as usize).checked_mul(size)?.checked_add(start)?;
+
+        Some(start..end)
+    }
+}
+
+/// A file the loader put into memory for the kernel, like the initial ramdisk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct Module {
+    /// Physical address of the first byte.
+    pub address: u64,
+    /// Length in bytes.
+    pub len: u64,
+    /// The name of the module, see [`Reader::module_name`].
+    pub name: Span,
+}
+
+impl Module {
+    /// Returns the physical addresses of the module.
+    #[inline]
+    #[must_use]
+    pub const fn memory(&self) -> Range<u64> {
+        self.address..self.address + self.len
+    }
+}
+
+/// The header at the start of the boot information.
+///
+/// Like the handoffs between the stages, the kernel must check the version
+/// before touching anything else, which [`Reader::new`] does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct BootInfo {
+    /// Must be [`MAGIC`].
+    ///
+    /// This, [`BootInfo::version`], [`BootInfo::header_size`] and
+    /// [`BootInfo::total_size`] must always stay at the start of the struct.
+    pub magic: u64,
+    /// The version of the layout, must be [`VERSION`].
+    pub version: u16,
+    /// The size of this header in bytes.
+    pub header_size: u16,
+    /// The size of the whole blob in bytes, header included.
+    pub total_size: u32,
+    /// The memory map, as [`MemoryRegion`]s.
+    ///
+    /// Everything the loader handed to the kernel is marked as
+    /// [`MemoryKind::Loader`](crate::memory_map::MemoryKind::Loader), the boot
+    /// information included.
+    pub memory_map: Span,
+    /// The kernel command line, UTF-8 but not nul terminated.
+    pub cmdline: Span,
+    /// The loaded modules, as [`Module`]s.
+    pub modules: Span,
+    /// The name of the loader.
+    pub loader_name: Span,
+    /// The version of the loader.
+    pub loader_version: Span,
+    /// Physical address of the ACPI RSDP, zero if there is none.
+    pub rsdp: u64,
+    /// Physical address of the SMBIOS entry point, zero if there is none.
+    pub smbios: u64,
+    /// The BIOS drive number we booted from.
+    pub boot_drive: u8,
+    /// Reserved, must be zero.
+    pub reserved: [u8; 3],
+    /// The index of the partition we booted from, or [`NO_PARTITION`].
+    pub boot_partition: u32,
+    /// The framebuffer the loader set up, if any.
+    pub framebuffer: Framebuffer,
+}
+
+impl BootInfo {
+    /// The size of the header in bytes.
+    pub const SIZE: u16 = size_of::<Self>() as u16;
+
+    /// Creates an empty header of the current version.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            version: VERSION,
+            header_size: Self::SIZE,
+            total_size: Self::SIZE as u32,
+            memory_map: Span::EMPTY,
+            cmdline: Span::EMPTY,
+            modules: Span::EMPTY,
+            loader_name: Span::EMPTY,
+            loader_version: Span::EMPTY,
+            rsdp: 0,
+            smbios: 0,
+            boot_drive: 0,
+            reserved: [0; 3],
+            boot_partition: NO_PARTITION,
+            framebuffer: Framebuffer::NONE,
+        }
+    }
+
+    /// Returns whether this header has the magic, version and size we expect.
+    #[inline]
+    #[must_use]
+    pub const fn is_compatible(&self) -> bool {
+        (self.magic == MAGIC) & (self.version == VERSION) & (self.header_size == Self::SIZE)
+    }
+}
+
+impl Default for BootInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error returned when the boot information doesn't fit into the buffer of a [`Builder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BootInfoFull;
+
+impl fmt::Display for BootInfoFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("boot information doesn't fit")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BootInfoFull {}
+
+/// Writes boot information into a buffer.
+///
+/// The buffer should be aligned to [`ALIGN`], so the kernel can read the blob in
+/// place. Calling a method again replaces what the earlier call set, though the
+/// old data still takes up space.
+#[derive(Debug)]
+pub struct Builder<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    info: BootInfo,
+}
+
+impl<'a> Builder<'a> {
+    /// Starts writing boot information into `buffer`.
+    pub fn new(buffer: &'a mut [u8]) -> Result<Self, BootInfoFull> {
+        let mut builder = Self {
+            buffer,
+            len: 0,
+            info: BootInfo::new(),
+        };
+
+        // The header is written by `finish`, once all spans are known.
+        builder.push(&[0; BootInfo::SIZE as usize])?;
+
+        Ok(builder)
+    }
+
+    /// Sets the memory map.
+    pub fn memory_map(&mut self, regions: &[MemoryRegion]) -> Result<(), BootInfoFull> {
+        let offset = self.align()?;
+
+        for region in regions {
+            self.push(&region.start.to_le_bytes())?;
+            self.push(&region.end.to_le_bytes())?;
+            self.push(&(region.kind as u32).to_le_bytes())?;
+            self.push(&[0; size_of::<MemoryRegion>() - 20])?;
+        }
+
+        self.info.memory_map = Span {
+            offset,
+            len: regions.len() as u32,
+        };
+
+        Ok(())
+    }
+
+    /// Sets the kernel command line.
+    pub fn cmdline(&mut self, cmdline: &str) -> Result<(), BootInfoFull> {
+        self.info.cmdline = self.string(cmdline)?;
+
+        Ok(())
+    }
+
+    /// Sets the modules, each given as its physical addresses and name.
+    pub fn modules(&mut self, modules: &[(Range<u64>, &str)]) -> Result<(), BootInfoFull> {
+        let offset = self.align()?;
+
+        // Leave room for the entries, the names follow after them.
+        let entries = offset as usize;
+        self.push_zeroes(modules.len() * size_of::<Module>())?;
+
+        for (index, (memory, name)) in modules.iter().enumerate() {
+            let module = Module {
+                address: memory.start,
+                len: memory.end.saturating_sub(memory.start),
+                name: self.string(name)?,
+            };
+            let start = entries + index * size_of::<Module>();
+
+            // SAFETY: `push_zeroes` made sure the entry is within the buffer.
+            unsafe {
+                ptr::write_unaligned(self.buffer[start..].as_mut_ptr().cast::<Module>(), module)
+            };
+        }
+
+        self.info.modules = Span {
+            offset,
+            len: modules.len() as u32,
+        };
+
+        Ok(())
+    }
+
+    /// Sets the framebuffer.
+    pub fn framebuffer(&mut self, framebuffer: Framebuffer) {
+        self.info.framebuffer = framebuffer;
+    }
+
+    /// Sets the physical address of the ACPI RSDP.
+    pub fn rsdp(&mut self, address: u64) {
+        self.info.rsdp = address;
+    }
+
+    /// Sets the physical address of the SMBIOS entry point.
+    pub fn smbios(&mut self, address: u64) {
+        self.info.smbios = address;
+    }
+
+    /// Sets the BIOS drive and the index of the partition we booted from.
+    pub fn boot_device(&mut self, drive: u8, partition: Option<u32>) {
+        self.info.boot_drive = drive;
+        self.info.boot_partition = partition.unwrap_or(NO_PARTITION);
+    }
+
+    /// Sets the name and version of the loader.
+    pub fn loader(&mut self, name: &str, version: &str) -> Result<(), BootInfoFull> {
+        self.info.loader_name = self.string(name)?;
+        self.info.loader_version = self.string(version)?;
+
+        Ok(())
+    }
+
+    /// Writes the header, returning the boot information.
+    pub fn finish(mut self) -> Result<&'a mut [u8], BootInfoFull> {
+        self.align()?;
+
+        let Self {
+            buffer,
+            len,
+            mut info,
+        } = self;
+
+        info.total_size = len as u32;
+
+        // SAFETY: `new` made sure the header is within the buffer, and it has no
+        // padding, so every byte of the blob is initialized.
+        unsafe { ptr::write_unaligned(buffer.as_mut_ptr().cast::<BootInfo>(), info) };
+
+        Ok(&mut buffer[..len])
+    }
+
+    /// Appends `string`, returning where it ended up.
+    fn string(&mut self, string: &str) -> Result<Span, BootInfoFull> {
+        let offset = self.len as u32;
+        self.push(string.as_bytes())?;
+
+        Ok(Span {
+            offset,
+            len: string.len() as u32,
+        })
+    }
+
+    /// Pads the blob to [`ALIGN`], returning the new length.
+    fn align(&mut self) -> Result<u32, BootInfoFull> {
+        self.push_zeroes(self.len.next_multiple_of(ALIGN) - self.len)?;
+
+        u32::try_from(self.len).map_err(|_| BootInfoFull)
+    }
+
+    /// Appends `len` zero bytes.
+    fn push_zeroes(&mut self, len: usize) -> Result<(), BootInfoFull> {
+        let end = self.len.checked_add(len).ok_or(BootInfoFull)?;
+
+        self.buffer
+            .get_mut(self.len..end)
+            .ok_or(BootInfoFull)?
+            .fill(0);
+        self.len = end;
+
+        Ok(())
+    }
+
+    /// Appends `bytes`.
+    fn push(&mut self, bytes: &[u8]) -> Result<(), BootInfoFull> {
+        let end = self.len.checked_add(bytes.len()).ok_or(BootInfoFull)?;
+
+        self.buffer
+            .get_mut(self.len..end)
+            .ok_or(BootInfoFull)?
+            .copy_from_slice(bytes);
+        self.len = end;
+
+        Ok(())
+    }
+}
+
+/// Errors that can occur while checking boot information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error {
+    /// The blob isn't aligned to [`ALIGN`].
+    Misaligned,
+    /// The blob doesn't start with [`MAGIC`].
+    BadMagic,
+    /// The blob was written for a different version of the layout.
+    Incompatible,
+    /// The blob is shorter than it claims to be.
+    Truncated,
+    /// A span points outside of the blob or isn't aligned.
+    BadSpan,
+    /// A string isn't valid UTF-8.
+    BadString,
+    /// The memory map contains a kind of memory we don't know.
+    BadMemoryKind(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Misaligned => f.write_str("boot information is misaligned"),
+            Error::BadMagic => f.write_str("not boot information"),
+            Error::Incompatible => f.write_str("incompatible boot information version"),
+            Error::Truncated => f.write_str("boot information is truncated"),
+            Error::BadSpan => f.write_str("boot information points outside of itself"),
+            Error::BadString => f.write_str("boot information contains invalid UTF-8"),
+            Error::BadMemoryKind(kind) => write!(f, "unknown memory kind {kind:#x}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Checked access to boot information.
+#[derive(Debug, Clone, Copy)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the boot information in `bytes`, which may be followed by anything.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.as_ptr().align_offset(ALIGN) != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        if bytes.len() < 16 {
+            return Err(Error::Truncated);
+        }
+
+        let field = |offset: usize, len: usize| &bytes[offset..offset + len];
+
+        if field(0, 8) != MAGIC.to_le_bytes() {
+            return Err(Error::BadMagic);
+        }
+
+        let header_size = u16::from_le_bytes(field(10, 2).try_into().unwrap());
+        let total_size = u32::from_le_bytes(field(12, 4).try_into().unwrap()) as usize;
+
+        if (u16::from_le_bytes(field(8, 2).try_into().unwrap()) != VERSION)
+            | (header_size != BootInfo::SIZE)
+        {
+            return Err(Error::Incompatible);
+        }
+
+        if (total_size < BootInfo::SIZE as usize) | (total_size > bytes.len()) {
+            return Err(Error::Truncated);
+        }
+
+        let reader = Self {
+            bytes: &bytes[..total_size],
+        };
+        let info = reader.info();
+
+        // Memory regions are only valid with a known kind, so check the raw values
+        // before ever making a reference to one.
+        let regions = reader.slice(info.memory_map, size_of::<MemoryRegion>())?;
+
+        if regions.as_ptr().align_offset(align_of::<MemoryRegion>()) != 0 {
+            return Err(Error::BadSpan);
+        }
+
+        let (regions, _) = regions.as_chunks::<{ size_of::<MemoryRegion>() }>();
+
+        for region in regions {
+            let kind = u32::from_le_bytes(region[16..20].try_into().unwrap());
+
+            if !matches!(kind, 1..=5 | 0x1000) {
+                return Err(Error::BadMemoryKind(kind));
+            }
+        }
+
+        reader.items::<MemoryRegion>(info.memory_map)?;
+        reader.items::<Module>(info.modules)?;
+
+        for span in [info.cmdline, info.loader_name, info.loader_version] {
+            reader.str(span)?;
+        }
+
+        for module in reader.modules() {
+            reader.str(module.name)?;
+        }
+
+        Ok(reader)
+    }
+
+    /// Checks the boot information at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to readable memory, as far as the blob says it is long.
+    pub unsafe fn from_ptr(ptr: *const BootInfo) -> Result<Self, Error> {
+        if ptr.align_offset(ALIGN) != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        let magic = unsafe { ptr::addr_of!((*ptr).magic).read() };
+
+        if magic != MAGIC {
+            return Err(Error::BadMagic);
+        }
+
+        let total_size = unsafe { ptr::addr_of!((*ptr).total_size).read() } as usize;
+        let bytes = unsafe { slice::from_raw_parts(ptr.cast::<u8>(), total_size) };
+
+        Self::new(bytes)
+    }
+
+    /// Returns the whole blob.
+    #[inline]
+    #[must_use]
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the header.
+    #[inline]
+    #[must_use]
+    pub fn info(&self) -> &'a BootInfo {
+        // SAFETY: `new` checked the size and alignment, and every bit pattern is a
+        // valid header.
+        unsafe { &*self.bytes.as_ptr().cast::<BootInfo>() }
+    }
+
+    /// Returns the memory map.
+    #[inline]
+    #[must_use]
+    pub fn memory_map(&self) -> &'a [MemoryRegion] {
+        // `new` checked the span and the kinds.
+        self.items(self.info().memory_map).unwrap_or(&[])
+    }
+
+    /// Returns the kernel command line.
+    #[inline]
+    #[must_use]
+    pub fn cmdline(&self) -> &'a str {
+        self.str(self.info().cmdline).unwrap_or("")
+    }
+
+    /// Returns the loaded modules.
+    #[inline]
+    #[must_use]
+    pub fn modules(&self) -> &'a [Module] {
+        self.items(self.info().modules).unwrap_or(&[])
+    }
+
+    /// Returns the name of `module`, which should be one of [`Reader::modules`].
+    #[inline]
+    #[must_use]
+    pub fn module_name(&self, module: &Module) -> &'a str {
+        self.str(module.name).unwrap_or("")
+    }
+
+    /// Returns the name of the loader.
+    #[inline]
+    #[must_use]
+    pub fn loader_name(&self) -> &'a str {
+        self.str(self.info().loader_name).unwrap_or("")
+    }
+
+    /// Returns the version of the loader.
+    #[inline]
+    #[must_use]
+    pub fn loader_version(&self) -> &'a str {
+        self.str(self.info().loader_version).unwrap_or("")
+    }
+
+    /// Returns the framebuffer, if the loader set one up.
+    #[inline]
+    #[must_use]
+    pub fn framebuffer(&self) -> Option<Framebuffer> {
+        let framebuffer = self.info().framebuffer;
+
+        (framebuffer.address != 0).then_some(framebuffer)
+    }
+
+    /// Returns the physical address of the ACPI RSDP, if the loader found one.
+    #[inline]
+    #[must_use]
+    pub fn rsdp(&self) -> Option<u64> {
+        Some(self.info().rsdp).filter(|&address| address != 0)
+    }
+
+    /// Returns the physical address of the SMBIOS entry point, if the loader found one.
+    #[inline]
+    #[must_use]
+    pub fn smbios(&self) -> Option<u64> {
+        Some(self.info().smbios).filter(|&address| address != 0)
+    }
+
+    /// Returns the BIOS drive number we booted from.
+    #[inline]
+    #[must_use]
+    pub fn boot_drive(&self) -> u8 {
+        self.info().boot_drive
+    }
+
+    /// Returns the index of the partition we booted from, if any.
+    #[inline]
+    #[must_use]
+    pub fn boot_partition(&self) -> Option<u32> {
+        Some(self.info().boot_partition).filter(|&partition| partition != NO_PARTITION)
+    }
+
+    /// Returns the bytes `span` points to.
+    fn slice(&self, span: Span, size: usize) -> Result<&'a [u8], Error> {
+        span.bytes(size)
+            .and_then(|range| self.bytes.get(range))
+            .ok_or(Error::BadSpan)
+    }
+
+    /// Returns the string `span` points to.
+    fn str(&self, span: Span) -> Result<&'a str, Error> {
+        str::from_utf8(self.slice(span, 1)?).map_err(|_| Error::BadString)
+    }
+
+    /// Returns the items `span` points to.
+    ///
+    /// Only use this for types that are valid for any bit pattern, or which have
+    /// been checked separately.
+    fn items<T>(&self, span: Span) -> Result<&'a [T], Error> {
+        let bytes = self.slice(span, size_of::<T>())?;
+
+        if bytes.as_ptr().align_offset(align_of::<T>()) != 0 {
+            return Err(Error::BadSpan);
+        }
+
+        // SAFETY: The span is within the blob and aligned.
+        Ok(unsafe { slice::from_raw_parts(bytes.as_ptr().cast::<T>(), span.len as usize) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{framebuffer::ColorMask, memory_map::MemoryKind};
+    use std::{vec, vec::Vec};
+
+    /// Returns a zeroed buffer of `len` bytes aligned to [`ALIGN`].
+    fn buffer(len: usize) -> Vec<u64> {
+        vec![0; len.div_ceil(8)]
+    }
+
+    fn bytes(words: &mut [u64]) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(words.as_mut_ptr().cast::<u8>(), words.len() * 8) }
+    }
+
+    const REGIONS: [MemoryRegion; 3] = [
+        MemoryRegion::new(0, 0x9_fc00, MemoryKind::Usable),
+        MemoryRegion::new(0x10_0000, 0x20_0000, MemoryKind::Loader),
+        MemoryRegion::new(0x20_0000, 0x800_0000, MemoryKind::Usable),
+    ];
+
+    const FRAMEBUFFER: Framebuffer = Framebuffer {
+        address: 0xfd00_0000,
+        size: 1024 * 768 * 4,
+        width: 1024,
+        height: 768,
+        pitch: 4096,
+        bpp: 32,
+        red: ColorMask::new(16, 8),
+        green: ColorMask::new(8, 8),
+        blue: ColorMask::new(0, 8),
+        reserved: [0; 5],
+    };
+
+    fn build(buffer: &mut [u8]) -> usize {
+        let mut builder = Builder::new(buffer).unwrap();
+        builder.memory_map(&REGIONS).unwrap();
+        builder.cmdline("console=ttyS0 quiet").unwrap();
+        builder
+            .modules(&[
+                (0x30_0000..0x30_1234, "initrd"),
+                (0x40_0000..0x40_0010, "x"),
+            ])
+            .unwrap();
+        builder.framebuffer(FRAMEBUFFER);
+        builder.rsdp(0xf_6a20);
+        builder.boot_device(0x80, Some(1));
+        builder.loader("mrow", "0.1.0").unwrap();
+
+        builder.finish().unwrap().len()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut words = buffer(1024);
+        let buffer = bytes(&mut words);
+        let len = build(buffer);
+
+        assert_eq!(len % ALIGN, 0);
+
+        let reader = Reader::new(buffer).unwrap();
+
+        assert_eq!(reader.as_bytes().len(), len);
+        assert_eq!(reader.memory_map(), &REGIONS);
+        assert_eq!(reader.cmdline(), "console=ttyS0 quiet");
+        assert_eq!(reader.loader_name(), "mrow");
+        assert_eq!(reader.loader_version(), "0.1.0");
+        assert_eq!(reader.framebuffer(), Some(FRAMEBUFFER));
+        assert_eq!(reader.rsdp(), Some(0xf_6a20));
+        assert_eq!(reader.smbios(), None);
+        assert_eq!(reader.boot_drive(), 0x80);
+        assert_eq!(reader.boot_partition(), Some(1));
+
+        let modules = reader.modules();
+
+        assert_eq!(modules.len(), 2);
+        assert_eq!(modules[0].memory(), 0x30_0000..0x30_1234);
+        assert_eq!(reader.module_name(&modules[0]), "initrd");
+        assert_eq!(modules[1].memory(), 0x40_0000..0x40_0010);
+        assert_eq!(reader.module_name(&modules[1]), "x");
+    }
+
+    #[test]
+    fn empty() {
+        let mut words = buffer(BootInfo::SIZE as usize);
+        let buffer = bytes(&mut words);
+        let len = Builder::new(buffer).unwrap().finish().unwrap().len();
+
+        assert_eq!(len, BootInfo::SIZE as usize);
+
+        let reader = Reader::new(buffer).unwrap();
+
+        assert!(reader.memory_map().is_empty());
+        assert!(reader.modules().is_empty());
+        assert_eq!(reader.cmdline(), "");
+        assert_eq!(reader.framebuffer(), None);
+        assert_eq!(reader.rsdp(), None);
+        assert_eq!(reader.boot_partition(), None);
+    }
+
+    #[test]
+    fn position_independent() {
+        let mut words = buffer(1024);
+        let len = build(bytes(&mut words));
+
+        // Move the blob somewhere else, it should read the same from there.
+        let mut moved = buffer(2048);
+        bytes(&mut moved)[512..512 + len].copy_from_slice(&bytes(&mut words)[..len]);
+        words.fill(0);
+
+        let moved = bytes(&mut moved);
+        let reader = unsafe { Reader::from_ptr(moved[512..].as_ptr().cast::<BootInfo>()) }.unwrap();
+
+        assert_eq!(reader.memory_map(), &REGIONS);
+        assert_eq!(reader.cmdline(), "console=ttyS0 quiet");
+        assert_eq!(reader.module_name(&reader.modules()[0]), "initrd");
+    }
+
+    #[test]
+    fn full() {
+        let mut words = buffer(256);
+        let buffer = bytes(&mut words);
+
+        assert_eq!(Builder::new(&mut buffer[..64]).unwrap_err(), BootInfoFull);
+
+        let mut builder = Builder::new(buffer).unwrap();
+
+        assert_eq!(builder.memory_map(&[REGIONS[0]; 8]), Err(BootInfoFull));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut words = buffer(1024);
+        let len = build(bytes(&mut words));
+        let buffer = bytes(&mut words);
+
+        assert_eq!(Reader::new(&buffer[1..]).unwrap_err(), Error::Misaligned);
+        assert_eq!(
+            Reader::new(&buffer[..len - 8]).unwrap_err(),
+            Error::Truncated
+        );
+
+        let corrupt = |offset: usize, patch: &[u8], error: Error| {
+            let mut copy = words.clone();
+            let buffer = bytes(&mut copy);
+            buffer[offset..offset + patch.len()].copy_from_slice(patch);
+
+            assert_eq!(Reader::new(buffer).unwrap_err(), error);
+        };
+
+        corrupt(0, b"meowboot", Error::BadMagic);
+        corrupt(8, &2_u16.to_le_bytes(), Error::Incompatible);
+        // The span of the command line.
+        corrupt(28, &u32::MAX.to_le_bytes(), Error::BadSpan);
+
+        let cmdline = BootInfo::SIZE as usize + REGIONS.len() * size_of::<MemoryRegion>();
+        corrupt(cmdline, &[0xff], Error::BadString);
+
+        let kind = BootInfo::SIZE as usize + 16;
+        corrupt(kind, &7_u32.to_le_bytes(), Error::BadMemoryKind(7));
+    }
+}
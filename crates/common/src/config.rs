@@ -0,0 +1,740 @@
+//! The loader configuration file.
+//!
+//! The format is a small subset of INI. Global settings come first, followed by
+//! any amount of named boot entries:
+//!
+//! ```text
+//! # Comments take up whole lines.
+//! timeout = 5
+//! default = mrow
+//! video = auto
+//!
+//! [mrow]
+//! kernel = /boot/kernel
+//! cmdline = console=ttyS0 loglevel=debug
+//! initrd = /boot/initrd
+//! video = 1024x768x32
+//! ```
+//!
+//! Values run up to the end of the line, with surrounding whitespace removed.
+//! Entries need a `kernel`, everything else is optional. A `timeout` of zero
+//! boots the default entry right away, without showing the boot menu.
+
+use core::fmt;
+
+use crate::vbe::ModeRequest;
+
+/// Where stage 2 looks for the configuration on the boot partition.
+pub const PATH: &str = "/boot/mrow.cfg";
+
+/// What stage 2 boots when there is no configuration file, without showing the menu.
+pub const DEFAULT: &str = "timeout = 0\n\n[mrow]\nkernel = /boot/kernel\n";
+
+/// How many seconds the boot menu waits when the configuration doesn't say.
+pub const DEFAULT_TIMEOUT: u32 = 5;
+
+/// Which video mode to set before booting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Video {
+    /// The preferred mode of the display, or [`ModeRequest::DEFAULT`] if it doesn't tell.
+    Auto,
+    /// Stay in text mode.
+    Text,
+    /// The mode closest to this one.
+    Mode(ModeRequest),
+}
+
+impl Video {
+    /// Parses `auto`, `text`, `WIDTHxHEIGHT` or `WIDTHxHEIGHTxBPP`.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => return Some(Video::Auto),
+            "text" => return Some(Video::Text),
+            _ => {}
+        }
+
+        let mut parts = s.split('x');
+        let width = parse_number(parts.next()?)?;
+        let height = parse_number(parts.next()?)?;
+        let bpp = match parts.next() {
+            Some(bpp) => parse_number(bpp)?,
+            None => ModeRequest::DEFAULT.bpp,
+        };
+
+        if parts.next().is_some() | (width == 0) | (height == 0) | (bpp == 0) {
+            return None;
+        }
+
+        Some(Video::Mode(ModeRequest::new(width, height, bpp)))
+    }
+}
+
+/// Parses a plain decimal number, without the signs and underscores [`str::parse`] allows.
+fn parse_number<T: core::str::FromStr>(s: &str) -> Option<T> {
+    let digits = !s.is_empty() && s.bytes().all(|byte| byte.is_ascii_digit());
+
+    digits.then(|| s.parse().ok()).flatten()
+}
+
+/// What went wrong in a configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorKind {
+    /// A line is neither a section nor a `key = value` pair.
+    MissingEquals,
+    /// There's nothing in front of the `=`.
+    MissingKey,
+    /// A section header lacks its closing `]`, or has something after it.
+    UnclosedSection,
+    /// A section header has no name.
+    EmptySectionName,
+    /// The key isn't allowed here.
+    UnknownKey,
+    /// The key was already set in this section.
+    DuplicateKey,
+    /// An entry with this name already exists.
+    DuplicateEntry,
+    /// An entry has no kernel.
+    MissingKernel,
+    /// The value must not be empty.
+    EmptyValue,
+    /// The timeout isn't a number of seconds.
+    InvalidTimeout,
+    /// The video mode isn't `auto`, `text`, `WIDTHxHEIGHT` or `WIDTHxHEIGHTxBPP`.
+    InvalidVideo,
+    /// The default entry doesn't exist.
+    UnknownDefault,
+    /// There are no entries at all.
+    NoEntries,
+}
+
+impl ErrorKind {
+    /// Returns a human readable description of this error.
+    #[inline]
+    #[must_use]
+    pub const fn message(self) -> &'static str {
+        match self {
+            ErrorKind::MissingEquals => "expected `key = value` or `[entry]`",
+            ErrorKind::MissingKey => "missing key before `=`",
+            ErrorKind::UnclosedSection => "expected `]` at the end of the line",
+            ErrorKind::EmptySectionName => "entry name must not be empty",
+            ErrorKind::UnknownKey => "unknown key",
+            ErrorKind::DuplicateKey => "key is already set",
+            ErrorKind::DuplicateEntry => "entry already exists",
+            ErrorKind::MissingKernel => "entry has no kernel",
+            ErrorKind::EmptyValue => "value must not be empty",
+            ErrorKind::InvalidTimeout => "timeout must be a number of seconds",
+            ErrorKind::InvalidVideo => {
+                "video must be `auto`, `text`, `WIDTHxHEIGHT` or `WIDTHxHEIGHTxBPP`"
+            }
+            ErrorKind::UnknownDefault => "default entry doesn't exist",
+            ErrorKind::NoEntries => "no entries",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// An error in a configuration file, with where it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Error {
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The column in characters, starting at 1.
+    pub column: usize,
+    /// What went wrong.
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// A boot entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Entry<'a> {
+    /// The name in the section header.
+    pub name: &'a str,
+    /// The path of the kernel on the boot partition.
+    pub kernel: &'a str,
+    /// The command line handed to the kernel, empty if there is none.
+    pub cmdline: &'a str,
+    /// The path of the initial ramdisk on the boot partition.
+    pub initrd: Option<&'a str>,
+    /// The video mode, falling back to the global one.
+    pub video: Video,
+}
+
+/// A validated configuration file.
+///
+/// This borrows the text rather than copying it, entries are parsed again
+/// whenever they're asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Config<'a> {
+    text: &'a str,
+    timeout: u32,
+    default: Option<&'a str>,
+    video: Video,
+}
+
+impl<'a> Config<'a> {
+    /// Parses and validates `text`.
+    pub fn parse(text: &'a str) -> Result<Self, Error> {
+        let mut config = Self {
+            text,
+            timeout: DEFAULT_TIMEOUT,
+            default: None,
+            video: Video::Auto,
+        };
+
+        // Where the default was set, to report it if the entry doesn't exist.
+        let mut default_position = (0, 0);
+        // The header of the entry we're in.
+        let mut entry: Option<Section<'a>> = None;
+        let mut has_kernel = false;
+        // Bits of the keys set in the current section.
+        let mut seen = 0_u8;
+
+        for (line_number, offset, line) in lines(text) {
+            let error = |column, kind| Error {
+                line: line_number,
+                column,
+                kind,
+            };
+
+            match line.map_err(|(column, kind)| error(column, kind))? {
+                Line::Blank => {}
+                Line::Section(section) => {
+                    if let Some(entry) = entry.filter(|_| !has_kernel) {
+                        return Err(entry.error(ErrorKind::MissingKernel));
+                    }
+
+                    // Only look at what comes before, which doesn't include this entry.
+                    let duplicate = Entries::new(&text[..offset], config.video)
+                        .any(|entry| entry.name == section.name);
+
+                    if duplicate {
+                        return Err(section.error(ErrorKind::DuplicateEntry));
+                    }
+
+                    entry = Some(section);
+                    has_kernel = false;
+                    seen = 0;
+                }
+                Line::Pair(pair) => {
+                    let key = match entry {
+                        Some(_) => ENTRY_KEYS.iter().position(|&key| key == pair.key),
+                        None => GLOBAL_KEYS.iter().position(|&key| key == pair.key),
+                    };
+
+                    let Some(key) = key else {
+                        return Err(error(pair.key_column, ErrorKind::UnknownKey));
+                    };
+
+                    if seen & (1 << key) != 0 {
+                        return Err(error(pair.key_column, ErrorKind::DuplicateKey));
+                    }
+
+                    seen |= 1 << key;
+
+                    let invalid = |kind| Err(error(pair.value_column, kind));
+
+                    match (entry.is_some(), pair.key) {
+                        (false, "timeout") => match parse_number(pair.value) {
+                            Some(timeout) => config.timeout = timeout,
+                            None => return invalid(ErrorKind::InvalidTimeout),
+                        },
+                        (false, "default") if pair.value.is_empty() => {
+                            return invalid(ErrorKind::EmptyValue);
+                        }
+                        (false, "default") => {
+                            config.default = Some(pair.value);
+                            default_position = (line_number, pair.value_column);
+                        }
+                        (_, "video") => match Video::parse(pair.value) {
+                            Some(video) if entry.is_none() => config.video = video,
+                            Some(_) => {}
+                            None => return invalid(ErrorKind::InvalidVideo),
+                        },
+                        (true, "kernel" | "initrd") if pair.value.is_empty() => {
+                            return invalid(ErrorKind::EmptyValue);
+                        }
+                        (true, "kernel") => has_kernel = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        match entry {
+            Some(entry) if !has_kernel => return Err(entry.error(ErrorKind::MissingKernel)),
+            Some(_) => {}
+            None => {
+                return Err(Error {
+                    line: 1,
+                    column: 1,
+                    kind: ErrorKind::NoEntries,
+                })
+            }
+        }
+
+        if let Some(default) = config.default {
+            if config.entry(default).is_none() {
+                return Err(Error {
+                    line: default_position.0,
+                    column: default_position.1,
+                    kind: ErrorKind::UnknownDefault,
+                });
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Returns how many seconds the boot menu waits before booting the default entry.
+    #[inline]
+    #[must_use]
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Returns the global video mode, which entries fall back to.
+    #[inline]
+    #[must_use]
+    pub const fn video(&self) -> Video {
+        self.video
+    }
+
+    /// Returns the entries in the order they appear in.
+    #[inline]
+    pub fn entries(&self) -> Entries<'a> {
+        Entries::new(self.text, self.video)
+    }
+
+    /// Returns the entry called `name`.
+    #[must_use]
+    pub fn entry(&self, name: &str) -> Option<Entry<'a>> {
+        self.entries().find(|entry| entry.name == name)
+    }
+
+    /// Returns the entry to boot if nobody picks one, which is the first one
+    /// unless the configuration says otherwise.
+    #[must_use]
+    pub fn default_entry(&self) -> Entry<'a> {
+        let default = self.default.and_then(|name| self.entry(name));
+
+        // `parse` made sure there is at least one entry.
+        default.or_else(|| self.entries().next()).unwrap()
+    }
+}
+
+/// Keys allowed before the first entry, at most 8 to fit the bits of `seen`.
+const GLOBAL_KEYS: [&str; 3] = ["timeout", "default", "video"];
+
+/// Keys allowed in entries.
+const ENTRY_KEYS: [&str; 4] = ["kernel", "cmdline", "initrd", "video"];
+
+/// An iterator over the entries of a [`Config`].
+#[derive(Debug, Clone)]
+pub struct Entries<'a> {
+    lines: core::str::Split<'a, char>,
+    video: Video,
+    /// The name of the entry whose header we already consumed.
+    next: Option<&'a str>,
+}
+
+impl<'a> Entries<'a> {
+    /// Creates an iterator over the entries in `text`, which fall back to `video`.
+    fn new(text: &'a str, video: Video) -> Self {
+        Self {
+            lines: text.split('\n'),
+            video,
+            next: None,
+        }
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut entry: Option<Entry<'a>> = self.next.take().map(|name| Entry {
+            name,
+            kernel: "",
+            cmdline: "",
+            initrd: None,
+            video: self.video,
+        });
+
+        for line in self.lines.by_ref() {
+            // The config was validated, so errors can't happen here.
+            match parse_line(line) {
+                Ok(Line::Section(section)) if entry.is_some() => {
+                    self.next = Some(section.name);
+                    return entry;
+                }
+                Ok(Line::Section(section)) => {
+                    entry = Some(Entry {
+                        name: section.name,
+                        kernel: "",
+                        cmdline: "",
+                        initrd: None,
+                        video: self.video,
+                    });
+                }
+                Ok(Line::Pair(pair)) => {
+                    let Some(entry) = &mut entry else {
+                        continue;
+                    };
+
+                    match pair.key {
+                        "kernel" => entry.kernel = pair.value,
+                        "cmdline" => entry.cmdline = pair.value,
+                        "initrd" => entry.initrd = Some(pair.value),
+                        "video" => entry.video = Video::parse(pair.value).unwrap_or(self.video),
+                        _ => {}
+                    }
+                }
+                Ok(Line::Blank) | Err(_) => {}
+            }
+        }
+
+        entry
+    }
+}
+
+/// A section header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Section<'a> {
+    name: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl Section<'_> {
+    /// Returns an error pointing at the name of this section.
+    const fn error(&self, kind: ErrorKind) -> Error {
+        Error {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+}
+
+/// A `key = value` pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pair<'a> {
+    key: &'a str,
+    key_column: usize,
+    value: &'a str,
+    value_column: usize,
+}
+
+/// A single line of a configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    /// An empty line or a comment.
+    Blank,
+    Section(Section<'a>),
+    Pair(Pair<'a>),
+}
+
+/// Returns the lines of `text` along with their numbers, starting at 1, and
+/// where they start in `text`.
+///
+/// Errors are the column and what went wrong.
+fn lines(text: &str) -> impl Iterator<Item = (usize, usize, Result<Line<'_>, (usize, ErrorKind)>)> {
+    text.split('\n').zip(1..).map(move |(line, number)| {
+        let offset = line.as_ptr() as usize - text.as_ptr() as usize;
+        let line = parse_line(line).map(|line| match line {
+            Line::Section(section) => Line::Section(Section {
+                line: number,
+                ..section
+            }),
+            line => line,
+        });
+
+        (number, offset, line)
+    })
+}
+
+/// Parses a single line, without a trailing `\n`.
+fn parse_line(line: &str) -> Result<Line<'_>, (usize, ErrorKind)> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let trimmed = line.trim();
+
+    // Columns count characters, starting at 1.
+    let column = |s: &str| {
+        line[..s.as_ptr() as usize - line.as_ptr() as usize]
+            .chars()
+            .count()
+            + 1
+    };
+
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(Line::Blank);
+    }
+
+    if let Some(rest) = trimmed.strip_prefix('[') {
+        let Some((name, after)) = rest.split_once(']') else {
+            return Err((
+                column(trimmed) + trimmed.chars().count(),
+                ErrorKind::UnclosedSection,
+            ));
+        };
+
+        // `trimmed` has no trailing whitespace, so anything here is garbage.
+        if !after.is_empty() {
+            return Err((column(after.trim_start()), ErrorKind::UnclosedSection));
+        }
+
+        let name = name.trim();
+
+        if name.is_empty() {
+            return Err((column(rest), ErrorKind::EmptySectionName));
+        }
+
+        return Ok(Line::Section(Section {
+            name,
+            line: 0,
+            column: column(name),
+        }));
+    }
+
+    let Some((key, value)) = trimmed.split_once('=') else {
+        return Err((column(trimmed), ErrorKind::MissingEquals));
+    };
+
+    let key = key.trim_end();
+
+    if key.is_empty() {
+        return Err((column(trimmed), ErrorKind::MissingKey));
+    }
+
+    // An empty value still needs a position, so use the one right after the `=`.
+    let value_column = match value.trim_start() {
+        "" => column(value),
+        value => column(value),
+    };
+
+    Ok(Line::Pair(Pair {
+        key,
+        key_column: column(key),
+        value: value.trim(),
+        value_column,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn error(text: &str) -> (usize, usize, ErrorKind) {
+        let err = Config::parse(text).unwrap_err();
+
+        (err.line, err.column, err.kind)
+    }
+
+    #[test]
+    fn parses_default() {
+        let config = Config::parse(DEFAULT).unwrap();
+        let entry = config.default_entry();
+
+        assert_eq!(config.timeout(), 0);
+        assert_eq!(entry.name, "mrow");
+        assert_eq!(entry.kernel, "/boot/kernel");
+        assert_eq!(entry.cmdline, "");
+        assert_eq!(entry.initrd, None);
+        assert_eq!(entry.video, Video::Auto);
+    }
+
+    #[test]
+    fn parses_entries() {
+        let text = "\
+# The usual setup.
+timeout = 10
+default = rescue mode
+video = 800x600
+
+[mrow]
+kernel = /boot/kernel
+cmdline =  console=ttyS0 loglevel=debug  \r
+initrd = /boot/initrd
+
+  [ rescue mode ]
+kernel=/boot/rescue
+video = text
+";
+        let config = Config::parse(text).unwrap();
+        let entries: Vec<_> = config.entries().collect();
+
+        assert_eq!(config.timeout(), 10);
+        assert_eq!(config.video(), Video::Mode(ModeRequest::new(800, 600, 32)));
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    name: "mrow",
+                    kernel: "/boot/kernel",
+                    cmdline: "console=ttyS0 loglevel=debug",
+                    initrd: Some("/boot/initrd"),
+                    video: Video::Mode(ModeRequest::new(800, 600, 32)),
+                },
+                Entry {
+                    name: "rescue mode",
+                    kernel: "/boot/rescue",
+                    cmdline: "",
+                    initrd: None,
+                    video: Video::Text,
+                },
+            ]
+        );
+        assert_eq!(config.default_entry().name, "rescue mode");
+        assert_eq!(config.entry("mrow").unwrap().kernel, "/boot/kernel");
+        assert_eq!(config.entry("missing"), None);
+    }
+
+    #[test]
+    fn defaults_to_first_entry() {
+        let config = Config::parse("[one]\nkernel = a\n[two]\nkernel = b").unwrap();
+
+        assert_eq!(config.default_entry().name, "one");
+        assert_eq!(config.entries().count(), 2);
+    }
+
+    #[test]
+    fn parses_video_modes() {
+        assert_eq!(Video::parse("auto"), Some(Video::Auto));
+        assert_eq!(Video::parse("text"), Some(Video::Text));
+        assert_eq!(
+            Video::parse("1920x1080"),
+            Some(Video::Mode(ModeRequest::new(1920, 1080, 32)))
+        );
+        assert_eq!(
+            Video::parse("640x480x16"),
+            Some(Video::Mode(ModeRequest::new(640, 480, 16)))
+        );
+
+        for invalid in [
+            "",
+            "x",
+            "640",
+            "640x",
+            "0x480",
+            "640x480x0",
+            "640x480x16x1",
+            "+640x480",
+            "70000x1",
+            "Auto",
+        ] {
+            assert_eq!(Video::parse(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert_eq!(
+            error("[a]\nkernel = k\n  oops"),
+            (3, 3, ErrorKind::MissingEquals)
+        );
+        assert_eq!(error("[a]\n = k"), (2, 2, ErrorKind::MissingKey));
+        assert_eq!(
+            error("[a]\nkernel = k\n[b"),
+            (3, 3, ErrorKind::UnclosedSection)
+        );
+        assert_eq!(
+            error("[a]\nkernel = k\n[b] c"),
+            (3, 5, ErrorKind::UnclosedSection)
+        );
+        assert_eq!(error("[ ]"), (1, 2, ErrorKind::EmptySectionName));
+    }
+
+    #[test]
+    fn reports_semantic_errors() {
+        assert_eq!(
+            error("kernel = k\n[a]\nkernel = k"),
+            (1, 1, ErrorKind::UnknownKey)
+        );
+        assert_eq!(
+            error("[a]\nkernel = k\ntimeout = 1"),
+            (3, 1, ErrorKind::UnknownKey)
+        );
+        assert_eq!(
+            error("[a]\nkernel = k\n  kernel = j"),
+            (3, 3, ErrorKind::DuplicateKey)
+        );
+        assert_eq!(
+            error("[a]\nkernel = k\n[ a ]\nkernel = k"),
+            (3, 3, ErrorKind::DuplicateEntry)
+        );
+        assert_eq!(
+            error("[a]\ncmdline = x\n[b]\nkernel = k"),
+            (1, 2, ErrorKind::MissingKernel)
+        );
+        assert_eq!(
+            error("[a]\nkernel = k\n[b]"),
+            (3, 2, ErrorKind::MissingKernel)
+        );
+        assert_eq!(error("[a]\nkernel =\n"), (2, 9, ErrorKind::EmptyValue));
+        assert_eq!(
+            error("timeout = soon\n[a]\nkernel = k"),
+            (1, 11, ErrorKind::InvalidTimeout)
+        );
+        assert_eq!(
+            error("timeout = -1\n[a]\nkernel = k"),
+            (1, 11, ErrorKind::InvalidTimeout)
+        );
+        assert_eq!(
+            error("[a]\nkernel = k\nvideo = big"),
+            (3, 9, ErrorKind::InvalidVideo)
+        );
+        assert_eq!(
+            error("default = b\n[a]\nkernel = k"),
+            (1, 11, ErrorKind::UnknownDefault)
+        );
+        assert_eq!(error("# nothing\n"), (1, 1, ErrorKind::NoEntries));
+    }
+
+    #[test]
+    fn counts_columns_in_characters() {
+        assert_eq!(
+            error("[ä]\nkernel = k\n[ö]\nkernël = k"),
+            (4, 1, ErrorKind::UnknownKey)
+        );
+        assert_eq!(
+            error("[ä]\nkernel = k\nvideo = ö"),
+            (3, 9, ErrorKind::InvalidVideo)
+        );
+        assert_eq!(
+            error("[äö] x\nkernel = k"),
+            (1, 6, ErrorKind::UnclosedSection)
+        );
+    }
+
+    #[test]
+    fn formats_errors() {
+        let err = Config::parse("[a]\nkernel = k\nvideo = big").unwrap_err();
+
+        assert_eq!(
+            std::format!("{err}"),
+            "line 3, column 9: video must be `auto`, `text`, `WIDTHxHEIGHT` or `WIDTHxHEIGHTxBPP`"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(target_arch = "x86")]
+use core::arch::x86::__cpuid;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::__cpuid;
+
+/// The CPUID leaf with the extended feature bits.
+const EXTENDED_FEATURES: u32 = 0x8000_0001;
+
+/// Returns whether the CPU supports long mode.
+///
+/// This assumes CPUID is available, which holds for anything from the i686 onwards.
+#[inline]
+#[must_use]
+pub fn has_long_mode() -> bool {
+    const LONG_MODE: u32 = 1 << 29;
+
+    extended_features() & LONG_MODE != 0
+}
+
+/// Returns whether pages can be marked as not executable.
+#[inline]
+#[must_use]
+pub fn has_no_execute() -> bool {
+    const NO_EXECUTE: u32 = 1 << 20;
+
+    extended_features() & NO_EXECUTE != 0
+}
+
+/// Returns whether the PDPT can map 1 GiB pages.
+#[inline]
+#[must_use]
+pub fn has_huge_pages() -> bool {
+    const HUGE_PAGES: u32 = 1 << 26;
+
+    extended_features() & HUGE_PAGES != 0
+}
+
+/// Returns the extended feature bits in `edx`, or nothing if there are none.
+fn extended_features() -> u32 {
+    // The highest extended leaf comes first, anything before that can't have
+    // the feature bits we need.
+    if __cpuid(0x8000_0000).eax < EXTENDED_FEATURES {
+        return 0;
+    }
+
+    __cpuid(EXTENDED_FEATURES).edx
+}
@@ -0,0 +1,1178 @@
+use core::{fmt, ops::Range};
+
+/// The magic number every ELF file starts with.
+pub const MAGIC: [u8; 4] = *b"\x7fELF";
+
+/// Machine number of 32-bit x86.
+pub const MACHINE_X86: u16 = 3;
+
+/// Machine number of x86_64.
+pub const MACHINE_X86_64: u16 = 62;
+
+/// Values of [`ProgramHeader::kind`].
+pub mod program_kind {
+    /// An unused entry.
+    pub const NULL: u32 = 0;
+    /// A segment to load into memory.
+    pub const LOAD: u32 = 1;
+    /// Dynamic linking information.
+    pub const DYNAMIC: u32 = 2;
+    /// The path of an interpreter.
+    pub const INTERP: u32 = 3;
+    /// Auxiliary information.
+    pub const NOTE: u32 = 4;
+    /// The program header table itself.
+    pub const PHDR: u32 = 6;
+    /// The thread local storage template.
+    pub const TLS: u32 = 7;
+    /// Tells whether the stack should be executable.
+    pub const GNU_STACK: u32 = 0x6474_e551;
+    /// Memory to make read-only after relocation.
+    pub const GNU_RELRO: u32 = 0x6474_e552;
+}
+
+/// Bits of [`ProgramHeader::flags`].
+pub mod segment_flags {
+    /// The segment is executable.
+    pub const EXECUTE: u32 = 0x1;
+    /// The segment is writable.
+    pub const WRITE: u32 = 0x2;
+    /// The segment is readable.
+    pub const READ: u32 = 0x4;
+}
+
+/// Tags of dynamic section entries we care about.
+mod dynamic_tag {
+    pub const NULL: u64 = 0;
+    pub const RELA: u64 = 7;
+    pub const RELA_SIZE: u64 = 8;
+    pub const RELA_ENTRY: u64 = 9;
+    pub const REL: u64 = 17;
+    pub const REL_SIZE: u64 = 18;
+    pub const REL_ENTRY: u64 = 19;
+}
+
+/// Relocation kinds we know how to apply.
+///
+/// Both x86 and x86_64 happen to use the same numbers for these.
+mod relocation_kind {
+    pub const NONE: u32 = 0;
+    pub const RELATIVE: u32 = 8;
+}
+
+/// Whether a file uses 32 or 64-bit addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Class {
+    /// ELF32, for x86.
+    Elf32,
+    /// ELF64, for x86_64.
+    Elf64,
+}
+
+impl Class {
+    /// Returns the size of an address in bytes.
+    #[inline]
+    #[must_use]
+    pub const fn word_size(self) -> usize {
+        match self {
+            Class::Elf32 => 4,
+            Class::Elf64 => 8,
+        }
+    }
+
+    /// Returns the size of the file header.
+    #[inline]
+    #[must_use]
+    const fn header_size(self) -> usize {
+        match self {
+            Class::Elf32 => 52,
+            Class::Elf64 => 64,
+        }
+    }
+
+    /// Returns the size of a program header.
+    #[inline]
+    #[must_use]
+    const fn program_header_size(self) -> usize {
+        match self {
+            Class::Elf32 => 32,
+            Class::Elf64 => 56,
+        }
+    }
+
+    /// Returns the machine number files of this class must have.
+    #[inline]
+    #[must_use]
+    const fn machine(self) -> u16 {
+        match self {
+            Class::Elf32 => MACHINE_X86,
+            Class::Elf64 => MACHINE_X86_64,
+        }
+    }
+}
+
+/// Errors that can occur while validating an ELF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error {
+    /// The file ends before a structure it describes.
+    Truncated,
+    /// The file doesn't start with [`MAGIC`].
+    BadMagic,
+    /// The file is neither ELF32 nor ELF64.
+    UnsupportedClass,
+    /// The file isn't little endian.
+    UnsupportedEndianness,
+    /// The file uses an ELF version other than 1.
+    UnsupportedVersion,
+    /// The file is neither an executable nor a position independent executable.
+    UnsupportedType,
+    /// The file isn't for x86 or x86_64, matching its class.
+    UnsupportedMachine,
+    /// A program header describes a segment that doesn't make sense.
+    BadProgramHeader,
+    /// The dynamic segment or the relocations it points to are malformed.
+    BadDynamic,
+    /// A relocation patches memory outside of any loaded segment.
+    BadRelocation,
+    /// A relocation kind we don't know how to apply.
+    UnsupportedRelocation(u32),
+    /// A load bias was given for a file that must be loaded at a fixed address.
+    NotRelocatable,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated => f.write_str("file is truncated"),
+            Error::BadMagic => f.write_str("not an elf file"),
+            Error::UnsupportedClass => f.write_str("unsupported elf class"),
+            Error::UnsupportedEndianness => f.write_str("not little endian"),
+            Error::UnsupportedVersion => f.write_str("unsupported elf version"),
+            Error::UnsupportedType => f.write_str("not an executable"),
+            Error::UnsupportedMachine => f.write_str("not an x86 or x86_64 executable"),
+            Error::BadProgramHeader => f.write_str("bad program header"),
+            Error::BadDynamic => f.write_str("bad dynamic segment"),
+            Error::BadRelocation => f.write_str("relocation outside of loaded segments"),
+            Error::UnsupportedRelocation(kind) => write!(f, "unsupported relocation kind {kind}"),
+            Error::NotRelocatable => f.write_str("executable can't be relocated"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Errors that can occur while loading an ELF file with [`Elf::load`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LoadError<E> {
+    /// The file is invalid or can't be loaded.
+    Elf(Error),
+    /// The mapper failed to provide memory for a segment.
+    Mapper(E),
+}
+
+impl<E> From<Error> for LoadError<E> {
+    #[inline]
+    fn from(err: Error) -> Self {
+        LoadError::Elf(err)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for LoadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Elf(err) => err.fmt(f),
+            LoadError::Mapper(err) => write!(f, "failed to map segment: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> std::error::Error for LoadError<E> {}
+
+/// Provides the memory segments get loaded into.
+pub trait Mapper {
+    /// The error mapping a segment can fail with.
+    type Error;
+
+    /// Backs `segment` at `address` with writable memory and returns it.
+    ///
+    /// `address` already includes the load bias. The returned memory must be
+    /// exactly [`ProgramHeader::mem_size`] bytes long, its contents don't
+    /// matter as the loader overwrites all of it.
+    fn map(&mut self, address: u64, segment: &ProgramHeader) -> Result<&mut [u8], Self::Error>;
+}
+
+impl<T: Mapper + ?Sized> Mapper for &mut T {
+    type Error = T::Error;
+
+    #[inline]
+    fn map(&mut self, address: u64, segment: &ProgramHeader) -> Result<&mut [u8], Self::Error> {
+        (**self).map(address, segment)
+    }
+}
+
+/// A program header, widened to 64 bits for ELF32 files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProgramHeader {
+    /// See [`program_kind`].
+    pub kind: u32,
+    /// See [`segment_flags`].
+    pub flags: u32,
+    /// Where the contents of the segment start in the file.
+    pub offset: u64,
+    /// The virtual address of the segment, before applying a load bias.
+    pub virtual_address: u64,
+    /// The physical address of the segment.
+    pub physical_address: u64,
+    /// How many bytes of the segment are in the file.
+    pub file_size: u64,
+    /// How many bytes the segment takes in memory, the rest is zeroed.
+    pub mem_size: u64,
+    /// The alignment of the segment in memory and in the file.
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    /// Returns the virtual addresses the segment covers, before applying a load bias.
+    #[inline]
+    #[must_use]
+    pub const fn memory_range(&self) -> Range<u64> {
+        self.virtual_address..self.virtual_address + self.mem_size
+    }
+
+    /// Returns the file offsets the contents of the segment cover.
+    #[inline]
+    #[must_use]
+    pub const fn file_range(&self) -> Range<u64> {
+        self.offset..self.offset + self.file_size
+    }
+}
+
+/// A relocation, with its addend if it has an explicit one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Relocation {
+    offset: u64,
+    kind: u32,
+    addend: Option<i64>,
+}
+
+/// A table of relocations in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct RelocationTable {
+    /// Where the table starts in the file.
+    offset: usize,
+    /// The amount of relocations.
+    len: usize,
+    /// The size of a single relocation.
+    entry_size: usize,
+    /// Whether this is a `RELA` rather than a `REL` table.
+    explicit_addend: bool,
+}
+
+/// A validated ELF file of an x86 or x86_64 executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    class: Class,
+    position_independent: bool,
+    entry: u64,
+    program_headers: usize,
+    program_header_count: usize,
+    program_header_size: usize,
+}
+
+impl<'a> Elf<'a> {
+    /// Validates the file header and program headers of `bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.get(..4) != Some(&MAGIC) {
+            return Err(Error::BadMagic);
+        }
+
+        let class = match bytes.get(4) {
+            Some(1) => Class::Elf32,
+            Some(2) => Class::Elf64,
+            _ => return Err(Error::UnsupportedClass),
+        };
+
+        if bytes.get(5) != Some(&1) {
+            return Err(Error::UnsupportedEndianness);
+        }
+
+        if bytes.get(6) != Some(&1) {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        if bytes.len() < class.header_size() {
+            return Err(Error::Truncated);
+        }
+
+        let mut elf = Self {
+            bytes,
+            class,
+            position_independent: false,
+            entry: 0,
+            program_headers: 0,
+            program_header_count: 0,
+            program_header_size: 0,
+        };
+
+        elf.position_independent = match elf.u16(16)? {
+            2 => false,
+            3 => true,
+            _ => return Err(Error::UnsupportedType),
+        };
+
+        if elf.u16(18)? != class.machine() {
+            return Err(Error::UnsupportedMachine);
+        }
+
+        if elf.u32(20)? != 1 {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let (entry, program_headers, program_header_size, program_header_count) = match class {
+            Class::Elf32 => (24, 28, 42, 44),
+            Class::Elf64 => (24, 32, 54, 56),
+        };
+
+        elf.entry = elf.word(entry)?;
+        elf.program_headers =
+            usize::try_from(elf.word(program_headers)?).map_err(|_| Error::Truncated)?;
+        elf.program_header_size = elf.u16(program_header_size)? as usize;
+        elf.program_header_count = elf.u16(program_header_count)? as usize;
+
+        if (elf.program_header_count != 0) & (elf.program_header_size < class.program_header_size())
+        {
+            return Err(Error::BadProgramHeader);
+        }
+
+        let table_end = elf
+            .program_header_count
+            .checked_mul(elf.program_header_size)
+            .and_then(|len| len.checked_add(elf.program_headers));
+
+        if table_end.is_none_or(|end| end > bytes.len()) {
+            return Err(Error::Truncated);
+        }
+
+        for header in elf.program_headers() {
+            let valid = (header.file_size <= header.mem_size)
+                & header.offset.checked_add(header.file_size).is_some()
+                & header
+                    .virtual_address
+                    .checked_add(header.mem_size)
+                    .is_some()
+                & usize::try_from(header.mem_size).is_ok()
+                & ((header.align == 0) | header.align.is_power_of_two());
+
+            if !valid {
+                return Err(Error::BadProgramHeader);
+            }
+
+            if header.offset + header.file_size > bytes.len() as u64 {
+                return Err(Error::Truncated);
+            }
+        }
+
+        Ok(elf)
+    }
+
+    /// Returns whether this is an ELF32 or ELF64 file.
+    #[inline]
+    #[must_use]
+    pub const fn class(&self) -> Class {
+        self.class
+    }
+
+    /// Returns whether this is a position independent executable.
+    ///
+    /// Only those can be loaded with a non-zero bias.
+    #[inline]
+    #[must_use]
+    pub const fn is_position_independent(&self) -> bool {
+        self.position_independent
+    }
+
+    /// Returns the entry point, before applying a load bias.
+    #[inline]
+    #[must_use]
+    pub const fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Returns the program headers.
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let elf = *self;
+
+        (0..self.program_header_count).map(move |index| {
+            let offset = elf.program_headers + index * elf.program_header_size;
+
+            // `parse` made sure the whole table is in bounds.
+            elf.program_header(offset).unwrap()
+        })
+    }
+
+    /// Returns the virtual addresses all loadable segments cover together,
+    /// before applying a load bias.
+    ///
+    /// Returns `None` if there is nothing to load.
+    #[must_use]
+    pub fn load_range(&self) -> Option<Range<u64>> {
+        self.program_headers()
+            .filter(|header| (header.kind == program_kind::LOAD) & (header.mem_size != 0))
+            .map(|header| header.memory_range())
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+    }
+
+    /// Loads every loadable segment through `mapper` and returns the entry point.
+    ///
+    /// `bias` is added to every address, and must be zero unless the file is
+    /// [position independent](Self::is_position_independent). Relocations of
+    /// position independent files are applied, as long as they are all
+    /// relative, which is what a statically linked kernel needs.
+    ///
+    /// Nothing is mapped if validating the relocations fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mapper` returns less memory than asked for.
+    pub fn load<M: Mapper>(&self, mut mapper: M, bias: u64) -> Result<u64, LoadError<M::Error>> {
+        if !self.position_independent && bias != 0 {
+            return Err(Error::NotRelocatable.into());
+        }
+
+        let tables = match self.position_independent {
+            true => self.relocation_tables()?,
+            false => [None; 2],
+        };
+
+        // Check every relocation up front, so we don't leave half a kernel behind.
+        for table in tables.iter().flatten() {
+            for index in 0..table.len {
+                let relocation = self.relocation(table, index)?;
+
+                if relocation.kind == relocation_kind::NONE {
+                    continue;
+                }
+
+                if relocation.kind != relocation_kind::RELATIVE {
+                    return Err(Error::UnsupportedRelocation(relocation.kind).into());
+                }
+
+                let end = relocation
+                    .offset
+                    .checked_add(self.class.word_size() as u64)
+                    .ok_or(Error::BadRelocation)?;
+                let target = relocation.offset..end;
+
+                let inside = self.program_headers().any(|header| {
+                    let range = header.memory_range();
+
+                    (header.kind == program_kind::LOAD)
+                        & (range.start <= target.start)
+                        & (target.end <= range.end)
+                });
+
+                if !inside {
+                    return Err(Error::BadRelocation.into());
+                }
+            }
+        }
+
+        for header in self.program_headers() {
+            if (header.kind != program_kind::LOAD) | (header.mem_size == 0) {
+                continue;
+            }
+
+            let memory = mapper
+                .map(header.virtual_address.wrapping_add(bias), &header)
+                .map_err(LoadError::Mapper)?;
+            let memory = &mut memory[..header.mem_size as usize];
+            let (file, bss) = memory.split_at_mut(header.file_size as usize);
+
+            file.copy_from_slice(
+                &self.bytes[header.offset as usize..][..header.file_size as usize],
+            );
+            bss.fill(0);
+
+            for table in tables.iter().flatten() {
+                self.relocate(table, &header, memory, bias)?;
+            }
+        }
+
+        Ok(self.entry.wrapping_add(bias))
+    }
+
+    /// Applies the relocations of `table` that fall within `header`, loaded at `memory`.
+    fn relocate(
+        &self,
+        table: &RelocationTable,
+        header: &ProgramHeader,
+        memory: &mut [u8],
+        bias: u64,
+    ) -> Result<(), Error> {
+        let word_size = self.class.word_size();
+
+        for index in 0..table.len {
+            let relocation = self.relocation(table, index)?;
+
+            if relocation.kind != relocation_kind::RELATIVE {
+                continue;
+            }
+
+            let Some(offset) = relocation.offset.checked_sub(header.virtual_address) else {
+                continue;
+            };
+
+            let end = offset
+                .checked_add(word_size as u64)
+                .ok_or(Error::BadRelocation)?;
+
+            if end > header.mem_size {
+                continue;
+            }
+
+            let target = &mut memory[offset as usize..][..word_size];
+
+            match self.class {
+                Class::Elf32 => {
+                    let addend = relocation.addend.map_or_else(
+                        || u32::from_le_bytes(target.try_into().unwrap()),
+                        |addend| addend as u32,
+                    );
+
+                    target.copy_from_slice(&(bias as u32).wrapping_add(addend).to_le_bytes());
+                }
+                Class::Elf64 => {
+                    let addend = relocation.addend.map_or_else(
+                        || u64::from_le_bytes(target.try_into().unwrap()),
+                        |addend| addend as u64,
+                    );
+
+                    target.copy_from_slice(&bias.wrapping_add(addend).to_le_bytes());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the `REL` and `RELA` tables the dynamic segment points to.
+    fn relocation_tables(&self) -> Result<[Option<RelocationTable>; 2], Error> {
+        let Some(dynamic) = self
+            .program_headers()
+            .find(|header| header.kind == program_kind::DYNAMIC)
+        else {
+            return Ok([None; 2]);
+        };
+
+        let word_size = self.class.word_size();
+        let entry_size = word_size * 2;
+
+        // Address, size and entry size of the `REL` and `RELA` tables.
+        let mut rel = [None; 3];
+        let mut rela = [None; 3];
+
+        for index in 0..dynamic.file_size as usize / entry_size {
+            let offset = dynamic.offset as usize + index * entry_size;
+            let tag = self.word(offset)?;
+            let value = self.word(offset + word_size)?;
+
+            match tag {
+                dynamic_tag::NULL => break,
+                dynamic_tag::REL => rel[0] = Some(value),
+                dynamic_tag::REL_SIZE => rel[1] = Some(value),
+                dynamic_tag::REL_ENTRY => rel[2] = Some(value),
+                dynamic_tag::RELA => rela[0] = Some(value),
+                dynamic_tag::RELA_SIZE => rela[1] = Some(value),
+                dynamic_tag::RELA_ENTRY => rela[2] = Some(value),
+                _ => {}
+            }
+        }
+
+        Ok([
+            self.relocation_table(rel, false)?,
+            self.relocation_table(rela, true)?,
+        ])
+    }
+
+    /// Turns the address, size and entry size from the dynamic segment into a table.
+    fn relocation_table(
+        &self,
+        [address, size, entry_size]: [Option<u64>; 3],
+        explicit_addend: bool,
+    ) -> Result<Option<RelocationTable>, Error> {
+        let (address, size) = match (address, size) {
+            (Some(address), Some(size)) => (address, size),
+            (None, None | Some(0)) => return Ok(None),
+            _ => return Err(Error::BadDynamic),
+        };
+
+        let min_entry_size = self.class.word_size() * if explicit_addend { 3 } else { 2 };
+        let entry_size = entry_size.unwrap_or(min_entry_size as u64);
+
+        if entry_size < min_entry_size as u64 {
+            return Err(Error::BadDynamic);
+        }
+
+        // The table is only given by its address, so find where that is in the file.
+        let offset = self
+            .program_headers()
+            .filter(|header| header.kind == program_kind::LOAD)
+            .find_map(|header| {
+                let start = address.checked_sub(header.virtual_address)?;
+
+                (start.checked_add(size)? <= header.file_size).then(|| header.offset + start)
+            })
+            .ok_or(Error::BadDynamic)?;
+
+        Ok(Some(RelocationTable {
+            offset: offset as usize,
+            len: (size / entry_size) as usize,
+            entry_size: entry_size as usize,
+            explicit_addend,
+        }))
+    }
+
+    /// Reads the relocation at `index` of `table`.
+    fn relocation(&self, table: &RelocationTable, index: usize) -> Result<Relocation, Error> {
+        let word_size = self.class.word_size();
+        let offset = table.offset + index * table.entry_size;
+        let info = self.word(offset + word_size)?;
+
+        let kind = match self.class {
+            Class::Elf32 => info as u32 & 0xff,
+            Class::Elf64 => info as u32,
+        };
+
+        let addend = match (table.explicit_addend, self.class) {
+            (false, _) => None,
+            (true, Class::Elf32) => Some(self.u32(offset + 8)? as i32 as i64),
+            (true, Class::Elf64) => Some(self.u64(offset + 16)? as i64),
+        };
+
+        Ok(Relocation {
+            offset: self.word(offset)?,
+            kind,
+            addend,
+        })
+    }
+
+    /// Reads the program header at `offset`.
+    fn program_header(&self, offset: usize) -> Result<ProgramHeader, Error> {
+        Ok(match self.class {
+            Class::Elf32 => ProgramHeader {
+                kind: self.u32(offset)?,
+                offset: self.u32(offset + 4)? as u64,
+                virtual_address: self.u32(offset + 8)? as u64,
+                physical_address: self.u32(offset + 12)? as u64,
+                file_size: self.u32(offset + 16)? as u64,
+                mem_size: self.u32(offset + 20)? as u64,
+                flags: self.u32(offset + 24)?,
+                align: self.u32(offset + 28)? as u64,
+            },
+            Class::Elf64 => ProgramHeader {
+                kind: self.u32(offset)?,
+                flags: self.u32(offset + 4)?,
+                offset: self.u64(offset + 8)?,
+                virtual_address: self.u64(offset + 16)?,
+                physical_address: self.u64(offset + 24)?,
+                file_size: self.u64(offset + 32)?,
+                mem_size: self.u64(offset + 40)?,
+                align: self.u64(offset + 48)?,
+            },
+        })
+    }
+
+    /// Reads `N` bytes at `offset`.
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], Error> {
+        offset
+            .checked_add(N)
+            .and_then(|end| self.bytes.get(offset..end))
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or(Error::Truncated)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, Error> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Error> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, Error> {
+        self.bytes(offset).map(u64::from_le_bytes)
+    }
+
+    /// Reads an address sized value, which is 32 or 64 bits depending on the class.
+    fn word(&self, offset: usize) -> Result<u64, Error> {
+        match self.class {
+            Class::Elf32 => self.u32(offset).map(u64::from),
+            Class::Elf64 => self.u64(offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, vec, vec::Vec};
+
+    use super::*;
+
+    /// Where test files want their first segment.
+    const BASE: u64 = 0x40_0000;
+
+    /// A segment for [`build`].
+    struct Segment {
+        kind: u32,
+        flags: u32,
+        address: u64,
+        data: Vec<u8>,
+        mem_size: u64,
+    }
+
+    impl Segment {
+        fn load(flags: u32, address: u64, data: &[u8], mem_size: u64) -> Self {
+            Self {
+                kind: program_kind::LOAD,
+                flags,
+                address,
+                data: data.to_vec(),
+                mem_size,
+            }
+        }
+    }
+
+    /// Writes an ELF file with `segments`, laid out one after another after the headers.
+    fn build(class: Class, kind: u16, entry: u64, segments: &[Segment]) -> Vec<u8> {
+        let header_size = class.header_size();
+        let program_header_size = class.program_header_size();
+        let mut bytes = vec![0; header_size + program_header_size * segments.len()];
+        let mut offsets = Vec::new();
+
+        for segment in segments {
+            offsets.push(bytes.len() as u64);
+            bytes.extend_from_slice(&segment.data);
+        }
+
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = match class {
+            Class::Elf32 => 1,
+            Class::Elf64 => 2,
+        };
+        bytes[5] = 1;
+        bytes[6] = 1;
+        bytes[16..18].copy_from_slice(&kind.to_le_bytes());
+        bytes[18..20].copy_from_slice(&class.machine().to_le_bytes());
+        bytes[20..24].copy_from_slice(&1_u32.to_le_bytes());
+
+        let put = |bytes: &mut Vec<u8>, offset: usize, value: u64| match class {
+            Class::Elf32 => {
+                bytes[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes())
+            }
+            Class::Elf64 => bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes()),
+        };
+
+        match class {
+            Class::Elf32 => {
+                put(&mut bytes, 24, entry);
+                put(&mut bytes, 28, header_size as u64);
+                bytes[42..44].copy_from_slice(&(program_header_size as u16).to_le_bytes());
+                bytes[44..46].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+            }
+            Class::Elf64 => {
+                put(&mut bytes, 24, entry);
+                put(&mut bytes, 32, header_size as u64);
+                bytes[54..56].copy_from_slice(&(program_header_size as u16).to_le_bytes());
+                bytes[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+            }
+        }
+
+        for (index, (segment, offset)) in segments.iter().zip(offsets).enumerate() {
+            let header = header_size + index * program_header_size;
+            let file_size = segment.data.len() as u64;
+
+            bytes[header..header + 4].copy_from_slice(&segment.kind.to_le_bytes());
+
+            match class {
+                Class::Elf32 => {
+                    for (field, value) in [
+                        offset,
+                        segment.address,
+                        segment.address,
+                        file_size,
+                        segment.mem_size,
+                    ]
+                    .into_iter()
+                    .enumerate()
+                    {
+                        put(&mut bytes, header + 4 + field * 4, value);
+                    }
+
+                    bytes[header + 24..header + 28].copy_from_slice(&segment.flags.to_le_bytes());
+                    put(&mut bytes, header + 28, 0x1000);
+                }
+                Class::Elf64 => {
+                    bytes[header + 4..header + 8].copy_from_slice(&segment.flags.to_le_bytes());
+
+                    for (field, value) in [
+                        offset,
+                        segment.address,
+                        segment.address,
+                        file_size,
+                        segment.mem_size,
+                        0x1000,
+                    ]
+                    .into_iter()
+                    .enumerate()
+                    {
+                        put(&mut bytes, header + 8 + field * 8, value);
+                    }
+                }
+            }
+        }
+
+        bytes
+    }
+
+    /// Encodes words of the size `class` uses.
+    fn words(class: Class, values: &[u64]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes()[..class.word_size()].to_vec())
+            .collect()
+    }
+
+    /// Maps everything into one buffer starting at `base`, which starts out as garbage.
+    struct Memory {
+        base: u64,
+        bytes: Vec<u8>,
+        mapped: Vec<(u64, u32)>,
+    }
+
+    impl Memory {
+        fn new(base: u64, len: usize) -> Self {
+            Self {
+                base,
+                bytes: vec![0xaa; len],
+                mapped: Vec::new(),
+            }
+        }
+
+        fn at(&self, address: u64, len: usize) -> &[u8] {
+            &self.bytes[(address - self.base) as usize..][..len]
+        }
+    }
+
+    impl Mapper for Memory {
+        type Error = Infallible;
+
+        fn map(&mut self, address: u64, segment: &ProgramHeader) -> Result<&mut [u8], Infallible> {
+            self.mapped.push((address, segment.flags));
+
+            Ok(&mut self.bytes[(address - self.base) as usize..][..segment.mem_size as usize])
+        }
+    }
+
+    /// Builds a file with a text segment, and a data segment with a bss.
+    fn executable(class: Class) -> Vec<u8> {
+        build(
+            class,
+            2,
+            BASE + 4,
+            &[
+                Segment::load(
+                    segment_flags::READ | segment_flags::EXECUTE,
+                    BASE,
+                    b"\x90\x90\x90\x90\xf4",
+                    5,
+                ),
+                Segment::load(
+                    segment_flags::READ | segment_flags::WRITE,
+                    BASE + 0x1000,
+                    b"data",
+                    0x20,
+                ),
+            ],
+        )
+    }
+
+    /// Builds a position independent file with relocations of the data segment.
+    fn position_independent(class: Class, explicit_addend: bool) -> Vec<u8> {
+        let word = class.word_size() as u64;
+        let dynamic_address = 0x2000;
+        let table_address = 0x3000;
+
+        let (table, tags) = match explicit_addend {
+            true => (
+                words(
+                    class,
+                    &[
+                        0x1000,
+                        relocation_kind::RELATIVE as u64,
+                        0x1008,
+                        0x1000 + word,
+                        relocation_kind::RELATIVE as u64,
+                        0x1234,
+                    ],
+                ),
+                [
+                    dynamic_tag::RELA,
+                    dynamic_tag::RELA_SIZE,
+                    dynamic_tag::RELA_ENTRY,
+                ],
+            ),
+            false => (
+                words(
+                    class,
+                    &[
+                        0x1000,
+                        relocation_kind::RELATIVE as u64,
+                        0x1000 + word,
+                        relocation_kind::RELATIVE as u64,
+                    ],
+                ),
+                [
+                    dynamic_tag::REL,
+                    dynamic_tag::REL_SIZE,
+                    dynamic_tag::REL_ENTRY,
+                ],
+            ),
+        };
+
+        let entry_size = table.len() as u64 / 2;
+
+        // Two words to relocate, which hold their own addends with REL.
+        let data = match explicit_addend {
+            true => words(class, &[0, 0, 0]),
+            false => words(class, &[0x1008, 0x1234, 0]),
+        };
+
+        let dynamic = words(
+            class,
+            &[
+                tags[0],
+                table_address,
+                tags[1],
+                table.len() as u64,
+                tags[2],
+                entry_size,
+                dynamic_tag::NULL,
+                0,
+            ],
+        );
+
+        build(
+            class,
+            3,
+            0x10,
+            &[
+                Segment::load(
+                    segment_flags::READ | segment_flags::EXECUTE,
+                    0,
+                    &[0xc3; 0x20],
+                    0x20,
+                ),
+                Segment::load(
+                    segment_flags::READ | segment_flags::WRITE,
+                    0x1000,
+                    &data,
+                    0x40,
+                ),
+                Segment {
+                    kind: program_kind::DYNAMIC,
+                    flags: segment_flags::READ,
+                    address: dynamic_address,
+                    data: dynamic.clone(),
+                    mem_size: dynamic.len() as u64,
+                },
+                Segment::load(
+                    segment_flags::READ,
+                    dynamic_address,
+                    &dynamic,
+                    dynamic.len() as u64,
+                ),
+                Segment::load(
+                    segment_flags::READ,
+                    table_address,
+                    &table,
+                    table.len() as u64,
+                ),
+            ],
+        )
+    }
+
+    const CLASSES: [Class; 2] = [Class::Elf32, Class::Elf64];
+
+    #[test]
+    fn parses_headers() {
+        for class in CLASSES {
+            let bytes = executable(class);
+            let elf = Elf::parse(&bytes).unwrap();
+
+            assert_eq!(elf.class(), class);
+            assert_eq!(elf.entry(), BASE + 4);
+            assert!(!elf.is_position_independent());
+            assert_eq!(elf.load_range(), Some(BASE..BASE + 0x1020));
+
+            let headers: Vec<_> = elf.program_headers().collect();
+
+            assert_eq!(headers.len(), 2);
+            assert_eq!(headers[0].kind, program_kind::LOAD);
+            assert_eq!(
+                headers[0].flags,
+                segment_flags::READ | segment_flags::EXECUTE
+            );
+            assert_eq!(headers[1].memory_range(), BASE + 0x1000..BASE + 0x1020);
+            assert_eq!(headers[1].file_size, 4);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let good = executable(Class::Elf64);
+
+        let with = |offset: usize, value: &[u8]| {
+            let mut bytes = good.clone();
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+            Elf::parse(&bytes).map(|_| ())
+        };
+
+        assert_eq!(Elf::parse(b"").unwrap_err(), Error::BadMagic);
+        assert_eq!(Elf::parse(&good[..40]).unwrap_err(), Error::Truncated);
+        assert_eq!(Elf::parse(&good[..100]).unwrap_err(), Error::Truncated);
+        assert_eq!(with(0, b"\x7fELG"), Err(Error::BadMagic));
+        assert_eq!(with(4, &[3]), Err(Error::UnsupportedClass));
+        assert_eq!(with(5, &[2]), Err(Error::UnsupportedEndianness));
+        assert_eq!(with(6, &[0]), Err(Error::UnsupportedVersion));
+        assert_eq!(with(16, &[1, 0]), Err(Error::UnsupportedType));
+        assert_eq!(
+            with(18, &MACHINE_X86.to_le_bytes()),
+            Err(Error::UnsupportedMachine)
+        );
+        assert_eq!(with(54, &[8, 0]), Err(Error::BadProgramHeader));
+
+        // A file size larger than the memory size.
+        assert_eq!(with(64 + 32, &[0x40]), Err(Error::BadProgramHeader));
+        // Contents past the end of the file.
+        assert_eq!(with(64 + 8, &[0xff, 0xff]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn loads_segments_and_zeroes_bss() {
+        for class in CLASSES {
+            let bytes = executable(class);
+            let elf = Elf::parse(&bytes).unwrap();
+            let mut memory = Memory::new(BASE, 0x2000);
+
+            assert_eq!(elf.load(&mut memory, 0), Ok(BASE + 4));
+            assert_eq!(memory.at(BASE, 6), b"\x90\x90\x90\x90\xf4\xaa");
+            assert_eq!(memory.at(BASE + 0x1000, 4), b"data");
+            assert_eq!(memory.at(BASE + 0x1004, 0x1c), [0; 0x1c]);
+            assert_eq!(memory.at(BASE + 0x1020, 1), [0xaa]);
+            assert_eq!(
+                memory.mapped,
+                [
+                    (BASE, segment_flags::READ | segment_flags::EXECUTE),
+                    (BASE + 0x1000, segment_flags::READ | segment_flags::WRITE),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_to_relocate_executables() {
+        let bytes = executable(Class::Elf64);
+        let elf = Elf::parse(&bytes).unwrap();
+        let mut memory = Memory::new(BASE, 0x4000);
+
+        assert_eq!(
+            elf.load(&mut memory, 0x1000),
+            Err(LoadError::Elf(Error::NotRelocatable))
+        );
+        assert!(memory.mapped.is_empty());
+    }
+
+    #[test]
+    fn relocates_position_independent_files() {
+        for class in CLASSES {
+            for explicit_addend in [false, true] {
+                let bytes = position_independent(class, explicit_addend);
+                let elf = Elf::parse(&bytes).unwrap();
+                let bias = 0x8000_0000;
+                let mut memory = Memory::new(bias, 0x4000);
+                let word = class.word_size();
+
+                assert!(elf.is_position_independent());
+                assert_eq!(elf.load(&mut memory, bias), Ok(bias + 0x10));
+
+                let relocated = words(class, &[bias + 0x1008, bias + 0x1234, 0]);
+
+                assert_eq!(
+                    memory.at(bias + 0x1000, word * 3),
+                    relocated,
+                    "{class:?}, explicit addend: {explicit_addend}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_relocations() {
+        let mut bytes = position_independent(Class::Elf64, true);
+        let elf = Elf::parse(&bytes).unwrap();
+        let table = elf.relocation_tables().unwrap()[1].unwrap();
+
+        // R_X86_64_64, which needs a symbol table.
+        bytes[table.offset + 8] = 1;
+
+        let elf = Elf::parse(&bytes).unwrap();
+        let mut memory = Memory::new(0, 0x4000);
+
+        assert_eq!(
+            elf.load(&mut memory, 0),
+            Err(LoadError::Elf(Error::UnsupportedRelocation(1)))
+        );
+        assert!(memory.mapped.is_empty());
+
+        // A relative relocation pointing outside of every segment.
+        bytes[table.offset + 8] = relocation_kind::RELATIVE as u8;
+        bytes[table.offset + 1] = 0x90;
+
+        let elf = Elf::parse(&bytes).unwrap();
+
+        assert_eq!(
+            elf.load(&mut memory, 0),
+            Err(LoadError::Elf(Error::BadRelocation))
+        );
+        assert!(memory.mapped.is_empty());
+
+        // One whose end wraps around.
+        bytes[table.offset..table.offset + 8].copy_from_slice(&(u64::MAX - 3).to_le_bytes());
+
+        let elf = Elf::parse(&bytes).unwrap();
+
+        assert_eq!(
+            elf.load(&mut memory, 0),
+            Err(LoadError::Elf(Error::BadRelocation))
+        );
+        assert!(memory.mapped.is_empty());
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn parses_own_executable() {
+        let bytes = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let elf = Elf::parse(&bytes).unwrap();
+        let range = elf.load_range().unwrap();
+
+        assert_eq!(elf.class(), Class::Elf64);
+        assert!(range.contains(&elf.entry()));
+        assert!(elf
+            .program_headers()
+            .any(|header| (header.kind == program_kind::LOAD)
+                & (header.flags & segment_flags::EXECUTE != 0)));
+    }
+}
@@ -0,0 +1,1453 @@
+use core::{char, fmt};
+
+/// The only sector size we support.
+pub const SECTOR_SIZE: usize = 512;
+
+/// The size of a directory entry in bytes.
+const ENTRY_SIZE: usize = 32;
+
+/// How many characters of a long name fit into one entry.
+const LONG_NAME_CHARS: usize = 13;
+
+/// Offsets of the UTF-16 characters within a long name entry.
+const LONG_NAME_OFFSETS: [usize; LONG_NAME_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// The longest name a long name can have, in UTF-16 code units.
+const MAX_LONG_NAME: usize = 255;
+
+/// Reads sectors from wherever the filesystem lives.
+pub trait SectorRead {
+    /// The error reading a sector can fail with.
+    type Error;
+
+    /// Reads the sector at `lba` into `buffer`.
+    fn read_sector(&mut self, lba: u64, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), Self::Error>;
+
+    /// Reads consecutive sectors starting at `lba` into `buffer`.
+    ///
+    /// Devices that can read several sectors at once should override this, as
+    /// whole clusters of a file are read through it.
+    fn read_sectors(
+        &mut self,
+        lba: u64,
+        buffer: &mut [[u8; SECTOR_SIZE]],
+    ) -> Result<(), Self::Error> {
+        for (sector, buffer) in (lba..).zip(buffer) {
+            self.read_sector(sector, buffer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: SectorRead + ?Sized> SectorRead for &mut T {
+    type Error = T::Error;
+
+    #[inline]
+    fn read_sector(&mut self, lba: u64, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), Self::Error> {
+        (**self).read_sector(lba, buffer)
+    }
+
+    #[inline]
+    fn read_sectors(
+        &mut self,
+        lba: u64,
+        buffer: &mut [[u8; SECTOR_SIZE]],
+    ) -> Result<(), Self::Error> {
+        (**self).read_sectors(lba, buffer)
+    }
+}
+
+/// Which variant of FAT a volume uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FatKind {
+    /// 12-bit cluster numbers, for volumes with less than 4085 clusters.
+    Fat12,
+    /// 16-bit cluster numbers, for volumes with less than 65525 clusters.
+    Fat16,
+    /// 28-bit cluster numbers.
+    Fat32,
+}
+
+impl FatKind {
+    /// Returns the kind of volume with `clusters` clusters.
+    #[inline]
+    #[must_use]
+    pub const fn from_cluster_count(clusters: u32) -> Self {
+        match clusters {
+            0..4085 => FatKind::Fat12,
+            4085..65525 => FatKind::Fat16,
+            _ => FatKind::Fat32,
+        }
+    }
+
+    /// Returns the smallest FAT entry that marks the end of a chain.
+    #[inline]
+    #[must_use]
+    const fn end_of_chain(self) -> u32 {
+        match self {
+            FatKind::Fat12 => 0xff8,
+            FatKind::Fat16 => 0xfff8,
+            FatKind::Fat32 => 0x0fff_fff8,
+        }
+    }
+}
+
+/// Errors that can occur while reading a FAT volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error<E> {
+    /// The device failed to read a sector.
+    Device(E),
+    /// The boot sector doesn't describe a FAT volume.
+    NotFat,
+    /// The volume uses sectors other than 512 bytes.
+    UnsupportedSectorSize,
+    /// There's nothing at the path.
+    NotFound,
+    /// A path component that isn't the last one is a file.
+    NotADirectory,
+    /// The path points to a directory where a file was expected.
+    IsADirectory,
+    /// A cluster chain is broken, or shorter than the file it belongs to.
+    BadCluster,
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Device(err) => write!(f, "failed to read sector: {err}"),
+            Error::NotFat => f.write_str("not a fat volume"),
+            Error::UnsupportedSectorSize => f.write_str("sector size is not 512 bytes"),
+            Error::NotFound => f.write_str("no such file or directory"),
+            Error::NotADirectory => f.write_str("not a directory"),
+            Error::IsADirectory => f.write_str("is a directory"),
+            Error::BadCluster => f.write_str("broken cluster chain"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> std::error::Error for Error<E> {}
+
+/// The BIOS parameter block of a FAT volume, parsed from its boot sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bpb {
+    /// Bytes per sector.
+    pub bytes_per_sector: u16,
+    /// Sectors per cluster, a power of two.
+    pub sectors_per_cluster: u8,
+    /// Sectors before the first FAT, including the boot sector.
+    pub reserved_sectors: u16,
+    /// The amount of copies of the FAT.
+    pub fat_count: u8,
+    /// Entries in the fixed root directory, zero on FAT32.
+    pub root_entries: u16,
+    /// Sectors in the volume.
+    pub total_sectors: u32,
+    /// Sectors per FAT.
+    pub sectors_per_fat: u32,
+    /// First cluster of the root directory on FAT32.
+    pub root_cluster: u32,
+}
+
+impl Bpb {
+    /// Parses the BPB out of a boot sector, returning `None` if it isn't FAT.
+    #[must_use]
+    pub fn parse(sector: &[u8; SECTOR_SIZE]) -> Option<Self> {
+        let u16_at = |offset: usize| u16::from_le_bytes([sector[offset], sector[offset + 1]]);
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                sector[offset],
+                sector[offset + 1],
+                sector[offset + 2],
+                sector[offset + 3],
+            ])
+        };
+
+        if u16_at(510) != 0xaa55 {
+            return None;
+        }
+
+        let bpb = Self {
+            bytes_per_sector: u16_at(11),
+            sectors_per_cluster: sector[13],
+            reserved_sectors: u16_at(14),
+            fat_count: sector[16],
+            root_entries: u16_at(17),
+            total_sectors: match u16_at(19) {
+                0 => u32_at(32),
+                total => total as u32,
+            },
+            sectors_per_fat: match u16_at(22) {
+                0 => u32_at(36),
+                sectors => sectors as u32,
+            },
+            root_cluster: u32_at(44),
+        };
+
+        let valid = bpb.bytes_per_sector.is_power_of_two()
+            & (512..=4096).contains(&bpb.bytes_per_sector)
+            & bpb.sectors_per_cluster.is_power_of_two()
+            & (bpb.reserved_sectors != 0)
+            & (bpb.fat_count != 0)
+            & (bpb.sectors_per_fat != 0);
+
+        // The layout only makes sense once we know we won't divide by zero.
+        (valid && bpb.data_start() < bpb.total_sectors as u64).then_some(bpb)
+    }
+
+    /// Returns the first sector of the first FAT.
+    #[inline]
+    #[must_use]
+    pub const fn fat_start(&self) -> u64 {
+        self.reserved_sectors as u64
+    }
+
+    /// Returns the first sector of the fixed root directory.
+    #[inline]
+    #[must_use]
+    pub const fn root_dir_start(&self) -> u64 {
+        self.fat_start() + self.fat_count as u64 * self.sectors_per_fat as u64
+    }
+
+    /// Returns the size of the fixed root directory in sectors.
+    #[inline]
+    #[must_use]
+    pub const fn root_dir_sectors(&self) -> u64 {
+        (self.root_entries as u64 * ENTRY_SIZE as u64).div_ceil(self.bytes_per_sector as u64)
+    }
+
+    /// Returns the first sector of cluster 2.
+    #[inline]
+    #[must_use]
+    pub const fn data_start(&self) -> u64 {
+        self.root_dir_start() + self.root_dir_sectors()
+    }
+
+    /// Returns the amount of data clusters.
+    #[inline]
+    #[must_use]
+    pub const fn cluster_count(&self) -> u32 {
+        ((self.total_sectors as u64 - self.data_start()) / self.sectors_per_cluster as u64) as u32
+    }
+
+    /// Returns which variant of FAT the volume uses.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> FatKind {
+        FatKind::from_cluster_count(self.cluster_count())
+    }
+}
+
+/// Attribute bits of a directory entry.
+pub mod attributes {
+    /// The file must not be written to.
+    pub const READ_ONLY: u8 = 0x01;
+    /// The file is hidden.
+    pub const HIDDEN: u8 = 0x02;
+    /// The file belongs to the system.
+    pub const SYSTEM: u8 = 0x04;
+    /// The entry is the volume label.
+    pub const VOLUME_ID: u8 = 0x08;
+    /// The entry is a directory.
+    pub const DIRECTORY: u8 = 0x10;
+    /// The file changed since the last backup.
+    pub const ARCHIVE: u8 = 0x20;
+    /// The entry is part of a long name.
+    pub const LONG_NAME: u8 = READ_ONLY | HIDDEN | SYSTEM | VOLUME_ID;
+}
+
+/// Returns the checksum of a short name that long name entries refer to.
+#[inline]
+#[must_use]
+pub const fn short_name_checksum(name: &[u8; 11]) -> u8 {
+    let mut sum = 0_u8;
+    let mut i = 0;
+
+    while i < name.len() {
+        sum = sum.rotate_right(1).wrapping_add(name[i]);
+        i += 1;
+    }
+
+    sum
+}
+
+/// A directory entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DirEntry {
+    /// The 8.3 name, padded with spaces.
+    pub short_name: [u8; 11],
+    /// See [`attributes`].
+    pub attributes: u8,
+    /// The first cluster, zero for empty files and the root directory.
+    pub cluster: u32,
+    /// The size in bytes, zero for directories.
+    pub size: u32,
+}
+
+impl DirEntry {
+    /// Parses a raw short entry.
+    fn parse(raw: &[u8; ENTRY_SIZE]) -> Self {
+        let mut short_name = [0; 11];
+        short_name.copy_from_slice(&raw[..11]);
+
+        // A leading 0xe5 is stored as 0x05, as 0xe5 marks deleted entries.
+        if short_name[0] == 0x05 {
+            short_name[0] = 0xe5;
+        }
+
+        let high = u16::from_le_bytes([raw[20], raw[21]]) as u32;
+        let low = u16::from_le_bytes([raw[26], raw[27]]) as u32;
+
+        Self {
+            short_name,
+            attributes: raw[11],
+            cluster: (high << 16) | low,
+            size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]),
+        }
+    }
+
+    /// Returns whether this entry is a directory.
+    #[inline]
+    #[must_use]
+    pub const fn is_dir(&self) -> bool {
+        self.attributes & attributes::DIRECTORY != 0
+    }
+
+    /// Returns whether the short name is `name`, ignoring ASCII case.
+    #[must_use]
+    pub fn short_name_matches(&self, name: &str) -> bool {
+        let (base, extension) = self.short_name.split_at(8);
+        let base = base.trim_ascii_end();
+        let extension = extension.trim_ascii_end();
+
+        let (name_base, name_extension) = match name.as_bytes() {
+            // Don't split `.` and `..` at their dots.
+            b"." | b".." => (name.as_bytes(), &[][..]),
+            name => match name.iter().rposition(|&byte| byte == b'.') {
+                Some(dot) => (&name[..dot], &name[dot + 1..]),
+                None => (name, &[][..]),
+            },
+        };
+
+        base.eq_ignore_ascii_case(name_base) & extension.eq_ignore_ascii_case(name_extension)
+    }
+}
+
+/// A directory to look things up in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dir {
+    /// The first cluster, zero for the fixed root directory of FAT12 and FAT16.
+    cluster: u32,
+}
+
+/// An open file, which keeps track of how far it has been read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct File {
+    entry: DirEntry,
+    position: u32,
+    cursor: Cursor,
+}
+
+impl File {
+    /// Returns the directory entry of the file.
+    #[inline]
+    #[must_use]
+    pub const fn entry(&self) -> &DirEntry {
+        &self.entry
+    }
+
+    /// Returns the size of the file in bytes.
+    #[inline]
+    #[must_use]
+    pub const fn size(&self) -> u32 {
+        self.entry.size
+    }
+
+    /// Returns how many bytes have been read so far.
+    #[inline]
+    #[must_use]
+    pub const fn position(&self) -> u32 {
+        self.position
+    }
+}
+
+/// A sector within a directory or file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Cursor {
+    /// A sector of the fixed root directory.
+    Root { sector: u64 },
+    /// A sector of a cluster.
+    Cluster { cluster: u32, sector: u8 },
+    /// Past the last sector.
+    End,
+}
+
+/// A long name being put together from its entries, which come last part first.
+struct LongName {
+    units: [u16; MAX_LONG_NAME + LONG_NAME_CHARS],
+    len: usize,
+    /// The sequence number of the entry we expect next, zero once complete.
+    next: u8,
+    checksum: u8,
+    valid: bool,
+}
+
+impl LongName {
+    const fn new() -> Self {
+        Self {
+            units: [0; MAX_LONG_NAME + LONG_NAME_CHARS],
+            len: 0,
+            next: 0,
+            checksum: 0,
+            valid: false,
+        }
+    }
+
+    /// Adds a long name entry.
+    fn push(&mut self, raw: &[u8; ENTRY_SIZE]) {
+        let sequence = raw[0] & 0x1f;
+        let checksum = raw[13];
+
+        if raw[0] & 0x40 != 0 {
+            // The last part comes first and tells us the length.
+            self.valid = (1..=20).contains(&sequence);
+            self.len = sequence as usize * LONG_NAME_CHARS;
+            self.checksum = checksum;
+        } else if (sequence == 0) | (sequence != self.next) | (checksum != self.checksum) {
+            // Zero is never a sequence number, but is what `next` is once the
+            // name is complete.
+            self.valid = false;
+        }
+
+        if !self.valid {
+            return;
+        }
+
+        let start = (sequence as usize - 1) * LONG_NAME_CHARS;
+
+        for (i, offset) in LONG_NAME_OFFSETS.into_iter().enumerate() {
+            let unit = u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+
+            // The name ends at a nul, anything after it is padding.
+            if unit == 0 {
+                self.len = self.len.min(start + i);
+            }
+
+            self.units[start + i] = unit;
+        }
+
+        self.next = sequence - 1;
+    }
+
+    /// Returns the name if it's complete and belongs to the short entry `name`.
+    fn finish(&mut self, name: &[u8; 11]) -> Option<&[u16]> {
+        let valid = self.valid & (self.next == 0) & (self.checksum == short_name_checksum(name));
+
+        self.valid = false;
+
+        (valid & (self.len <= MAX_LONG_NAME)).then(|| &self.units[..self.len])
+    }
+
+    /// Forgets the name, for entries that can't have one.
+    fn reset(&mut self) {
+        self.valid = false;
+    }
+}
+
+/// Returns whether the UTF-16 `units` spell out `name`, ignoring ASCII case.
+fn long_name_matches(units: &[u16], name: &str) -> bool {
+    char::decode_utf16(units.iter().copied())
+        .map(|c| c.map(|c| c.to_ascii_lowercase()))
+        .eq(name.chars().map(|c| Ok(c.to_ascii_lowercase())))
+}
+
+/// A read-only FAT12, FAT16 or FAT32 volume.
+pub struct FileSystem<D> {
+    device: D,
+    /// The sector the volume starts at on the device.
+    start: u64,
+    bpb: Bpb,
+    kind: FatKind,
+    /// The last sector read through [`FileSystem::read_cached`].
+    cache: [u8; SECTOR_SIZE],
+    cached: Option<u64>,
+}
+
+impl<D: SectorRead> FileSystem<D> {
+    /// Opens the volume starting at sector `start` of `device`.
+    pub fn new(mut device: D, start: u64) -> Result<Self, Error<D::Error>> {
+        let mut cache = [0; SECTOR_SIZE];
+
+        device
+            .read_sector(start, &mut cache)
+            .map_err(Error::Device)?;
+
+        let bpb = Bpb::parse(&cache).ok_or(Error::NotFat)?;
+
+        if bpb.bytes_per_sector as usize != SECTOR_SIZE {
+            return Err(Error::UnsupportedSectorSize);
+        }
+
+        let kind = bpb.kind();
+
+        Ok(Self {
+            device,
+            start,
+            bpb,
+            kind,
+            cache,
+            cached: Some(start),
+        })
+    }
+
+    /// Returns the BIOS parameter block.
+    #[inline]
+    #[must_use]
+    pub const fn bpb(&self) -> &Bpb {
+        &self.bpb
+    }
+
+    /// Returns which variant of FAT the volume uses.
+    #[inline]
+    #[must_use]
+    pub const fn kind(&self) -> FatKind {
+        self.kind
+    }
+
+    /// Gives back the device.
+    #[inline]
+    #[must_use]
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Returns the root directory.
+    #[inline]
+    #[must_use]
+    pub const fn root_dir(&self) -> Dir {
+        Dir {
+            cluster: match self.kind {
+                FatKind::Fat32 => self.bpb.root_cluster,
+                FatKind::Fat12 | FatKind::Fat16 => 0,
+            },
+        }
+    }
+
+    /// Looks up `path`, which is relative to the root directory.
+    ///
+    /// Components are separated by `/` and matched against both long and short
+    /// names, ignoring ASCII case. An empty path is the root directory itself.
+    pub fn find(&mut self, path: &str) -> Result<Option<DirEntry>, Error<D::Error>> {
+        let mut dir = self.root_dir();
+        let mut components = path.split('/').filter(|component| !component.is_empty());
+        let mut entry = None;
+
+        while let Some(component) = components.next() {
+            let found = self.find_in(dir, component)?.ok_or(Error::NotFound)?;
+
+            if components.clone().next().is_some() {
+                if !found.is_dir() {
+                    return Err(Error::NotADirectory);
+                }
+
+                // `..` pointing to the root uses cluster zero, even on FAT32.
+                dir = match found.cluster {
+                    0 => self.root_dir(),
+                    cluster => Dir { cluster },
+                };
+            }
+
+            entry = Some(found);
+        }
+
+        Ok(entry)
+    }
+
+    /// Opens the file at `path`, see [`FileSystem::find`].
+    pub fn open(&mut self, path: &str) -> Result<File, Error<D::Error>> {
+        let entry = self.find(path)?.ok_or(Error::IsADirectory)?;
+
+        if entry.is_dir() {
+            return Err(Error::IsADirectory);
+        }
+
+        let cursor = match entry.size {
+            0 => Cursor::End,
+            _ => self.cluster_cursor(entry.cluster)?,
+        };
+
+        Ok(File {
+            entry,
+            position: 0,
+            cursor,
+        })
+    }
+
+    /// Reads from the current position of `file` into `buffer`.
+    ///
+    /// Returns how many bytes were read, which is only less than the size of
+    /// `buffer` at the end of the file.
+    pub fn read(&mut self, file: &mut File, buffer: &mut [u8]) -> Result<usize, Error<D::Error>> {
+        let mut done = 0;
+
+        while (done < buffer.len()) & (file.position < file.entry.size) {
+            let lba = self.cursor_lba(file.cursor).ok_or(Error::BadCluster)?;
+            let offset = file.position as usize % SECTOR_SIZE;
+            let remaining = (file.entry.size - file.position) as usize;
+            let len = (SECTOR_SIZE - offset)
+                .min(buffer.len() - done)
+                .min(remaining);
+
+            // Whole sectors go straight into the buffer, as many as the cluster has left.
+            if (offset == 0) & (len == SECTOR_SIZE) {
+                let sectors = ((buffer.len() - done).min(remaining) / SECTOR_SIZE)
+                    .min(self.sectors_left(file.cursor) as usize);
+                let target = &mut buffer[done..done + sectors * SECTOR_SIZE];
+
+                self.device
+                    .read_sectors(self.start + lba, target.as_chunks_mut().0)
+                    .map_err(Error::Device)?;
+
+                done += target.len();
+                file.position += target.len() as u32;
+                file.cursor = self.skip(file.cursor, sectors as u64 - 1);
+            } else {
+                buffer[done..done + len]
+                    .copy_from_slice(&self.read_cached(lba)?[offset..offset + len]);
+
+                done += len;
+                file.position += len as u32;
+            }
+
+            if (file.position as usize).is_multiple_of(SECTOR_SIZE)
+                & (file.position < file.entry.size)
+            {
+                file.cursor = self.advance(file.cursor)?;
+            }
+        }
+
+        Ok(done)
+    }
+
+    /// Returns the cluster following `cluster`, or `None` at the end of the chain.
+    pub fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, Error<D::Error>> {
+        self.check_cluster(cluster)?;
+
+        let offset = match self.kind {
+            FatKind::Fat12 => cluster as u64 + cluster as u64 / 2,
+            FatKind::Fat16 => cluster as u64 * 2,
+            FatKind::Fat32 => cluster as u64 * 4,
+        };
+
+        let lba = self.bpb.fat_start() + offset / SECTOR_SIZE as u64;
+        let offset = offset as usize % SECTOR_SIZE;
+        let kind = self.kind;
+        let sector = self.read_cached(lba)?;
+
+        let next = match kind {
+            FatKind::Fat12 => {
+                let low = sector[offset];
+
+                // An entry can straddle two sectors.
+                let high = match sector.get(offset + 1) {
+                    Some(&high) => high,
+                    None => self.read_cached(lba + 1)?[0],
+                };
+                let value = u16::from_le_bytes([low, high]) as u32;
+
+                match cluster % 2 {
+                    0 => value & 0xfff,
+                    _ => value >> 4,
+                }
+            }
+            FatKind::Fat16 => u16::from_le_bytes([sector[offset], sector[offset + 1]]) as u32,
+            FatKind::Fat32 => {
+                u32::from_le_bytes([
+                    sector[offset],
+                    sector[offset + 1],
+                    sector[offset + 2],
+                    sector[offset + 3],
+                ]) & 0x0fff_ffff
+            }
+        };
+
+        if next >= self.kind.end_of_chain() {
+            return Ok(None);
+        }
+
+        self.check_cluster(next)?;
+
+        Ok(Some(next))
+    }
+
+    /// Looks for `name` directly within `dir`.
+    fn find_in(&mut self, dir: Dir, name: &str) -> Result<Option<DirEntry>, Error<D::Error>> {
+        let mut cursor = match dir.cluster {
+            0 => Cursor::Root { sector: 0 },
+            cluster => self.cluster_cursor(cluster)?,
+        };
+        let mut long_name = LongName::new();
+
+        // A chain that loops would keep us here forever.
+        let max_sectors = self.bpb.cluster_count() as u64 * self.bpb.sectors_per_cluster as u64
+            + self.bpb.root_dir_sectors();
+
+        for _ in 0..max_sectors {
+            let Some(lba) = self.cursor_lba(cursor) else {
+                return Ok(None);
+            };
+
+            let sector = *self.read_cached(lba)?;
+
+            for raw in sector.as_chunks::<ENTRY_SIZE>().0 {
+                match (raw[0], raw[11]) {
+                    // The end of the directory.
+                    (0x00, _) => return Ok(None),
+                    // A deleted entry.
+                    (0xe5, _) => long_name.reset(),
+                    (_, attributes) if attributes & 0x3f == attributes::LONG_NAME => {
+                        long_name.push(raw);
+                    }
+                    (_, attributes) if attributes & attributes::VOLUME_ID != 0 => {
+                        long_name.reset();
+                    }
+                    _ => {
+                        let entry = DirEntry::parse(raw);
+                        let long_matches = long_name
+                            .finish(raw[..11].try_into().unwrap())
+                            .is_some_and(|units| long_name_matches(units, name));
+
+                        if long_matches || entry.short_name_matches(name) {
+                            return Ok(Some(entry));
+                        }
+                    }
+                }
+            }
+
+            cursor = self.advance(cursor)?;
+        }
+
+        Err(Error::BadCluster)
+    }
+
+    /// Returns a cursor at the first sector of `cluster`.
+    fn cluster_cursor(&self, cluster: u32) -> Result<Cursor, Error<D::Error>> {
+        self.check_cluster(cluster)?;
+
+        Ok(Cursor::Cluster { cluster, sector: 0 })
+    }
+
+    /// Returns the sector `cursor` points to, relative to the start of the volume.
+    fn cursor_lba(&self, cursor: Cursor) -> Option<u64> {
+        match cursor {
+            Cursor::Root { sector } => Some(self.bpb.root_dir_start() + sector),
+            Cursor::Cluster { cluster, sector } => Some(
+                self.bpb.data_start()
+                    + (cluster as u64 - 2) * self.bpb.sectors_per_cluster as u64
+                    + sector as u64,
+            ),
+            Cursor::End => None,
+        }
+    }
+
+    /// Returns how many sectors there are from `cursor` up to the end of its
+    /// cluster or the fixed root directory, the one it points to included.
+    fn sectors_left(&self, cursor: Cursor) -> u64 {
+        match cursor {
+            Cursor::Root { sector } => self.bpb.root_dir_sectors() - sector,
+            Cursor::Cluster { sector, .. } => (self.bpb.sectors_per_cluster - sector) as u64,
+            Cursor::End => 0,
+        }
+    }
+
+    /// Moves `cursor` forward by `sectors`, which must stay within [`FileSystem::sectors_left`].
+    fn skip(&self, cursor: Cursor, sectors: u64) -> Cursor {
+        match cursor {
+            Cursor::Root { sector } => Cursor::Root {
+                sector: sector + sectors,
+            },
+            Cursor::Cluster { cluster, sector } => Cursor::Cluster {
+                cluster,
+                sector: sector + sectors as u8,
+            },
+            Cursor::End => Cursor::End,
+        }
+    }
+
+    /// Moves `cursor` to the next sector, following the cluster chain.
+    fn advance(&mut self, cursor: Cursor) -> Result<Cursor, Error<D::Error>> {
+        Ok(match cursor {
+            Cursor::Root { sector } if sector + 1 < self.bpb.root_dir_sectors() => {
+                Cursor::Root { sector: sector + 1 }
+            }
+            Cursor::Cluster { cluster, sector } if sector + 1 < self.bpb.sectors_per_cluster => {
+                Cursor::Cluster {
+                    cluster,
+                    sector: sector + 1,
+                }
+            }
+            Cursor::Cluster { cluster, .. } => match self.next_cluster(cluster)? {
+                Some(cluster) => Cursor::Cluster { cluster, sector: 0 },
+                None => Cursor::End,
+            },
+            Cursor::Root { .. } | Cursor::End => Cursor::End,
+        })
+    }
+
+    /// Returns an error if `cluster` isn't a data cluster of this volume.
+    fn check_cluster(&self, cluster: u32) -> Result<(), Error<D::Error>> {
+        match (2..self.bpb.cluster_count() + 2).contains(&cluster) {
+            true => Ok(()),
+            false => Err(Error::BadCluster),
+        }
+    }
+
+    /// Reads a sector relative to the start of the volume through the cache.
+    fn read_cached(&mut self, lba: u64) -> Result<&[u8; SECTOR_SIZE], Error<D::Error>> {
+        let lba = self.start + lba;
+
+        if self.cached != Some(lba) {
+            // Don't leave a half read sector looking valid.
+            self.cached = None;
+            self.device
+                .read_sector(lba, &mut self.cache)
+                .map_err(Error::Device)?;
+            self.cached = Some(lba);
+        }
+
+        Ok(&self.cache)
+    }
+}
+
+impl<D> fmt::Debug for FileSystem<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileSystem")
+            .field("start", &self.start)
+            .field("bpb", &self.bpb)
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, convert::Infallible, vec, vec::Vec};
+
+    use super::*;
+
+    /// A sparse disk, sectors nobody wrote to read as zeroes.
+    #[derive(Default)]
+    struct Disk {
+        sectors: BTreeMap<u64, [u8; SECTOR_SIZE]>,
+        reads: usize,
+        batches: usize,
+    }
+
+    impl Disk {
+        fn write(&mut self, offset: u64, bytes: &[u8]) {
+            for (i, byte) in bytes.iter().enumerate() {
+                let offset = offset + i as u64;
+                let sector = self
+                    .sectors
+                    .entry(offset / SECTOR_SIZE as u64)
+                    .or_insert([0; SECTOR_SIZE]);
+
+                sector[offset as usize % SECTOR_SIZE] = *byte;
+            }
+        }
+    }
+
+    impl SectorRead for Disk {
+        type Error = Infallible;
+
+        fn read_sector(
+            &mut self,
+            lba: u64,
+            buffer: &mut [u8; SECTOR_SIZE],
+        ) -> Result<(), Infallible> {
+            self.reads += 1;
+            *buffer = self.sectors.get(&lba).copied().unwrap_or([0; SECTOR_SIZE]);
+
+            Ok(())
+        }
+
+        fn read_sectors(
+            &mut self,
+            lba: u64,
+            buffer: &mut [[u8; SECTOR_SIZE]],
+        ) -> Result<(), Infallible> {
+            self.batches += 1;
+
+            for (lba, buffer) in (lba..).zip(buffer) {
+                self.read_sector(lba, buffer)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Formats a volume on a [`Disk`] and fills it with files.
+    struct Builder {
+        disk: Disk,
+        start: u64,
+        bpb: Bpb,
+        kind: FatKind,
+        next_cluster: u32,
+        /// The next free entry of each directory, keyed by first cluster.
+        slots: BTreeMap<u32, usize>,
+    }
+
+    impl Builder {
+        fn new(kind: FatKind, start: u64) -> Self {
+            Self::with_cluster_size(kind, start, 1)
+        }
+
+        fn with_cluster_size(kind: FatKind, start: u64, sectors_per_cluster: u8) -> Self {
+            // The volume grows with the cluster size, so the kind stays the same.
+            let (total, sectors_per_fat, root_entries, reserved_sectors) = match kind {
+                FatKind::Fat12 => (4000, 12, 64, 1),
+                FatKind::Fat16 => (40_000, 160, 64, 1),
+                FatKind::Fat32 => (70_000, 550, 0, 32),
+            };
+
+            let bpb = Bpb {
+                bytes_per_sector: SECTOR_SIZE as u16,
+                sectors_per_cluster,
+                reserved_sectors,
+                fat_count: 2,
+                root_entries,
+                total_sectors: total * sectors_per_cluster as u32,
+                sectors_per_fat,
+                root_cluster: 2,
+            };
+
+            let mut boot = [0; SECTOR_SIZE];
+            boot[..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+            boot[3..11].copy_from_slice(b"MROWTEST");
+            boot[11..13].copy_from_slice(&bpb.bytes_per_sector.to_le_bytes());
+            boot[13] = bpb.sectors_per_cluster;
+            boot[14..16].copy_from_slice(&bpb.reserved_sectors.to_le_bytes());
+            boot[16] = bpb.fat_count;
+            boot[17..19].copy_from_slice(&bpb.root_entries.to_le_bytes());
+            boot[21] = 0xf8;
+            boot[32..36].copy_from_slice(&bpb.total_sectors.to_le_bytes());
+            boot[510..].copy_from_slice(&[0x55, 0xaa]);
+
+            match kind {
+                FatKind::Fat32 => {
+                    boot[36..40].copy_from_slice(&bpb.sectors_per_fat.to_le_bytes());
+                    boot[44..48].copy_from_slice(&bpb.root_cluster.to_le_bytes());
+                }
+                FatKind::Fat12 | FatKind::Fat16 => {
+                    boot[22..24].copy_from_slice(&(bpb.sectors_per_fat as u16).to_le_bytes());
+                }
+            }
+
+            let mut builder = Self {
+                disk: Disk::default(),
+                start,
+                bpb,
+                kind,
+                next_cluster: 2,
+                slots: BTreeMap::new(),
+            };
+
+            builder.disk.write(start * SECTOR_SIZE as u64, &boot);
+            assert_eq!(bpb.kind(), kind);
+
+            if kind == FatKind::Fat32 {
+                let root = builder.allocate(1);
+                assert_eq!(root, [bpb.root_cluster]);
+            }
+
+            builder
+        }
+
+        fn root(&self) -> u32 {
+            match self.kind {
+                FatKind::Fat32 => self.bpb.root_cluster,
+                FatKind::Fat12 | FatKind::Fat16 => 0,
+            }
+        }
+
+        fn set_fat(&mut self, cluster: u32, value: u32) {
+            let fat = (self.start + self.bpb.fat_start()) * SECTOR_SIZE as u64;
+
+            match self.kind {
+                FatKind::Fat12 => {
+                    let offset = fat + cluster as u64 + cluster as u64 / 2;
+                    let old = self.read_u16(offset);
+                    let new = match cluster % 2 {
+                        0 => (old & 0xf000) | value as u16,
+                        _ => (old & 0x000f) | ((value as u16) << 4),
+                    };
+
+                    self.disk.write(offset, &new.to_le_bytes());
+                }
+                FatKind::Fat16 => self
+                    .disk
+                    .write(fat + cluster as u64 * 2, &(value as u16).to_le_bytes()),
+                FatKind::Fat32 => self
+                    .disk
+                    .write(fat + cluster as u64 * 4, &value.to_le_bytes()),
+            }
+        }
+
+        fn read_u16(&mut self, offset: u64) -> u16 {
+            let mut sector = [0; SECTOR_SIZE];
+            let mut bytes = [0; 2];
+
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                let offset = offset + i as u64;
+                self.disk
+                    .read_sector(offset / SECTOR_SIZE as u64, &mut sector)
+                    .unwrap();
+                *byte = sector[offset as usize % SECTOR_SIZE];
+            }
+
+            u16::from_le_bytes(bytes)
+        }
+
+        /// Allocates a chain of `len` clusters, leaving a gap after every one
+        /// so the chain is fragmented.
+        fn allocate(&mut self, len: usize) -> Vec<u32> {
+            let chain: Vec<u32> = (0..len)
+                .map(|_| {
+                    let cluster = self.next_cluster;
+                    self.next_cluster += 2;
+                    cluster
+                })
+                .collect();
+
+            for pair in chain.windows(2) {
+                self.set_fat(pair[0], pair[1]);
+            }
+
+            if let Some(&last) = chain.last() {
+                self.set_fat(last, 0x0fff_ffff & self.kind.end_of_chain() | 0xf);
+            }
+
+            chain
+        }
+
+        fn cluster_offset(&self, cluster: u32) -> u64 {
+            let sector =
+                self.bpb.data_start() + (cluster as u64 - 2) * self.cluster_sectors() as u64;
+
+            (self.start + sector) * SECTOR_SIZE as u64
+        }
+
+        fn cluster_sectors(&self) -> usize {
+            self.bpb.sectors_per_cluster as usize
+        }
+
+        fn write_chain(&mut self, chain: &[u32], data: &[u8]) {
+            for (cluster, chunk) in chain
+                .iter()
+                .zip(data.chunks(SECTOR_SIZE * self.cluster_sectors()))
+            {
+                self.disk.write(self.cluster_offset(*cluster), chunk);
+            }
+        }
+
+        fn push_entry(&mut self, dir: u32, raw: [u8; ENTRY_SIZE]) {
+            let slot = self.slots.entry(dir).or_insert(0);
+            let index = *slot;
+            *slot += 1;
+
+            let offset = match dir {
+                0 => (self.start + self.bpb.root_dir_start()) * SECTOR_SIZE as u64,
+                // Directories get a single cluster, which is plenty for tests.
+                dir => {
+                    assert!(index < SECTOR_SIZE / ENTRY_SIZE);
+                    self.cluster_offset(dir)
+                }
+            };
+
+            self.disk.write(offset + (index * ENTRY_SIZE) as u64, &raw);
+        }
+
+        fn add_entry(
+            &mut self,
+            dir: u32,
+            short_name: &[u8; 11],
+            long_name: Option<&str>,
+            attributes: u8,
+            cluster: u32,
+            size: u32,
+        ) {
+            if let Some(long_name) = long_name {
+                let mut units: Vec<u16> = long_name.encode_utf16().collect();
+
+                if !units.len().is_multiple_of(LONG_NAME_CHARS) {
+                    units.push(0);
+                }
+
+                while !units.len().is_multiple_of(LONG_NAME_CHARS) {
+                    units.push(0xffff);
+                }
+
+                let parts = units.len() / LONG_NAME_CHARS;
+                let checksum = short_name_checksum(short_name);
+
+                for part in (1..=parts).rev() {
+                    let mut raw = [0; ENTRY_SIZE];
+
+                    raw[0] = part as u8 | if part == parts { 0x40 } else { 0 };
+                    raw[11] = attributes::LONG_NAME;
+                    raw[13] = checksum;
+
+                    let chars = &units[(part - 1) * LONG_NAME_CHARS..part * LONG_NAME_CHARS];
+
+                    for (unit, offset) in chars.iter().zip(LONG_NAME_OFFSETS) {
+                        raw[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+                    }
+
+                    self.push_entry(dir, raw);
+                }
+            }
+
+            let mut raw = [0; ENTRY_SIZE];
+            raw[..11].copy_from_slice(short_name);
+            raw[11] = attributes;
+            raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+            raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+            raw[28..32].copy_from_slice(&size.to_le_bytes());
+
+            self.push_entry(dir, raw);
+        }
+
+        fn file(&mut self, dir: u32, short_name: &[u8; 11], long_name: Option<&str>, data: &[u8]) {
+            let chain = self.allocate(data.len().div_ceil(SECTOR_SIZE * self.cluster_sectors()));
+            let cluster = chain.first().copied().unwrap_or(0);
+
+            self.write_chain(&chain, data);
+            self.add_entry(
+                dir,
+                short_name,
+                long_name,
+                attributes::ARCHIVE,
+                cluster,
+                data.len() as u32,
+            );
+        }
+
+        fn dir(&mut self, parent: u32, short_name: &[u8; 11], long_name: Option<&str>) -> u32 {
+            let cluster = self.allocate(1)[0];
+
+            self.add_entry(
+                parent,
+                short_name,
+                long_name,
+                attributes::DIRECTORY,
+                cluster,
+                0,
+            );
+            self.add_entry(
+                cluster,
+                b".          ",
+                None,
+                attributes::DIRECTORY,
+                cluster,
+                0,
+            );
+            self.add_entry(
+                cluster,
+                b"..         ",
+                None,
+                attributes::DIRECTORY,
+                parent,
+                0,
+            );
+
+            cluster
+        }
+
+        fn build(self) -> FileSystem<Disk> {
+            FileSystem::new(self.disk, self.start).unwrap()
+        }
+    }
+
+    const KINDS: [FatKind; 3] = [FatKind::Fat12, FatKind::Fat16, FatKind::Fat32];
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    fn read_all(fs: &mut FileSystem<Disk>, path: &str) -> Vec<u8> {
+        let mut file = fs.open(path).unwrap();
+        let mut data = vec![0; file.size() as usize];
+
+        assert_eq!(fs.read(&mut file, &mut data).unwrap(), data.len());
+        assert_eq!(fs.read(&mut file, &mut [0; 16]).unwrap(), 0);
+
+        data
+    }
+
+    #[test]
+    fn detects_kind() {
+        for kind in KINDS {
+            assert_eq!(Builder::new(kind, 0).build().kind(), kind);
+        }
+    }
+
+    #[test]
+    fn rejects_non_fat() {
+        let mut disk = Disk::default();
+        assert_eq!(FileSystem::new(&mut disk, 0).unwrap_err(), Error::NotFat);
+
+        disk.write(510, &[0x55, 0xaa]);
+        assert_eq!(FileSystem::new(&mut disk, 0).unwrap_err(), Error::NotFat);
+    }
+
+    #[test]
+    fn finds_short_names_ignoring_case() {
+        for kind in KINDS {
+            let mut builder = Builder::new(kind, 0);
+            let root = builder.root();
+            builder.file(root, b"README  TXT", None, b"hello");
+            builder.file(root, b"NOEXT      ", None, b"bare");
+
+            let mut fs = builder.build();
+
+            assert_eq!(read_all(&mut fs, "readme.txt"), b"hello", "{kind:?}");
+            assert_eq!(read_all(&mut fs, "/README.TXT"), b"hello", "{kind:?}");
+            assert_eq!(read_all(&mut fs, "NoExt"), b"bare", "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn finds_long_names_in_subdirectories() {
+        for kind in KINDS {
+            let mut builder = Builder::new(kind, 0);
+            let root = builder.root();
+            let boot = builder.dir(root, b"BOOT       ", Some("boot"));
+            let nested = builder.dir(boot, b"MROWSY~1   ", Some("mrow system files"));
+            let data = pattern(3000);
+            builder.file(nested, b"KERNEL~1ELF", Some("The Kernel Image.elf"), &data);
+
+            let mut fs = builder.build();
+
+            assert_eq!(
+                read_all(&mut fs, "/boot/mrow system files/the kernel image.elf"),
+                data,
+                "{kind:?}"
+            );
+            assert_eq!(
+                read_all(&mut fs, "BOOT/MROWSY~1/KERNEL~1.ELF"),
+                data,
+                "{kind:?}"
+            );
+            assert_eq!(
+                read_all(
+                    &mut fs,
+                    "boot/../boot/./mrow system files/The Kernel Image.elf"
+                ),
+                data,
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_long_names_with_wrong_checksum() {
+        let mut builder = Builder::new(FatKind::Fat16, 0);
+        builder.file(0, b"KERNEL  ELF", Some("kernel.elf"), b"one");
+
+        // Point a long name at a short name it doesn't belong to.
+        builder.add_entry(
+            0,
+            b"SOMETHINGEL",
+            Some("another file.bin"),
+            attributes::ARCHIVE,
+            0,
+            0,
+        );
+        let short = (builder.slots[&0] - 1) * ENTRY_SIZE;
+        let offset = builder.bpb.root_dir_start() * SECTOR_SIZE as u64 + short as u64;
+        builder.disk.write(offset, b"OTHER   BIN");
+
+        let mut fs = builder.build();
+
+        assert_eq!(read_all(&mut fs, "kernel.elf"), b"one");
+        assert_eq!(fs.open("another file.bin").unwrap_err(), Error::NotFound);
+        assert_eq!(fs.open("OTHER.BIN").unwrap().size(), 0);
+    }
+
+    #[test]
+    fn ignores_long_name_entries_with_sequence_zero() {
+        let mut builder = Builder::new(FatKind::Fat16, 0);
+        let checksum = short_name_checksum(b"KERNEL  ELF");
+
+        // A complete single part name, then a stray part numbered zero.
+        for sequence in [0x41, 0x20] {
+            let mut raw = [0; ENTRY_SIZE];
+            raw[0] = sequence;
+            raw[11] = attributes::LONG_NAME;
+            raw[13] = checksum;
+
+            builder.push_entry(0, raw);
+        }
+
+        builder.file(0, b"KERNEL  ELF", None, b"one");
+
+        let mut fs = builder.build();
+
+        assert_eq!(read_all(&mut fs, "kernel.elf"), b"one");
+    }
+
+    #[test]
+    fn follows_fragmented_chains() {
+        for kind in KINDS {
+            let mut builder = Builder::new(kind, 0);
+            let root = builder.root();
+            // Long enough for FAT12 entries to straddle a sector boundary.
+            let data = pattern(400 * SECTOR_SIZE + 123);
+            builder.file(root, b"BIG     BIN", None, &data);
+
+            let mut fs = builder.build();
+
+            assert_eq!(read_all(&mut fs, "big.bin"), data, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn reads_in_uneven_chunks() {
+        let mut builder = Builder::new(FatKind::Fat12, 0);
+        let data = pattern(5 * SECTOR_SIZE + 17);
+        builder.file(0, b"CHUNKS  BIN", None, &data);
+
+        let mut fs = builder.build();
+        let mut file = fs.open("chunks.bin").unwrap();
+        let mut read = Vec::new();
+        let mut chunk = [0; 100];
+
+        loop {
+            let len = fs.read(&mut file, &mut chunk).unwrap();
+
+            if len == 0 {
+                break;
+            }
+
+            read.extend_from_slice(&chunk[..len]);
+        }
+
+        assert_eq!(read, data);
+        assert_eq!(file.position(), data.len() as u32);
+    }
+
+    #[test]
+    fn reads_whole_sectors_directly() {
+        let mut builder = Builder::new(FatKind::Fat32, 0);
+        let data = pattern(8 * SECTOR_SIZE);
+        builder.file(2, b"ALIGNED BIN", None, &data);
+
+        let mut fs = builder.build();
+        let mut file = fs.open("aligned.bin").unwrap();
+        let mut read = vec![0; data.len()];
+        let before = fs.device.reads;
+
+        fs.read(&mut file, &mut read).unwrap();
+
+        assert_eq!(read, data);
+        // One read per sector plus one per FAT sector, which all fit in one.
+        assert!(
+            fs.device.reads - before <= 8 + 8,
+            "{}",
+            fs.device.reads - before
+        );
+    }
+
+    #[test]
+    fn reads_whole_clusters_at_once() {
+        for kind in KINDS {
+            let mut builder = Builder::with_cluster_size(kind, 0, 4);
+            let root = builder.root();
+            let data = pattern(8 * 4 * SECTOR_SIZE + 100);
+            builder.file(root, b"CLUSTERSBIN", None, &data);
+
+            let mut fs = builder.build();
+            let mut file = fs.open("clusters.bin").unwrap();
+            let mut read = vec![0; data.len()];
+
+            assert_eq!(fs.read(&mut file, &mut read).unwrap(), data.len());
+            assert_eq!(read, data, "{kind:?}");
+            // One batch per cluster, the partial sector at the end goes through the cache.
+            assert_eq!(fs.device.batches, 8, "{kind:?}");
+
+            // Starting in the middle of a cluster only reads the rest of it at once.
+            let mut file = fs.open("clusters.bin").unwrap();
+            let mut read = vec![0; data.len()];
+
+            assert_eq!(
+                fs.read(&mut file, &mut read[..SECTOR_SIZE + 1]).unwrap(),
+                SECTOR_SIZE + 1
+            );
+            assert_eq!(
+                fs.read(&mut file, &mut read[SECTOR_SIZE + 1..]).unwrap(),
+                data.len() - SECTOR_SIZE - 1
+            );
+            assert_eq!(read, data, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn handles_partition_offset() {
+        for kind in KINDS {
+            let mut builder = Builder::new(kind, 2048);
+            let root = builder.root();
+            builder.file(root, b"KERNEL     ", Some("kernel"), b"offset");
+
+            let mut fs = builder.build();
+
+            assert_eq!(read_all(&mut fs, "kernel"), b"offset", "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn reports_lookup_errors() {
+        for kind in KINDS {
+            let mut builder = Builder::new(kind, 0);
+            let root = builder.root();
+            let boot = builder.dir(root, b"BOOT       ", None);
+            builder.file(boot, b"KERNEL     ", None, b"kernel");
+            builder.file(root, b"EMPTY      ", None, b"");
+
+            let mut fs = builder.build();
+
+            assert_eq!(fs.open("missing").unwrap_err(), Error::NotFound);
+            assert_eq!(fs.open("boot/missing").unwrap_err(), Error::NotFound);
+            assert_eq!(fs.open("boot").unwrap_err(), Error::IsADirectory);
+            assert_eq!(fs.open("/").unwrap_err(), Error::IsADirectory);
+            assert_eq!(fs.open("boot/kernel/x").unwrap_err(), Error::NotADirectory);
+            assert_eq!(fs.find("").unwrap(), None);
+            assert!(fs.find("boot").unwrap().unwrap().is_dir());
+            assert_eq!(read_all(&mut fs, "empty"), b"");
+        }
+    }
+
+    #[test]
+    fn detects_broken_chains() {
+        let mut builder = Builder::new(FatKind::Fat16, 0);
+        let data = pattern(3 * SECTOR_SIZE);
+        builder.file(0, b"BROKEN  BIN", None, &data);
+        // Cut the chain short after the first cluster.
+        builder.set_fat(2, 0xffff);
+
+        let mut fs = builder.build();
+        let mut file = fs.open("broken.bin").unwrap();
+        let mut read = vec![0; data.len()];
+
+        assert_eq!(
+            fs.read(&mut file, &mut read).unwrap_err(),
+            Error::BadCluster
+        );
+    }
+
+    #[test]
+    fn checksums_short_names() {
+        assert_eq!(short_name_checksum(b"KERNEL  ELF"), 0x95);
+        assert_eq!(short_name_checksum(b"README  TXT"), 0x73);
+    }
+}
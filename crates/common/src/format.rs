@@ -0,0 +1,307 @@
+//! Formatting for when `core::fmt` is too big.
+//!
+//! There are no format strings, [`format_to!`](crate::format_to) writes its
+//! arguments one after the other, and each of them says how it wants to be
+//! formatted. Plain strings and integers format as they are, [`Int`] and [`Pad`]
+//! cover hexadecimal and padding, and [`Fmt`] falls back to `core::fmt` where
+//! size doesn't matter.
+
+use core::{ffi::CStr, fmt};
+
+/// Where formatted bytes go.
+pub trait Sink {
+    /// Writes `bytes`, which are usually but not always UTF-8.
+    fn write_bytes(&mut self, bytes: &[u8]);
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        (**self).write_bytes(bytes);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Sink for alloc::vec::Vec<u8> {
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Something that can be written to a [`Sink`].
+pub trait Format {
+    /// Writes `self` to `sink`.
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S);
+}
+
+impl<T: Format + ?Sized> Format for &T {
+    #[inline]
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        (**self).format(sink);
+    }
+}
+
+impl Format for str {
+    #[inline]
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        sink.write_bytes(self.as_bytes());
+    }
+}
+
+impl Format for [u8] {
+    #[inline]
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        sink.write_bytes(self);
+    }
+}
+
+impl Format for CStr {
+    #[inline]
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        sink.write_bytes(self.to_bytes());
+    }
+}
+
+impl Format for char {
+    #[inline]
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        self.encode_utf8(&mut [0; 4]).format(sink);
+    }
+}
+
+/// An integer, in decimal or hexadecimal and optionally padded.
+///
+/// Plain integers format as [`Int::dec`], use this for anything else. Everything
+/// goes through `u64`, so there's only one copy of the digit loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Int {
+    value: u64,
+    negative: bool,
+    radix: u8,
+    width: u8,
+    fill: u8,
+}
+
+impl Int {
+    /// Formats `value` in decimal.
+    #[inline]
+    #[must_use]
+    pub const fn dec(value: u64) -> Self {
+        Self {
+            value,
+            negative: false,
+            radix: 10,
+            width: 0,
+            fill: b' ',
+        }
+    }
+
+    /// Formats `value` in decimal, with a minus sign if it's negative.
+    #[inline]
+    #[must_use]
+    pub const fn signed(value: i64) -> Self {
+        Self {
+            negative: value < 0,
+            ..Self::dec(value.unsigned_abs())
+        }
+    }
+
+    /// Formats `value` in lowercase hexadecimal, without a prefix.
+    #[inline]
+    #[must_use]
+    pub const fn hex(value: u64) -> Self {
+        Self {
+            radix: 16,
+            ..Self::dec(value)
+        }
+    }
+
+    /// Pads the number on the left to at least `width` characters.
+    #[inline]
+    #[must_use]
+    pub const fn width(self, width: u8) -> Self {
+        Self { width, ..self }
+    }
+
+    /// Pads with zeroes rather than spaces, after the sign.
+    #[inline]
+    #[must_use]
+    pub const fn zeroes(self) -> Self {
+        Self { fill: b'0', ..self }
+    }
+}
+
+impl Format for Int {
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+        // Enough for `u64::MAX` in decimal.
+        let mut digits = [0; 20];
+        let mut start = digits.len();
+        let mut value = self.value;
+
+        loop {
+            start -= 1;
+            digits[start] = DIGITS[(value % self.radix as u64) as usize];
+            value /= self.radix as u64;
+
+            if value == 0 {
+                break;
+            }
+        }
+
+        let len = digits.len() - start + self.negative as usize;
+        let sign = if self.negative { &b"-"[..] } else { &[] };
+
+        // Spaces go before the sign, zeroes after it.
+        if self.fill == b'0' {
+            sink.write_bytes(sign);
+        }
+
+        for _ in len..self.width as usize {
+            sink.write_bytes(&[self.fill]);
+        }
+
+        if self.fill != b'0' {
+            sink.write_bytes(sign);
+        }
+
+        sink.write_bytes(&digits[start..]);
+    }
+}
+
+macro_rules! format_int {
+    ($($ty:ty => $int:ident),* $(,)?) => {
+        $(
+            impl Format for $ty {
+                #[inline]
+                fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+                    Int::$int(*self as _).format(sink);
+                }
+            }
+        )*
+    };
+}
+
+format_int!(
+    u8 => dec,
+    u16 => dec,
+    u32 => dec,
+    u64 => dec,
+    usize => dec,
+    i8 => signed,
+    i16 => signed,
+    i32 => signed,
+    i64 => signed,
+    isize => signed,
+);
+
+/// A string padded on the right to at least `width` characters, for columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pad<'a> {
+    s: &'a str,
+    width: u8,
+}
+
+impl<'a> Pad<'a> {
+    /// Pads `s` to `width` characters.
+    #[inline]
+    #[must_use]
+    pub const fn new(s: &'a str, width: u8) -> Self {
+        Self { s, width }
+    }
+}
+
+impl Format for Pad<'_> {
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        self.s.format(sink);
+
+        for _ in self.s.chars().count()..self.width as usize {
+            sink.write_bytes(b" ");
+        }
+    }
+}
+
+/// Anything [`Display`](fmt::Display), formatted through `core::fmt`.
+///
+/// This pulls in the formatting machinery, so the BIOS stages should stay away
+/// from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fmt<T>(pub T);
+
+impl<T: fmt::Display> Format for Fmt<T> {
+    fn format<S: Sink + ?Sized>(&self, sink: &mut S) {
+        struct Adapter<'a, S: ?Sized>(&'a mut S);
+
+        impl<S: Sink + ?Sized> fmt::Write for Adapter<'_, S> {
+            #[inline]
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.0.write_bytes(s.as_bytes());
+                Ok(())
+            }
+        }
+
+        // Sinks can't fail, only a broken `Display` impl can.
+        let _ = fmt::write(&mut Adapter(sink), format_args!("{}", self.0));
+    }
+}
+
+/// Writes each argument to a [`Sink`](crate::format::Sink) in turn.
+///
+/// Arguments can be anything that implements [`Format`](crate::format::Format).
+#[macro_export]
+macro_rules! format_to {
+    ($sink:expr $(, $arg:expr)* $(,)?) => {{
+        let sink = &mut $sink;
+        $( $crate::format::Format::format(&$arg, sink); )*
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn format(value: impl Format) -> Vec<u8> {
+        let mut out = Vec::new();
+        value.format(&mut out);
+
+        out
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(format(0_u8), b"0");
+        assert_eq!(format(u64::MAX), b"18446744073709551615");
+        assert_eq!(format(-42_i32), b"-42");
+        assert_eq!(format(i64::MIN), b"-9223372036854775808");
+        assert_eq!(format(Int::hex(0xdead_beef)), b"deadbeef");
+        assert_eq!(format(Int::hex(0)), b"0");
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(format(Int::dec(42).width(5)), b"   42");
+        assert_eq!(format(Int::hex(0x7c00).width(8).zeroes()), b"00007c00");
+        assert_eq!(format(Int::signed(-7).width(4)), b"  -7");
+        assert_eq!(format(Int::signed(-7).width(4).zeroes()), b"-007");
+        assert_eq!(format(Int::dec(123_456).width(3)), b"123456");
+        assert_eq!(format(Pad::new("äb", 4)), "äb  ".as_bytes());
+        assert_eq!(format(Pad::new("long", 2)), b"long");
+    }
+
+    #[test]
+    fn macro_writes_in_order() {
+        let mut out = Vec::new();
+        format_to!(out, "at ", c"0x", Int::hex(0x10), ':', 3_usize, &b"!"[..]);
+
+        assert_eq!(out, b"at 0x10:3!");
+    }
+
+    #[test]
+    fn falls_back_to_fmt() {
+        assert_eq!(format(Fmt(1.5)), b"1.5");
+        assert_eq!(format(Fmt(format_args!("{:>4}", "ab"))), b"  ab");
+    }
+}
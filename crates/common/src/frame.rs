@@ -0,0 +1,455 @@
+//! Handing out physical memory in 4 KiB frames.
+//!
+//! [`FrameAllocator`] keeps two bits per frame in a bitmap the caller provides,
+//! so it works before there's a heap. It's seeded from the memory map the
+//! loader hands over, where everything the loader still owns is already marked
+//! as [`MemoryKind::Loader`].
+
+use core::ops::Range;
+
+use crate::memory_map::{MemoryKind, MemoryRegion};
+
+/// The size of a frame in bytes.
+pub const FRAME_SIZE: u64 = 0x1000;
+
+/// Memory below this is never handed out.
+///
+/// The BIOS data area, the EBDA and option ROMs all live down there, and
+/// firmware is known to report parts of it as usable.
+pub const LOW_MEMORY_END: u64 = 0x10_0000;
+
+/// How many frames one word of the bitmap covers.
+const WORD_FRAMES: usize = u64::BITS as usize;
+
+/// How much of the memory an allocator manages is still free, in frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Stats {
+    /// Every frame the allocator manages, free or not.
+    pub total: usize,
+    /// Frames that can still be allocated.
+    pub free: usize,
+}
+
+impl Stats {
+    /// Returns the amount of allocated frames.
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> usize {
+        self.total - self.free
+    }
+}
+
+/// A bitmap allocator for physical frames.
+///
+/// A set bit means the frame is in use, or was never usable to begin with.
+/// A second bitmap tells the two apart, so frames that were never usable can't
+/// be freed into the allocator.
+#[derive(Debug)]
+pub struct FrameAllocator<'a> {
+    bitmap: &'a mut [u64],
+    /// Which frames were usable to begin with, with a set bit for each.
+    managed: &'a mut [u64],
+    total: usize,
+    free: usize,
+    /// The word [`FrameAllocator::allocate`] starts looking in.
+    next: usize,
+}
+
+impl<'a> FrameAllocator<'a> {
+    /// Returns how many words of bitmap are needed to cover all usable memory
+    /// in `memory_map`, two bits per frame.
+    #[must_use]
+    pub fn bitmap_len(memory_map: &[MemoryRegion]) -> usize {
+        let end = memory_map
+            .iter()
+            .filter(|region| region.kind == MemoryKind::Usable)
+            .map(|region| region.end / FRAME_SIZE)
+            .max()
+            .unwrap_or(0);
+
+        // Half for the frames in use, half for the frames that are managed.
+        (end as usize).div_ceil(WORD_FRAMES) * 2
+    }
+
+    /// Creates an allocator handing out the usable memory in `memory_map`, minus
+    /// anything below [`LOW_MEMORY_END`] or in `reserved`.
+    ///
+    /// Only whole frames are handed out, and memory past what `bitmap` covers
+    /// is ignored, see [`FrameAllocator::bitmap_len`]. `reserved` is for whatever
+    /// the caller is still using that the memory map doesn't know about, like the
+    /// bitmap itself.
+    #[must_use]
+    pub fn new(
+        bitmap: &'a mut [u64],
+        memory_map: &[MemoryRegion],
+        reserved: &[Range<u64>],
+    ) -> Self {
+        let (bitmap, managed) = bitmap.split_at_mut(bitmap.len() / 2);
+
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = Self {
+            bitmap,
+            managed,
+            total: 0,
+            free: 0,
+            next: 0,
+        };
+
+        let usable = memory_map
+            .iter()
+            .filter(|region| region.kind == MemoryKind::Usable);
+
+        for region in usable {
+            let start = region.start.max(LOW_MEMORY_END).div_ceil(FRAME_SIZE);
+            let end = region.end / FRAME_SIZE;
+
+            allocator.set(allocator.clamp(start, end), false);
+        }
+
+        for range in reserved {
+            let start = range.start / FRAME_SIZE;
+            let end = range.end.div_ceil(FRAME_SIZE);
+
+            allocator.set(allocator.clamp(start, end), true);
+        }
+
+        for (managed, used) in allocator.managed.iter_mut().zip(allocator.bitmap.iter()) {
+            *managed = !used;
+        }
+
+        allocator.free = allocator.count_free();
+        allocator.total = allocator.free;
+
+        allocator
+    }
+
+    /// Returns how many frames there are and how many of them are free.
+    #[inline]
+    #[must_use]
+    pub const fn stats(&self) -> Stats {
+        Stats {
+            total: self.total,
+            free: self.free,
+        }
+    }
+
+    /// Returns whether the frame at `address` can be allocated.
+    #[must_use]
+    pub fn is_free(&self, address: u64) -> bool {
+        let frame = (address / FRAME_SIZE) as usize;
+
+        (frame < self.frames()) && !self.is_used(frame)
+    }
+
+    /// Allocates a single frame and returns its address.
+    #[must_use]
+    pub fn allocate(&mut self) -> Option<u64> {
+        if self.free == 0 {
+            return None;
+        }
+
+        let words = self.bitmap.len();
+        let word = (self.next..words)
+            .chain(0..self.next)
+            .find(|&word| self.bitmap[word] != u64::MAX)?;
+
+        let frame = word * WORD_FRAMES + self.bitmap[word].trailing_ones() as usize;
+
+        self.set(frame..frame + 1, true);
+        self.free -= 1;
+        self.next = word;
+
+        Some(frame as u64 * FRAME_SIZE)
+    }
+
+    /// Allocates `count` consecutive frames starting on an `align` boundary and
+    /// ending at or below `limit`, and returns the address of the first one.
+    ///
+    /// This is for devices doing DMA, which need physically contiguous buffers
+    /// and often can't reach all of memory. `align` must be a power of two, and
+    /// anything below [`FRAME_SIZE`] means frame aligned.
+    #[must_use]
+    pub fn allocate_contiguous(&mut self, count: usize, align: u64, limit: u64) -> Option<u64> {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+
+        if (count == 0) | (count > self.free) {
+            return None;
+        }
+
+        let step = (align / FRAME_SIZE).max(1) as usize;
+        let end = self.frames().min((limit / FRAME_SIZE) as usize);
+        let mut start = 0;
+
+        while start + count <= end {
+            // Look from the back, so a used frame lets us skip as far as possible.
+            match (start..start + count)
+                .rev()
+                .find(|&frame| self.is_used(frame))
+            {
+                Some(used) => start = (used + 1).next_multiple_of(step),
+                None => {
+                    self.set(start..start + count, true);
+                    self.free -= count;
+
+                    return Some(start as u64 * FRAME_SIZE);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Frees the frame at `address`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frame isn't allocated, as freeing it twice means somebody
+    /// else may be using it by now.
+    pub fn free(&mut self, address: u64) {
+        self.free_contiguous(address, 1);
+    }
+
+    /// Frees `count` consecutive frames starting at `address`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `address` isn't frame aligned or any of the frames isn't
+    /// allocated, including frames that were never usable.
+    pub fn free_contiguous(&mut self, address: u64, count: usize) {
+        assert!(
+            address.is_multiple_of(FRAME_SIZE),
+            "frame address is misaligned"
+        );
+
+        let start = (address / FRAME_SIZE) as usize;
+        let frames = start..start + count;
+
+        assert!(
+            (frames.end <= self.frames()) && frames.clone().all(|frame| self.is_managed(frame)),
+            "freeing frames that were never usable"
+        );
+        assert!(
+            frames.clone().all(|frame| self.is_used(frame)),
+            "freeing frames that aren't allocated"
+        );
+
+        self.set(frames, false);
+        self.free += count;
+
+        assert!(self.free <= self.total, "more frames free than there are");
+
+        self.next = self.next.min(start / WORD_FRAMES);
+    }
+
+    /// Returns how many frames the bitmap covers.
+    #[inline]
+    fn frames(&self) -> usize {
+        self.bitmap.len() * WORD_FRAMES
+    }
+
+    /// Returns whether `frame` is in use.
+    #[inline]
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / WORD_FRAMES] & (1 << (frame % WORD_FRAMES)) != 0
+    }
+
+    /// Returns whether `frame` was usable to begin with.
+    #[inline]
+    fn is_managed(&self, frame: usize) -> bool {
+        self.managed[frame / WORD_FRAMES] & (1 << (frame % WORD_FRAMES)) != 0
+    }
+
+    /// Turns the frame numbers `start..end` into a range the bitmap covers.
+    #[inline]
+    fn clamp(&self, start: u64, end: u64) -> Range<usize> {
+        let frames = self.frames() as u64;
+
+        start.min(frames) as usize..end.min(frames) as usize
+    }
+
+    /// Marks `frames` as used or free, a whole word at a time where possible.
+    fn set(&mut self, frames: Range<usize>, used: bool) {
+        let mut frame = frames.start;
+
+        while frame < frames.end {
+            let word = frame / WORD_FRAMES;
+            let bit = frame % WORD_FRAMES;
+            let len = (WORD_FRAMES - bit).min(frames.end - frame);
+            let mask = (u64::MAX >> (WORD_FRAMES - len)) << bit;
+
+            if used {
+                self.bitmap[word] |= mask;
+            } else {
+                self.bitmap[word] &= !mask;
+            }
+
+            frame += len;
+        }
+    }
+
+    /// Counts the free frames by going through the whole bitmap.
+    fn count_free(&self) -> usize {
+        self.bitmap
+            .iter()
+            .map(|word| word.count_zeros() as usize)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    const MIB: u64 = 0x10_0000;
+
+    /// A typical map of a machine with 64 MiB of memory, with the loader's
+    /// memory in the middle of it.
+    fn memory_map() -> Vec<MemoryRegion> {
+        vec![
+            MemoryRegion::new(0, 0x9_fc00, MemoryKind::Usable),
+            MemoryRegion::new(0x9_fc00, 0xa_0000, MemoryKind::Reserved),
+            MemoryRegion::new(0xf_0000, MIB, MemoryKind::Reserved),
+            MemoryRegion::new(MIB, 2 * MIB, MemoryKind::Usable),
+            MemoryRegion::new(2 * MIB, 3 * MIB, MemoryKind::Loader),
+            MemoryRegion::new(3 * MIB, 0x3ff_0000, MemoryKind::Usable),
+            MemoryRegion::new(0x3ff_0000, 64 * MIB, MemoryKind::AcpiReclaimable),
+        ]
+    }
+
+    fn frames(bytes: u64) -> usize {
+        (bytes / FRAME_SIZE) as usize
+    }
+
+    #[test]
+    fn seeds_from_memory_map() {
+        let map = memory_map();
+        let mut bitmap = vec![0; FrameAllocator::bitmap_len(&map)];
+
+        assert_eq!(bitmap.len(), frames(0x3ff_0000).div_ceil(64) * 2);
+
+        let reserved = [0x30_0800..0x30_2000, 2 * MIB..2 * MIB + 0x1000];
+        let allocator = FrameAllocator::new(&mut bitmap, &map, &reserved);
+        let usable = frames(MIB) + frames(0x3ff_0000 - 3 * MIB);
+
+        // The first reserved range isn't frame aligned, so it covers two frames,
+        // and the second one wasn't usable anyway.
+        assert_eq!(
+            allocator.stats(),
+            Stats {
+                total: usable - 2,
+                free: usable - 2
+            }
+        );
+
+        assert!(!allocator.is_free(0x8000));
+        assert!(!allocator.is_free(2 * MIB));
+        assert!(!allocator.is_free(0x30_1000));
+        assert!(!allocator.is_free(64 * MIB));
+        assert!(allocator.is_free(MIB));
+        assert!(allocator.is_free(0x30_2000));
+    }
+
+    #[test]
+    fn ignores_partial_frames() {
+        let map = [MemoryRegion::new(
+            MIB + 0x800,
+            MIB + 0x3800,
+            MemoryKind::Usable,
+        )];
+        let mut bitmap = vec![0; FrameAllocator::bitmap_len(&map)];
+        let mut allocator = FrameAllocator::new(&mut bitmap, &map, &[]);
+
+        assert_eq!(allocator.stats().total, 2);
+        assert_eq!(allocator.allocate(), Some(MIB + 0x1000));
+        assert_eq!(allocator.allocate(), Some(MIB + 0x2000));
+        assert_eq!(allocator.allocate(), None);
+    }
+
+    #[test]
+    fn allocates_and_frees() {
+        let map = memory_map();
+        let mut bitmap = vec![0; FrameAllocator::bitmap_len(&map)];
+        let mut allocator = FrameAllocator::new(&mut bitmap, &map, &[]);
+        let total = allocator.stats().total;
+
+        let mut allocated = Vec::new();
+
+        while let Some(frame) = allocator.allocate() {
+            assert!(frame >= LOW_MEMORY_END);
+            assert!(!(2 * MIB..3 * MIB).contains(&frame));
+            allocated.push(frame);
+        }
+
+        assert_eq!(allocated.len(), total);
+        assert_eq!(allocator.stats().used(), total);
+
+        allocated.sort_unstable();
+        allocated.dedup();
+        assert_eq!(allocated.len(), total);
+
+        allocator.free(0x50_0000);
+        assert_eq!(allocator.stats().free, 1);
+        assert_eq!(allocator.allocate(), Some(0x50_0000));
+    }
+
+    #[test]
+    fn allocates_contiguous() {
+        let map = memory_map();
+        let mut bitmap = vec![0; FrameAllocator::bitmap_len(&map)];
+        let mut allocator = FrameAllocator::new(&mut bitmap, &map, &[]);
+
+        // Break up the start of memory a bit.
+        assert_eq!(allocator.allocate(), Some(MIB));
+
+        // Doesn't fit below the loader's memory, and must skip the used frame.
+        assert_eq!(
+            allocator.allocate_contiguous(512, FRAME_SIZE, u64::MAX),
+            Some(3 * MIB)
+        );
+        assert_eq!(
+            allocator.allocate_contiguous(4, 0x10000, u64::MAX),
+            Some(0x11_0000)
+        );
+
+        // What's left below 2 MiB is 15 frames before the aligned block and 236 after it.
+        assert_eq!(
+            allocator.allocate_contiguous(236, FRAME_SIZE, 2 * MIB),
+            Some(0x11_4000)
+        );
+        assert_eq!(allocator.allocate_contiguous(16, FRAME_SIZE, 2 * MIB), None);
+        assert_eq!(
+            allocator.allocate_contiguous(15, FRAME_SIZE, 2 * MIB),
+            Some(0x10_1000)
+        );
+        assert_eq!(allocator.allocate_contiguous(1, FRAME_SIZE, 2 * MIB), None);
+        assert_eq!(allocator.allocate_contiguous(0, FRAME_SIZE, u64::MAX), None);
+
+        allocator.free_contiguous(3 * MIB, 512);
+        assert!(allocator.is_free(3 * MIB + 0x1000 * 511));
+    }
+
+    #[test]
+    #[should_panic = "freeing frames that aren't allocated"]
+    fn rejects_double_free() {
+        let map = memory_map();
+        let mut bitmap = vec![0; FrameAllocator::bitmap_len(&map)];
+        let mut allocator = FrameAllocator::new(&mut bitmap, &map, &[]);
+
+        let frame = allocator.allocate().unwrap();
+        allocator.free(frame);
+        allocator.free(frame);
+    }
+
+    #[test]
+    #[should_panic = "freeing frames that were never usable"]
+    fn rejects_freeing_reserved_frames() {
+        let map = memory_map();
+        let mut bitmap = vec![0; FrameAllocator::bitmap_len(&map)];
+        let mut allocator = FrameAllocator::new(&mut bitmap, &map, &[]);
+
+        // Reserved by the firmware, so its bit is set without being allocated.
+        allocator.free(0xf_0000);
+    }
+}
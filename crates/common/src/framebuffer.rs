@@ -0,0 +1,87 @@
+/// Where a color component sits within a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct ColorMask {
+    /// The lowest bit of the component.
+    pub position: u8,
+    /// The amount of bits in the component.
+    pub size: u8,
+}
+
+impl ColorMask {
+    /// Creates a mask of `size` bits starting at `position`.
+    #[inline]
+    #[must_use]
+    pub const fn new(position: u8, size: u8) -> Self {
+        Self { position, size }
+    }
+
+    /// Returns the mask as bits of a pixel.
+    #[inline]
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        if self.size >= 32 {
+            return u32::MAX << self.position;
+        }
+
+        ((1 << self.size) - 1) << self.position
+    }
+}
+
+/// A linear framebuffer set up by the loader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct Framebuffer {
+    /// Physical address of the first pixel, zero if there is no framebuffer.
+    pub address: u64,
+    /// The size of the framebuffer in bytes.
+    pub size: u64,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// The distance between the start of two lines in bytes.
+    pub pitch: u32,
+    /// Bits per pixel.
+    pub bpp: u8,
+    /// The red component of a pixel.
+    pub red: ColorMask,
+    /// The green component of a pixel.
+    pub green: ColorMask,
+    /// The blue component of a pixel.
+    pub blue: ColorMask,
+    /// Reserved, must be zero.
+    pub reserved: [u8; 5],
+}
+
+impl Framebuffer {
+    /// No framebuffer at all, we're still in text mode.
+    pub const NONE: Self = Self {
+        address: 0,
+        size: 0,
+        width: 0,
+        height: 0,
+        pitch: 0,
+        bpp: 0,
+        red: ColorMask::new(0, 0),
+        green: ColorMask::new(0, 0),
+        blue: ColorMask::new(0, 0),
+        reserved: [0; 5],
+    };
+
+    /// Returns whether there is a framebuffer.
+    #[inline]
+    #[must_use]
+    pub const fn is_present(&self) -> bool {
+        self.address != 0
+    }
+
+    /// Returns the amount of bytes per pixel, rounding up.
+    #[inline]
+    #[must_use]
+    pub const fn bytes_per_pixel(&self) -> u32 {
+        (self.bpp as u32).div_ceil(8)
+    }
+}
@@ -0,0 +1,240 @@
+use core::{
+    mem::size_of_val,
+    ptr::{addr_of, addr_of_mut},
+};
+
+/// A segment descriptor in the global descriptor table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(transparent)]
+pub struct Descriptor(pub u64);
+
+impl Descriptor {
+    /// The mandatory null descriptor.
+    pub const NULL: Self = Self(0);
+
+    /// A flat 4 GiB 32-bit code segment.
+    pub const CODE_32: Self = Self::flat(access::CODE, flags::GRANULARITY | flags::SIZE_32);
+    /// A flat 4 GiB 32-bit data segment.
+    pub const DATA_32: Self = Self::flat(access::DATA, flags::GRANULARITY | flags::SIZE_32);
+    /// A flat 64 KiB 16-bit code segment.
+    pub const CODE_16: Self = Self::new(0, 0xffff, access::CODE, 0);
+    /// A flat 64 KiB 16-bit data segment.
+    pub const DATA_16: Self = Self::new(0, 0xffff, access::DATA, 0);
+    /// A flat 4 GiB data segment that keeps pushes and pops on `sp` rather than `esp`.
+    ///
+    /// This is what the stack segment needs in unreal mode, where the BIOS still
+    /// expects a 16-bit stack.
+    pub const DATA_16_FLAT: Self = Self::flat(access::DATA, flags::GRANULARITY);
+    /// A 64-bit code segment, base and limit are ignored in long mode.
+    pub const CODE_64: Self = Self::flat(access::CODE, flags::GRANULARITY | flags::LONG);
+    /// A 64-bit data segment, base and limit are ignored in long mode.
+    pub const DATA_64: Self = Self::flat(access::DATA, flags::GRANULARITY);
+
+    /// Creates a descriptor from its parts.
+    ///
+    /// Only the lower 20 bits of `limit` and the lower 4 bits of `flags` are used.
+    #[inline]
+    #[must_use]
+    pub const fn new(base: u32, limit: u32, access: u8, flags: u8) -> Self {
+        let base = base as u64;
+        let limit = limit as u64;
+
+        Self(
+            (limit & 0xffff)
+                | ((base & 0xff_ffff) << 16)
+                | ((access as u64) << 40)
+                | (((limit >> 16) & 0xf) << 48)
+                | (((flags as u64) & 0xf) << 52)
+                | (((base >> 24) & 0xff) << 56),
+        )
+    }
+
+    /// Creates a descriptor spanning the entire 4 GiB address space.
+    #[inline]
+    #[must_use]
+    pub const fn flat(access: u8, flags: u8) -> Self {
+        Self::new(0, 0xf_ffff, access, flags)
+    }
+
+    /// Creates the two descriptors a 64-bit [`TaskStateSegment`] at `base` takes up.
+    #[inline]
+    #[must_use]
+    pub const fn tss(base: u64) -> [Self; 2] {
+        [
+            Self::new(
+                base as u32,
+                size_of::<TaskStateSegment>() as u32 - 1,
+                access::PRESENT | access::TSS,
+                0,
+            ),
+            Self(base >> 32),
+        ]
+    }
+}
+
+/// Bits of the access byte of a [`Descriptor`].
+pub mod access {
+    /// The segment has been accessed, set by the CPU.
+    pub const ACCESSED: u8 = 1 << 0;
+    /// Code segments are readable, data segments are writable.
+    pub const READ_WRITE: u8 = 1 << 1;
+    /// The segment is executable.
+    pub const EXECUTABLE: u8 = 1 << 3;
+    /// The segment is a code or data segment rather than a system segment.
+    pub const CODE_DATA: u8 = 1 << 4;
+    /// The segment is accessible from ring 3.
+    pub const RING_3: u8 = 3 << 5;
+    /// The segment is present.
+    pub const PRESENT: u8 = 1 << 7;
+
+    /// The system segment type of an available 64-bit task state segment.
+    pub const TSS: u8 = 0x9;
+
+    /// A present, readable ring 0 code segment.
+    pub const CODE: u8 = PRESENT | CODE_DATA | EXECUTABLE | READ_WRITE;
+    /// A present, writable ring 0 data segment.
+    pub const DATA: u8 = PRESENT | CODE_DATA | READ_WRITE;
+}
+
+/// Bits of the flags nibble of a [`Descriptor`].
+pub mod flags {
+    /// The segment is a 64-bit code segment.
+    pub const LONG: u8 = 1 << 1;
+    /// The segment is a 32-bit segment rather than a 16-bit one.
+    pub const SIZE_32: u8 = 1 << 2;
+    /// The limit is in 4 KiB pages rather than bytes.
+    pub const GRANULARITY: u8 = 1 << 3;
+}
+
+/// The 64-bit task state segment, which only holds stacks in long mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    reserved_1: u32,
+    /// The stacks for switching to rings 0 to 2.
+    pub privilege_stacks: [u64; 3],
+    reserved_2: u64,
+    /// The interrupt stack table, the stacks interrupt gates can ask for.
+    ///
+    /// Gates number them from 1, so the first one is stack 1.
+    pub interrupt_stacks: [u64; 7],
+    reserved_3: u64,
+    reserved_4: u16,
+    /// The offset of the I/O permission bitmap, past the end for none.
+    pub io_map_base: u16,
+}
+
+impl TaskStateSegment {
+    /// Creates a task state segment without any stacks or I/O permissions.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            reserved_1: 0,
+            privilege_stacks: [0; 3],
+            reserved_2: 0,
+            interrupt_stacks: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
+            io_map_base: size_of::<Self>() as u16,
+        }
+    }
+}
+
+/// The operand of `lgdt` outside of long mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C, packed)]
+pub struct Pointer32 {
+    /// Size of the table in bytes minus one.
+    pub limit: u16,
+    /// Linear address of the table.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`Pointer32::base`] and [`Pointer32::set_base`].
+    pub base: u32,
+}
+
+impl Pointer32 {
+    /// Creates a pointer to a table of descriptors.
+    ///
+    /// The address is truncated to 32 bits, so the table must live below 4 GiB.
+    #[inline]
+    #[must_use]
+    pub fn new(table: &[Descriptor]) -> Self {
+        Self {
+            limit: (size_of_val(table) - 1) as u16,
+            base: table.as_ptr() as usize as u32,
+        }
+    }
+
+    /// Reads the linear address of the table.
+    #[inline]
+    #[must_use]
+    pub const fn base(&self) -> u32 {
+        unsafe { addr_of!(self.base).read_unaligned() }
+    }
+
+    /// Sets a new value to the linear address of the table.
+    #[inline]
+    pub fn set_base(&mut self, base: u32) {
+        unsafe { addr_of_mut!(self.base).write_unaligned(base) }
+    }
+}
+
+/// The operand of `lgdt` in long mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C, packed)]
+pub struct Pointer64 {
+    /// Size of the table in bytes minus one.
+    pub limit: u16,
+    /// Linear address of the table.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`Pointer64::base`] and [`Pointer64::set_base`].
+    pub base: u64,
+}
+
+impl Pointer64 {
+    /// Creates a pointer to a table of descriptors.
+    #[inline]
+    #[must_use]
+    pub fn new(table: &[Descriptor]) -> Self {
+        Self {
+            limit: (size_of_val(table) - 1) as u16,
+            base: table.as_ptr() as usize as u64,
+        }
+    }
+
+    /// Reads the linear address of the table.
+    #[inline]
+    #[must_use]
+    pub const fn base(&self) -> u64 {
+        unsafe { addr_of!(self.base).read_unaligned() }
+    }
+
+    /// Sets a new value to the linear address of the table.
+    #[inline]
+    pub fn set_base(&mut self, base: u64) {
+        unsafe { addr_of_mut!(self.base).write_unaligned(base) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tss_descriptor() {
+        assert_eq!(size_of::<TaskStateSegment>(), 104);
+
+        let [low, high] = Descriptor::tss(0xffff_ffff_8012_3450);
+
+        assert_eq!(low.0, 0x8000_8912_3450_0067);
+        assert_eq!(high.0, 0xffff_ffff);
+    }
+}
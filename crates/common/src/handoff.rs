@@ -0,0 +1,261 @@
+use core::{ffi::c_char, mem::size_of, slice, str};
+
+use crate::{
+    framebuffer::Framebuffer,
+    mbr::{MasterBootRecord, TableEntry},
+    memory_map::MemoryRegion,
+};
+
+/// The current version of the [`Handoff`] layout.
+///
+/// Bump this whenever the layout of [`Handoff`], [`Services`] or [`Stage3Handoff`]
+/// changes. What the kernel gets is versioned separately, see [`boot_info`](crate::boot_info).
+pub const VERSION: u16 = 5;
+
+/// The virtual address the kernel image is mapped at.
+///
+/// The image starts with a [`StageHeader`](crate::header::StageHeader), and the
+/// entry point follows directly after it.
+pub const KERNEL_BASE: u64 = 0xffff_ffff_8000_0000;
+
+/// The signature of the stage 2 entry point.
+pub type Stage2Entry = unsafe extern "C" fn(handoff: &Handoff) -> !;
+
+/// The signature of the stage 3 entry point.
+pub type Stage3Entry = unsafe extern "C" fn(handoff: &Stage3Handoff) -> !;
+
+/// The signature of the kernel entry point.
+///
+/// Stage 3 calls it in long mode with interrupts disabled, the first 4 GiB
+/// identity mapped and the kernel image mapped at [`KERNEL_BASE`]. The stack is
+/// still the one of the loader, somewhere in the first 64 KiB.
+///
+/// `boot_info` is the physical address of the
+/// [`BootInfo`](crate::boot_info::BootInfo), read it with
+/// [`Reader::from_ptr`](crate::boot_info::Reader::from_ptr).
+#[cfg(target_arch = "x86_64")]
+pub type KernelEntry = unsafe extern "sysv64" fn(boot_info: *const crate::boot_info::BootInfo) -> !;
+
+/// Prints a nul terminated string.
+pub type PrintFn = unsafe extern "C" fn(ptr: *const c_char);
+
+/// Reads `sectors` sectors starting at `lba` from `drive` into `target`.
+///
+/// Returns whether the read succeeded.
+pub type ReadSectorsFn =
+    unsafe extern "C" fn(drive: u8, lba: u32, sectors: u16, target: *mut u8) -> bool;
+
+/// Services stage 1 provides to the next stage.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Services {
+    /// Prints a nul terminated string through the BIOS.
+    pub print: PrintFn,
+    /// Reads sectors through the BIOS extended disk read.
+    ///
+    /// The target must be below 1 MiB.
+    pub read_sectors: ReadSectorsFn,
+}
+
+/// Everything stage 1 hands to stage 2.
+///
+/// The layout of this struct is the ABI between the two stages, so stage 2
+/// must check [`Handoff::is_compatible`] before touching anything else.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Handoff {
+    /// The version of the handoff, must be [`VERSION`].
+    ///
+    /// This and [`Handoff::size`] must always stay at the start of the struct.
+    pub version: u16,
+    /// The size of the handoff in bytes.
+    pub size: u16,
+    /// The BIOS drive number we booted from.
+    pub boot_drive: u8,
+    /// Reserved, must be zero.
+    pub reserved: [u8; 3],
+    /// The partition table entry stage 2 was loaded from.
+    ///
+    /// This points into the master boot record, so copy it before overwriting it.
+    pub partition: *const TableEntry,
+    /// Address of the master boot record.
+    pub mbr: *const MasterBootRecord,
+    /// Services provided by stage 1.
+    pub services: Services,
+}
+
+impl Handoff {
+    /// The size of the handoff in bytes.
+    pub const SIZE: u16 = size_of::<Self>() as u16;
+
+    /// Creates a new handoff of the current version.
+    #[inline]
+    #[must_use]
+    pub const fn new(
+        boot_drive: u8,
+        partition: *const TableEntry,
+        mbr: *const MasterBootRecord,
+        services: Services,
+    ) -> Self {
+        Self {
+            version: VERSION,
+            size: Self::SIZE,
+            boot_drive,
+            reserved: [0; 3],
+            partition,
+            mbr,
+            services,
+        }
+    }
+
+    /// Returns whether this handoff has the version and size we expect.
+    #[inline]
+    #[must_use]
+    pub const fn is_compatible(&self) -> bool {
+        (self.version == VERSION) & (self.size == Self::SIZE)
+    }
+}
+
+/// Everything stage 2 hands to stage 3.
+///
+/// Stage 3 runs in protected mode, so this can't contain anything that
+/// relies on the BIOS. Like [`Handoff`], check [`Stage3Handoff::is_compatible`] first.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Stage3Handoff {
+    /// The version of the handoff, must be [`VERSION`].
+    ///
+    /// This and [`Stage3Handoff::size`] must always stay at the start of the struct.
+    pub version: u16,
+    /// The size of the handoff in bytes.
+    pub size: u16,
+    /// The BIOS drive number we booted from.
+    pub boot_drive: u8,
+    /// The index of the partition the files were loaded from, or [`u8::MAX`] if
+    /// there is none.
+    pub boot_partition: u8,
+    /// Reserved, must be zero.
+    pub reserved: [u8; 2],
+    /// The sanitized memory map.
+    pub memory_map: *const MemoryRegion,
+    /// The amount of regions in the memory map.
+    pub memory_map_len: u32,
+    /// Physical address of the kernel image.
+    pub kernel: *const u8,
+    /// Length of the kernel image in bytes, zero if there is none.
+    pub kernel_len: u32,
+    /// Physical address of the kernel command line, which is UTF-8 but not nul terminated.
+    pub cmdline: *const u8,
+    /// Length of the kernel command line in bytes.
+    pub cmdline_len: u32,
+    /// Physical address of the initial ramdisk.
+    pub initrd: *const u8,
+    /// Length of the initial ramdisk in bytes, zero if there is none.
+    pub initrd_len: u32,
+    /// The framebuffer stage 2 set up, if any.
+    pub framebuffer: Framebuffer,
+}
+
+impl Stage3Handoff {
+    /// The size of the handoff in bytes.
+    pub const SIZE: u16 = size_of::<Self>() as u16;
+
+    /// Creates a new handoff of the current version.
+    #[inline]
+    #[must_use]
+    pub const fn new(
+        boot_drive: u8,
+        boot_partition: Option<u8>,
+        memory_map: &[MemoryRegion],
+        kernel: &[u8],
+        cmdline: &str,
+        initrd: &[u8],
+        framebuffer: Framebuffer,
+    ) -> Self {
+        Self {
+            version: VERSION,
+            size: Self::SIZE,
+            boot_drive,
+            boot_partition: match boot_partition {
+                Some(partition) => partition,
+                None => u8::MAX,
+            },
+            reserved: [0; 2],
+            memory_map: memory_map.as_ptr(),
+            memory_map_len: memory_map.len() as u32,
+            kernel: kernel.as_ptr(),
+            kernel_len: kernel.len() as u32,
+            cmdline: cmdline.as_ptr(),
+            cmdline_len: cmdline.len() as u32,
+            initrd: initrd.as_ptr(),
+            initrd_len: initrd.len() as u32,
+            framebuffer,
+        }
+    }
+
+    /// Returns whether this handoff has the version and size we expect.
+    #[inline]
+    #[must_use]
+    pub const fn is_compatible(&self) -> bool {
+        (self.version == VERSION) & (self.size == Self::SIZE)
+    }
+
+    /// Returns the index of the partition the files were loaded from, if any.
+    #[inline]
+    #[must_use]
+    pub const fn boot_partition(&self) -> Option<u8> {
+        match self.boot_partition {
+            u8::MAX => None,
+            partition => Some(partition),
+        }
+    }
+
+    /// Returns the memory map.
+    ///
+    /// # Safety
+    ///
+    /// The memory map must still be where stage 2 left it.
+    #[inline]
+    #[must_use]
+    pub unsafe fn memory_map<'a>(&self) -> &'a [MemoryRegion] {
+        unsafe { slice::from_raw_parts(self.memory_map, self.memory_map_len as usize) }
+    }
+
+    /// Returns the kernel image, which is empty if stage 2 didn't find one.
+    ///
+    /// # Safety
+    ///
+    /// The kernel must still be where stage 2 left it.
+    #[inline]
+    #[must_use]
+    pub unsafe fn kernel<'a>(&self) -> &'a [u8] {
+        unsafe { slice::from_raw_parts(self.kernel, self.kernel_len as usize) }
+    }
+
+    /// Returns the kernel command line.
+    ///
+    /// # Safety
+    ///
+    /// The command line must still be where stage 2 left it.
+    #[inline]
+    #[must_use]
+    pub unsafe fn cmdline<'a>(&self) -> &'a str {
+        unsafe {
+            str::from_utf8_unchecked(slice::from_raw_parts(
+                self.cmdline,
+                self.cmdline_len as usize,
+            ))
+        }
+    }
+
+    /// Returns the initial ramdisk, which is empty if the boot entry has none.
+    ///
+    /// # Safety
+    ///
+    /// The initial ramdisk must still be where stage 2 left it.
+    #[inline]
+    #[must_use]
+    pub unsafe fn initrd<'a>(&self) -> &'a [u8] {
+        unsafe { slice::from_raw_parts(self.initrd, self.initrd_len as usize) }
+    }
+}
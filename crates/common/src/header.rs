@@ -0,0 +1,104 @@
+use core::mem::size_of;
+
+/// The magic number every stage header starts with, `"MROW"` in little endian.
+pub const MAGIC: u32 = u32::from_le_bytes(*b"MROW");
+
+/// The current version of the [`StageHeader`] layout.
+pub const VERSION: u16 = 1;
+
+/// A small fixed header placed at the very start of a loaded stage.
+///
+/// The stage itself only reserves space for the header, the host tool is
+/// responsible for filling in [`StageHeader::payload_len`] and
+/// [`StageHeader::checksum`] once the stage has been built. The previous stage
+/// then verifies it before transferring control to the code directly after
+/// the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct StageHeader {
+    /// Must always be [`MAGIC`].
+    pub magic: u32,
+    /// The version of the header, must be [`VERSION`].
+    pub version: u16,
+    /// Reserved, must be zero.
+    pub reserved: u16,
+    /// Length in bytes of the payload following the header.
+    pub payload_len: u32,
+    /// The [`checksum`] of the payload.
+    pub checksum: u32,
+}
+
+impl StageHeader {
+    /// The size of the header in bytes.
+    pub const SIZE: usize = size_of::<Self>();
+
+    /// Creates a header with an empty payload length and checksum.
+    ///
+    /// This is what stages should embed, the real values are filled in after building.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            version: VERSION,
+            reserved: 0,
+            payload_len: 0,
+            checksum: 0,
+        }
+    }
+
+    /// Returns whether the magic number and version match what we expect.
+    #[inline]
+    #[must_use]
+    pub const fn is_compatible(&self) -> bool {
+        (self.magic == MAGIC) & (self.version == VERSION)
+    }
+
+    /// Returns whether `payload` matches the length and checksum in this header.
+    #[inline]
+    #[must_use]
+    pub const fn verify(&self, payload: &[u8]) -> bool {
+        (payload.len() == self.payload_len as usize) & (checksum(payload) == self.checksum)
+    }
+
+    /// Fills in the payload length and checksum for a given payload.
+    ///
+    /// Returns `None` if the payload is too large.
+    #[inline]
+    #[must_use]
+    pub const fn seal(mut self, payload: &[u8]) -> Option<Self> {
+        if payload.len() > u32::MAX as usize {
+            return None;
+        }
+
+        self.payload_len = payload.len() as u32;
+        self.checksum = checksum(payload);
+
+        Some(self)
+    }
+}
+
+impl Default for StageHeader {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Computes the checksum of a stage payload.
+///
+/// This is just a wrapping sum of every byte, as it needs to fit into stage 1.
+/// It catches truncated or garbled reads, not deliberate tampering.
+#[inline]
+#[must_use]
+pub const fn checksum(mut bytes: &[u8]) -> u32 {
+    let mut sum = 0_u32;
+
+    while let [byte, rest @ ..] = bytes {
+        sum = sum.wrapping_add(*byte as u32);
+        bytes = rest;
+    }
+
+    sum
+}
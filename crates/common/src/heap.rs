@@ -0,0 +1,344 @@
+//! A heap made of size-class slabs on top of whole pages.
+//!
+//! Small allocations are carved out of pages split into equal objects, one free
+//! list per size class. Anything larger than the biggest class gets pages of
+//! its own. Where the pages come from is up to the [`PageSource`].
+
+use core::{alloc::Layout, ptr::NonNull};
+
+/// The size of the pages a [`PageSource`] hands out.
+pub const PAGE_SIZE: usize = 0x1000;
+
+/// The sizes of the slab objects, each also aligned to its size.
+pub const CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// Where a [`Heap`] gets its memory from.
+///
+/// # Safety
+///
+/// Pages handed out must be writable, aligned to [`PAGE_SIZE`] and not used by
+/// anything else until they are freed.
+pub unsafe trait PageSource {
+    /// Returns `count` consecutive pages, or `None` if there's no memory left.
+    fn allocate_pages(&mut self, count: usize) -> Option<NonNull<u8>>;
+
+    /// Gives back pages returned by [`PageSource::allocate_pages`].
+    ///
+    /// # Safety
+    ///
+    /// `pages` and `count` must be what an earlier allocation returned and asked
+    /// for, and nothing may use the pages anymore.
+    unsafe fn free_pages(&mut self, pages: NonNull<u8>, count: usize);
+}
+
+/// How much memory a [`Heap`] has taken from its source, and how much of it is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Stats {
+    /// Pages split into slab objects, which are never given back.
+    pub slab_pages: usize,
+    /// Pages backing large allocations.
+    pub large_pages: usize,
+    /// Bytes handed out, rounded up to the size class or page.
+    pub allocated: usize,
+}
+
+/// A free slab object, which holds the link to the next one.
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+/// A slab and page allocator.
+#[derive(Debug)]
+pub struct Heap<P> {
+    source: P,
+    free: [Option<NonNull<FreeObject>>; CLASSES.len()],
+    stats: Stats,
+}
+
+// The free lists only point into memory the heap owns.
+unsafe impl<P: Send> Send for Heap<P> {}
+
+impl<P: PageSource> Heap<P> {
+    /// Creates an empty heap, which takes pages from `source` as it needs them.
+    #[inline]
+    #[must_use]
+    pub const fn new(source: P) -> Self {
+        Self {
+            source,
+            free: [None; CLASSES.len()],
+            stats: Stats {
+                slab_pages: 0,
+                large_pages: 0,
+                allocated: 0,
+            },
+        }
+    }
+
+    /// Returns where the heap gets its pages from.
+    #[inline]
+    #[must_use]
+    pub const fn source(&self) -> &P {
+        &self.source
+    }
+
+    /// Returns how much memory is in use.
+    #[inline]
+    #[must_use]
+    pub const fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Allocates memory for `layout`.
+    ///
+    /// Returns `None` if the source is out of pages, or for alignments above
+    /// [`PAGE_SIZE`], which nothing in the kernel needs.
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        match class(layout) {
+            Some(class) => {
+                let object = match self.free[class] {
+                    Some(object) => object,
+                    None => self.grow(class)?,
+                };
+
+                self.free[class] = unsafe { object.as_ref().next };
+                self.stats.allocated += CLASSES[class];
+
+                Some(object.cast())
+            }
+            None if layout.align() <= PAGE_SIZE => {
+                let count = layout.size().div_ceil(PAGE_SIZE);
+                let pages = self.source.allocate_pages(count)?;
+
+                self.stats.large_pages += count;
+                self.stats.allocated += count * PAGE_SIZE;
+
+                Some(pages)
+            }
+            None => None,
+        }
+    }
+
+    /// Frees memory returned by [`Heap::allocate`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this heap with the same `layout`, and
+    /// must not be used anymore.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        match class(layout) {
+            Some(class) => {
+                let object = ptr.cast::<FreeObject>();
+
+                unsafe {
+                    object.write(FreeObject {
+                        next: self.free[class],
+                    });
+                }
+
+                self.free[class] = Some(object);
+                self.stats.allocated -= CLASSES[class];
+            }
+            None => {
+                let count = layout.size().div_ceil(PAGE_SIZE);
+
+                unsafe { self.source.free_pages(ptr, count) };
+
+                self.stats.large_pages -= count;
+                self.stats.allocated -= count * PAGE_SIZE;
+            }
+        }
+    }
+
+    /// Splits a new page into objects of `class` and returns the first one,
+    /// putting the rest on the free list.
+    fn grow(&mut self, class: usize) -> Option<NonNull<FreeObject>> {
+        let page = self.source.allocate_pages(1)?;
+        let size = CLASSES[class];
+
+        self.stats.slab_pages += 1;
+
+        // Link them back to front, so they're handed out in address order.
+        for offset in (size..PAGE_SIZE).step_by(size).rev() {
+            let object = unsafe { page.add(offset) }.cast::<FreeObject>();
+
+            unsafe {
+                object.write(FreeObject {
+                    next: self.free[class],
+                });
+            }
+
+            self.free[class] = Some(object);
+        }
+
+        let first = page.cast::<FreeObject>();
+
+        unsafe {
+            first.write(FreeObject {
+                next: self.free[class],
+            })
+        };
+
+        Some(first)
+    }
+}
+
+/// Returns the index of the smallest size class `layout` fits in, with the
+/// alignment it needs.
+#[inline]
+fn class(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+
+    CLASSES.iter().position(|&class| size <= class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        alloc::{alloc, dealloc},
+        collections::BTreeMap,
+        vec::Vec,
+    };
+
+    /// Pages from the host allocator, at most `limit` of them at once.
+    struct Pages {
+        allocated: BTreeMap<usize, usize>,
+        limit: usize,
+    }
+
+    impl Pages {
+        fn layout(count: usize) -> Layout {
+            Layout::from_size_align(count * PAGE_SIZE, PAGE_SIZE).unwrap()
+        }
+
+        fn used(&self) -> usize {
+            self.allocated.values().sum()
+        }
+    }
+
+    unsafe impl PageSource for Pages {
+        fn allocate_pages(&mut self, count: usize) -> Option<NonNull<u8>> {
+            if self.used() + count > self.limit {
+                return None;
+            }
+
+            let pages = NonNull::new(unsafe { alloc(Self::layout(count)) })?;
+            self.allocated.insert(pages.as_ptr() as usize, count);
+
+            Some(pages)
+        }
+
+        unsafe fn free_pages(&mut self, pages: NonNull<u8>, count: usize) {
+            assert_eq!(
+                self.allocated.remove(&(pages.as_ptr() as usize)),
+                Some(count)
+            );
+
+            unsafe { dealloc(pages.as_ptr(), Self::layout(count)) };
+        }
+    }
+
+    impl Drop for Pages {
+        fn drop(&mut self) {
+            for (&pages, &count) in &self.allocated {
+                unsafe { dealloc(pages as *mut u8, Self::layout(count)) };
+            }
+        }
+    }
+
+    fn heap(limit: usize) -> Heap<Pages> {
+        Heap::new(Pages {
+            allocated: BTreeMap::new(),
+            limit,
+        })
+    }
+
+    #[test]
+    fn picks_size_classes() {
+        let class = |size, align| class(Layout::from_size_align(size, align).unwrap());
+
+        assert_eq!(class(1, 1), Some(0));
+        assert_eq!(class(16, 8), Some(0));
+        assert_eq!(class(17, 1), Some(1));
+        assert_eq!(class(8, 64), Some(2));
+        assert_eq!(class(2048, 8), Some(7));
+        assert_eq!(class(2049, 8), None);
+        assert_eq!(class(8, 4096), None);
+    }
+
+    #[test]
+    fn slabs_share_pages() {
+        let mut heap = heap(4);
+        let layout = Layout::new::<[u64; 3]>();
+
+        let objects: Vec<_> = (0..PAGE_SIZE / 32)
+            .map(|_| heap.allocate(layout).unwrap())
+            .collect();
+
+        assert_eq!(heap.stats().slab_pages, 1);
+        assert_eq!(heap.stats().allocated, PAGE_SIZE);
+
+        for pair in objects.windows(2) {
+            assert_eq!(pair[1].as_ptr() as usize - pair[0].as_ptr() as usize, 32);
+        }
+
+        // The page is full, so the next one takes another.
+        let extra = heap.allocate(layout).unwrap();
+        assert_eq!(heap.stats().slab_pages, 2);
+
+        unsafe { heap.deallocate(objects[5], layout) };
+        assert_eq!(heap.allocate(layout), Some(objects[5]));
+
+        for object in objects.into_iter().chain([extra]) {
+            unsafe { heap.deallocate(object, layout) };
+        }
+
+        assert_eq!(heap.stats().allocated, 0);
+    }
+
+    #[test]
+    fn objects_are_aligned_and_usable() {
+        let mut heap = heap(16);
+        let mut objects = Vec::new();
+
+        for (size, align) in [(1, 1), (24, 8), (100, 64), (700, 16), (2048, 2048)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = heap.allocate(layout).unwrap();
+
+            assert_eq!(ptr.as_ptr() as usize % align, 0);
+
+            unsafe { ptr.write_bytes(0xa5, size) };
+            objects.push((ptr, layout));
+        }
+
+        for (ptr, layout) in objects {
+            let bytes = unsafe { std::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+
+            assert!(bytes.iter().all(|&byte| byte == 0xa5));
+            unsafe { heap.deallocate(ptr, layout) };
+        }
+    }
+
+    #[test]
+    fn large_allocations_get_pages() {
+        let mut heap = heap(4);
+        let layout = Layout::from_size_align(3 * PAGE_SIZE - 100, 8).unwrap();
+
+        let large = heap.allocate(layout).unwrap();
+
+        assert_eq!(large.as_ptr() as usize % PAGE_SIZE, 0);
+        assert_eq!(heap.stats().large_pages, 3);
+        assert_eq!(heap.source().used(), 3);
+
+        // Only one page left, which goes to the first slab.
+        assert_eq!(heap.allocate(layout), None);
+        assert!(heap.allocate(Layout::new::<u8>()).is_some());
+        assert!(heap.allocate(Layout::new::<u16>()).is_some());
+        assert_eq!(heap.allocate(Layout::new::<[u8; 32]>()), None);
+
+        unsafe { heap.deallocate(large, layout) };
+
+        assert_eq!(heap.stats().large_pages, 0);
+        assert_eq!(heap.source().used(), 1);
+    }
+}
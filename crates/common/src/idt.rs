@@ -0,0 +1,238 @@
+//! The long mode interrupt descriptor table and what handlers get to see.
+
+use core::mem::size_of_val;
+
+use crate::gdt::Pointer64;
+
+/// How many vectors are reserved for CPU exceptions.
+pub const EXCEPTIONS: usize = 32;
+
+/// The exceptions the CPU pushes an error code for, one bit per vector.
+pub const ERROR_CODES: u32 = (1 << vectors::DOUBLE_FAULT)
+    | (1 << vectors::INVALID_TSS)
+    | (1 << vectors::SEGMENT_NOT_PRESENT)
+    | (1 << vectors::STACK_SEGMENT_FAULT)
+    | (1 << vectors::GENERAL_PROTECTION)
+    | (1 << vectors::PAGE_FAULT)
+    | (1 << vectors::ALIGNMENT_CHECK)
+    | (1 << vectors::CONTROL_PROTECTION)
+    | (1 << vectors::VMM_COMMUNICATION)
+    | (1 << vectors::SECURITY);
+
+/// Vectors of the CPU exceptions.
+pub mod vectors {
+    /// Division by zero or a quotient that doesn't fit.
+    pub const DIVIDE_ERROR: u8 = 0;
+    /// A debug trap or fault.
+    pub const DEBUG: u8 = 1;
+    /// A non-maskable interrupt.
+    pub const NMI: u8 = 2;
+    /// An `int3`.
+    pub const BREAKPOINT: u8 = 3;
+    /// An invalid or undefined opcode, like `ud2`.
+    pub const INVALID_OPCODE: u8 = 6;
+    /// An exception while delivering another one.
+    pub const DOUBLE_FAULT: u8 = 8;
+    /// A broken task state segment.
+    pub const INVALID_TSS: u8 = 10;
+    /// A segment that isn't present.
+    pub const SEGMENT_NOT_PRESENT: u8 = 11;
+    /// A stack segment that isn't present or a non-canonical stack address.
+    pub const STACK_SEGMENT_FAULT: u8 = 12;
+    /// Everything that's not allowed and doesn't have an exception of its own.
+    pub const GENERAL_PROTECTION: u8 = 13;
+    /// An access the page tables don't allow, with the address in `CR2`.
+    pub const PAGE_FAULT: u8 = 14;
+    /// An unaligned access with alignment checking on.
+    pub const ALIGNMENT_CHECK: u8 = 17;
+    /// A machine check, the hardware is in trouble.
+    pub const MACHINE_CHECK: u8 = 18;
+    /// A control-flow enforcement violation.
+    pub const CONTROL_PROTECTION: u8 = 21;
+    /// A request from the VMM, in SEV-ES guests.
+    pub const VMM_COMMUNICATION: u8 = 29;
+    /// A security violation, in SVM.
+    pub const SECURITY: u8 = 30;
+}
+
+/// Returns what the CPU exception `vector` is called.
+#[must_use]
+pub const fn exception_name(vector: u8) -> &'static str {
+    match vector {
+        0 => "divide error",
+        1 => "debug",
+        2 => "non-maskable interrupt",
+        3 => "breakpoint",
+        4 => "overflow",
+        5 => "bound range exceeded",
+        6 => "invalid opcode",
+        7 => "device not available",
+        8 => "double fault",
+        9 => "coprocessor segment overrun",
+        10 => "invalid TSS",
+        11 => "segment not present",
+        12 => "stack-segment fault",
+        13 => "general protection fault",
+        14 => "page fault",
+        16 => "x87 floating-point exception",
+        17 => "alignment check",
+        18 => "machine check",
+        19 => "SIMD floating-point exception",
+        20 => "virtualization exception",
+        21 => "control protection exception",
+        28 => "hypervisor injection exception",
+        29 => "VMM communication exception",
+        30 => "security exception",
+        15 | 22..=27 | 31 => "reserved exception",
+        _ => "interrupt",
+    }
+}
+
+/// Returns whether the CPU pushes an error code for `vector`.
+#[inline]
+#[must_use]
+pub const fn has_error_code(vector: u8) -> bool {
+    (vector as usize) < EXCEPTIONS && ERROR_CODES & (1 << vector) != 0
+}
+
+/// Bits of the error code of a page fault.
+pub mod page_fault {
+    /// The page was present, so this is a protection violation.
+    pub const PRESENT: u64 = 1 << 0;
+    /// The access was a write.
+    pub const WRITE: u64 = 1 << 1;
+    /// The access came from ring 3.
+    pub const USER: u64 = 1 << 2;
+    /// A table entry had reserved bits set.
+    pub const RESERVED: u64 = 1 << 3;
+    /// The access was an instruction fetch.
+    pub const INSTRUCTION_FETCH: u64 = 1 << 4;
+}
+
+/// An entry of the interrupt descriptor table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct Gate {
+    /// The handler's offset, selector, stack and attributes.
+    pub low: u64,
+    /// The upper half of the handler's offset.
+    pub high: u64,
+}
+
+impl Gate {
+    /// A gate that isn't present, raising a general protection fault instead.
+    pub const MISSING: Self = Self { low: 0, high: 0 };
+
+    /// Creates a gate to `handler` in the code segment `selector`.
+    ///
+    /// `stack` picks an entry of the interrupt stack table, with 0 staying on the
+    /// current stack. Only the lower 3 bits are used.
+    #[inline]
+    #[must_use]
+    pub const fn new(handler: u64, selector: u16, stack: u8, attributes: u8) -> Self {
+        Self {
+            low: (handler & 0xffff)
+                | ((selector as u64) << 16)
+                | (((stack & 0x7) as u64) << 32)
+                | ((attributes as u64) << 40)
+                | ((handler & 0xffff_0000) << 32),
+            high: handler >> 32,
+        }
+    }
+
+    /// Returns the address of the handler.
+    #[inline]
+    #[must_use]
+    pub const fn handler(self) -> u64 {
+        (self.low & 0xffff) | ((self.low >> 32) & 0xffff_0000) | (self.high << 32)
+    }
+}
+
+/// Bits of the attribute byte of a [`Gate`].
+pub mod attributes {
+    /// An interrupt gate, which turns interrupts off on entry.
+    pub const INTERRUPT: u8 = 0xe;
+    /// A trap gate, which leaves interrupts alone.
+    pub const TRAP: u8 = 0xf;
+    /// The gate can be used with `int` from ring 3.
+    pub const RING_3: u8 = 3 << 5;
+    /// The gate is present.
+    pub const PRESENT: u8 = 1 << 7;
+}
+
+/// Creates the operand of `lidt` for `table`.
+#[inline]
+#[must_use]
+pub fn pointer(table: &[Gate]) -> Pointer64 {
+    Pointer64 {
+        limit: (size_of_val(table) - 1) as u16,
+        base: table.as_ptr() as usize as u64,
+    }
+}
+
+/// What the entry stubs leave on the stack for the handler, lowest address first.
+///
+/// The general purpose registers are pushed by the stub, then come the vector and
+/// error code, with a made-up 0 for vectors without one, and then what the CPU
+/// pushed on entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(C)]
+pub struct InterruptFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    /// The vector of the interrupt.
+    pub vector: u64,
+    /// The error code, or 0 if there isn't one.
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gate_layout() {
+        let gate = Gate::new(
+            0xffff_ffff_8012_3456,
+            0x08,
+            1,
+            attributes::PRESENT | attributes::INTERRUPT,
+        );
+
+        assert_eq!(gate.low, 0x8012_8e01_0008_3456);
+        assert_eq!(gate.high, 0xffff_ffff);
+        assert_eq!(gate.handler(), 0xffff_ffff_8012_3456);
+    }
+
+    #[test]
+    fn exceptions() {
+        assert!(has_error_code(vectors::PAGE_FAULT));
+        assert!(has_error_code(vectors::DOUBLE_FAULT));
+        assert!(!has_error_code(vectors::BREAKPOINT));
+        assert!(!has_error_code(vectors::MACHINE_CHECK));
+        assert!(!has_error_code(40));
+
+        assert_eq!(exception_name(vectors::PAGE_FAULT), "page fault");
+        assert_eq!(exception_name(15), "reserved exception");
+        assert_eq!(exception_name(32), "interrupt");
+    }
+}
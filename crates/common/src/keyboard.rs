@@ -0,0 +1,929 @@
+//! PS/2 keyboards, without any of the hardware.
+//!
+//! A [`Decoder`] turns the bytes of scancode set 1 or 2 into [`KeyEvent`]s, and a
+//! [`Keyboard`] keeps track of modifiers and lock keys on top and maps keys to
+//! characters with the US layout.
+
+/// The scancode sets we can decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ScancodeSet {
+    /// The XT set, which controllers translate to by default.
+    Set1,
+    /// The AT set, which keyboards send by default.
+    Set2,
+}
+
+/// A key, by where it is on a US keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyCode {
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Backtick,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Enter,
+    LeftShift,
+    /// The extra key next to left shift on ISO keyboards.
+    NonUsBackslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+    LeftCtrl,
+    LeftGui,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightGui,
+    Menu,
+    RightCtrl,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    NumLock,
+    KeypadDivide,
+    KeypadMultiply,
+    KeypadMinus,
+    KeypadPlus,
+    KeypadEnter,
+    KeypadPeriod,
+    Keypad0,
+    Keypad1,
+    Keypad2,
+    Keypad3,
+    Keypad4,
+    Keypad5,
+    Keypad6,
+    Keypad7,
+    Keypad8,
+    Keypad9,
+}
+
+/// A key going down or coming back up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyEvent {
+    /// The key.
+    pub code: KeyCode,
+    /// Whether it went down, which it also does when it repeats.
+    pub pressed: bool,
+}
+
+/// Where a [`Decoder`] is within a scancode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum State {
+    /// Not within a scancode.
+    Ground,
+    /// After `0xe0`.
+    Extended,
+    /// After `0xf0`, in set 2.
+    Release,
+    /// After `0xe0 0xf0`, in set 2.
+    ExtendedRelease,
+    /// After `0xe1`, which starts the sequences Pause sends.
+    PauseStart,
+    /// Within the sequence Pause sends, with `remaining` bytes to go.
+    Pause { remaining: u8, pressed: bool },
+}
+
+/// Turns scancode bytes into [`KeyEvent`]s.
+///
+/// Bytes that aren't part of a known scancode are skipped, as are the fake
+/// shifts some keys send along in set 1 and 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Decoder {
+    set: ScancodeSet,
+    state: State,
+}
+
+impl Decoder {
+    /// Creates a decoder for `set`.
+    #[inline]
+    #[must_use]
+    pub const fn new(set: ScancodeSet) -> Self {
+        Self {
+            set,
+            state: State::Ground,
+        }
+    }
+
+    /// Returns the scancode set being decoded.
+    #[inline]
+    #[must_use]
+    pub const fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    /// Feeds the next byte from the keyboard, and returns the event it completes.
+    pub fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        match self.set {
+            ScancodeSet::Set1 => self.feed_set1(byte),
+            ScancodeSet::Set2 => self.feed_set2(byte),
+        }
+    }
+
+    fn feed_set1(&mut self, byte: u8) -> Option<KeyEvent> {
+        let (code, pressed) = (byte & 0x7f, byte & 0x80 == 0);
+
+        match (self.state, byte) {
+            // Pause is `e1 1d 45` followed by `e1 9d c5` right away.
+            (State::PauseStart, _) => {
+                self.state = State::Pause {
+                    remaining: 1,
+                    pressed,
+                };
+                None
+            }
+            (State::Pause { remaining, pressed }, _) => self.pause(remaining, pressed),
+            (State::Ground, 0xe1) => {
+                self.state = State::PauseStart;
+                None
+            }
+            (State::Ground, 0xe0) => {
+                self.state = State::Extended;
+                None
+            }
+            (State::Ground, 0x00 | 0xff) => None,
+            (State::Ground, _) => set1(code).map(|code| KeyEvent { code, pressed }),
+            (_, _) => {
+                self.state = State::Ground;
+
+                set1_extended(code).map(|code| KeyEvent { code, pressed })
+            }
+        }
+    }
+
+    fn feed_set2(&mut self, byte: u8) -> Option<KeyEvent> {
+        match (self.state, byte) {
+            // Pause is `e1 14 77` followed by `e1 f0 14 f0 77` right away.
+            (State::PauseStart, _) => {
+                self.state = if byte == 0xf0 {
+                    State::Pause {
+                        remaining: 3,
+                        pressed: false,
+                    }
+                } else {
+                    State::Pause {
+                        remaining: 1,
+                        pressed: true,
+                    }
+                };
+                None
+            }
+            (State::Pause { remaining, pressed }, _) => self.pause(remaining, pressed),
+            (State::Ground, 0xe1) => {
+                self.state = State::PauseStart;
+                None
+            }
+            (State::Ground, 0xe0) => {
+                self.state = State::Extended;
+                None
+            }
+            (State::Ground, 0xf0) => {
+                self.state = State::Release;
+                None
+            }
+            (State::Extended, 0xf0) => {
+                self.state = State::ExtendedRelease;
+                None
+            }
+            (State::Ground, 0x00 | 0xff) => None,
+            (State::Ground | State::Release, _) => {
+                let pressed = self.state == State::Ground;
+                self.state = State::Ground;
+
+                set2(byte).map(|code| KeyEvent { code, pressed })
+            }
+            (State::Extended | State::ExtendedRelease, _) => {
+                let pressed = self.state == State::Extended;
+                self.state = State::Ground;
+
+                set2_extended(byte).map(|code| KeyEvent { code, pressed })
+            }
+        }
+    }
+
+    /// Counts down the bytes of a Pause sequence.
+    fn pause(&mut self, remaining: u8, pressed: bool) -> Option<KeyEvent> {
+        if remaining > 1 {
+            self.state = State::Pause {
+                remaining: remaining - 1,
+                pressed,
+            };
+            return None;
+        }
+
+        self.state = State::Ground;
+
+        Some(KeyEvent {
+            code: KeyCode::Pause,
+            pressed,
+        })
+    }
+}
+
+/// Returns the key with the set 1 scancode `code`, without the release bit.
+const fn set1(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x01 => Escape,
+        0x02 => Digit1,
+        0x03 => Digit2,
+        0x04 => Digit3,
+        0x05 => Digit4,
+        0x06 => Digit5,
+        0x07 => Digit6,
+        0x08 => Digit7,
+        0x09 => Digit8,
+        0x0a => Digit9,
+        0x0b => Digit0,
+        0x0c => Minus,
+        0x0d => Equals,
+        0x0e => Backspace,
+        0x0f => Tab,
+        0x10 => Q,
+        0x11 => W,
+        0x12 => E,
+        0x13 => R,
+        0x14 => T,
+        0x15 => Y,
+        0x16 => U,
+        0x17 => I,
+        0x18 => O,
+        0x19 => P,
+        0x1a => LeftBracket,
+        0x1b => RightBracket,
+        0x1c => Enter,
+        0x1d => LeftCtrl,
+        0x1e => A,
+        0x1f => S,
+        0x20 => D,
+        0x21 => F,
+        0x22 => G,
+        0x23 => H,
+        0x24 => J,
+        0x25 => K,
+        0x26 => L,
+        0x27 => Semicolon,
+        0x28 => Quote,
+        0x29 => Backtick,
+        0x2a => LeftShift,
+        0x2b => Backslash,
+        0x2c => Z,
+        0x2d => X,
+        0x2e => C,
+        0x2f => V,
+        0x30 => B,
+        0x31 => N,
+        0x32 => M,
+        0x33 => Comma,
+        0x34 => Period,
+        0x35 => Slash,
+        0x36 => RightShift,
+        0x37 => KeypadMultiply,
+        0x38 => LeftAlt,
+        0x39 => Space,
+        0x3a => CapsLock,
+        0x3b => F1,
+        0x3c => F2,
+        0x3d => F3,
+        0x3e => F4,
+        0x3f => F5,
+        0x40 => F6,
+        0x41 => F7,
+        0x42 => F8,
+        0x43 => F9,
+        0x44 => F10,
+        0x45 => NumLock,
+        0x46 => ScrollLock,
+        0x47 => Keypad7,
+        0x48 => Keypad8,
+        0x49 => Keypad9,
+        0x4a => KeypadMinus,
+        0x4b => Keypad4,
+        0x4c => Keypad5,
+        0x4d => Keypad6,
+        0x4e => KeypadPlus,
+        0x4f => Keypad1,
+        0x50 => Keypad2,
+        0x51 => Keypad3,
+        0x52 => Keypad0,
+        0x53 => KeypadPeriod,
+        0x56 => NonUsBackslash,
+        0x57 => F11,
+        0x58 => F12,
+        _ => return None,
+    })
+}
+
+/// Returns the key with the set 1 scancode `0xe0 code`, without the release bit.
+///
+/// The fake shifts around Print Screen and the navigation keys, `0x2a` and
+/// `0x36`, aren't keys.
+const fn set1_extended(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x1c => KeypadEnter,
+        0x1d => RightCtrl,
+        0x35 => KeypadDivide,
+        0x37 => PrintScreen,
+        0x38 => RightAlt,
+        0x47 => Home,
+        0x48 => Up,
+        0x49 => PageUp,
+        0x4b => Left,
+        0x4d => Right,
+        0x4f => End,
+        0x50 => Down,
+        0x51 => PageDown,
+        0x52 => Insert,
+        0x53 => Delete,
+        0x5b => LeftGui,
+        0x5c => RightGui,
+        0x5d => Menu,
+        _ => return None,
+    })
+}
+
+/// Returns the key with the set 2 scancode `code`.
+const fn set2(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x01 => F9,
+        0x03 => F5,
+        0x04 => F3,
+        0x05 => F1,
+        0x06 => F2,
+        0x07 => F12,
+        0x09 => F10,
+        0x0a => F8,
+        0x0b => F6,
+        0x0c => F4,
+        0x0d => Tab,
+        0x0e => Backtick,
+        0x11 => LeftAlt,
+        0x12 => LeftShift,
+        0x14 => LeftCtrl,
+        0x15 => Q,
+        0x16 => Digit1,
+        0x1a => Z,
+        0x1b => S,
+        0x1c => A,
+        0x1d => W,
+        0x1e => Digit2,
+        0x21 => C,
+        0x22 => X,
+        0x23 => D,
+        0x24 => E,
+        0x25 => Digit4,
+        0x26 => Digit3,
+        0x29 => Space,
+        0x2a => V,
+        0x2b => F,
+        0x2c => T,
+        0x2d => R,
+        0x2e => Digit5,
+        0x31 => N,
+        0x32 => B,
+        0x33 => H,
+        0x34 => G,
+        0x35 => Y,
+        0x36 => Digit6,
+        0x3a => M,
+        0x3b => J,
+        0x3c => U,
+        0x3d => Digit7,
+        0x3e => Digit8,
+        0x41 => Comma,
+        0x42 => K,
+        0x43 => I,
+        0x44 => O,
+        0x45 => Digit0,
+        0x46 => Digit9,
+        0x49 => Period,
+        0x4a => Slash,
+        0x4b => L,
+        0x4c => Semicolon,
+        0x4d => P,
+        0x4e => Minus,
+        0x52 => Quote,
+        0x54 => LeftBracket,
+        0x55 => Equals,
+        0x58 => CapsLock,
+        0x59 => RightShift,
+        0x5a => Enter,
+        0x5b => RightBracket,
+        0x5d => Backslash,
+        0x61 => NonUsBackslash,
+        0x66 => Backspace,
+        0x69 => Keypad1,
+        0x6b => Keypad4,
+        0x6c => Keypad7,
+        0x70 => Keypad0,
+        0x71 => KeypadPeriod,
+        0x72 => Keypad2,
+        0x73 => Keypad5,
+        0x74 => Keypad6,
+        0x75 => Keypad8,
+        0x76 => Escape,
+        0x77 => NumLock,
+        0x78 => F11,
+        0x79 => KeypadPlus,
+        0x7a => Keypad3,
+        0x7b => KeypadMinus,
+        0x7c => KeypadMultiply,
+        0x7d => Keypad9,
+        0x7e => ScrollLock,
+        0x83 => F7,
+        _ => return None,
+    })
+}
+
+/// Returns the key with the set 2 scancode `0xe0 code`.
+///
+/// The fake shifts around Print Screen and the navigation keys, `0x12` and
+/// `0x59`, aren't keys.
+const fn set2_extended(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x11 => RightAlt,
+        0x14 => RightCtrl,
+        0x1f => LeftGui,
+        0x27 => RightGui,
+        0x2f => Menu,
+        0x4a => KeypadDivide,
+        0x5a => KeypadEnter,
+        0x69 => End,
+        0x6b => Left,
+        0x6c => Home,
+        0x70 => Insert,
+        0x71 => Delete,
+        0x72 => Down,
+        0x74 => Right,
+        0x75 => Up,
+        0x7a => PageDown,
+        0x7c => PrintScreen,
+        0x7d => PageUp,
+        _ => return None,
+    })
+}
+
+/// Which modifiers are held down and which locks are on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub left_alt: bool,
+    pub right_alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl Modifiers {
+    /// Returns whether either shift key is down.
+    #[inline]
+    #[must_use]
+    pub const fn shift(&self) -> bool {
+        self.left_shift | self.right_shift
+    }
+
+    /// Returns whether either control key is down.
+    #[inline]
+    #[must_use]
+    pub const fn ctrl(&self) -> bool {
+        self.left_ctrl | self.right_ctrl
+    }
+
+    /// Returns whether either alt key is down.
+    #[inline]
+    #[must_use]
+    pub const fn alt(&self) -> bool {
+        self.left_alt | self.right_alt
+    }
+
+    /// Returns the argument of the keyboard's set LEDs command for the locks.
+    #[inline]
+    #[must_use]
+    pub const fn leds(&self) -> u8 {
+        (self.scroll_lock as u8) | ((self.num_lock as u8) << 1) | ((self.caps_lock as u8) << 2)
+    }
+
+    /// Updates the state for `event`.
+    ///
+    /// Locks toggle when their key goes down, which it does again on repeats, so
+    /// a lock key held down keeps toggling just like on other systems.
+    pub fn update(&mut self, event: KeyEvent) {
+        let pressed = event.pressed;
+
+        match event.code {
+            KeyCode::LeftShift => self.left_shift = pressed,
+            KeyCode::RightShift => self.right_shift = pressed,
+            KeyCode::LeftCtrl => self.left_ctrl = pressed,
+            KeyCode::RightCtrl => self.right_ctrl = pressed,
+            KeyCode::LeftAlt => self.left_alt = pressed,
+            KeyCode::RightAlt => self.right_alt = pressed,
+            KeyCode::CapsLock if pressed => self.caps_lock = !self.caps_lock,
+            KeyCode::NumLock if pressed => self.num_lock = !self.num_lock,
+            KeyCode::ScrollLock if pressed => self.scroll_lock = !self.scroll_lock,
+            _ => {}
+        }
+    }
+}
+
+/// Returns the character `code` types on a US keyboard with `modifiers`.
+///
+/// Control turns letters into control characters. Keys that don't type anything,
+/// like the arrows or the keypad with num lock off, return `None`.
+#[must_use]
+pub const fn us_layout(code: KeyCode, modifiers: &Modifiers) -> Option<char> {
+    use KeyCode::*;
+
+    let shift = modifiers.shift();
+
+    let letter = match code {
+        A => 'a',
+        B => 'b',
+        C => 'c',
+        D => 'd',
+        E => 'e',
+        F => 'f',
+        G => 'g',
+        H => 'h',
+        I => 'i',
+        J => 'j',
+        K => 'k',
+        L => 'l',
+        M => 'm',
+        N => 'n',
+        O => 'o',
+        P => 'p',
+        Q => 'q',
+        R => 'r',
+        S => 's',
+        T => 't',
+        U => 'u',
+        V => 'v',
+        W => 'w',
+        X => 'x',
+        Y => 'y',
+        Z => 'z',
+        _ => '\0',
+    };
+
+    if letter != '\0' {
+        if modifiers.ctrl() {
+            return Some((letter as u8 - b'a' + 1) as char);
+        }
+
+        return Some(if shift ^ modifiers.caps_lock {
+            letter.to_ascii_uppercase()
+        } else {
+            letter
+        });
+    }
+
+    let (plain, shifted) = match code {
+        Backtick => ('`', '~'),
+        Digit1 => ('1', '!'),
+        Digit2 => ('2', '@'),
+        Digit3 => ('3', '#'),
+        Digit4 => ('4', '$'),
+        Digit5 => ('5', '%'),
+        Digit6 => ('6', '^'),
+        Digit7 => ('7', '&'),
+        Digit8 => ('8', '*'),
+        Digit9 => ('9', '('),
+        Digit0 => ('0', ')'),
+        Minus => ('-', '_'),
+        Equals => ('=', '+'),
+        LeftBracket => ('[', '{'),
+        RightBracket => (']', '}'),
+        Backslash | NonUsBackslash => ('\\', '|'),
+        Semicolon => (';', ':'),
+        Quote => ('\'', '"'),
+        Comma => (',', '<'),
+        Period => ('.', '>'),
+        Slash => ('/', '?'),
+        Space => (' ', ' '),
+        Enter | KeypadEnter => ('\n', '\n'),
+        Tab => ('\t', '\t'),
+        Backspace => ('\x08', '\x08'),
+        Escape => ('\x1b', '\x1b'),
+        Delete => ('\x7f', '\x7f'),
+        KeypadDivide => ('/', '/'),
+        KeypadMultiply => ('*', '*'),
+        KeypadMinus => ('-', '-'),
+        KeypadPlus => ('+', '+'),
+        _ => {
+            // The rest of the keypad types with num lock on, and shift flips it
+            // back to navigation.
+            if !modifiers.num_lock | shift {
+                return None;
+            }
+
+            let digit = match code {
+                KeypadPeriod => return Some('.'),
+                Keypad0 => '0',
+                Keypad1 => '1',
+                Keypad2 => '2',
+                Keypad3 => '3',
+                Keypad4 => '4',
+                Keypad5 => '5',
+                Keypad6 => '6',
+                Keypad7 => '7',
+                Keypad8 => '8',
+                Keypad9 => '9',
+                _ => return None,
+            };
+
+            return Some(digit);
+        }
+    };
+
+    Some(if shift { shifted } else { plain })
+}
+
+/// A keyboard's state, turning scancode bytes into events and characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Keyboard {
+    decoder: Decoder,
+    modifiers: Modifiers,
+}
+
+impl Keyboard {
+    /// Creates a keyboard sending scancodes of `set`, with nothing held down and
+    /// all locks off.
+    #[inline]
+    #[must_use]
+    pub const fn new(set: ScancodeSet) -> Self {
+        Self {
+            decoder: Decoder::new(set),
+            modifiers: Modifiers {
+                left_shift: false,
+                right_shift: false,
+                left_ctrl: false,
+                right_ctrl: false,
+                left_alt: false,
+                right_alt: false,
+                caps_lock: false,
+                num_lock: false,
+                scroll_lock: false,
+            },
+        }
+    }
+
+    /// Returns the modifiers and locks.
+    #[inline]
+    #[must_use]
+    pub const fn modifiers(&self) -> &Modifiers {
+        &self.modifiers
+    }
+
+    /// Feeds the next byte from the keyboard, and returns the event it completes
+    /// along with the character it types, if any.
+    pub fn feed(&mut self, byte: u8) -> Option<(KeyEvent, Option<char>)> {
+        let event = self.decoder.feed(byte)?;
+        self.modifiers.update(event);
+
+        let char = if event.pressed {
+            us_layout(event.code, &self.modifiers)
+        } else {
+            None
+        };
+
+        Some((event, char))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{string::String, vec::Vec};
+
+    fn events(set: ScancodeSet, bytes: &[u8]) -> Vec<(KeyCode, bool)> {
+        let mut decoder = Decoder::new(set);
+
+        bytes
+            .iter()
+            .filter_map(|&byte| decoder.feed(byte))
+            .map(|event| (event.code, event.pressed))
+            .collect()
+    }
+
+    fn typed(set: ScancodeSet, bytes: &[u8]) -> String {
+        let mut keyboard = Keyboard::new(set);
+
+        bytes
+            .iter()
+            .filter_map(|&byte| keyboard.feed(byte)?.1)
+            .collect()
+    }
+
+    #[test]
+    fn decodes_set1() {
+        use KeyCode::*;
+
+        assert_eq!(
+            events(
+                ScancodeSet::Set1,
+                &[0x1e, 0x9e, 0xe0, 0x48, 0xe0, 0xc8, 0xe0, 0x1d, 0x3a, 0x00]
+            ),
+            [
+                (A, true),
+                (A, false),
+                (Up, true),
+                (Up, false),
+                (RightCtrl, true),
+                (CapsLock, true),
+            ]
+        );
+
+        // Print Screen with its fake shifts, then Pause.
+        assert_eq!(
+            events(
+                ScancodeSet::Set1,
+                &[
+                    0xe0, 0x2a, 0xe0, 0x37, 0xe0, 0xb7, 0xe0, 0xaa, 0xe1, 0x1d, 0x45, 0xe1, 0x9d,
+                    0xc5
+                ]
+            ),
+            [
+                (PrintScreen, true),
+                (PrintScreen, false),
+                (Pause, true),
+                (Pause, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_set2() {
+        use KeyCode::*;
+
+        assert_eq!(
+            events(
+                ScancodeSet::Set2,
+                &[0x1c, 0xf0, 0x1c, 0xe0, 0x75, 0xe0, 0xf0, 0x75, 0x83, 0xe0, 0x14, 0xff]
+            ),
+            [
+                (A, true),
+                (A, false),
+                (Up, true),
+                (Up, false),
+                (F7, true),
+                (RightCtrl, true),
+            ]
+        );
+
+        assert_eq!(
+            events(
+                ScancodeSet::Set2,
+                &[
+                    0xe0, 0x12, 0xe0, 0x7c, 0xe0, 0xf0, 0x7c, 0xe0, 0xf0, 0x12, 0xe1, 0x14, 0x77,
+                    0xe1, 0xf0, 0x14, 0xf0, 0x77, 0x29,
+                ]
+            ),
+            [
+                (PrintScreen, true),
+                (PrintScreen, false),
+                (Pause, true),
+                (Pause, false),
+                (Space, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_agree() {
+        // Every key has a scancode in both sets.
+        let set1: Vec<_> = (0..0x80)
+            .filter_map(set1)
+            .chain((0..0x80).filter_map(set1_extended))
+            .collect();
+        let set2: Vec<_> = (0..=0xff)
+            .filter_map(set2)
+            .chain((0..=0xff).filter_map(set2_extended))
+            .collect();
+
+        for code in &set1 {
+            assert!(set2.contains(code), "{code:?} is missing from set 2");
+        }
+
+        assert_eq!(set1.len(), set2.len());
+    }
+
+    #[test]
+    fn tracks_modifiers() {
+        let mut keyboard = Keyboard::new(ScancodeSet::Set2);
+
+        for byte in [0x12, 0x58, 0xf0, 0x58, 0x77, 0xf0, 0x77] {
+            keyboard.feed(byte);
+        }
+
+        let modifiers = keyboard.modifiers();
+
+        assert!(modifiers.left_shift & modifiers.shift());
+        assert!(!modifiers.ctrl());
+        assert!(modifiers.caps_lock & modifiers.num_lock & !modifiers.scroll_lock);
+        assert_eq!(modifiers.leds(), 0b110);
+
+        keyboard.feed(0xf0);
+        keyboard.feed(0x12);
+        assert!(!keyboard.modifiers().shift());
+    }
+
+    #[test]
+    fn types_us_layout() {
+        // "Hi!" with shift, then caps lock and "a1", then a control-C.
+        assert_eq!(
+            typed(
+                ScancodeSet::Set1,
+                &[
+                    0x2a, 0x23, 0xaa, 0x17, 0x2a, 0x02, 0xaa, 0x3a, 0xba, 0x1e, 0x02, 0x1d, 0x2e,
+                    0x9d, 0x1c,
+                ]
+            ),
+            "Hi!A1\x03\n"
+        );
+
+        // Shift cancels out caps lock, and the keypad types once num lock is on.
+        assert_eq!(
+            typed(
+                ScancodeSet::Set2,
+                &[0x58, 0x12, 0x1c, 0xf0, 0x12, 0x70, 0x77, 0x70, 0x71, 0xe0, 0x4a]
+            ),
+            "a0./"
+        );
+    }
+}
@@ -13,5 +13,73 @@ mod macros;
 #[doc(hidden)]
 pub mod __private;
 
+#[cfg(feature = "format")]
+pub mod format;
+#[cfg(feature = "log")]
+pub mod log;
+
 #[cfg(feature = "mbr")]
 pub mod mbr;
+
+#[cfg(feature = "header")]
+pub mod header;
+
+#[cfg(feature = "handoff")]
+pub mod handoff;
+
+#[cfg(feature = "memory_map")]
+pub mod memory_map;
+
+#[cfg(feature = "frame")]
+pub mod frame;
+
+#[cfg(feature = "paging")]
+pub mod paging;
+
+#[cfg(feature = "heap")]
+pub mod heap;
+
+#[cfg(feature = "gdt")]
+pub mod gdt;
+#[cfg(feature = "idt")]
+pub mod idt;
+
+#[cfg(feature = "framebuffer")]
+pub mod framebuffer;
+
+#[cfg(feature = "vbe")]
+pub mod vbe;
+
+#[cfg(feature = "fat")]
+pub mod fat;
+
+#[cfg(feature = "elf")]
+pub mod elf;
+
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "menu")]
+pub mod menu;
+#[cfg(feature = "multiboot2")]
+pub mod multiboot2;
+
+#[cfg(feature = "boot_info")]
+pub mod boot_info;
+
+#[cfg(feature = "acpi")]
+pub mod acpi;
+#[cfg(feature = "apic")]
+pub mod apic;
+#[cfg(feature = "keyboard")]
+pub mod keyboard;
+#[cfg(feature = "smbios")]
+pub mod smbios;
+
+#[cfg(all(feature = "cpu", any(target_arch = "x86", target_arch = "x86_64")))]
+pub mod cpu;
+
+#[cfg(all(feature = "port", any(target_arch = "x86", target_arch = "x86_64")))]
+pub mod port;
+
+#[cfg(all(feature = "serial", any(target_arch = "x86", target_arch = "x86_64")))]
+pub mod serial;
@@ -0,0 +1,133 @@
+//! Leveled logging on top of [`format`](crate::format).
+//!
+//! The log macros take a [`Sink`](crate::format::Sink) and the same arguments as
+//! [`format_to!`](crate::format_to), and end the line themselves. Messages below
+//! [`MAX_LEVEL`] compile out entirely, arguments included.
+
+/// How important a message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum Level {
+    /// Something failed, and we're most likely stuck.
+    Error = 1,
+    /// Something looks off, but we carry on.
+    Warn = 2,
+    /// Progress the user wants to see.
+    Info = 3,
+    /// Details for finding out what went wrong.
+    Debug = 4,
+    /// Everything else.
+    Trace = 5,
+}
+
+/// The least important level that still gets logged, set with `MROW_LOG` at build
+/// time.
+///
+/// `MROW_LOG` is the number of a [`Level`], with 0 turning logging off.
+pub const MAX_LEVEL: u8 = option_var!("MROW_LOG", u8, Level::Info as u8);
+
+impl Level {
+    /// Returns whether messages of this level are logged.
+    #[inline]
+    #[must_use]
+    pub const fn enabled(self) -> bool {
+        self as u8 <= MAX_LEVEL
+    }
+
+    /// Returns what messages of this level start with.
+    ///
+    /// Messages for the user don't get a prefix.
+    #[inline]
+    #[must_use]
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "error: ",
+            Level::Warn => "warning: ",
+            Level::Info => "",
+            Level::Debug => "debug: ",
+            Level::Trace => "trace: ",
+        }
+    }
+}
+
+/// Writes a line to a [`Sink`](crate::format::Sink) if `level` is
+/// [enabled](crate::log::Level::enabled).
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $sink:expr $(, $arg:expr)* $(,)?) => {{
+        const LEVEL: $crate::log::Level = $level;
+
+        if const { LEVEL.enabled() } {
+            $crate::format_to!($sink, LEVEL.prefix() $(, $arg)*, "\r\n");
+        }
+    }};
+}
+
+/// Logs at [`Level::Error`](crate::log::Level::Error).
+#[macro_export]
+macro_rules! error {
+    ($sink:expr $(, $arg:expr)* $(,)?) => {
+        $crate::log!($crate::log::Level::Error, $sink $(, $arg)*)
+    };
+}
+
+/// Logs at [`Level::Warn`](crate::log::Level::Warn).
+#[macro_export]
+macro_rules! warn {
+    ($sink:expr $(, $arg:expr)* $(,)?) => {
+        $crate::log!($crate::log::Level::Warn, $sink $(, $arg)*)
+    };
+}
+
+/// Logs at [`Level::Info`](crate::log::Level::Info).
+#[macro_export]
+macro_rules! info {
+    ($sink:expr $(, $arg:expr)* $(,)?) => {
+        $crate::log!($crate::log::Level::Info, $sink $(, $arg)*)
+    };
+}
+
+/// Logs at [`Level::Debug`](crate::log::Level::Debug).
+#[macro_export]
+macro_rules! debug {
+    ($sink:expr $(, $arg:expr)* $(,)?) => {
+        $crate::log!($crate::log::Level::Debug, $sink $(, $arg)*)
+    };
+}
+
+/// Logs at [`Level::Trace`](crate::log::Level::Trace).
+#[macro_export]
+macro_rules! trace {
+    ($sink:expr $(, $arg:expr)* $(,)?) => {
+        $crate::log!($crate::log::Level::Trace, $sink $(, $arg)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Int;
+    use std::vec::Vec;
+
+    #[test]
+    fn logs_enabled_levels() {
+        let mut out = Vec::new();
+        error!(out, "bad sector ", Int::hex(0x3f));
+        info!(out, "hello");
+        trace!(out, "skipped ", 1_u8);
+
+        let mut expected = Vec::new();
+
+        for (level, line) in [
+            (Level::Error, &b"error: bad sector 3f\r\n"[..]),
+            (Level::Info, b"hello\r\n"),
+            (Level::Trace, b"trace: skipped 1\r\n"),
+        ] {
+            if level.enabled() {
+                expected.extend_from_slice(line);
+            }
+        }
+
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,673 @@
+use core::{
+    fmt,
+    ptr::{addr_of, addr_of_mut},
+};
+
+/// The kind of memory a region contains.
+///
+/// The discriminants match the E820 region types, except for [`MemoryKind::Loader`]
+/// which the firmware never reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u32)]
+pub enum MemoryKind {
+    /// Free memory that can be used for anything.
+    Usable = 1,
+    /// Memory that must not be touched.
+    Reserved = 2,
+    /// Memory holding ACPI tables, usable once they have been parsed.
+    AcpiReclaimable = 3,
+    /// Memory that must be preserved across sleep states.
+    AcpiNvs = 4,
+    /// Memory the firmware detected errors in.
+    BadMemory = 5,
+    /// Memory the bootloader handed to the kernel, like the kernel image, page
+    /// tables and boot information.
+    ///
+    /// The kernel can reuse it once it no longer needs any of that.
+    Loader = 0x1000,
+}
+
+impl MemoryKind {
+    /// Converts an E820 region type, treating anything unknown as reserved.
+    #[inline]
+    #[must_use]
+    pub const fn from_e820(kind: u32) -> Self {
+        match kind {
+            1 => MemoryKind::Usable,
+            3 => MemoryKind::AcpiReclaimable,
+            4 => MemoryKind::AcpiNvs,
+            5 => MemoryKind::BadMemory,
+            _ => MemoryKind::Reserved,
+        }
+    }
+
+    /// Returns which kind wins when two regions overlap, the higher the stronger.
+    ///
+    /// Anything that isn't usable beats usable memory, since handing out memory
+    /// the firmware still needs is far worse than wasting some.
+    #[inline]
+    #[must_use]
+    pub const fn priority(self) -> u8 {
+        match self {
+            MemoryKind::Usable => 0,
+            MemoryKind::Loader => 1,
+            MemoryKind::AcpiReclaimable => 2,
+            MemoryKind::AcpiNvs => 3,
+            MemoryKind::Reserved => 4,
+            MemoryKind::BadMemory => 5,
+        }
+    }
+}
+
+/// A raw entry as returned by `int 15h, eax=e820h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C, packed)]
+pub struct E820Entry {
+    /// Start address of the region.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`E820Entry::base`].
+    pub base: u64,
+    /// Length of the region in bytes.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`E820Entry::len`].
+    pub len: u64,
+    /// The E820 region type.
+    pub kind: u32,
+    /// ACPI 3.0 extended attributes.
+    pub attributes: u32,
+}
+
+impl E820Entry {
+    /// The size of an entry including the extended attributes.
+    pub const SIZE: u32 = 24;
+
+    /// Reads the start address of the entry.
+    #[inline]
+    #[must_use]
+    pub const fn base(&self) -> u64 {
+        unsafe { addr_of!(self.base).read_unaligned() }
+    }
+
+    /// Reads the length of the entry.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> u64 {
+        unsafe { addr_of!(self.len).read_unaligned() }
+    }
+
+    /// Returns whether the entry is empty.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the firmware asked us to ignore this entry.
+    ///
+    /// Only meaningful if the firmware returned [`E820Entry::SIZE`] bytes,
+    /// so set [`E820Entry::attributes`] to `1` before every call.
+    #[inline]
+    #[must_use]
+    pub const fn is_ignored(&self) -> bool {
+        self.attributes & 0x01 == 0
+    }
+
+    /// Converts this entry into a region, saturating at the end of the address space.
+    #[inline]
+    #[must_use]
+    pub const fn region(&self) -> MemoryRegion {
+        MemoryRegion::new(
+            self.base(),
+            self.base().saturating_add(self.len()),
+            MemoryKind::from_e820(self.kind),
+        )
+    }
+
+    /// Sets a new value to the start address of the entry.
+    #[inline]
+    pub fn set_base(&mut self, base: u64) {
+        unsafe { addr_of_mut!(self.base).write_unaligned(base) }
+    }
+
+    /// Sets a new value to the length of the entry.
+    #[inline]
+    pub fn set_len(&mut self, len: u64) {
+        unsafe { addr_of_mut!(self.len).write_unaligned(len) }
+    }
+}
+
+/// A range of physical memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub struct MemoryRegion {
+    /// The first address of the region.
+    pub start: u64,
+    /// The address directly after the region.
+    pub end: u64,
+    /// What the region contains.
+    pub kind: MemoryKind,
+}
+
+impl MemoryRegion {
+    /// Creates a new region.
+    #[inline]
+    #[must_use]
+    pub const fn new(start: u64, end: u64, kind: MemoryKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    /// Returns the length of the region in bytes.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Returns whether the region is empty.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+}
+
+/// Error returned when a [`MemoryMap`] runs out of space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MemoryMapFull;
+
+impl fmt::Display for MemoryMapFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("memory map is full")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MemoryMapFull {}
+
+/// A sanitized map of physical memory with room for `N` regions.
+///
+/// The regions are always sorted, never overlap, and adjacent regions of the
+/// same kind are merged. When regions overlap the kind with the higher
+/// [`MemoryKind::priority`] wins.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryMap<const N: usize> {
+    regions: [MemoryRegion; N],
+    len: usize,
+}
+
+impl<const N: usize> MemoryMap<N> {
+    /// Creates an empty memory map.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            regions: [MemoryRegion::new(0, 0, MemoryKind::Reserved); N],
+            len: 0,
+        }
+    }
+
+    /// Returns the sanitized regions.
+    #[inline]
+    #[must_use]
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions[..self.len]
+    }
+
+    /// Returns the amount of regions.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no regions.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes every region.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Adds a region to the map, splitting and merging existing regions as needed.
+    ///
+    /// Empty regions are ignored. If the map runs out of space the regions
+    /// added so far stay sanitized, but `region` may only be partially applied.
+    pub fn insert(&mut self, region: MemoryRegion) -> Result<(), MemoryMapFull> {
+        let result = self.apply(region);
+
+        self.coalesce();
+
+        result
+    }
+
+    /// Returns the kind of memory at `address`, if it's in the map.
+    #[must_use]
+    pub fn kind_at(&self, address: u64) -> Option<MemoryKind> {
+        self.regions()
+            .iter()
+            .find(|region| (region.start <= address) & (address < region.end))
+            .map(|region| region.kind)
+    }
+
+    /// Returns whether `start..end` is entirely made out of memory of `kind`.
+    #[must_use]
+    pub fn contains(&self, start: u64, end: u64, kind: MemoryKind) -> bool {
+        let mut cursor = start;
+
+        for region in self.regions() {
+            if cursor >= end {
+                break;
+            }
+
+            if region.end <= cursor {
+                continue;
+            }
+
+            if (region.start > cursor) | (region.kind != kind) {
+                return false;
+            }
+
+            cursor = region.end;
+        }
+
+        cursor >= end
+    }
+
+    /// Finds the lowest address at or above `start` where `len` bytes of `kind`
+    /// fit without crossing `end`, aligned to `align`.
+    ///
+    /// `align` must be a power of two.
+    #[must_use]
+    pub fn find(
+        &self,
+        start: u64,
+        end: u64,
+        len: u64,
+        align: u64,
+        kind: MemoryKind,
+    ) -> Option<u64> {
+        self.regions()
+            .iter()
+            .filter(|region| region.kind == kind)
+            .find_map(|region| {
+                let candidate = region.start.max(start).checked_next_multiple_of(align)?;
+                let candidate_end = candidate.checked_add(len)?;
+
+                ((candidate_end <= region.end) & (candidate_end <= end)).then_some(candidate)
+            })
+    }
+
+    /// Returns the total amount of bytes of a given kind.
+    #[must_use]
+    pub fn total(&self, kind: MemoryKind) -> u64 {
+        self.regions()
+            .iter()
+            .filter(|region| region.kind == kind)
+            .map(MemoryRegion::len)
+            .sum()
+    }
+
+    /// Splits `region` into the parts that aren't covered by anything stronger.
+    fn apply(&mut self, region: MemoryRegion) -> Result<(), MemoryMapFull> {
+        let MemoryRegion { end, kind, .. } = region;
+        let mut cursor = region.start;
+        let mut index = 0;
+
+        while cursor < end {
+            // Skip everything that ends before the cursor.
+            while index < self.len && self.regions[index].end <= cursor {
+                index += 1;
+            }
+
+            let next = self.regions().get(index).copied();
+
+            match next {
+                // There's a gap in front of the next region, or no next region at all.
+                Some(next) if next.start > cursor => {
+                    let gap_end = end.min(next.start);
+
+                    self.insert_at(index, MemoryRegion::new(cursor, gap_end, kind))?;
+                    cursor = gap_end;
+                }
+                None => {
+                    self.insert_at(index, MemoryRegion::new(cursor, end, kind))?;
+                    cursor = end;
+                }
+                // The next region covers the cursor.
+                Some(next) => {
+                    let overlap_end = end.min(next.end);
+
+                    if kind.priority() > next.kind.priority() {
+                        index = self.overwrite(index, cursor, overlap_end, kind)?;
+                    }
+
+                    cursor = overlap_end;
+                }
+            }
+
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Inserts a region at an index, shifting everything after it.
+    fn insert_at(&mut self, index: usize, region: MemoryRegion) -> Result<(), MemoryMapFull> {
+        if self.len == N {
+            return Err(MemoryMapFull);
+        }
+
+        self.regions.copy_within(index..self.len, index + 1);
+        self.regions[index] = region;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Changes `start..end` of the region at `index` to `kind`, splitting it if needed.
+    ///
+    /// Returns the index of the region covering `start..end`.
+    fn overwrite(
+        &mut self,
+        mut index: usize,
+        start: u64,
+        end: u64,
+        kind: MemoryKind,
+    ) -> Result<usize, MemoryMapFull> {
+        let old = self.regions[index];
+
+        if old.start < start {
+            self.insert_at(index, MemoryRegion::new(old.start, start, old.kind))?;
+            index += 1;
+        }
+
+        if end < old.end {
+            self.insert_at(index + 1, MemoryRegion::new(end, old.end, old.kind))?;
+        }
+
+        self.regions[index] = MemoryRegion::new(start, end, kind);
+
+        Ok(index)
+    }
+
+    /// Merges adjacent regions of the same kind.
+    fn coalesce(&mut self) {
+        let mut read = 0;
+        let mut write: usize = 0;
+
+        while read < self.len {
+            let region = self.regions[read];
+
+            match write.checked_sub(1).map(|last| &mut self.regions[last]) {
+                Some(last) if (last.end == region.start) & (last.kind == region.kind) => {
+                    last.end = region.end;
+                }
+                _ => {
+                    self.regions[write] = region;
+                    write += 1;
+                }
+            }
+
+            read += 1;
+        }
+
+        self.len = write;
+    }
+}
+
+impl<const N: usize> Default for MemoryMap<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MemoryKind::*;
+
+    fn build(regions: &[(u64, u64, MemoryKind)]) -> MemoryMap<16> {
+        let mut map = MemoryMap::new();
+
+        for &(start, end, kind) in regions {
+            map.insert(MemoryRegion::new(start, end, kind)).unwrap();
+        }
+
+        map
+    }
+
+    fn regions(map: &MemoryMap<16>) -> std::vec::Vec<(u64, u64, MemoryKind)> {
+        map.regions()
+            .iter()
+            .map(|region| (region.start, region.end, region.kind))
+            .collect()
+    }
+
+    #[test]
+    fn sorts_regions() {
+        let map = build(&[
+            (0x100000, 0x200000, Usable),
+            (0x0, 0x9fc00, Usable),
+            (0xf0000, 0x100000, Reserved),
+        ]);
+
+        assert_eq!(
+            regions(&map),
+            [
+                (0x0, 0x9fc00, Usable),
+                (0xf0000, 0x100000, Reserved),
+                (0x100000, 0x200000, Usable),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_adjacent_and_overlapping_regions_of_the_same_kind() {
+        let map = build(&[
+            (0x0, 0x1000, Usable),
+            (0x1000, 0x3000, Usable),
+            (0x2000, 0x5000, Usable),
+        ]);
+
+        assert_eq!(regions(&map), [(0x0, 0x5000, Usable)]);
+    }
+
+    #[test]
+    fn reserved_wins_over_usable() {
+        let map = build(&[(0x0, 0x10000, Usable), (0x4000, 0x8000, Reserved)]);
+
+        assert_eq!(
+            regions(&map),
+            [
+                (0x0, 0x4000, Usable),
+                (0x4000, 0x8000, Reserved),
+                (0x8000, 0x10000, Usable),
+            ]
+        );
+
+        // The order the firmware reports them in must not matter.
+        let map = build(&[(0x4000, 0x8000, Reserved), (0x0, 0x10000, Usable)]);
+
+        assert_eq!(
+            regions(&map),
+            [
+                (0x0, 0x4000, Usable),
+                (0x4000, 0x8000, Reserved),
+                (0x8000, 0x10000, Usable),
+            ]
+        );
+    }
+
+    #[test]
+    fn stronger_kinds_win() {
+        let map = build(&[
+            (0x0, 0x4000, AcpiReclaimable),
+            (0x2000, 0x6000, AcpiNvs),
+            (0x3000, 0x5000, BadMemory),
+            (0x0, 0x8000, Usable),
+        ]);
+
+        assert_eq!(
+            regions(&map),
+            [
+                (0x0, 0x2000, AcpiReclaimable),
+                (0x2000, 0x3000, AcpiNvs),
+                (0x3000, 0x5000, BadMemory),
+                (0x5000, 0x6000, AcpiNvs),
+                (0x6000, 0x8000, Usable),
+            ]
+        );
+    }
+
+    #[test]
+    fn loader_memory_only_beats_usable() {
+        let map = build(&[
+            (0x0, 0x4000, Usable),
+            (0x3000, 0x5000, Reserved),
+            (0x1000, 0x6000, Loader),
+        ]);
+
+        assert_eq!(
+            regions(&map),
+            [
+                (0x0, 0x1000, Usable),
+                (0x1000, 0x3000, Loader),
+                (0x3000, 0x5000, Reserved),
+                (0x5000, 0x6000, Loader),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_multiple_regions_and_gaps() {
+        let map = build(&[
+            (0x1000, 0x2000, Usable),
+            (0x3000, 0x4000, Reserved),
+            (0x0, 0x5000, AcpiNvs),
+        ]);
+
+        assert_eq!(
+            regions(&map),
+            [
+                (0x0, 0x3000, AcpiNvs),
+                (0x3000, 0x4000, Reserved),
+                (0x4000, 0x5000, AcpiNvs),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_empty_regions() {
+        let map = build(&[(0x1000, 0x1000, Usable), (0x2000, 0x1000, Reserved)]);
+
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn messy_firmware_map() {
+        // Duplicated, out of order and overlapping entries, like some real firmware reports.
+        let map = build(&[
+            (0x0, 0x9fc00, Usable),
+            (0x9fc00, 0xa0000, Reserved),
+            (0xe0000, 0x100000, Reserved),
+            (0x100000, 0x7fe0000, Usable),
+            (0x0, 0x9fc00, Usable),
+            (0x7fe0000, 0x8000000, Reserved),
+            (0xfffc0000, 0x100000000, Reserved),
+            (0x7fd0000, 0x7ff0000, AcpiReclaimable),
+        ]);
+
+        assert_eq!(
+            regions(&map),
+            [
+                (0x0, 0x9fc00, Usable),
+                (0x9fc00, 0xa0000, Reserved),
+                (0xe0000, 0x100000, Reserved),
+                (0x100000, 0x7fd0000, Usable),
+                (0x7fd0000, 0x7fe0000, AcpiReclaimable),
+                (0x7fe0000, 0x8000000, Reserved),
+                (0xfffc0000, 0x100000000, Reserved),
+            ]
+        );
+
+        assert_eq!(map.total(Usable), 0x9fc00 + (0x7fd0000 - 0x100000));
+        assert_eq!(map.kind_at(0x7fd0000), Some(AcpiReclaimable));
+        assert_eq!(map.kind_at(0xa0000), None);
+        assert!(map.contains(0x100000, 0x200000, Usable));
+        assert!(!map.contains(0x9f000, 0xa0000, Usable));
+        assert!(!map.contains(0x7f00000, 0x7fe0000, Usable));
+    }
+
+    #[test]
+    fn reports_running_out_of_space() {
+        let mut map = MemoryMap::<2>::new();
+
+        map.insert(MemoryRegion::new(0x0, 0x1000, Usable)).unwrap();
+        map.insert(MemoryRegion::new(0x2000, 0x3000, Usable))
+            .unwrap();
+
+        assert_eq!(
+            map.insert(MemoryRegion::new(0x4000, 0x5000, Usable)),
+            Err(MemoryMapFull)
+        );
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn finds_aligned_memory() {
+        let map = build(&[
+            (0x0, 0x9_f000, Usable),
+            (0x9_f000, 0x10_0000, Reserved),
+            (0x10_0000, 0x10_8000, Usable),
+            (0x10_8000, 0x10_9000, BadMemory),
+            (0x10_9000, 0x100_0000, Usable),
+        ]);
+
+        assert_eq!(
+            map.find(0x10_0000, u64::MAX, 0x8000, 0x1000, Usable),
+            Some(0x10_0000)
+        );
+        assert_eq!(
+            map.find(0x10_0000, u64::MAX, 0x9000, 0x1000, Usable),
+            Some(0x10_9000)
+        );
+        assert_eq!(
+            map.find(0x10_9001, u64::MAX, 0x1000, 0x1000, Usable),
+            Some(0x10_a000)
+        );
+        assert_eq!(map.find(0x10_0000, 0x10_9000, 0x9000, 0x1000, Usable), None);
+        assert_eq!(
+            map.find(0x0, u64::MAX, 0x1000, 0x1000, Reserved),
+            Some(0x9_f000)
+        );
+        assert_eq!(map.find(0x0, u64::MAX, 0x100_0000, 0x1000, Usable), None);
+    }
+
+    #[test]
+    fn converts_e820_entries() {
+        let mut entry = E820Entry {
+            kind: 0xf00,
+            attributes: 1,
+            ..Default::default()
+        };
+        entry.set_base(u64::MAX - 0xfff);
+        entry.set_len(0x2000);
+
+        assert!(!entry.is_ignored());
+        assert_eq!(
+            entry.region(),
+            MemoryRegion::new(u64::MAX - 0xfff, u64::MAX, Reserved)
+        );
+    }
+}
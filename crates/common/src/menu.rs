@@ -0,0 +1,457 @@
+//! The boot menu, without any of the input and output.
+//!
+//! Stage 2 turns keyboard and serial input into [`Key`]s, ticks the menu once a
+//! second, and redraws it whenever it asks to.
+
+use crate::config::{Config, Entry};
+
+/// How long an edited command line may be in bytes.
+pub const CMDLINE_LEN: usize = 256;
+
+/// A key the menu reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    /// The up arrow.
+    Up,
+    /// The down arrow.
+    Down,
+    /// Enter, or carriage return and line feed over serial.
+    Enter,
+    /// Escape on its own, not as part of an escape sequence.
+    Escape,
+    /// Backspace, or delete over serial.
+    Backspace,
+    /// A printable ASCII character.
+    Char(u8),
+}
+
+impl Key {
+    /// Decodes the scancode and ASCII character `int 16h` returns.
+    #[must_use]
+    pub const fn from_bios(scancode: u8, ascii: u8) -> Option<Self> {
+        match (scancode, ascii) {
+            (_, b'\r') => Some(Key::Enter),
+            (_, 0x1b) => Some(Key::Escape),
+            (_, 0x08) => Some(Key::Backspace),
+            // Extended keys come with either zero or 0xe0 depending on the keyboard.
+            (0x48, 0x00 | 0xe0) => Some(Key::Up),
+            (0x50, 0x00 | 0xe0) => Some(Key::Down),
+            (_, 0x20..=0x7e) => Some(Key::Char(ascii)),
+            _ => None,
+        }
+    }
+}
+
+/// Where a [`SerialDecoder`] is within an escape sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum State {
+    /// Not within an escape sequence.
+    Ground,
+    /// After an escape byte.
+    Escape,
+    /// After an escape byte followed by `[` or `O`.
+    Sequence,
+}
+
+/// Turns bytes from a serial terminal into [`Key`]s.
+///
+/// Terminals send the arrow keys as escape sequences, so a lone escape byte is only
+/// a [`Key::Escape`] once [`SerialDecoder::idle`] says nothing followed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SerialDecoder {
+    state: State,
+    /// Whether [`SerialDecoder::idle`] was called since the last byte.
+    waited: bool,
+}
+
+impl SerialDecoder {
+    /// Creates a decoder that isn't within an escape sequence.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            waited: false,
+        }
+    }
+
+    /// Feeds the next byte, returning the key it completes.
+    pub fn feed(&mut self, byte: u8) -> Option<Key> {
+        self.waited = false;
+
+        match (self.state, byte) {
+            (State::Ground, 0x1b) => {
+                self.state = State::Escape;
+                None
+            }
+            (State::Ground, b'\r' | b'\n') => Some(Key::Enter),
+            (State::Ground, 0x08 | 0x7f) => Some(Key::Backspace),
+            (State::Ground, 0x20..=0x7e) => Some(Key::Char(byte)),
+            (State::Ground, _) => None,
+            (State::Escape, b'[' | b'O') => {
+                self.state = State::Sequence;
+                None
+            }
+            // Nobody types escape and another key this quickly, so drop the key.
+            (State::Escape, _) => {
+                self.state = State::Ground;
+                Some(Key::Escape)
+            }
+            (State::Sequence, b'A') => {
+                self.state = State::Ground;
+                Some(Key::Up)
+            }
+            (State::Sequence, b'B') => {
+                self.state = State::Ground;
+                Some(Key::Down)
+            }
+            // Anything else ends with a byte in this range, parameters come before it.
+            (State::Sequence, 0x40..=0x7e) => {
+                self.state = State::Ground;
+                None
+            }
+            (State::Sequence, _) => None,
+        }
+    }
+
+    /// Tells the decoder that no byte arrived for a while.
+    ///
+    /// Call this periodically, it returns [`Key::Escape`] once an escape byte went
+    /// unanswered for two calls in a row.
+    pub fn idle(&mut self) -> Option<Key> {
+        if self.state != State::Escape {
+            return None;
+        }
+
+        if !self.waited {
+            self.waited = true;
+            return None;
+        }
+
+        self.state = State::Ground;
+        self.waited = false;
+
+        Some(Key::Escape)
+    }
+}
+
+impl Default for SerialDecoder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the menu wants done after a key or tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    /// Nothing changed.
+    None,
+    /// Something changed, so draw the menu again.
+    Redraw,
+    /// Boot [`Menu::entry`] with [`Menu::cmdline`].
+    Boot,
+}
+
+/// The state of the boot menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Menu<'a> {
+    config: Config<'a>,
+    len: usize,
+    selected: usize,
+    /// Seconds left until the selected entry boots, `None` once someone pressed a key.
+    remaining: Option<u32>,
+    editing: bool,
+    /// Whether the command line in `cmdline` replaces the one of the entry.
+    edited: bool,
+    cmdline: [u8; CMDLINE_LEN],
+    cmdline_len: usize,
+}
+
+impl<'a> Menu<'a> {
+    /// Creates a menu with the default entry selected, counting down from the
+    /// configured timeout.
+    #[must_use]
+    pub fn new(config: Config<'a>) -> Self {
+        let default = config.default_entry().name;
+
+        Self {
+            config,
+            len: config.entries().count(),
+            selected: config
+                .entries()
+                .position(|entry| entry.name == default)
+                .unwrap_or(0),
+            remaining: Some(config.timeout()),
+            editing: false,
+            edited: false,
+            cmdline: [0; CMDLINE_LEN],
+            cmdline_len: 0,
+        }
+    }
+
+    /// Returns the configuration the entries come from.
+    #[inline]
+    #[must_use]
+    pub const fn config(&self) -> &Config<'a> {
+        &self.config
+    }
+
+    /// Returns the index of the selected entry.
+    #[inline]
+    #[must_use]
+    pub const fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Returns the selected entry.
+    #[must_use]
+    pub fn entry(&self) -> Entry<'a> {
+        // `selected` is always in bounds, and configs always have an entry.
+        self.config.entries().nth(self.selected).unwrap()
+    }
+
+    /// Returns how many seconds are left until the selected entry boots, if we're
+    /// still counting down.
+    #[inline]
+    #[must_use]
+    pub const fn remaining(&self) -> Option<u32> {
+        self.remaining
+    }
+
+    /// Returns whether the command line is being edited.
+    #[inline]
+    #[must_use]
+    pub const fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Returns the command line to boot with, which is the one being edited while
+    /// [`Menu::is_editing`].
+    #[must_use]
+    pub fn cmdline(&self) -> &str {
+        if !(self.editing | self.edited) {
+            return self.entry().cmdline;
+        }
+
+        // Only whole characters ever go in and come out of the buffer.
+        core::str::from_utf8(&self.cmdline[..self.cmdline_len]).unwrap_or_default()
+    }
+
+    /// Counts down by a second.
+    pub fn tick(&mut self) -> Action {
+        match self.remaining {
+            Some(0 | 1) => Action::Boot,
+            Some(remaining) => {
+                self.remaining = Some(remaining - 1);
+                Action::Redraw
+            }
+            None => Action::None,
+        }
+    }
+
+    /// Handles a key press, which also stops the countdown.
+    pub fn key(&mut self, key: Key) -> Action {
+        let counting = self.remaining.take().is_some();
+
+        if self.editing {
+            return self.edit(key);
+        }
+
+        match key {
+            Key::Up => self.selected = self.selected.checked_sub(1).unwrap_or(self.len - 1),
+            Key::Down => self.selected = (self.selected + 1) % self.len,
+            Key::Enter => return Action::Boot,
+            Key::Char(b'e') => {
+                let cmdline = self.entry().cmdline;
+                let mut len = cmdline.len().min(CMDLINE_LEN);
+
+                while !cmdline.is_char_boundary(len) {
+                    len -= 1;
+                }
+
+                self.cmdline[..len].copy_from_slice(&cmdline.as_bytes()[..len]);
+                self.cmdline_len = len;
+                self.editing = true;
+            }
+            _ if counting => {}
+            _ => return Action::None,
+        }
+
+        Action::Redraw
+    }
+
+    /// Handles a key press while editing the command line.
+    fn edit(&mut self, key: Key) -> Action {
+        match key {
+            Key::Enter => {
+                self.editing = false;
+                self.edited = true;
+                return Action::Boot;
+            }
+            Key::Escape => self.editing = false,
+            Key::Backspace => {
+                let last = self.cmdline().chars().next_back();
+                self.cmdline_len -= last.map_or(0, char::len_utf8);
+            }
+            Key::Char(byte) if self.cmdline_len < CMDLINE_LEN => {
+                self.cmdline[self.cmdline_len] = byte;
+                self.cmdline_len += 1;
+            }
+            Key::Char(_) | Key::Up | Key::Down => return Action::None,
+        }
+
+        Action::Redraw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+timeout = 3
+default = two
+
+[one]
+kernel = /boot/one
+
+[two]
+kernel = /boot/two
+cmdline = quiet
+
+[three]
+kernel = /boot/three
+";
+
+    fn menu() -> Menu<'static> {
+        Menu::new(Config::parse(CONFIG).unwrap())
+    }
+
+    #[test]
+    fn decodes_bios_keys() {
+        assert_eq!(Key::from_bios(0x48, 0xe0), Some(Key::Up));
+        assert_eq!(Key::from_bios(0x50, 0x00), Some(Key::Down));
+        assert_eq!(Key::from_bios(0x1c, b'\r'), Some(Key::Enter));
+        assert_eq!(Key::from_bios(0x01, 0x1b), Some(Key::Escape));
+        assert_eq!(Key::from_bios(0x0e, 0x08), Some(Key::Backspace));
+        assert_eq!(Key::from_bios(0x12, b'e'), Some(Key::Char(b'e')));
+        // F1
+        assert_eq!(Key::from_bios(0x3b, 0x00), None);
+    }
+
+    #[test]
+    fn decodes_serial_escape_sequences() {
+        let mut decoder = SerialDecoder::new();
+        let mut feed = |bytes: &[u8]| -> Option<Key> {
+            let mut keys = bytes.iter().filter_map(|&byte| decoder.feed(byte));
+            let key = keys.next();
+            assert_eq!(keys.next(), None);
+            key
+        };
+
+        assert_eq!(feed(b"\x1b[A"), Some(Key::Up));
+        assert_eq!(feed(b"\x1bOB"), Some(Key::Down));
+        // Right arrow, and a modified left arrow, which the menu doesn't use.
+        assert_eq!(feed(b"\x1b[C"), None);
+        assert_eq!(feed(b"\x1b[1;5D"), None);
+        assert_eq!(feed(b"\r"), Some(Key::Enter));
+        assert_eq!(feed(b"\x7f"), Some(Key::Backspace));
+        assert_eq!(feed(b"x"), Some(Key::Char(b'x')));
+    }
+
+    #[test]
+    fn decodes_lone_serial_escape() {
+        let mut decoder = SerialDecoder::new();
+
+        assert_eq!(decoder.feed(0x1b), None);
+        assert_eq!(decoder.idle(), None);
+        assert_eq!(decoder.idle(), Some(Key::Escape));
+        assert_eq!(decoder.idle(), None);
+
+        // A byte in between means the sequence may still be going.
+        assert_eq!(decoder.feed(0x1b), None);
+        assert_eq!(decoder.idle(), None);
+        assert_eq!(decoder.feed(b'['), None);
+        assert_eq!(decoder.idle(), None);
+        assert_eq!(decoder.idle(), None);
+        assert_eq!(decoder.feed(b'A'), Some(Key::Up));
+    }
+
+    #[test]
+    fn counts_down_to_default() {
+        let mut menu = menu();
+
+        assert_eq!(menu.selected(), 1);
+        assert_eq!(menu.remaining(), Some(3));
+        assert_eq!(menu.tick(), Action::Redraw);
+        assert_eq!(menu.tick(), Action::Redraw);
+        assert_eq!(menu.remaining(), Some(1));
+        assert_eq!(menu.tick(), Action::Boot);
+        assert_eq!(menu.entry().name, "two");
+        assert_eq!(menu.cmdline(), "quiet");
+    }
+
+    #[test]
+    fn selects_entries() {
+        let mut menu = menu();
+
+        assert_eq!(menu.key(Key::Down), Action::Redraw);
+        assert_eq!(menu.remaining(), None);
+        assert_eq!(menu.tick(), Action::None);
+        assert_eq!(menu.entry().name, "three");
+
+        // Wraps around both ways.
+        menu.key(Key::Down);
+        assert_eq!(menu.entry().name, "one");
+        menu.key(Key::Up);
+        assert_eq!(menu.entry().name, "three");
+
+        assert_eq!(menu.key(Key::Char(b'x')), Action::None);
+        assert_eq!(menu.key(Key::Enter), Action::Boot);
+        assert_eq!(menu.cmdline(), "");
+    }
+
+    #[test]
+    fn edits_cmdline() {
+        let mut menu = menu();
+
+        assert_eq!(menu.key(Key::Char(b'e')), Action::Redraw);
+        assert!(menu.is_editing());
+        assert_eq!(menu.cmdline(), "quiet");
+
+        // Arrows don't move the selection while editing.
+        assert_eq!(menu.key(Key::Up), Action::None);
+        menu.key(Key::Backspace);
+        menu.key(Key::Backspace);
+        menu.key(Key::Char(b'e'));
+        assert_eq!(menu.cmdline(), "quie");
+
+        // Escape throws the edit away.
+        assert_eq!(menu.key(Key::Escape), Action::Redraw);
+        assert!(!menu.is_editing());
+        assert_eq!(menu.cmdline(), "quiet");
+
+        menu.key(Key::Char(b'e'));
+        for &byte in b" debug" {
+            menu.key(Key::Char(byte));
+        }
+        assert_eq!(menu.key(Key::Enter), Action::Boot);
+        assert_eq!(menu.entry().name, "two");
+        assert_eq!(menu.cmdline(), "quiet debug");
+    }
+
+    #[test]
+    fn edits_multibyte_cmdline() {
+        let mut menu = Menu::new(Config::parse("[a]\nkernel = k\ncmdline = ä").unwrap());
+
+        menu.key(Key::Char(b'e'));
+        menu.key(Key::Char(b'x'));
+        assert_eq!(menu.cmdline(), "äx");
+        menu.key(Key::Backspace);
+        menu.key(Key::Backspace);
+        assert_eq!(menu.cmdline(), "");
+        menu.key(Key::Backspace);
+        assert_eq!(menu.cmdline(), "");
+    }
+}
@@ -0,0 +1,1106 @@
+//! Multiboot2 kernel headers and the boot information handed to such kernels.
+//!
+//! This lets the loader boot any kernel following the [Multiboot2 specification],
+//! not just ours, which gives us plenty of existing kernels to test against.
+//!
+//! [Multiboot2 specification]: https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+
+use core::{convert::Infallible, fmt, ops::Range};
+
+use crate::{
+    elf::{self, program_kind, Elf, Mapper, ProgramHeader},
+    framebuffer::Framebuffer,
+    memory_map::{MemoryKind, MemoryRegion},
+};
+
+/// The magic number a Multiboot2 header starts with.
+pub const HEADER_MAGIC: u32 = 0xe852_50d6;
+
+/// The magic number the kernel finds in `eax`, telling it a Multiboot2 loader booted it.
+pub const BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+/// The architecture field of headers for 32-bit protected mode x86.
+pub const ARCHITECTURE_I386: u32 = 0;
+
+/// How far into the image the header may start.
+pub const SEARCH_LEN: usize = 32 * 1024;
+
+/// The alignment of the header, of every tag in it and of every boot information tag.
+pub const ALIGN: usize = 8;
+
+/// Kinds of tags in the kernel header.
+pub mod header_tag {
+    /// The last tag.
+    pub const END: u16 = 0;
+    /// Boot information tags the kernel wants.
+    pub const INFORMATION_REQUEST: u16 = 1;
+    /// Where to load an image that isn't ELF.
+    pub const ADDRESS: u16 = 2;
+    /// Where to jump to, overriding the ELF entry point.
+    pub const ENTRY_ADDRESS: u16 = 3;
+    /// What kind of console the kernel supports.
+    pub const CONSOLE_FLAGS: u16 = 4;
+    /// The video mode the kernel prefers.
+    pub const FRAMEBUFFER: u16 = 5;
+    /// Modules must be page aligned.
+    pub const MODULE_ALIGN: u16 = 6;
+    /// Don't exit EFI boot services.
+    pub const EFI_BOOT_SERVICES: u16 = 7;
+    /// Where to jump to when booted by 32-bit EFI.
+    pub const ENTRY_ADDRESS_EFI32: u16 = 8;
+    /// Where to jump to when booted by 64-bit EFI.
+    pub const ENTRY_ADDRESS_EFI64: u16 = 9;
+    /// The image may be loaded elsewhere.
+    pub const RELOCATABLE: u16 = 10;
+}
+
+/// Kinds of boot information tags.
+pub mod info_tag {
+    /// The last tag.
+    pub const END: u32 = 0;
+    /// The kernel command line.
+    pub const CMDLINE: u32 = 1;
+    /// The name of the loader.
+    pub const BOOT_LOADER_NAME: u32 = 2;
+    /// A module, like an initial ramdisk.
+    pub const MODULE: u32 = 3;
+    /// The amount of lower and upper memory.
+    pub const BASIC_MEMINFO: u32 = 4;
+    /// The BIOS drive and partition we booted from.
+    pub const BOOTDEV: u32 = 5;
+    /// The memory map.
+    pub const MEMORY_MAP: u32 = 6;
+    /// The framebuffer, or the text mode buffer.
+    pub const FRAMEBUFFER: u32 = 8;
+    /// A copy of the ACPI 1.0 RSDP.
+    pub const ACPI_OLD: u32 = 14;
+    /// A copy of the ACPI 2.0 RSDP.
+    pub const ACPI_NEW: u32 = 15;
+}
+
+/// Bit of the flags of a header tag telling us we may ignore it.
+const OPTIONAL: u16 = 1 << 0;
+
+/// Boot information tags we know how to provide, which kernels may require.
+const SUPPORTED_INFO: [u32; 9] = [
+    info_tag::CMDLINE,
+    info_tag::BOOT_LOADER_NAME,
+    info_tag::MODULE,
+    info_tag::BASIC_MEMINFO,
+    info_tag::BOOTDEV,
+    info_tag::MEMORY_MAP,
+    info_tag::FRAMEBUFFER,
+    info_tag::ACPI_OLD,
+    info_tag::ACPI_NEW,
+];
+
+/// Where the text mode buffer starts, which the framebuffer tag points to in text mode.
+const TEXT_BUFFER: u64 = 0xb8000;
+
+/// Kinds of framebuffers in the framebuffer tag.
+mod framebuffer_kind {
+    pub const RGB: u8 = 1;
+    pub const EGA_TEXT: u8 = 2;
+}
+
+/// Errors that can occur while parsing a header or loading a kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error {
+    /// The header claims to be longer than the image.
+    Truncated,
+    /// The header is for another architecture.
+    UnsupportedArchitecture(u32),
+    /// A tag is too short, runs past the header, or the end tag is missing.
+    BadTag,
+    /// A tag we don't know and aren't allowed to ignore.
+    UnsupportedTag(u16),
+    /// The kernel requires boot information we can't provide.
+    UnsupportedRequest(u32),
+    /// The address tag doesn't match the image, or the kernel doesn't fit below 4 GiB.
+    BadAddress,
+    /// The address tag is present without an entry address tag.
+    MissingEntry,
+    /// The kernel has nothing to load.
+    Empty,
+    /// The kernel is an invalid ELF file.
+    Elf(elf::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated => f.write_str("header is truncated"),
+            Error::UnsupportedArchitecture(architecture) => {
+                write!(f, "unsupported architecture {architecture}")
+            }
+            Error::BadTag => f.write_str("bad header tag"),
+            Error::UnsupportedTag(kind) => write!(f, "unsupported header tag {kind}"),
+            Error::UnsupportedRequest(kind) => {
+                write!(f, "unsupported boot information request {kind}")
+            }
+            Error::BadAddress => f.write_str("bad load address"),
+            Error::MissingEntry => f.write_str("address tag without entry address tag"),
+            Error::Empty => f.write_str("nothing to load"),
+            Error::Elf(err) => write!(f, "bad elf file: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl From<elf::Error> for Error {
+    #[inline]
+    fn from(err: elf::Error) -> Self {
+        Error::Elf(err)
+    }
+}
+
+/// Where to load an image that isn't ELF, all physical addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AddressTag {
+    /// Where the magic number of the header ends up.
+    pub header_address: u32,
+    /// Where the start of the loaded part of the image ends up.
+    pub load_address: u32,
+    /// Where the loaded part of the image ends, zero for the end of the image.
+    pub load_end_address: u32,
+    /// Where the zeroed memory after the image ends, zero if there is none.
+    pub bss_end_address: u32,
+}
+
+/// The video mode the kernel prefers, where zero means no preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FramebufferTag {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Bits per pixel.
+    pub depth: u32,
+}
+
+/// A validated Multiboot2 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Header {
+    offset: usize,
+    address: Option<AddressTag>,
+    entry: Option<u32>,
+    framebuffer: Option<FramebufferTag>,
+}
+
+impl Header {
+    /// Looks for a header in the first [`SEARCH_LEN`] bytes of `image` and validates it.
+    ///
+    /// Returns `None` if there is no header with a valid checksum at all.
+    #[must_use]
+    pub fn find(image: &[u8]) -> Option<Result<Self, Error>> {
+        let search = image.len().min(SEARCH_LEN);
+
+        (0..search.saturating_sub(15))
+            .step_by(ALIGN)
+            .find(|&offset| {
+                let field = |index: usize| u32_at(image, offset + 4 * index).unwrap_or(0);
+                let sum = (0..4).fold(0_u32, |sum, index| sum.wrapping_add(field(index)));
+
+                (field(0) == HEADER_MAGIC) & (sum == 0)
+            })
+            .map(|offset| Self::parse(image, offset))
+    }
+
+    /// Validates the header at `offset`, which has a valid magic number and checksum.
+    fn parse(image: &[u8], offset: usize) -> Result<Self, Error> {
+        let field = |offset| u32_at(image, offset).ok_or(Error::Truncated);
+
+        let architecture = field(offset + 4)?;
+
+        if architecture != ARCHITECTURE_I386 {
+            return Err(Error::UnsupportedArchitecture(architecture));
+        }
+
+        let len = field(offset + 8)? as usize;
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| (len >= 16) & (end <= image.len()))
+            .ok_or(Error::Truncated)?;
+
+        let mut header = Self {
+            offset,
+            address: None,
+            entry: None,
+            framebuffer: None,
+        };
+        let mut cursor = offset + 16;
+
+        loop {
+            if cursor + 8 > end {
+                return Err(Error::BadTag);
+            }
+
+            let kind = u16_at(image, cursor).ok_or(Error::BadTag)?;
+            let flags = u16_at(image, cursor + 2).ok_or(Error::BadTag)?;
+            let size = field(cursor + 4)? as usize;
+            let tag_end = cursor
+                .checked_add(size)
+                .filter(|&tag_end| (size >= 8) & (tag_end <= end))
+                .ok_or(Error::BadTag)?;
+
+            // The fields after the type, flags and size.
+            let tag_field = |index: usize| u32_at(image, cursor + 8 + 4 * index).unwrap_or(0);
+            let expect_fields = |count: usize| match size >= 8 + 4 * count {
+                true => Ok(()),
+                false => Err(Error::BadTag),
+            };
+
+            match kind {
+                header_tag::END => break,
+                header_tag::INFORMATION_REQUEST => {
+                    for index in 0..(size - 8) / 4 {
+                        let request = tag_field(index);
+
+                        if (flags & OPTIONAL == 0) & !SUPPORTED_INFO.contains(&request) {
+                            return Err(Error::UnsupportedRequest(request));
+                        }
+                    }
+                }
+                header_tag::ADDRESS => {
+                    expect_fields(4)?;
+
+                    header.address = Some(AddressTag {
+                        header_address: tag_field(0),
+                        load_address: tag_field(1),
+                        load_end_address: tag_field(2),
+                        bss_end_address: tag_field(3),
+                    });
+                }
+                header_tag::ENTRY_ADDRESS => {
+                    expect_fields(1)?;
+
+                    header.entry = Some(tag_field(0));
+                }
+                header_tag::FRAMEBUFFER => {
+                    expect_fields(3)?;
+
+                    header.framebuffer = Some(FramebufferTag {
+                        width: tag_field(0),
+                        height: tag_field(1),
+                        depth: tag_field(2),
+                    });
+                }
+                // We always have a text console or a framebuffer, modules are always
+                // page aligned, the EFI tags don't matter when booting through the
+                // BIOS, and loading a relocatable kernel where it was linked is fine.
+                header_tag::CONSOLE_FLAGS
+                | header_tag::MODULE_ALIGN
+                | header_tag::EFI_BOOT_SERVICES
+                | header_tag::ENTRY_ADDRESS_EFI32
+                | header_tag::ENTRY_ADDRESS_EFI64
+                | header_tag::RELOCATABLE => {}
+                _ if flags & OPTIONAL != 0 => {}
+                _ => return Err(Error::UnsupportedTag(kind)),
+            }
+
+            cursor = tag_end.next_multiple_of(ALIGN);
+        }
+
+        Ok(header)
+    }
+
+    /// Returns where the header starts in the image.
+    #[inline]
+    #[must_use]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the address tag, if the image isn't loaded as ELF.
+    #[inline]
+    #[must_use]
+    pub const fn address(&self) -> Option<AddressTag> {
+        self.address
+    }
+
+    /// Returns the entry point from the entry address tag, if there is one.
+    #[inline]
+    #[must_use]
+    pub const fn entry(&self) -> Option<u32> {
+        self.entry
+    }
+
+    /// Returns the video mode the kernel prefers, if it wants a framebuffer at all.
+    #[inline]
+    #[must_use]
+    pub const fn framebuffer(&self) -> Option<FramebufferTag> {
+        self.framebuffer
+    }
+
+    /// Returns the physical memory the kernel in `image` occupies once loaded.
+    pub fn load_range(&self, image: &[u8]) -> Result<Range<u64>, Error> {
+        let range = match self.address {
+            Some(address) => self.flat(image, address)?.1,
+            None => {
+                let elf = Elf::parse(image)?;
+                let mut range: Option<Range<u64>> = None;
+
+                // Only the virtual addresses are checked by the ELF parser.
+                for header in loaded(&elf) {
+                    let start = header.physical_address;
+                    let end = start
+                        .checked_add(header.mem_size)
+                        .ok_or(Error::BadAddress)?;
+
+                    range = Some(match range {
+                        Some(range) => range.start.min(start)..range.end.max(end),
+                        None => start..end,
+                    });
+                }
+
+                range.ok_or(Error::Empty)?
+            }
+        };
+
+        if (range.start >= range.end) | (range.end > 1 << 32) {
+            return Err(Error::BadAddress);
+        }
+
+        Ok(range)
+    }
+
+    /// Loads the kernel in `image` into `memory`, and returns its physical entry point.
+    ///
+    /// `memory` is where [`Header::load_range`] starts. ELF segments are loaded at
+    /// their physical addresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `memory` is shorter than [`Header::load_range`].
+    pub fn load(&self, image: &[u8], memory: &mut [u8]) -> Result<u32, Error> {
+        let range = self.load_range(image)?;
+        let memory = &mut memory[..(range.end - range.start) as usize];
+
+        if let Some(address) = self.address {
+            let (data, _) = self.flat(image, address)?;
+            let (file, bss) = memory.split_at_mut(data.len());
+
+            file.copy_from_slice(data);
+            bss.fill(0);
+
+            return self.entry.ok_or(Error::MissingEntry);
+        }
+
+        let elf = Elf::parse(image)?;
+        let mapper = PhysicalMapper {
+            memory,
+            base: range.start,
+        };
+
+        elf.load(mapper, 0).map_err(|err| match err {
+            elf::LoadError::Elf(err) => Error::Elf(err),
+            elf::LoadError::Mapper(never) => match never {},
+        })?;
+
+        if let Some(entry) = self.entry {
+            return Ok(entry);
+        }
+
+        // Higher half kernels link their entry point at its virtual address.
+        let entry = elf.entry();
+        let entry = loaded(&elf)
+            .find(|header| header.memory_range().contains(&entry))
+            .map_or(entry, |header| {
+                entry - header.virtual_address + header.physical_address
+            });
+
+        u32::try_from(entry).map_err(|_| Error::BadAddress)
+    }
+
+    /// Returns the part of `image` the address tag says to load, and the memory
+    /// it ends up in, bss included.
+    fn flat<'a>(&self, image: &'a [u8], tag: AddressTag) -> Result<(&'a [u8], Range<u64>), Error> {
+        let start = tag
+            .header_address
+            .checked_sub(tag.load_address)
+            .and_then(|before| self.offset.checked_sub(before as usize))
+            .ok_or(Error::BadAddress)?;
+
+        let end = match tag.load_end_address {
+            0 => image.len(),
+            load_end => load_end
+                .checked_sub(tag.load_address)
+                .map(|len| start + len as usize)
+                .ok_or(Error::BadAddress)?,
+        };
+
+        let data = image.get(start..end).ok_or(Error::BadAddress)?;
+        let data_end = tag.load_address as u64 + data.len() as u64;
+
+        let end = match tag.bss_end_address as u64 {
+            0 => data_end,
+            bss_end if bss_end >= data_end => bss_end,
+            _ => return Err(Error::BadAddress),
+        };
+
+        Ok((data, tag.load_address as u64..end))
+    }
+}
+
+/// Returns the segments of `elf` that take up memory.
+fn loaded<'a>(elf: &Elf<'a>) -> impl Iterator<Item = ProgramHeader> + 'a {
+    elf.program_headers()
+        .filter(|header| (header.kind == program_kind::LOAD) & (header.mem_size != 0))
+}
+
+/// Maps segments at their physical address, within memory starting at `base`.
+struct PhysicalMapper<'a> {
+    memory: &'a mut [u8],
+    base: u64,
+}
+
+impl Mapper for PhysicalMapper<'_> {
+    type Error = Infallible;
+
+    fn map(&mut self, _address: u64, segment: &ProgramHeader) -> Result<&mut [u8], Infallible> {
+        let start = (segment.physical_address - self.base) as usize;
+
+        Ok(&mut self.memory[start..][..segment.mem_size as usize])
+    }
+}
+
+/// Reads a little endian `u16` at `offset`.
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+/// Reads a little endian `u32` at `offset`.
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+/// Error returned when the boot information doesn't fit into the buffer of an [`InfoBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct InfoFull;
+
+impl fmt::Display for InfoFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("boot information doesn't fit")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InfoFull {}
+
+/// Writes the boot information a kernel finds in `ebx`.
+///
+/// The buffer must be aligned to [`ALIGN`], tags are padded to keep it that way.
+#[derive(Debug)]
+pub struct InfoBuilder<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> InfoBuilder<'a> {
+    /// Starts writing boot information into `buffer`.
+    pub fn new(buffer: &'a mut [u8]) -> Result<Self, InfoFull> {
+        let mut builder = Self { buffer, len: 0 };
+
+        // The total size and a reserved field, the size is filled in by `finish`.
+        builder.push(&[0; 8])?;
+
+        Ok(builder)
+    }
+
+    /// Adds the kernel command line.
+    pub fn cmdline(&mut self, cmdline: &str) -> Result<(), InfoFull> {
+        self.tag(info_tag::CMDLINE, &[cmdline.as_bytes(), &[0]])
+    }
+
+    /// Adds the name of the loader.
+    pub fn boot_loader_name(&mut self, name: &str) -> Result<(), InfoFull> {
+        self.tag(info_tag::BOOT_LOADER_NAME, &[name.as_bytes(), &[0]])
+    }
+
+    /// Adds a module at the physical addresses `module`, described by `string`.
+    pub fn module(&mut self, module: Range<u32>, string: &str) -> Result<(), InfoFull> {
+        self.tag(
+            info_tag::MODULE,
+            &[
+                &module.start.to_le_bytes(),
+                &module.end.to_le_bytes(),
+                string.as_bytes(),
+                &[0],
+            ],
+        )
+    }
+
+    /// Adds how much memory there is from zero and from 1 MiB until the first hole,
+    /// according to the firmware memory map in `regions`.
+    pub fn basic_meminfo(&mut self, regions: &[MemoryRegion]) -> Result<(), InfoFull> {
+        let usable_from = |start: u64| {
+            regions
+                .iter()
+                .filter(|region| matches!(region.kind, MemoryKind::Usable | MemoryKind::Loader))
+                .fold(start, |end, region| {
+                    if (region.start <= end) & (end < region.end) {
+                        region.end
+                    } else {
+                        end
+                    }
+                })
+        };
+
+        let lower = usable_from(0).min(640 * 1024) / 1024;
+        let upper = (usable_from(0x10_0000) - 0x10_0000).min(u32::MAX as u64 * 1024) / 1024;
+
+        self.tag(
+            info_tag::BASIC_MEMINFO,
+            &[&(lower as u32).to_le_bytes(), &(upper as u32).to_le_bytes()],
+        )
+    }
+
+    /// Adds the BIOS drive we booted from, with `u32::MAX` for unknown partitions.
+    pub fn boot_device(
+        &mut self,
+        drive: u32,
+        partition: u32,
+        sub_partition: u32,
+    ) -> Result<(), InfoFull> {
+        self.tag(
+            info_tag::BOOTDEV,
+            &[
+                &drive.to_le_bytes(),
+                &partition.to_le_bytes(),
+                &sub_partition.to_le_bytes(),
+            ],
+        )
+    }
+
+    /// Adds the memory map, where memory of the loader counts as available.
+    pub fn memory_map(&mut self, regions: &[MemoryRegion]) -> Result<(), InfoFull> {
+        const ENTRY_SIZE: u32 = 24;
+        const ENTRY_VERSION: u32 = 0;
+
+        let start = self.start_tag(info_tag::MEMORY_MAP)?;
+
+        self.push(&ENTRY_SIZE.to_le_bytes())?;
+        self.push(&ENTRY_VERSION.to_le_bytes())?;
+
+        for region in regions {
+            let kind = match region.kind {
+                MemoryKind::Loader => MemoryKind::Usable as u32,
+                kind => kind as u32,
+            };
+
+            self.push(&region.start.to_le_bytes())?;
+            self.push(&region.len().to_le_bytes())?;
+            self.push(&kind.to_le_bytes())?;
+            self.push(&[0; 4])?;
+        }
+
+        self.end_tag(start)
+    }
+
+    /// Adds `framebuffer`, or the text mode buffer if it isn't present.
+    pub fn framebuffer(&mut self, framebuffer: &Framebuffer) -> Result<(), InfoFull> {
+        if !framebuffer.is_present() {
+            let (width, height) = (80_u32, 25_u32);
+
+            return self.tag(
+                info_tag::FRAMEBUFFER,
+                &[
+                    &TEXT_BUFFER.to_le_bytes(),
+                    &(width * 2).to_le_bytes(),
+                    &width.to_le_bytes(),
+                    &height.to_le_bytes(),
+                    &[16, framebuffer_kind::EGA_TEXT, 0, 0],
+                ],
+            );
+        }
+
+        self.tag(
+            info_tag::FRAMEBUFFER,
+            &[
+                &framebuffer.address.to_le_bytes(),
+                &framebuffer.pitch.to_le_bytes(),
+                &framebuffer.width.to_le_bytes(),
+                &framebuffer.height.to_le_bytes(),
+                &[framebuffer.bpp, framebuffer_kind::RGB, 0, 0],
+                &[
+                    framebuffer.red.position,
+                    framebuffer.red.size,
+                    framebuffer.green.position,
+                    framebuffer.green.size,
+                    framebuffer.blue.position,
+                    framebuffer.blue.size,
+                ],
+            ],
+        )
+    }
+
+    /// Adds a copy of the ACPI RSDP, picking the tag from its revision.
+    ///
+    /// `rsdp` is the whole structure, 20 bytes for revision 0 and 36 bytes from
+    /// revision 2 on.
+    pub fn rsdp(&mut self, rsdp: &[u8]) -> Result<(), InfoFull> {
+        let kind = match rsdp.get(15) {
+            Some(0) | None => info_tag::ACPI_OLD,
+            Some(_) => info_tag::ACPI_NEW,
+        };
+
+        self.tag(kind, &[rsdp])
+    }
+
+    /// Adds the end tag and fills in the total size, returning the boot information.
+    pub fn finish(mut self) -> Result<&'a mut [u8], InfoFull> {
+        self.tag(info_tag::END, &[])?;
+
+        let Self { buffer, len } = self;
+
+        buffer[..4].copy_from_slice(&(len as u32).to_le_bytes());
+
+        Ok(&mut buffer[..len])
+    }
+
+    /// Adds a tag made up of `parts`.
+    fn tag(&mut self, kind: u32, parts: &[&[u8]]) -> Result<(), InfoFull> {
+        let start = self.start_tag(kind)?;
+
+        for part in parts {
+            self.push(part)?;
+        }
+
+        self.end_tag(start)
+    }
+
+    /// Writes the type of a tag and leaves room for its size, returning where it starts.
+    fn start_tag(&mut self, kind: u32) -> Result<usize, InfoFull> {
+        let start = self.len;
+
+        self.push(&kind.to_le_bytes())?;
+        self.push(&[0; 4])?;
+
+        Ok(start)
+    }
+
+    /// Fills in the size of the tag at `start` and pads it.
+    fn end_tag(&mut self, start: usize) -> Result<(), InfoFull> {
+        let size = (self.len - start) as u32;
+
+        self.buffer[start + 4..start + 8].copy_from_slice(&size.to_le_bytes());
+
+        let padding = self.len.next_multiple_of(ALIGN) - self.len;
+
+        self.push(&[0; ALIGN][..padding])
+    }
+
+    /// Appends `bytes`.
+    fn push(&mut self, bytes: &[u8]) -> Result<(), InfoFull> {
+        let end = self.len + bytes.len();
+
+        self.buffer
+            .get_mut(self.len..end)
+            .ok_or(InfoFull)?
+            .copy_from_slice(bytes);
+        self.len = end;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::ColorMask;
+    use std::{vec, vec::Vec};
+
+    /// Builds a header with `tags`, each given as type, flags and fields.
+    fn header_bytes(architecture: u32, tags: &[(u16, u16, &[u32])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for &(kind, flags, fields) in tags.iter().chain([&(header_tag::END, 0, &[][..])]) {
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            bytes.extend_from_slice(&flags.to_le_bytes());
+            bytes.extend_from_slice(&(8 + 4 * fields.len() as u32).to_le_bytes());
+            fields
+                .iter()
+                .for_each(|field| bytes.extend_from_slice(&field.to_le_bytes()));
+            bytes.resize(bytes.len().next_multiple_of(ALIGN), 0);
+        }
+
+        let len = 16 + bytes.len() as u32;
+        let checksum = 0_u32
+            .wrapping_sub(HEADER_MAGIC)
+            .wrapping_sub(architecture)
+            .wrapping_sub(len);
+
+        [HEADER_MAGIC, architecture, len, checksum]
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .chain(bytes)
+            .collect()
+    }
+
+    /// Builds an ELF32 file with one segment linked at `virtual_address`, loaded
+    /// at `physical_address`, followed by `header` at offset 88.
+    fn elf(virtual_address: u32, physical_address: u32, header: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; 88];
+        let file_size = (88 + header.len()) as u32;
+        let mut put = |offset: usize, value: &[u8]| {
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+        };
+
+        put(0, b"\x7fELF\x01\x01\x01");
+        put(16, &2_u16.to_le_bytes());
+        put(18, &elf::MACHINE_X86.to_le_bytes());
+        put(20, &1_u32.to_le_bytes());
+        put(24, &(virtual_address + 0x58).to_le_bytes());
+        put(28, &52_u32.to_le_bytes());
+        put(40, &52_u16.to_le_bytes());
+        put(42, &32_u16.to_le_bytes());
+        put(44, &1_u16.to_le_bytes());
+
+        put(52, &program_kind::LOAD.to_le_bytes());
+        put(60, &virtual_address.to_le_bytes());
+        put(64, &physical_address.to_le_bytes());
+        put(68, &file_size.to_le_bytes());
+        put(72, &(file_size + 0x100).to_le_bytes());
+
+        bytes.extend_from_slice(header);
+        bytes
+    }
+
+    /// Builds an ELF64 file with one segment linked at `virtual_address`, loaded
+    /// at `physical_address`, followed by `header` at offset 120.
+    fn elf64(virtual_address: u64, physical_address: u64, header: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; 120];
+        let file_size = (120 + header.len()) as u64;
+        let mut put = |offset: usize, value: &[u8]| {
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+        };
+
+        put(0, b"\x7fELF\x02\x01\x01");
+        put(16, &2_u16.to_le_bytes());
+        put(18, &elf::MACHINE_X86_64.to_le_bytes());
+        put(20, &1_u32.to_le_bytes());
+        put(24, &(virtual_address + 0x78).to_le_bytes());
+        put(32, &64_u64.to_le_bytes());
+        put(52, &64_u16.to_le_bytes());
+        put(54, &56_u16.to_le_bytes());
+        put(56, &1_u16.to_le_bytes());
+
+        put(64, &program_kind::LOAD.to_le_bytes());
+        put(80, &virtual_address.to_le_bytes());
+        put(88, &physical_address.to_le_bytes());
+        put(96, &file_size.to_le_bytes());
+        put(104, &(file_size + 0x100).to_le_bytes());
+
+        bytes.extend_from_slice(header);
+        bytes
+    }
+
+    /// Builds a header with just an address tag.
+    fn header_bytes_with_address(address: [u32; 4]) -> Vec<u8> {
+        header_bytes(0, &[(header_tag::ADDRESS, 0, &address)])
+    }
+
+    /// Finds the header in `image`, which must be valid.
+    fn header_of(image: &[u8]) -> Header {
+        Header::find(image).unwrap().unwrap()
+    }
+
+    #[test]
+    fn finds_header() {
+        let mut image = vec![0xcc; 40];
+        // A magic number with a bad checksum, and one that isn't aligned.
+        image[8..12].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
+        image[20..36].copy_from_slice(&header_bytes(0, &[])[..16]);
+        image.extend(header_bytes(
+            0,
+            &[(header_tag::ENTRY_ADDRESS, 0, &[0x10_0040])],
+        ));
+
+        let header = Header::find(&image).unwrap().unwrap();
+
+        assert_eq!(header.offset(), 40);
+        assert_eq!(header.entry(), Some(0x10_0040));
+        assert_eq!(header.address(), None);
+
+        assert_eq!(Header::find(&[0; 64]), None);
+
+        // Too far in.
+        let mut image = vec![0; SEARCH_LEN];
+        image.extend(header_bytes(0, &[]));
+        assert_eq!(Header::find(&image), None);
+    }
+
+    #[test]
+    fn validates_tags() {
+        let parse = |header: &[u8]| Header::find(header).unwrap();
+
+        assert_eq!(
+            parse(&header_bytes(4, &[])),
+            Err(Error::UnsupportedArchitecture(4))
+        );
+        assert_eq!(
+            parse(&header_bytes(0, &[(42, 0, &[])])),
+            Err(Error::UnsupportedTag(42))
+        );
+        assert!(parse(&header_bytes(0, &[(42, OPTIONAL, &[])])).is_ok());
+
+        let request = [info_tag::MEMORY_MAP, 12];
+        assert_eq!(
+            parse(&header_bytes(
+                0,
+                &[(header_tag::INFORMATION_REQUEST, 0, &request)]
+            )),
+            Err(Error::UnsupportedRequest(12))
+        );
+        assert!(parse(&header_bytes(
+            0,
+            &[(header_tag::INFORMATION_REQUEST, OPTIONAL, &request)]
+        ))
+        .is_ok());
+
+        assert_eq!(
+            parse(&header_bytes(0, &[(header_tag::ADDRESS, 0, &[1, 2])])),
+            Err(Error::BadTag)
+        );
+
+        // Cut off the end tag, with the length fixed up to match.
+        let mut truncated = header_bytes(0, &[]);
+        truncated.truncate(16);
+        truncated[8..12].copy_from_slice(&16_u32.to_le_bytes());
+        let checksum = 0_u32.wrapping_sub(HEADER_MAGIC).wrapping_sub(16);
+        truncated[12..16].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(parse(&truncated), Err(Error::BadTag));
+
+        let mut long = header_bytes(0, &[]);
+        long[8..12].copy_from_slice(&64_u32.to_le_bytes());
+        let checksum = 0_u32.wrapping_sub(HEADER_MAGIC).wrapping_sub(64);
+        long[12..16].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(parse(&long), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn loads_with_address_tag() {
+        let mut image = vec![0xaa; 16];
+        let address = [0x10_0010, 0x10_0000, 0, 0x10_1000];
+        image.extend(header_bytes(
+            0,
+            &[
+                (header_tag::ADDRESS, 0, &address),
+                (header_tag::ENTRY_ADDRESS, 0, &[0x10_0008]),
+                (header_tag::FRAMEBUFFER, OPTIONAL, &[1024, 768, 32]),
+            ],
+        ));
+
+        let header = Header::find(&image).unwrap().unwrap();
+
+        assert_eq!(
+            header.framebuffer(),
+            Some(FramebufferTag {
+                width: 1024,
+                height: 768,
+                depth: 32
+            })
+        );
+        assert_eq!(header.load_range(&image), Ok(0x10_0000..0x10_1000));
+
+        let mut memory = vec![0x55; 0x1000];
+
+        assert_eq!(header.load(&image, &mut memory), Ok(0x10_0008));
+        assert_eq!(&memory[..image.len()], &image[..]);
+        assert!(memory[image.len()..].iter().all(|&byte| byte == 0));
+
+        // The header can't be before the start of the image.
+        let mut image = header_bytes_with_address([0x10_0020, 0x10_0000, 0, 0]);
+        assert_eq!(header_of(&image).load_range(&image), Err(Error::BadAddress));
+
+        // Nor can bss end before the data.
+        image = header_bytes_with_address([0x10_0000, 0x10_0000, 0, 0x10_0001]);
+        assert_eq!(header_of(&image).load_range(&image), Err(Error::BadAddress));
+
+        let image = header_bytes_with_address([0x10_0000, 0x10_0000, 0, 0]);
+        assert_eq!(
+            header_of(&image).load(&image, &mut memory),
+            Err(Error::MissingEntry)
+        );
+    }
+
+    #[test]
+    fn loads_elf_at_physical_address() {
+        let image = elf(0xc020_0000, 0x20_0000, &header_bytes(0, &[]));
+        let header = header_of(&image);
+
+        assert_eq!(header.offset(), 88);
+        assert_eq!(
+            header.load_range(&image),
+            Ok(0x20_0000..0x20_0000 + image.len() as u64 + 0x100)
+        );
+
+        let mut memory = vec![0x55; image.len() + 0x100];
+
+        // The virtual entry point is translated to its physical address.
+        assert_eq!(header.load(&image, &mut memory), Ok(0x20_0058));
+        assert_eq!(&memory[..image.len()], &image[..]);
+        assert!(memory[image.len()..].iter().all(|&byte| byte == 0));
+
+        // Unless the entry address tag overrides it.
+        let image = elf(
+            0x20_0000,
+            0x20_0000,
+            &header_bytes(0, &[(header_tag::ENTRY_ADDRESS, 0, &[0x20_1234])]),
+        );
+        let mut memory = vec![0; image.len() + 0x100];
+        assert_eq!(header_of(&image).load(&image, &mut memory), Ok(0x20_1234));
+
+        let image = elf(0x20_0000, 0xffff_ff00, &header_bytes(0, &[]));
+        assert_eq!(header_of(&image).load_range(&image), Err(Error::BadAddress));
+
+        let image = elf64(0x20_0000, 0x20_0000, &header_bytes(0, &[]));
+        assert_eq!(
+            header_of(&image).load_range(&image),
+            Ok(0x20_0000..0x20_0000 + image.len() as u64 + 0x100)
+        );
+
+        // A physical address that wraps around mustn't give a small range.
+        let image = elf64(0x20_0000, u64::MAX - 0xff, &header_bytes(0, &[]));
+        assert_eq!(header_of(&image).load_range(&image), Err(Error::BadAddress));
+        assert_eq!(
+            header_of(&image).load(&image, &mut [0; 0x1000]),
+            Err(Error::BadAddress)
+        );
+    }
+
+    /// Splits boot information into its tags, checking sizes and alignment.
+    fn tags(info: &[u8]) -> Vec<(u32, &[u8])> {
+        assert_eq!(u32_at(info, 0), Some(info.len() as u32));
+
+        let mut tags = Vec::new();
+        let mut cursor = 8;
+
+        loop {
+            assert_eq!(cursor % ALIGN, 0);
+
+            let kind = u32_at(info, cursor).unwrap();
+            let size = u32_at(info, cursor + 4).unwrap() as usize;
+
+            if kind == info_tag::END {
+                assert_eq!(size, 8);
+                assert_eq!(cursor + size, info.len());
+                return tags;
+            }
+
+            tags.push((kind, &info[cursor + 8..cursor + size]));
+            cursor = (cursor + size).next_multiple_of(ALIGN);
+        }
+    }
+
+    #[test]
+    fn builds_boot_information() {
+        let regions = [
+            MemoryRegion::new(0, 0x9fc00, MemoryKind::Usable),
+            MemoryRegion::new(0xf0000, 0x10_0000, MemoryKind::Reserved),
+            MemoryRegion::new(0x10_0000, 0x20_0000, MemoryKind::Loader),
+            MemoryRegion::new(0x20_0000, 0x800_0000, MemoryKind::Usable),
+            MemoryRegion::new(0x800_0000, 0x801_0000, MemoryKind::AcpiReclaimable),
+        ];
+        let framebuffer = Framebuffer {
+            address: 0xfd00_0000,
+            size: 1024 * 768 * 4,
+            width: 1024,
+            height: 768,
+            pitch: 4096,
+            bpp: 32,
+            red: ColorMask::new(16, 8),
+            green: ColorMask::new(8, 8),
+            blue: ColorMask::new(0, 8),
+            reserved: [0; 5],
+        };
+
+        let mut buffer = [0; 512];
+        let mut builder = InfoBuilder::new(&mut buffer).unwrap();
+
+        builder.cmdline("console=ttyS0").unwrap();
+        builder.boot_loader_name("mrow").unwrap();
+        builder.module(0x30_0000..0x30_1000, "initrd").unwrap();
+        builder.basic_meminfo(&regions).unwrap();
+        builder.boot_device(0x80, u32::MAX, u32::MAX).unwrap();
+        builder.memory_map(&regions).unwrap();
+        builder.framebuffer(&framebuffer).unwrap();
+        builder.rsdp(&[0; 20]).unwrap();
+
+        let info = builder.finish().unwrap();
+        let tags = tags(info);
+        let kinds: Vec<_> = tags.iter().map(|&(kind, _)| kind).collect();
+
+        assert_eq!(
+            kinds,
+            [
+                info_tag::CMDLINE,
+                info_tag::BOOT_LOADER_NAME,
+                info_tag::MODULE,
+                info_tag::BASIC_MEMINFO,
+                info_tag::BOOTDEV,
+                info_tag::MEMORY_MAP,
+                info_tag::FRAMEBUFFER,
+                info_tag::ACPI_OLD,
+            ]
+        );
+
+        assert_eq!(tags[0].1, b"console=ttyS0\0");
+        assert_eq!(&tags[2].1[8..], b"initrd\0");
+        assert_eq!(u32_at(tags[2].1, 0), Some(0x30_0000));
+        assert_eq!(u32_at(tags[3].1, 0), Some(639));
+        assert_eq!(u32_at(tags[3].1, 4), Some(0x07f0_0000 / 1024));
+
+        let memory_map = tags[5].1;
+        assert_eq!(memory_map.len(), 8 + 24 * regions.len());
+        assert_eq!(u32_at(memory_map, 0), Some(24));
+        let kinds: Vec<_> = memory_map[8..]
+            .chunks(24)
+            .map(|entry| u32_at(entry, 16).unwrap())
+            .collect();
+        assert_eq!(kinds, [1, 2, 1, 1, 3]);
+        assert_eq!(u32_at(&memory_map[8 + 24..], 8), Some(0x10000));
+
+        let framebuffer = tags[6].1;
+        assert_eq!(framebuffer.len(), 30);
+        assert_eq!(framebuffer[20], 32);
+        assert_eq!(framebuffer[21], framebuffer_kind::RGB);
+        assert_eq!(&framebuffer[24..], [16, 8, 8, 8, 0, 8]);
+
+        assert_eq!(
+            InfoBuilder::new(&mut [0; 16])
+                .unwrap()
+                .cmdline("too long")
+                .unwrap_err(),
+            InfoFull
+        );
+    }
+
+    #[test]
+    fn describes_text_mode() {
+        let mut buffer = [0; 64];
+        let mut builder = InfoBuilder::new(&mut buffer).unwrap();
+
+        builder.framebuffer(&Framebuffer::NONE).unwrap();
+        builder.rsdp(&[2; 36]).unwrap_err();
+
+        let mut buffer = [0; 128];
+        let mut builder = InfoBuilder::new(&mut buffer).unwrap();
+
+        builder.framebuffer(&Framebuffer::NONE).unwrap();
+        builder.rsdp(&[2; 36]).unwrap();
+
+        let info = builder.finish().unwrap();
+        let tags = tags(info);
+
+        assert_eq!(u32_at(tags[0].1, 0), Some(TEXT_BUFFER as u32));
+        assert_eq!(tags[0].1[21], framebuffer_kind::EGA_TEXT);
+        assert_eq!(tags[1].0, info_tag::ACPI_NEW);
+    }
+}
@@ -0,0 +1,803 @@
+//! 4-level page tables.
+//!
+//! [`Mapper`] edits tables through a window where all of physical memory is
+//! mapped, so it works with the loader's identity mapping as well as the
+//! kernel's [direct map](PHYSICAL_MAP_BASE).
+
+use core::fmt;
+
+#[cfg(feature = "frame")]
+use crate::frame::FrameAllocator;
+
+/// The size of a small page and of every page table.
+pub const PAGE_SIZE: u64 = 0x1000;
+
+/// How many entries a table has.
+pub const ENTRIES: usize = 512;
+
+/// The bits of an entry holding the physical address.
+pub const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Where the kernel maps all of physical memory, at the start of the higher half.
+pub const PHYSICAL_MAP_BASE: u64 = 0xffff_8000_0000_0000;
+
+/// Bits of an [`Entry`].
+pub mod flags {
+    /// The entry maps something.
+    pub const PRESENT: u64 = 1 << 0;
+    /// The entry allows writes.
+    pub const WRITABLE: u64 = 1 << 1;
+    /// The entry is accessible from ring 3.
+    pub const USER: u64 = 1 << 2;
+    /// Writes go straight to memory.
+    pub const WRITE_THROUGH: u64 = 1 << 3;
+    /// The memory isn't cached.
+    pub const NO_CACHE: u64 = 1 << 4;
+    /// The entry was used for a translation, set by the CPU.
+    pub const ACCESSED: u64 = 1 << 5;
+    /// The page was written to, set by the CPU.
+    pub const DIRTY: u64 = 1 << 6;
+    /// The entry maps a large page instead of pointing to a table.
+    ///
+    /// Only valid in a PDPT or page directory.
+    pub const HUGE: u64 = 1 << 7;
+    /// The translation survives address space switches, if `CR4.PGE` is set.
+    pub const GLOBAL: u64 = 1 << 8;
+    /// Nothing can be executed from the page, if `EFER.NXE` is set.
+    pub const NO_EXECUTE: u64 = 1 << 63;
+}
+
+/// An entry in any of the four levels of page tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(transparent)]
+pub struct Entry(pub u64);
+
+impl Entry {
+    /// An entry that maps nothing.
+    pub const UNUSED: Self = Self(0);
+
+    /// Creates an entry pointing at `address`, which must be page aligned.
+    #[inline]
+    #[must_use]
+    pub const fn new(address: u64, flags: u64) -> Self {
+        Self((address & ADDRESS_MASK) | (flags & !ADDRESS_MASK))
+    }
+
+    /// Returns the physical address of the page or table the entry points at.
+    #[inline]
+    #[must_use]
+    pub const fn address(self) -> u64 {
+        self.0 & ADDRESS_MASK
+    }
+
+    /// Returns the [`flags`] of the entry.
+    #[inline]
+    #[must_use]
+    pub const fn flags(self) -> u64 {
+        self.0 & !ADDRESS_MASK
+    }
+
+    /// Returns whether the entry maps something.
+    #[inline]
+    #[must_use]
+    pub const fn is_present(self) -> bool {
+        self.0 & flags::PRESENT != 0
+    }
+
+    /// Returns whether the entry maps a large page rather than pointing to a table.
+    #[inline]
+    #[must_use]
+    pub const fn is_huge(self) -> bool {
+        self.0 & flags::HUGE != 0
+    }
+}
+
+/// A page table of any level.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[repr(C, align(4096))]
+pub struct PageTable {
+    pub entries: [Entry; ENTRIES],
+}
+
+impl PageTable {
+    /// Creates a table that maps nothing.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: [Entry::UNUSED; ENTRIES],
+        }
+    }
+}
+
+impl Default for PageTable {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One of the four levels of page tables, from the top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// The page map level 4, whose address is in `CR3`.
+    Pml4,
+    /// The page directory pointer table, which can map 1 GiB pages.
+    Pdpt,
+    /// The page directory, which can map 2 MiB pages.
+    Pd,
+    /// The page table, which maps 4 KiB pages.
+    Pt,
+}
+
+impl Level {
+    /// Returns the lowest bit of an address this level translates.
+    #[inline]
+    #[must_use]
+    pub const fn shift(self) -> u32 {
+        match self {
+            Level::Pml4 => 39,
+            Level::Pdpt => 30,
+            Level::Pd => 21,
+            Level::Pt => 12,
+        }
+    }
+
+    /// Returns the index of the entry translating `address` at this level.
+    #[inline]
+    #[must_use]
+    pub const fn index(self, address: u64) -> usize {
+        ((address >> self.shift()) as usize) & (ENTRIES - 1)
+    }
+
+    /// Returns the level the entries of this one point to.
+    #[inline]
+    #[must_use]
+    pub const fn next(self) -> Option<Self> {
+        match self {
+            Level::Pml4 => Some(Level::Pdpt),
+            Level::Pdpt => Some(Level::Pd),
+            Level::Pd => Some(Level::Pt),
+            Level::Pt => None,
+        }
+    }
+
+    /// Returns the size of the pages entries at this level can map.
+    #[inline]
+    #[must_use]
+    pub const fn page_size(self) -> Option<PageSize> {
+        match self {
+            Level::Pml4 => None,
+            Level::Pdpt => Some(PageSize::Size1G),
+            Level::Pd => Some(PageSize::Size2M),
+            Level::Pt => Some(PageSize::Size4K),
+        }
+    }
+}
+
+/// The size of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PageSize {
+    /// A page mapped by a page table.
+    Size4K,
+    /// A large page mapped by a page directory.
+    Size2M,
+    /// A huge page mapped by a PDPT, if the CPU supports it.
+    Size1G,
+}
+
+impl PageSize {
+    /// Returns the size in bytes.
+    #[inline]
+    #[must_use]
+    pub const fn bytes(self) -> u64 {
+        1 << self.level().shift()
+    }
+
+    /// Returns the level the page is mapped at.
+    #[inline]
+    #[must_use]
+    pub const fn level(self) -> Level {
+        match self {
+            PageSize::Size4K => Level::Pt,
+            PageSize::Size2M => Level::Pd,
+            PageSize::Size1G => Level::Pdpt,
+        }
+    }
+}
+
+/// Returns whether `address` is canonical, with bits 48 to 63 copies of bit 47.
+#[inline]
+#[must_use]
+pub const fn is_canonical(address: u64) -> bool {
+    (((address << 16) as i64) >> 16) as u64 == address
+}
+
+/// Errors that can occur while changing mappings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error {
+    /// An address isn't aligned to the size of the page.
+    Misaligned,
+    /// The virtual address isn't canonical.
+    NotCanonical,
+    /// Something is already mapped at the address.
+    AlreadyMapped,
+    /// Nothing is mapped at the address.
+    NotMapped,
+    /// A larger page already covers the address.
+    HugePage,
+    /// There's no frame left for a new table.
+    OutOfFrames,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Misaligned => f.write_str("address is not aligned to the page size"),
+            Error::NotCanonical => f.write_str("address is not canonical"),
+            Error::AlreadyMapped => f.write_str("address is already mapped"),
+            Error::NotMapped => f.write_str("address is not mapped"),
+            Error::HugePage => f.write_str("address is part of a larger page"),
+            Error::OutOfFrames => f.write_str("out of frames for page tables"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Where [`Mapper`] gets frames for new page tables from.
+pub trait FrameSource {
+    /// Returns the physical address of an unused frame.
+    fn allocate_frame(&mut self) -> Option<u64>;
+}
+
+#[cfg(feature = "frame")]
+impl FrameSource for FrameAllocator<'_> {
+    #[inline]
+    fn allocate_frame(&mut self) -> Option<u64> {
+        self.allocate()
+    }
+}
+
+/// A page whose translation may still be cached in the TLB.
+#[derive(Debug, PartialEq, Eq)]
+#[must_use = "the TLB may still hold the old translation"]
+pub struct Flush(u64);
+
+impl Flush {
+    /// Returns the virtual address of the page.
+    #[inline]
+    pub const fn address(&self) -> u64 {
+        self.0
+    }
+
+    /// Invalidates the translation on this processor.
+    ///
+    /// This only works in ring 0, and other processors have to be told separately.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub fn flush(self) {
+        unsafe {
+            core::arch::asm!("invlpg [{}]", in(reg) self.0, options(nostack, preserves_flags));
+        }
+    }
+
+    /// Leaves the TLB alone, as the tables aren't active or the whole TLB gets
+    /// flushed anyway.
+    #[inline]
+    pub fn ignore(self) {}
+}
+
+/// A page that is mapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Mapping {
+    /// The physical address of the start of the page.
+    pub address: u64,
+    /// The size of the page.
+    pub size: PageSize,
+    /// The [`flags`] of the entry mapping the page.
+    pub flags: u64,
+}
+
+/// Changes and looks up mappings in a set of page tables.
+#[derive(Debug)]
+pub struct Mapper {
+    pml4: u64,
+    offset: u64,
+}
+
+impl Mapper {
+    /// Creates a mapper for the PML4 at physical address `pml4`, with physical
+    /// memory mapped at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// Every table reachable from `pml4` must be accessible at its physical
+    /// address plus `offset`, and nothing else may change the tables while the
+    /// mapper exists.
+    #[inline]
+    #[must_use]
+    pub const unsafe fn new(pml4: u64, offset: u64) -> Self {
+        Self { pml4, offset }
+    }
+
+    /// Returns the physical address of the PML4.
+    #[inline]
+    #[must_use]
+    pub const fn pml4(&self) -> u64 {
+        self.pml4
+    }
+
+    /// Returns where physical memory is mapped.
+    #[inline]
+    #[must_use]
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the page `address` is in, if it's mapped.
+    #[must_use]
+    pub fn translate(&self, address: u64) -> Option<Mapping> {
+        let (entry, size) = self.leaf(address)?;
+        let entry = unsafe { entry.read() };
+
+        Some(Mapping {
+            address: entry.address() & !(size.bytes() - 1),
+            size,
+            flags: entry.flags(),
+        })
+    }
+
+    /// Returns the physical address `address` is mapped to.
+    #[must_use]
+    pub fn translate_address(&self, address: u64) -> Option<u64> {
+        let mapping = self.translate(address)?;
+
+        Some(mapping.address + (address & (mapping.size.bytes() - 1)))
+    }
+
+    /// Maps the page of `size` at `virt` to `phys`.
+    ///
+    /// [`flags::PRESENT`], and [`flags::HUGE`] for large pages, are added to
+    /// `flags`. Missing tables are allocated from `frames`, and tables leading to
+    /// a [`flags::USER`] page are made accessible from ring 3.
+    ///
+    /// # Safety
+    ///
+    /// Mapping memory that is in use elsewhere breaks whatever is using it.
+    pub unsafe fn map<F: FrameSource + ?Sized>(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: PageSize,
+        flags: u64,
+        frames: &mut F,
+    ) -> Result<Flush, Error> {
+        if !is_canonical(virt) {
+            return Err(Error::NotCanonical);
+        }
+
+        if (virt | phys) & (size.bytes() - 1) != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        let mut table = self.table(self.pml4);
+        let mut level = Level::Pml4;
+
+        while level != size.level() {
+            let entry = unsafe { &mut (*table).entries[level.index(virt)] };
+
+            if !entry.is_present() {
+                let frame = frames.allocate_frame().ok_or(Error::OutOfFrames)?;
+
+                unsafe { self.table(frame).write(PageTable::new()) };
+
+                *entry = Entry::new(frame, flags::PRESENT | flags::WRITABLE);
+            } else if entry.is_huge() {
+                return Err(Error::HugePage);
+            }
+
+            entry.0 |= flags & flags::USER;
+
+            table = self.table(entry.address());
+            level = level.next().unwrap();
+        }
+
+        let entry = unsafe { &mut (*table).entries[level.index(virt)] };
+
+        if entry.is_present() {
+            return Err(Error::AlreadyMapped);
+        }
+
+        *entry = Entry::new(phys, leaf_flags(size, flags));
+
+        Ok(Flush(virt))
+    }
+
+    /// Maps `len` bytes at `virt` to the same amount at `phys`, using pages of `size`.
+    ///
+    /// Stops at the first error, leaving everything mapped so far in place. Only
+    /// new mappings are created, so there's nothing to flush.
+    ///
+    /// # Safety
+    ///
+    /// See [`Mapper::map`].
+    pub unsafe fn map_range<F: FrameSource + ?Sized>(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        len: u64,
+        size: PageSize,
+        flags: u64,
+        frames: &mut F,
+    ) -> Result<(), Error> {
+        for offset in (0..len).step_by(size.bytes() as usize) {
+            unsafe { self.map(virt + offset, phys + offset, size, flags, frames)? }.ignore();
+        }
+
+        Ok(())
+    }
+
+    /// Removes the page `virt` is the start of, and returns what was mapped.
+    ///
+    /// Tables that end up empty are kept around.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may be using the page anymore.
+    pub unsafe fn unmap(&mut self, virt: u64) -> Result<(Mapping, Flush), Error> {
+        let (entry, size) = self.leaf(virt).ok_or(Error::NotMapped)?;
+
+        if virt & (size.bytes() - 1) != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        let old = unsafe { entry.replace(Entry::UNUSED) };
+
+        Ok((
+            Mapping {
+                address: old.address() & !(size.bytes() - 1),
+                size,
+                flags: old.flags(),
+            },
+            Flush(virt),
+        ))
+    }
+
+    /// Replaces the [`flags`] of the page `virt` is the start of.
+    ///
+    /// [`flags::PRESENT`] and [`flags::HUGE`] are kept as they are. Tables
+    /// leading to the page aren't touched, so making a page accessible from ring
+    /// 3 this way only works if they already are.
+    ///
+    /// # Safety
+    ///
+    /// Taking away permissions from a page that is in use breaks whatever is
+    /// using it.
+    pub unsafe fn protect(&mut self, virt: u64, flags: u64) -> Result<Flush, Error> {
+        let (entry, size) = self.leaf(virt).ok_or(Error::NotMapped)?;
+
+        if virt & (size.bytes() - 1) != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        unsafe {
+            let old = entry.read();
+            entry.write(Entry::new(old.address(), leaf_flags(size, flags)));
+        }
+
+        Ok(Flush(virt))
+    }
+
+    /// Returns the entry mapping `address` and the size of the page it maps.
+    fn leaf(&self, address: u64) -> Option<(*mut Entry, PageSize)> {
+        if !is_canonical(address) {
+            return None;
+        }
+
+        let mut table = self.table(self.pml4);
+        let mut level = Level::Pml4;
+
+        loop {
+            let entry = unsafe { &raw mut (*table).entries[level.index(address)] };
+            let value = unsafe { entry.read() };
+
+            if !value.is_present() {
+                return None;
+            }
+
+            if let Some(size) = level.page_size() {
+                if value.is_huge() | (level == Level::Pt) {
+                    return Some((entry, size));
+                }
+            }
+
+            table = self.table(value.address());
+            level = level.next()?;
+        }
+    }
+
+    /// Returns where the table at physical address `phys` can be accessed.
+    #[inline]
+    fn table(&self, phys: u64) -> *mut PageTable {
+        self.offset.wrapping_add(phys) as usize as *mut PageTable
+    }
+}
+
+/// Returns the flags of an entry mapping a page of `size`.
+#[inline]
+const fn leaf_flags(size: PageSize, flags: u64) -> u64 {
+    // The same bit is the PAT bit in page tables.
+    let huge = match size {
+        PageSize::Size4K => 0,
+        PageSize::Size2M | PageSize::Size1G => flags::HUGE,
+    };
+
+    (flags & !flags::HUGE) | flags::PRESENT | huge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::boxed::Box;
+
+    /// Pretend physical memory, made out of page tables.
+    ///
+    /// Physical address 0 is the PML4, frames are handed out after it.
+    struct Memory {
+        tables: Box<[PageTable]>,
+        used: usize,
+    }
+
+    impl Memory {
+        fn new(frames: usize) -> Self {
+            Self {
+                tables: (0..frames).map(|_| PageTable::new()).collect(),
+                used: 1,
+            }
+        }
+
+        fn mapper(&mut self) -> Mapper {
+            unsafe { Mapper::new(0, self.tables.as_mut_ptr() as u64) }
+        }
+    }
+
+    impl FrameSource for Memory {
+        fn allocate_frame(&mut self) -> Option<u64> {
+            let frame = self.used;
+
+            (frame < self.tables.len()).then(|| {
+                self.used += 1;
+
+                // Make sure the mapper clears it.
+                self.tables[frame].entries.fill(Entry(u64::MAX));
+
+                frame as u64 * PAGE_SIZE
+            })
+        }
+    }
+
+    #[test]
+    fn levels() {
+        let address = 0xffff_ffff_8020_3123;
+
+        assert_eq!(Level::Pml4.index(address), 511);
+        assert_eq!(Level::Pdpt.index(address), 510);
+        assert_eq!(Level::Pd.index(address), 1);
+        assert_eq!(Level::Pt.index(address), 3);
+        assert_eq!(PageSize::Size2M.bytes(), 0x20_0000);
+        assert_eq!(PageSize::Size1G.bytes(), 0x4000_0000);
+
+        assert!(is_canonical(address));
+        assert!(is_canonical(0x7fff_ffff_ffff));
+        assert!(!is_canonical(0x8000_0000_0000));
+        assert!(!is_canonical(0xffff_0000_0000_0000));
+    }
+
+    #[test]
+    fn maps_and_unmaps_small_pages() {
+        let mut memory = Memory::new(8);
+        let mut mapper = memory.mapper();
+        let flags = flags::WRITABLE | flags::NO_EXECUTE;
+
+        unsafe {
+            mapper.map(
+                0xffff_ffff_8000_1000,
+                0x23_4000,
+                PageSize::Size4K,
+                flags,
+                &mut memory,
+            )
+        }
+        .unwrap()
+        .ignore();
+
+        // One table for each level below the PML4.
+        assert_eq!(memory.used, 4);
+        assert_eq!(
+            mapper.translate(0xffff_ffff_8000_1fff),
+            Some(Mapping {
+                address: 0x23_4000,
+                size: PageSize::Size4K,
+                flags: flags | flags::PRESENT
+            })
+        );
+        assert_eq!(
+            mapper.translate_address(0xffff_ffff_8000_1abc),
+            Some(0x23_4abc)
+        );
+        assert_eq!(mapper.translate(0xffff_ffff_8000_2000), None);
+
+        // The next page shares all the tables.
+        unsafe {
+            mapper.map(
+                0xffff_ffff_8000_2000,
+                0x50_0000,
+                PageSize::Size4K,
+                0,
+                &mut memory,
+            )
+        }
+        .unwrap()
+        .ignore();
+        assert_eq!(memory.used, 4);
+
+        let (mapping, flush) = unsafe { mapper.unmap(0xffff_ffff_8000_1000) }.unwrap();
+
+        assert_eq!(mapping.address, 0x23_4000);
+        assert_eq!(flush.address(), 0xffff_ffff_8000_1000);
+        flush.ignore();
+
+        assert_eq!(mapper.translate(0xffff_ffff_8000_1000), None);
+        assert_eq!(
+            unsafe { mapper.unmap(0xffff_ffff_8000_1000) },
+            Err(Error::NotMapped)
+        );
+        assert_eq!(
+            mapper.translate_address(0xffff_ffff_8000_2010),
+            Some(0x50_0010)
+        );
+    }
+
+    #[test]
+    fn maps_large_pages() {
+        let mut memory = Memory::new(8);
+        let mut mapper = memory.mapper();
+
+        unsafe {
+            mapper
+                .map(0x4000_0000, 0x8000_0000, PageSize::Size1G, 0, &mut memory)
+                .unwrap()
+                .ignore();
+            mapper
+                .map(0x20_0000, 0x60_0000, PageSize::Size2M, 0, &mut memory)
+                .unwrap()
+                .ignore();
+        }
+
+        let mapping = mapper.translate(0x7fff_ffff).unwrap();
+
+        assert_eq!(mapping.size, PageSize::Size1G);
+        assert_eq!(mapping.flags, flags::PRESENT | flags::HUGE);
+        assert_eq!(mapper.translate_address(0x4123_4567), Some(0x8123_4567));
+        assert_eq!(mapper.translate_address(0x21_2345), Some(0x61_2345));
+
+        let mut map = |virt, phys, size| unsafe { mapper.map(virt, phys, size, 0, &mut memory) };
+
+        assert_eq!(map(0x4020_0000, 0, PageSize::Size2M), Err(Error::HugePage));
+        assert_eq!(map(0x20_1000, 0, PageSize::Size4K), Err(Error::HugePage));
+        assert_eq!(
+            map(0x20_0000, 0, PageSize::Size2M),
+            Err(Error::AlreadyMapped)
+        );
+        assert_eq!(map(0x40_1000, 0, PageSize::Size2M), Err(Error::Misaligned));
+        assert_eq!(
+            map(0x40_0000, 0x1000, PageSize::Size2M),
+            Err(Error::Misaligned)
+        );
+        assert_eq!(
+            map(0x8000_0000_0000, 0, PageSize::Size4K),
+            Err(Error::NotCanonical)
+        );
+
+        assert_eq!(unsafe { mapper.unmap(0x4000_1000) }, Err(Error::Misaligned));
+    }
+
+    #[test]
+    fn propagates_user_and_protects() {
+        let mut memory = Memory::new(8);
+        let mut mapper = memory.mapper();
+
+        unsafe {
+            mapper
+                .map(
+                    0x40_0000,
+                    0x40_0000,
+                    PageSize::Size4K,
+                    flags::USER,
+                    &mut memory,
+                )
+                .unwrap()
+                .ignore();
+            mapper
+                .protect(0x40_0000, flags::USER | flags::NO_EXECUTE)
+                .unwrap()
+                .ignore();
+        }
+
+        let mut table = &memory.tables[0];
+        let mut level = Level::Pml4;
+
+        while let Some(next) = level.next() {
+            let entry = table.entries[level.index(0x40_0000)];
+
+            assert_eq!(
+                entry.flags(),
+                flags::PRESENT | flags::WRITABLE | flags::USER
+            );
+
+            table = &memory.tables[(entry.address() / PAGE_SIZE) as usize];
+            level = next;
+        }
+
+        assert_eq!(
+            table.entries[Level::Pt.index(0x40_0000)].flags(),
+            flags::PRESENT | flags::USER | flags::NO_EXECUTE
+        );
+        assert_eq!(
+            unsafe { mapper.protect(0x50_0000, 0) },
+            Err(Error::NotMapped)
+        );
+    }
+
+    #[test]
+    fn maps_ranges_until_out_of_frames() {
+        // Enough for a PDPT and one of the four page directories needed.
+        let mut memory = Memory::new(3);
+        let mut mapper = memory.mapper();
+
+        let result = unsafe {
+            mapper.map_range(
+                PHYSICAL_MAP_BASE,
+                0,
+                4 << 30,
+                PageSize::Size2M,
+                flags::WRITABLE,
+                &mut memory,
+            )
+        };
+
+        assert_eq!(result, Err(Error::OutOfFrames));
+        assert_eq!(
+            mapper.translate_address(PHYSICAL_MAP_BASE + 0x3fff_ffff),
+            Some(0x3fff_ffff)
+        );
+        assert_eq!(
+            mapper.translate_address(PHYSICAL_MAP_BASE + 0x4000_0000),
+            None
+        );
+
+        let mut memory = Memory::new(8);
+        let mut mapper = memory.mapper();
+
+        unsafe {
+            mapper.map_range(
+                PHYSICAL_MAP_BASE,
+                0,
+                4 << 30,
+                PageSize::Size2M,
+                flags::WRITABLE,
+                &mut memory,
+            )
+        }
+        .unwrap();
+
+        let translated = [0, 0x1234_5678, 0xffff_ffff]
+            .map(|address| mapper.translate_address(PHYSICAL_MAP_BASE + address));
+
+        assert_eq!(translated, [Some(0), Some(0x1234_5678), Some(0xffff_ffff)]);
+        // A PDPT and four page directories.
+        assert_eq!(memory.used, 6);
+    }
+}
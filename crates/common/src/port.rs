@@ -0,0 +1,51 @@
+use core::arch::asm;
+
+/// Reads a byte from an I/O port.
+///
+/// # Safety
+///
+/// Reading from a port can have arbitrary side effects on the hardware behind it.
+#[inline(always)]
+#[must_use]
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+
+    unsafe {
+        asm!(
+            "in al, dx",
+            out("al") value,
+            in("dx") port,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+
+    value
+}
+
+/// Writes a byte to an I/O port.
+///
+/// # Safety
+///
+/// Writing to a port can have arbitrary side effects on the hardware behind it.
+#[inline(always)]
+pub unsafe fn outb(port: u16, value: u8) {
+    unsafe {
+        asm!(
+            "out dx, al",
+            in("dx") port,
+            in("al") value,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+}
+
+/// Waits a tiny amount of time by writing to the unused POST code port.
+///
+/// # Safety
+///
+/// Some firmware uses port `0x80` for diagnostics, so only call this where
+/// that doesn't matter.
+#[inline(always)]
+pub unsafe fn io_wait() {
+    unsafe { outb(0x80, 0) }
+}
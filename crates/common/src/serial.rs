@@ -0,0 +1,163 @@
+use crate::port::{inb, outb};
+
+/// The I/O port base of the first serial port.
+pub const COM1: u16 = 0x3f8;
+
+/// The rate the UART clock divides down from.
+pub const BASE_BAUD: u32 = 115_200;
+
+/// Whether the loader mirrors its output to serial.
+///
+/// Set `MROW_SERIAL=0` at build time to disable it.
+pub const ENABLED: bool = option_var!("MROW_SERIAL", u8, 1) != 0;
+
+/// The baud rate the loader uses, set with `MROW_SERIAL_BAUD` at build time.
+pub const BAUD: u32 = option_var!("MROW_SERIAL_BAUD", u32, BASE_BAUD);
+
+/// The divisor for [`BAUD`].
+pub const DIVISOR: u16 = divisor(BAUD);
+
+/// How many times we poll the line status before dropping a byte.
+const TIMEOUT: u32 = 0x10000;
+
+/// Returns the divisor latch value for `baud`.
+///
+/// # Panics
+///
+/// Panics if `baud` is zero, above [`BASE_BAUD`] or too low for a 16-bit divisor.
+#[inline]
+#[must_use]
+pub const fn divisor(baud: u32) -> u16 {
+    assert!(
+        (baud != 0) & (baud <= BASE_BAUD),
+        "baud rate must be between 1 and 115200"
+    );
+
+    let divisor = BASE_BAUD / baud;
+
+    assert!(divisor <= u16::MAX as u32, "baud rate is too low");
+
+    divisor as u16
+}
+
+/// Register offsets from the base port.
+mod register {
+    /// Transmit holding and receive buffer register, or the low divisor byte with DLAB set.
+    pub const DATA: u16 = 0;
+    /// Interrupt enable register, or the high divisor byte with DLAB set.
+    pub const INTERRUPT_ENABLE: u16 = 1;
+    /// FIFO control register.
+    pub const FIFO_CONTROL: u16 = 2;
+    /// Line control register.
+    pub const LINE_CONTROL: u16 = 3;
+    /// Modem control register.
+    pub const MODEM_CONTROL: u16 = 4;
+    /// Line status register.
+    pub const LINE_STATUS: u16 = 5;
+}
+
+/// Makes the first two registers access the divisor latch.
+const DLAB: u8 = 1 << 7;
+/// 8 data bits, no parity, one stop bit.
+const EIGHT_N_ONE: u8 = 0x03;
+/// Enable and clear the FIFOs, with a 14 byte threshold.
+const FIFO_ENABLE: u8 = 0xc7;
+/// Data terminal ready and request to send.
+const DTR_RTS: u8 = 0x03;
+/// A received byte is waiting in the receive buffer.
+const DATA_READY: u8 = 1 << 0;
+/// The transmit holding register is empty.
+const TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// A polled 16550 UART.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    /// Creates a driver for the UART at `base`, without touching it.
+    #[inline]
+    #[must_use]
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    /// The first serial port.
+    #[inline]
+    #[must_use]
+    pub const fn com1() -> Self {
+        Self::new(COM1)
+    }
+
+    /// Programs the UART for `baud` 8N1 with interrupts off and FIFOs on.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the UART at the same time.
+    pub unsafe fn init(&self, baud: u32) {
+        let divisor = divisor(baud).to_le_bytes();
+
+        unsafe {
+            outb(self.base + register::INTERRUPT_ENABLE, 0);
+            outb(self.base + register::LINE_CONTROL, DLAB);
+            outb(self.base + register::DATA, divisor[0]);
+            outb(self.base + register::INTERRUPT_ENABLE, divisor[1]);
+            outb(self.base + register::LINE_CONTROL, EIGHT_N_ONE);
+            outb(self.base + register::FIFO_CONTROL, FIFO_ENABLE);
+            outb(self.base + register::MODEM_CONTROL, DTR_RTS);
+        }
+    }
+
+    /// Returns whether the UART can take another byte.
+    ///
+    /// # Safety
+    ///
+    /// See [`SerialPort::init`].
+    #[inline]
+    #[must_use]
+    pub unsafe fn is_transmit_empty(&self) -> bool {
+        unsafe { inb(self.base + register::LINE_STATUS) & TRANSMIT_EMPTY != 0 }
+    }
+
+    /// Sends a byte, waiting for the UART to be ready.
+    ///
+    /// The byte is dropped if the UART stays busy for too long, so a missing or
+    /// broken port can't hang us.
+    ///
+    /// # Safety
+    ///
+    /// See [`SerialPort::init`].
+    pub unsafe fn write_byte(&self, byte: u8) {
+        for _ in 0..TIMEOUT {
+            if unsafe { self.is_transmit_empty() } {
+                unsafe { outb(self.base + register::DATA, byte) };
+                return;
+            }
+        }
+    }
+
+    /// Returns the next received byte, without waiting for one.
+    ///
+    /// # Safety
+    ///
+    /// See [`SerialPort::init`].
+    pub unsafe fn read_byte(&self) -> Option<u8> {
+        unsafe {
+            let ready = inb(self.base + register::LINE_STATUS) & DATA_READY != 0;
+
+            ready.then(|| inb(self.base + register::DATA))
+        }
+    }
+
+    /// Sends every byte of `bytes`.
+    ///
+    /// # Safety
+    ///
+    /// See [`SerialPort::init`].
+    pub unsafe fn write_bytes(&self, bytes: &[u8]) {
+        for &byte in bytes {
+            unsafe { self.write_byte(byte) };
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Finding and checking the SMBIOS entry point.
+//!
+//! On BIOS machines the entry point is somewhere in the same areas as the ACPI
+//! RSDP, on a 16 byte boundary.
+
+use core::fmt;
+
+/// The anchor the 32-bit entry point starts with.
+pub const ANCHOR_32: [u8; 4] = *b"_SM_";
+
+/// The anchor the 64-bit entry point of SMBIOS 3.0 starts with.
+pub const ANCHOR_64: [u8; 5] = *b"_SM3_";
+
+/// The anchor of the intermediate structure inside the 32-bit entry point.
+pub const INTERMEDIATE_ANCHOR: [u8; 5] = *b"_DMI_";
+
+/// The boundary entry points are aligned to.
+pub const ALIGN: usize = 16;
+
+/// The smallest valid length of the 32-bit entry point.
+const LEN_32: usize = 0x1f;
+
+/// The smallest valid length of the 64-bit entry point.
+const LEN_64: usize = 0x18;
+
+/// Errors that can occur while checking an entry point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error {
+    /// The structure doesn't start with either anchor.
+    BadAnchor,
+    /// The structure is shorter than it claims to be, or claims to be too short.
+    Truncated,
+    /// The bytes don't add up to zero.
+    BadChecksum,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadAnchor => f.write_str("no SMBIOS anchor"),
+            Error::Truncated => f.write_str("SMBIOS entry point is truncated"),
+            Error::BadChecksum => f.write_str("bad SMBIOS entry point checksum"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// A checked SMBIOS entry point, either the 32-bit or the 64-bit one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryPoint<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> EntryPoint<'a> {
+    /// Checks the entry point at the start of `bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        let (len, min_len) = if bytes.starts_with(&ANCHOR_64) {
+            (bytes.get(6), LEN_64)
+        } else if bytes.starts_with(&ANCHOR_32) {
+            (bytes.get(5), LEN_32)
+        } else {
+            return Err(Error::BadAnchor);
+        };
+
+        let len = *len.ok_or(Error::Truncated)? as usize;
+
+        if len < min_len {
+            return Err(Error::Truncated);
+        }
+
+        let bytes = bytes.get(..len).ok_or(Error::Truncated)?;
+
+        if !checksum(bytes) {
+            return Err(Error::BadChecksum);
+        }
+
+        // The intermediate structure carries its own checksum.
+        if bytes.starts_with(&ANCHOR_32)
+            && !(bytes[0x10..].starts_with(&INTERMEDIATE_ANCHOR) && checksum(&bytes[0x10..0x1f]))
+        {
+            return Err(Error::BadChecksum);
+        }
+
+        Ok(Self { bytes })
+    }
+
+    /// Returns the whole structure.
+    #[inline]
+    #[must_use]
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns whether this is the 64-bit entry point of SMBIOS 3.0 or later.
+    #[inline]
+    #[must_use]
+    pub fn is_64_bit(&self) -> bool {
+        self.bytes.starts_with(&ANCHOR_64)
+    }
+
+    /// Returns the major and minor SMBIOS version.
+    #[inline]
+    #[must_use]
+    pub fn version(&self) -> (u8, u8) {
+        if self.is_64_bit() {
+            (self.bytes[7], self.bytes[8])
+        } else {
+            (self.bytes[6], self.bytes[7])
+        }
+    }
+
+    /// Returns the physical address of the structure table.
+    #[inline]
+    #[must_use]
+    pub fn table_address(&self) -> u64 {
+        if self.is_64_bit() {
+            u64::from_le_bytes(self.bytes[0x10..0x18].try_into().unwrap())
+        } else {
+            u32::from_le_bytes(self.bytes[0x18..0x1c].try_into().unwrap()) as u64
+        }
+    }
+
+    /// Returns the length of the structure table in bytes.
+    ///
+    /// For the 64-bit entry point this is only the maximum, the table ends with
+    /// an end-of-table structure.
+    #[inline]
+    #[must_use]
+    pub fn table_len(&self) -> u32 {
+        if self.is_64_bit() {
+            u32::from_le_bytes(self.bytes[0x0c..0x10].try_into().unwrap())
+        } else {
+            u16::from_le_bytes(self.bytes[0x16..0x18].try_into().unwrap()) as u32
+        }
+    }
+}
+
+/// Looks for a valid entry point in `area`, which must start on an [`ALIGN`] boundary.
+///
+/// Returns the offset of the entry point within `area`. The 64-bit entry point is
+/// preferred if the firmware provides both, as the table may be above 4 GiB.
+#[must_use]
+pub fn find(area: &[u8]) -> Option<(usize, EntryPoint<'_>)> {
+    let find_anchor = |anchor: &[u8]| {
+        (0..area.len())
+            .step_by(ALIGN)
+            .filter(|&offset| area[offset..].starts_with(anchor))
+            .find_map(|offset| Some((offset, EntryPoint::parse(&area[offset..]).ok()?)))
+    };
+
+    find_anchor(&ANCHOR_64).or_else(|| find_anchor(&ANCHOR_32))
+}
+
+/// Returns whether `bytes` add up to zero.
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    /// Returns the byte that makes `bytes` add up to zero.
+    fn fix(bytes: &[u8]) -> u8 {
+        0_u8.wrapping_sub(bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)))
+    }
+
+    /// Builds a 32-bit entry point of SMBIOS 2.8 with valid checksums.
+    fn entry_32() -> Vec<u8> {
+        let mut bytes = vec![0; LEN_32];
+        bytes[..4].copy_from_slice(&ANCHOR_32);
+        bytes[5] = LEN_32 as u8;
+        bytes[6] = 2;
+        bytes[7] = 8;
+        bytes[0x10..0x15].copy_from_slice(&INTERMEDIATE_ANCHOR);
+        bytes[0x16..0x18].copy_from_slice(&0x1a3_u16.to_le_bytes());
+        bytes[0x18..0x1c].copy_from_slice(&0x000f_0a40_u32.to_le_bytes());
+        bytes[0x15] = fix(&bytes[0x10..0x1f]);
+        bytes[4] = fix(&bytes);
+
+        bytes
+    }
+
+    /// Builds a 64-bit entry point of SMBIOS 3.2 with a valid checksum.
+    fn entry_64() -> Vec<u8> {
+        let mut bytes = vec![0; LEN_64];
+        bytes[..5].copy_from_slice(&ANCHOR_64);
+        bytes[6] = LEN_64 as u8;
+        bytes[7] = 3;
+        bytes[8] = 2;
+        bytes[0x0c..0x10].copy_from_slice(&0x2000_u32.to_le_bytes());
+        bytes[0x10..0x18].copy_from_slice(&0x1_0000_0000_u64.to_le_bytes());
+        bytes[5] = fix(&bytes);
+
+        bytes
+    }
+
+    #[test]
+    fn parses_32_bit() {
+        let bytes = entry_32();
+        let entry = EntryPoint::parse(&bytes).unwrap();
+
+        assert!(!entry.is_64_bit());
+        assert_eq!(entry.version(), (2, 8));
+        assert_eq!(entry.table_address(), 0xf_0a40);
+        assert_eq!(entry.table_len(), 0x1a3);
+    }
+
+    #[test]
+    fn parses_64_bit() {
+        let bytes = entry_64();
+        let entry = EntryPoint::parse(&bytes).unwrap();
+
+        assert!(entry.is_64_bit());
+        assert_eq!(entry.version(), (3, 2));
+        assert_eq!(entry.table_address(), 0x1_0000_0000);
+        assert_eq!(entry.table_len(), 0x2000);
+    }
+
+    #[test]
+    fn rejects_bad_entry_points() {
+        let mut bad = entry_32();
+        bad[0x18] ^= 1;
+        assert_eq!(EntryPoint::parse(&bad), Err(Error::BadChecksum));
+
+        // The whole structure adds up, but the intermediate part doesn't.
+        let mut bad = entry_32();
+        bad[0x15] ^= 1;
+        bad[4] = 0;
+        bad[4] = fix(&bad);
+        assert_eq!(EntryPoint::parse(&bad), Err(Error::BadChecksum));
+
+        let mut short = entry_64();
+        short[6] = 0x10;
+        assert_eq!(EntryPoint::parse(&short), Err(Error::Truncated));
+        assert_eq!(EntryPoint::parse(&entry_64()[..20]), Err(Error::Truncated));
+        assert_eq!(EntryPoint::parse(b"_SM2_"), Err(Error::BadAnchor));
+    }
+
+    #[test]
+    fn scans_bios_area() {
+        let mut area = vec![0; 0x2_0000];
+        area[0x1_0100..0x1_0100 + LEN_32].copy_from_slice(&entry_32());
+
+        assert_eq!(find(&area).unwrap().0, 0x1_0100);
+
+        area[0x1_8000..0x1_8000 + LEN_64].copy_from_slice(&entry_64());
+
+        let (offset, entry) = find(&area).unwrap();
+
+        assert_eq!(offset, 0x1_8000);
+        assert!(entry.is_64_bit());
+
+        // Off the boundary doesn't count.
+        let mut area = vec![0; 0x1000];
+        area[0x108..0x108 + LEN_32].copy_from_slice(&entry_32());
+
+        assert!(find(&area).is_none());
+    }
+}
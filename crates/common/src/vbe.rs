@@ -0,0 +1,605 @@
+use core::ptr::addr_of;
+
+use crate::framebuffer::{ColorMask, Framebuffer};
+
+/// The signature we put into [`VbeInfoBlock::signature`] to ask for VBE 2.0 information.
+pub const VBE2_SIGNATURE: [u8; 4] = *b"VBE2";
+
+/// The signature the firmware puts into [`VbeInfoBlock::signature`].
+pub const VESA_SIGNATURE: [u8; 4] = *b"VESA";
+
+/// Converts a real mode `segment:offset` pointer to a linear address.
+#[inline]
+#[must_use]
+pub const fn far_to_linear(pointer: u32) -> u32 {
+    ((pointer >> 16) << 4) + (pointer & 0xffff)
+}
+
+/// Information about the VBE controller, as returned by `int 10h, ax=4f00h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C, packed)]
+pub struct VbeInfoBlock {
+    /// [`VBE2_SIGNATURE`] before the call, [`VESA_SIGNATURE`] after it.
+    pub signature: [u8; 4],
+    /// The VBE version in BCD, like `0x0300`.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`VbeInfoBlock::version`].
+    pub version: u16,
+    /// Far pointer to the OEM name.
+    pub oem_string: u32,
+    /// Capabilities of the controller.
+    pub capabilities: u32,
+    /// Far pointer to the list of mode numbers, terminated by `0xffff`.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`VbeInfoBlock::video_modes`].
+    pub video_modes: u32,
+    /// Video memory in 64 KiB blocks.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`VbeInfoBlock::total_memory`].
+    pub total_memory: u16,
+    /// The OEM software revision.
+    pub oem_software_revision: u16,
+    /// Far pointer to the vendor name.
+    pub oem_vendor_name: u32,
+    /// Far pointer to the product name.
+    pub oem_product_name: u32,
+    /// Far pointer to the product revision.
+    pub oem_product_revision: u32,
+    /// Reserved, some firmware stores the mode list here.
+    pub reserved: [u8; 222],
+    /// Scratch space for the OEM strings.
+    pub oem_data: [u8; 256],
+}
+
+impl VbeInfoBlock {
+    /// Creates a block ready to be passed to `int 10h, ax=4f00h`.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            signature: VBE2_SIGNATURE,
+            version: 0,
+            oem_string: 0,
+            capabilities: 0,
+            video_modes: 0,
+            total_memory: 0,
+            oem_software_revision: 0,
+            oem_vendor_name: 0,
+            oem_product_name: 0,
+            oem_product_revision: 0,
+            reserved: [0; 222],
+            oem_data: [0; 256],
+        }
+    }
+
+    /// Returns whether the firmware filled in this block.
+    #[inline]
+    #[must_use]
+    pub const fn is_valid(&self) -> bool {
+        u32::from_le_bytes(self.signature) == u32::from_le_bytes(VESA_SIGNATURE)
+    }
+
+    /// Reads the VBE version.
+    #[inline]
+    #[must_use]
+    pub const fn version(&self) -> u16 {
+        unsafe { addr_of!(self.version).read_unaligned() }
+    }
+
+    /// Reads the far pointer to the list of mode numbers.
+    #[inline]
+    #[must_use]
+    pub const fn video_modes(&self) -> u32 {
+        unsafe { addr_of!(self.video_modes).read_unaligned() }
+    }
+
+    /// Reads the amount of video memory in 64 KiB blocks.
+    #[inline]
+    #[must_use]
+    pub const fn total_memory(&self) -> u16 {
+        unsafe { addr_of!(self.total_memory).read_unaligned() }
+    }
+}
+
+impl Default for VbeInfoBlock {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bits of [`ModeInfoBlock::attributes`].
+pub mod mode_attributes {
+    /// The mode is supported by the hardware.
+    pub const SUPPORTED: u16 = 1 << 0;
+    /// The mode is a color mode.
+    pub const COLOR: u16 = 1 << 3;
+    /// The mode is a graphics mode rather than a text mode.
+    pub const GRAPHICS: u16 = 1 << 4;
+    /// The mode has a linear framebuffer.
+    pub const LINEAR_FRAMEBUFFER: u16 = 1 << 7;
+}
+
+/// Values of [`ModeInfoBlock::memory_model`].
+pub mod memory_model {
+    /// Palette indices.
+    pub const PACKED_PIXEL: u8 = 4;
+    /// Pixels with separate red, green and blue components.
+    pub const DIRECT_COLOR: u8 = 6;
+}
+
+/// Information about a mode, as returned by `int 10h, ax=4f01h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C, packed)]
+pub struct ModeInfoBlock {
+    /// See [`mode_attributes`].
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`ModeInfoBlock::attributes`].
+    pub attributes: u16,
+    /// Attributes of window A.
+    pub window_a: u8,
+    /// Attributes of window B.
+    pub window_b: u8,
+    /// Window granularity in KiB.
+    pub granularity: u16,
+    /// Window size in KiB.
+    pub window_size: u16,
+    /// Segment of window A.
+    pub segment_a: u16,
+    /// Segment of window B.
+    pub segment_b: u16,
+    /// Far pointer to the window function.
+    pub window_function: u32,
+    /// Bytes per line in banked modes.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`ModeInfoBlock::pitch`].
+    pub pitch: u16,
+    /// Width in pixels.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`ModeInfoBlock::width`].
+    pub width: u16,
+    /// Height in pixels.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`ModeInfoBlock::height`].
+    pub height: u16,
+    /// Character cell width.
+    pub char_width: u8,
+    /// Character cell height.
+    pub char_height: u8,
+    /// Number of memory planes.
+    pub planes: u8,
+    /// Bits per pixel.
+    pub bpp: u8,
+    /// Number of banks.
+    pub banks: u8,
+    /// See [`memory_model`].
+    pub memory_model: u8,
+    /// Bank size in KiB.
+    pub bank_size: u8,
+    /// Number of images that fit into video memory, minus one.
+    pub image_pages: u8,
+    /// Reserved.
+    pub reserved_0: u8,
+    /// Size of the red component in bits.
+    pub red_size: u8,
+    /// Position of the red component.
+    pub red_position: u8,
+    /// Size of the green component in bits.
+    pub green_size: u8,
+    /// Position of the green component.
+    pub green_position: u8,
+    /// Size of the blue component in bits.
+    pub blue_size: u8,
+    /// Position of the blue component.
+    pub blue_position: u8,
+    /// Size of the reserved component in bits.
+    pub reserved_size: u8,
+    /// Position of the reserved component.
+    pub reserved_position: u8,
+    /// Attributes of direct color modes.
+    pub direct_color_attributes: u8,
+    /// Physical address of the linear framebuffer.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`ModeInfoBlock::framebuffer_address`].
+    pub framebuffer: u32,
+    /// Offset of off screen memory.
+    pub off_screen_memory: u32,
+    /// Size of off screen memory in KiB.
+    pub off_screen_memory_size: u16,
+    /// Bytes per line in linear modes, only valid since VBE 3.0.
+    ///
+    /// # Unaligned Accesses
+    ///
+    /// See [`ModeInfoBlock::linear_pitch`].
+    pub linear_pitch: u16,
+    /// Reserved.
+    pub reserved_1: [u8; 204],
+}
+
+impl ModeInfoBlock {
+    /// Creates an empty block.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            attributes: 0,
+            window_a: 0,
+            window_b: 0,
+            granularity: 0,
+            window_size: 0,
+            segment_a: 0,
+            segment_b: 0,
+            window_function: 0,
+            pitch: 0,
+            width: 0,
+            height: 0,
+            char_width: 0,
+            char_height: 0,
+            planes: 0,
+            bpp: 0,
+            banks: 0,
+            memory_model: 0,
+            bank_size: 0,
+            image_pages: 0,
+            reserved_0: 0,
+            red_size: 0,
+            red_position: 0,
+            green_size: 0,
+            green_position: 0,
+            blue_size: 0,
+            blue_position: 0,
+            reserved_size: 0,
+            reserved_position: 0,
+            direct_color_attributes: 0,
+            framebuffer: 0,
+            off_screen_memory: 0,
+            off_screen_memory_size: 0,
+            linear_pitch: 0,
+            reserved_1: [0; 204],
+        }
+    }
+
+    /// Reads the attributes of the mode.
+    #[inline]
+    #[must_use]
+    pub const fn attributes(&self) -> u16 {
+        unsafe { addr_of!(self.attributes).read_unaligned() }
+    }
+
+    /// Reads the bytes per line in banked modes.
+    #[inline]
+    #[must_use]
+    pub const fn pitch(&self) -> u16 {
+        unsafe { addr_of!(self.pitch).read_unaligned() }
+    }
+
+    /// Reads the width in pixels.
+    #[inline]
+    #[must_use]
+    pub const fn width(&self) -> u16 {
+        unsafe { addr_of!(self.width).read_unaligned() }
+    }
+
+    /// Reads the height in pixels.
+    #[inline]
+    #[must_use]
+    pub const fn height(&self) -> u16 {
+        unsafe { addr_of!(self.height).read_unaligned() }
+    }
+
+    /// Reads the physical address of the linear framebuffer.
+    #[inline]
+    #[must_use]
+    pub const fn framebuffer_address(&self) -> u32 {
+        unsafe { addr_of!(self.framebuffer).read_unaligned() }
+    }
+
+    /// Reads the bytes per line in linear modes.
+    #[inline]
+    #[must_use]
+    pub const fn linear_pitch(&self) -> u16 {
+        unsafe { addr_of!(self.linear_pitch).read_unaligned() }
+    }
+
+    /// Returns whether this is a direct color mode with a linear framebuffer.
+    #[inline]
+    #[must_use]
+    pub const fn is_usable(&self) -> bool {
+        const REQUIRED: u16 = mode_attributes::SUPPORTED
+            | mode_attributes::GRAPHICS
+            | mode_attributes::LINEAR_FRAMEBUFFER;
+
+        (self.attributes() & REQUIRED == REQUIRED)
+            & (self.memory_model == memory_model::DIRECT_COLOR)
+            & matches!(self.bpp, 15 | 16 | 24 | 32)
+            & (self.framebuffer_address() != 0)
+    }
+
+    /// Describes the framebuffer of this mode, `version` being the VBE version
+    /// of the controller.
+    #[inline]
+    #[must_use]
+    pub const fn framebuffer(&self, version: u16) -> Framebuffer {
+        // Before VBE 3.0 the banked and linear pitch were the same.
+        let pitch = if (version >= 0x0300) & (self.linear_pitch() != 0) {
+            self.linear_pitch()
+        } else {
+            self.pitch()
+        } as u32;
+
+        Framebuffer {
+            address: self.framebuffer_address() as u64,
+            size: pitch as u64 * self.height() as u64,
+            width: self.width() as u32,
+            height: self.height() as u32,
+            pitch,
+            bpp: self.bpp,
+            red: ColorMask::new(self.red_position, self.red_size),
+            green: ColorMask::new(self.green_position, self.green_size),
+            blue: ColorMask::new(self.blue_position, self.blue_size),
+            reserved: [0; 5],
+        }
+    }
+}
+
+impl Default for ModeInfoBlock {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The mode we would like to end up in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ModeRequest {
+    /// Width in pixels.
+    pub width: u16,
+    /// Height in pixels.
+    pub height: u16,
+    /// Bits per pixel.
+    pub bpp: u8,
+}
+
+impl ModeRequest {
+    /// What we ask for when nobody told us anything better.
+    pub const DEFAULT: Self = Self::new(1024, 768, 32);
+
+    /// Creates a new request.
+    #[inline]
+    #[must_use]
+    pub const fn new(width: u16, height: u16, bpp: u8) -> Self {
+        Self { width, height, bpp }
+    }
+
+    /// Ranks how well `mode` fulfills this request, the higher the better.
+    ///
+    /// Returns `None` for modes we can't use or that are larger than requested.
+    /// Among the rest, more pixels win, then the requested bpp, then more bpp.
+    #[inline]
+    #[must_use]
+    pub const fn rank(&self, mode: &ModeInfoBlock) -> Option<u64> {
+        if !mode.is_usable() | (mode.width() > self.width) | (mode.height() > self.height) {
+            return None;
+        }
+
+        let pixels = mode.width() as u64 * mode.height() as u64;
+        let exact = (mode.bpp == self.bpp) as u64;
+
+        Some((pixels << 16) | (exact << 8) | mode.bpp as u64)
+    }
+}
+
+impl Default for ModeRequest {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Extended display identification data, as returned by `int 10h, ax=4f15h, bl=01h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(transparent)]
+pub struct Edid(pub [u8; 128]);
+
+impl Edid {
+    /// The fixed header every EDID block starts with.
+    pub const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+    /// Offset of the first detailed timing descriptor, which is the preferred mode.
+    const PREFERRED_TIMING: usize = 54;
+
+    /// Creates an empty block.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self([0; 128])
+    }
+
+    /// Returns whether the header and checksum are correct.
+    #[inline]
+    #[must_use]
+    pub const fn is_valid(&self) -> bool {
+        let mut sum = 0_u8;
+        let mut i = 0;
+
+        while i < self.0.len() {
+            sum = sum.wrapping_add(self.0[i]);
+            i += 1;
+        }
+
+        let mut header = [0; 8];
+        header.copy_from_slice(self.0.split_at(8).0);
+
+        (u64::from_le_bytes(header) == u64::from_le_bytes(Self::HEADER)) & (sum == 0)
+    }
+
+    /// Returns the width and height of the preferred mode of the display.
+    #[inline]
+    #[must_use]
+    pub const fn preferred_resolution(&self) -> Option<(u16, u16)> {
+        let timing = self.0.split_at(Self::PREFERRED_TIMING).1;
+
+        // A zero pixel clock means this is a display descriptor instead.
+        if !self.is_valid() | ((timing[0] == 0) & (timing[1] == 0)) {
+            return None;
+        }
+
+        let width = timing[2] as u16 | ((timing[4] as u16 & 0xf0) << 4);
+        let height = timing[5] as u16 | ((timing[7] as u16 & 0xf0) << 4);
+
+        if (width == 0) | (height == 0) {
+            return None;
+        }
+
+        Some((width, height))
+    }
+}
+
+impl Default for Edid {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(width: u16, height: u16, bpp: u8) -> ModeInfoBlock {
+        let mut mode = ModeInfoBlock::new();
+
+        mode.attributes = mode_attributes::SUPPORTED
+            | mode_attributes::COLOR
+            | mode_attributes::GRAPHICS
+            | mode_attributes::LINEAR_FRAMEBUFFER;
+        mode.memory_model = memory_model::DIRECT_COLOR;
+        mode.width = width;
+        mode.height = height;
+        mode.bpp = bpp;
+        mode.pitch = width * bpp.div_ceil(8) as u16;
+        mode.framebuffer = 0xfd00_0000;
+
+        mode
+    }
+
+    fn best(request: ModeRequest, modes: &[ModeInfoBlock]) -> Option<(u16, u16, u8)> {
+        modes
+            .iter()
+            .filter_map(|mode| Some((request.rank(mode)?, mode)))
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, mode)| (mode.width(), mode.height(), mode.bpp))
+    }
+
+    #[test]
+    fn prefers_exact_match() {
+        let modes = [
+            mode(640, 480, 32),
+            mode(1024, 768, 16),
+            mode(1024, 768, 32),
+            mode(1024, 768, 24),
+            mode(1280, 1024, 32),
+        ];
+
+        assert_eq!(
+            best(ModeRequest::new(1024, 768, 32), &modes),
+            Some((1024, 768, 32))
+        );
+        assert_eq!(
+            best(ModeRequest::new(1024, 768, 16), &modes),
+            Some((1024, 768, 16))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_largest_smaller_mode() {
+        let modes = [mode(640, 480, 32), mode(800, 600, 16), mode(1920, 1080, 32)];
+
+        assert_eq!(
+            best(ModeRequest::new(1280, 1024, 32), &modes),
+            Some((800, 600, 16))
+        );
+        assert_eq!(best(ModeRequest::new(320, 200, 32), &modes), None);
+    }
+
+    #[test]
+    fn skips_unusable_modes() {
+        let mut banked = mode(1024, 768, 32);
+        banked.attributes &= !mode_attributes::LINEAR_FRAMEBUFFER;
+
+        let mut palette = mode(1024, 768, 8);
+        palette.memory_model = memory_model::PACKED_PIXEL;
+
+        let modes = [banked, palette, mode(640, 480, 24)];
+
+        assert_eq!(best(ModeRequest::DEFAULT, &modes), Some((640, 480, 24)));
+    }
+
+    #[test]
+    fn describes_framebuffer() {
+        let mut info = mode(1024, 768, 32);
+        info.linear_pitch = 4352;
+        info.red_position = 16;
+        info.red_size = 8;
+
+        let framebuffer = info.framebuffer(0x0200);
+        assert_eq!(framebuffer.pitch, 4096);
+        assert_eq!(framebuffer.size, 4096 * 768);
+        assert_eq!(framebuffer.red.bits(), 0x00ff_0000);
+
+        assert_eq!(info.framebuffer(0x0300).pitch, 4352);
+    }
+
+    fn edid(width: u16, height: u16) -> Edid {
+        let mut edid = Edid::new();
+
+        edid.0[..8].copy_from_slice(&Edid::HEADER);
+        edid.0[54] = 0x64;
+        edid.0[55] = 0x19;
+        edid.0[56] = width as u8;
+        edid.0[58] = ((width >> 8) as u8) << 4;
+        edid.0[59] = height as u8;
+        edid.0[61] = ((height >> 8) as u8) << 4;
+
+        let sum = edid.0[..127]
+            .iter()
+            .fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+        edid.0[127] = sum.wrapping_neg();
+
+        edid
+    }
+
+    #[test]
+    fn reads_preferred_resolution() {
+        assert_eq!(edid(1920, 1080).preferred_resolution(), Some((1920, 1080)));
+        assert_eq!(edid(1280, 800).preferred_resolution(), Some((1280, 800)));
+    }
+
+    #[test]
+    fn rejects_corrupt_edid() {
+        let mut corrupt = edid(1920, 1080);
+        corrupt.0[60] ^= 1;
+        assert_eq!(corrupt.preferred_resolution(), None);
+
+        assert_eq!(Edid::new().preferred_resolution(), None);
+    }
+}
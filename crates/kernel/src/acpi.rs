@@ -0,0 +1,47 @@
+//! Reading ACPI tables through the direct map.
+
+use core::slice;
+use mrow_common::{
+    acpi::{Rsdp, Sdt, RSDP_V2_LEN, SDT_HEADER_LEN},
+    paging::PHYSICAL_MAP_BASE,
+};
+
+/// Returns `len` bytes at the physical `address`.
+///
+/// # Safety
+///
+/// The direct map must be set up, and the bytes must not change while they're
+/// borrowed.
+unsafe fn physical(address: u64, len: usize) -> &'static [u8] {
+    unsafe { slice::from_raw_parts((PHYSICAL_MAP_BASE + address) as *const u8, len) }
+}
+
+/// Returns the table at the physical `address`, if it checks out.
+///
+/// # Safety
+///
+/// See [`physical`].
+unsafe fn table(address: u64) -> Option<Sdt<'static>> {
+    let len = Sdt::peek_len(unsafe { physical(address, SDT_HEADER_LEN) })?;
+
+    Sdt::parse(unsafe { physical(address, len) }).ok()
+}
+
+/// Looks for the table with `signature` through the RSDP at `rsdp`.
+///
+/// Tables that don't check out are skipped.
+///
+/// # Safety
+///
+/// The direct map must be set up, and `rsdp` must be where the loader found
+/// the RSDP.
+pub unsafe fn find_table(rsdp: u64, signature: [u8; 4]) -> Option<Sdt<'static>> {
+    // A revision 0 RSDP is shorter, but reading past it doesn't hurt.
+    let rsdp = Rsdp::parse(unsafe { physical(rsdp, RSDP_V2_LEN) }).ok()?;
+    let root = rsdp.xsdt_address().unwrap_or(rsdp.rsdt_address() as u64);
+
+    unsafe { table(root) }?
+        .table_addresses()
+        .filter_map(|address| unsafe { table(address) })
+        .find(|table| table.signature() == signature)
+}
@@ -0,0 +1,257 @@
+//! The local APIC and the I/O APICs, set up from the MADT.
+
+use alloc::vec::Vec;
+use core::{
+    ptr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use mrow_common::{
+    acpi::{InterruptOverride, Madt, MadtEntry, LOCAL_APIC_ENABLED},
+    apic::{io, local, lvt, redirection_entries, RedirectionEntry, Route},
+    frame::FRAME_SIZE,
+    paging,
+};
+
+use crate::{cpu, interrupts, memory, sync::SpinLock};
+
+/// The MSR holding the physical address of the local APIC.
+const APIC_BASE: u32 = 0x1b;
+
+/// The bit of [`APIC_BASE`] that turns the local APIC on.
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+/// Where the local APIC registers are mapped, or 0 before [`init`].
+static LOCAL_APIC: AtomicU64 = AtomicU64::new(0);
+
+/// The I/O APICs, and how ISA IRQs are wired to them.
+static IO_APICS: SpinLock<IoApics> = SpinLock::new(IoApics {
+    io_apics: Vec::new(),
+    overrides: Vec::new(),
+});
+
+/// What [`init`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// The physical address of the local APIC.
+    pub local_apic: u64,
+    /// How many processors there are with a usable local APIC.
+    pub processors: usize,
+    /// How many I/O APICs there are.
+    pub io_apics: usize,
+}
+
+#[derive(Debug)]
+struct IoApics {
+    io_apics: Vec<IoApic>,
+    overrides: Vec<InterruptOverride>,
+}
+
+/// An I/O APIC, through its mapped registers.
+#[derive(Debug)]
+struct IoApic {
+    base: u64,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    unsafe fn read(&self, register: u32) -> u32 {
+        unsafe {
+            ptr::write_volatile((self.base + io::SELECT as u64) as *mut u32, register);
+            ptr::read_volatile((self.base + io::WINDOW as u64) as *const u32)
+        }
+    }
+
+    unsafe fn write(&self, register: u32, value: u32) {
+        unsafe {
+            ptr::write_volatile((self.base + io::SELECT as u64) as *mut u32, register);
+            ptr::write_volatile((self.base + io::WINDOW as u64) as *mut u32, value);
+        }
+    }
+
+    /// Sets redirection entry `index`, masking it while it's half written.
+    unsafe fn set_entry(&self, index: u32, entry: RedirectionEntry) {
+        let register = io::REDIRECTION + index * 2;
+
+        unsafe {
+            self.write(register, RedirectionEntry::MASKED as u32);
+            self.write(register + 1, (entry.0 >> 32) as u32);
+            self.write(register, entry.0 as u32);
+        }
+    }
+}
+
+/// Reads a local APIC register.
+///
+/// # Panics
+///
+/// Panics before [`init`].
+pub fn read(register: usize) -> u32 {
+    let base = LOCAL_APIC.load(Ordering::Relaxed);
+    assert_ne!(base, 0, "the local APIC isn't set up");
+
+    unsafe { ptr::read_volatile((base + register as u64) as *const u32) }
+}
+
+/// Writes a local APIC register.
+///
+/// # Panics
+///
+/// Panics before [`init`].
+///
+/// # Safety
+///
+/// `value` must make sense for `register`.
+pub unsafe fn write(register: usize, value: u32) {
+    let base = LOCAL_APIC.load(Ordering::Relaxed);
+    assert_ne!(base, 0, "the local APIC isn't set up");
+
+    unsafe { ptr::write_volatile((base + register as u64) as *mut u32, value) };
+}
+
+/// Returns the ID of the local APIC of the processor we're running on.
+pub fn id() -> u8 {
+    (read(local::ID) >> 24) as u8
+}
+
+/// Tells the local APIC the current interrupt has been handled.
+///
+/// Does nothing before [`init`], when no interrupts can come in anyway.
+pub fn end_of_interrupt() {
+    if LOCAL_APIC.load(Ordering::Relaxed) != 0 {
+        unsafe { write(local::END_OF_INTERRUPT, 0) };
+    }
+}
+
+/// Turns on the local APIC of this processor and masks all I/O APIC inputs.
+///
+/// LINT inputs the MADT says are wired to NMI are set up that way, everything
+/// else stays masked until routed.
+///
+/// # Safety
+///
+/// Must only be called once, after the heap is set up and with interrupts off,
+/// with the 8259 PICs disabled.
+pub unsafe fn init(madt: &Madt<'_>) -> Result<Summary, paging::Error> {
+    let address = madt.local_apic_address();
+    let base = unsafe { memory::map_mmio(address, FRAME_SIZE) }?;
+
+    // The firmware usually leaves it on, but doesn't have to.
+    unsafe { cpu::write_msr(APIC_BASE, cpu::read_msr(APIC_BASE) | APIC_BASE_ENABLE) };
+
+    LOCAL_APIC.store(base, Ordering::Relaxed);
+
+    let id = id();
+    let mut processor = None;
+    let mut processors = 0;
+    let mut io_apics = IO_APICS.lock();
+
+    for entry in madt.entries() {
+        match entry {
+            MadtEntry::LocalApic {
+                processor: uid,
+                apic_id,
+                flags,
+            } => {
+                if apic_id == id {
+                    processor = Some(uid);
+                }
+
+                processors += usize::from(flags & LOCAL_APIC_ENABLED != 0);
+            }
+            MadtEntry::IoApic {
+                address, gsi_base, ..
+            } => {
+                let base = unsafe { memory::map_mmio(address as u64, 0x20) }?;
+                let mut io_apic = IoApic {
+                    base,
+                    gsi_base,
+                    entries: 0,
+                };
+
+                io_apic.entries = redirection_entries(unsafe { io_apic.read(io::VERSION) });
+
+                for index in 0..io_apic.entries {
+                    unsafe { io_apic.set_entry(index, RedirectionEntry(RedirectionEntry::MASKED)) };
+                }
+
+                io_apics.io_apics.push(io_apic);
+            }
+            MadtEntry::InterruptOverride(found) => io_apics.overrides.push(found),
+            _ => {}
+        }
+    }
+
+    unsafe {
+        write(local::TASK_PRIORITY, 0);
+        write(local::LVT_TIMER, lvt::MASKED);
+        write(local::LVT_ERROR, lvt::MASKED);
+        write(local::LVT_LINT0, lvt::MASKED);
+        write(local::LVT_LINT1, lvt::MASKED);
+    }
+
+    for entry in madt.entries() {
+        if let MadtEntry::LocalApicNmi {
+            processor: uid,
+            flags,
+            lint,
+        } = entry
+        {
+            if (uid != 0xff) & (Some(uid) != processor) {
+                continue;
+            }
+
+            let register = if lint == 0 {
+                local::LVT_LINT0
+            } else {
+                local::LVT_LINT1
+            };
+
+            // NMIs are always edge triggered.
+            let polarity = Route::with_flags(0, flags).lvt_bits() & lvt::ACTIVE_LOW;
+
+            unsafe { write(register, lvt::NMI | polarity) };
+        }
+    }
+
+    unsafe {
+        write(
+            local::SPURIOUS,
+            local::SOFTWARE_ENABLE | interrupts::SPURIOUS as u32,
+        );
+    }
+
+    Ok(Summary {
+        local_apic: address,
+        processors,
+        io_apics: io_apics.io_apics.len(),
+    })
+}
+
+/// Delivers ISA IRQ `irq` as `vector` to this processor, following the MADT's
+/// overrides.
+///
+/// Returns `false` if no I/O APIC handles the interrupt it's wired to.
+///
+/// # Safety
+///
+/// `vector` mustn't be used by anything else.
+pub unsafe fn route_isa_irq(irq: u8, vector: u8) -> bool {
+    let io_apics = IO_APICS.lock();
+    let route = Route::isa(irq, io_apics.overrides.iter().copied());
+
+    let Some(io_apic) = io_apics.io_apics.iter().find(|io_apic| {
+        (io_apic.gsi_base..io_apic.gsi_base + io_apic.entries).contains(&route.gsi)
+    }) else {
+        return false;
+    };
+
+    unsafe {
+        io_apic.set_entry(
+            route.gsi - io_apic.gsi_base,
+            RedirectionEntry::new(route, vector, id()),
+        );
+    }
+
+    true
+}
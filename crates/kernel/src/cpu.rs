@@ -0,0 +1,91 @@
+//! Control registers and model specific registers.
+
+use core::arch::asm;
+
+/// The model specific register holding the extended feature enables.
+const EFER: u32 = 0xc000_0080;
+
+/// The bit of [`EFER`] that makes the no-execute bit of page tables work.
+const EFER_NXE: u64 = 1 << 11;
+
+/// The bit of `CR4` that makes global pages survive `CR3` writes.
+const CR4_PGE: u64 = 1 << 7;
+
+/// Reads a model specific register.
+///
+/// # Safety
+///
+/// The register must exist.
+#[inline]
+pub unsafe fn read_msr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+
+    unsafe {
+        asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+
+    ((high as u64) << 32) | low as u64
+}
+
+/// Writes a model specific register.
+///
+/// # Safety
+///
+/// The register must exist, and `value` mustn't break anything relying on it.
+#[inline]
+pub unsafe fn write_msr(msr: u32, value: u64) {
+    unsafe {
+        asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32, options(nostack, preserves_flags));
+    }
+}
+
+/// Returns the address of the last page fault.
+#[inline]
+#[must_use]
+pub fn read_cr2() -> u64 {
+    let cr2;
+
+    unsafe { asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags)) };
+
+    cr2
+}
+
+/// Returns the physical address of the active PML4, along with the flags in the
+/// low bits of `CR3`.
+#[inline]
+#[must_use]
+pub fn read_cr3() -> u64 {
+    let cr3;
+
+    unsafe { asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags)) };
+
+    cr3
+}
+
+/// Turns on the no-execute bit of page tables, if the CPU supports it.
+///
+/// Returns whether it's on. Until it is, setting it in an entry is reserved and
+/// faults.
+pub fn enable_no_execute() -> bool {
+    if !mrow_common::cpu::has_no_execute() {
+        return false;
+    }
+
+    unsafe { write_msr(EFER, read_msr(EFER) | EFER_NXE) };
+
+    true
+}
+
+/// Makes pages marked as global survive switching page tables.
+pub fn enable_global_pages() {
+    unsafe {
+        asm!(
+            "mov {cr4}, cr4",
+            "or {cr4}, {pge}",
+            "mov cr4, {cr4}",
+            cr4 = out(reg) _,
+            pge = in(reg) CR4_PGE,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+}
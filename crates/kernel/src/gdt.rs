@@ -0,0 +1,85 @@
+//! The kernel's own segments and task state segment.
+//!
+//! The loader's GDT is in low memory we want to hand out, and has no room for a
+//! TSS, which we need for the interrupt stacks.
+
+use core::{
+    arch::asm,
+    ptr::{addr_of, addr_of_mut},
+};
+use mrow_common::gdt::{Descriptor, Pointer64, TaskStateSegment};
+
+/// The selector of the kernel code segment.
+pub const CODE: u16 = 0x08;
+
+/// The selector of the kernel data segment.
+pub const DATA: u16 = 0x10;
+
+/// The selector of the task state segment.
+pub const TSS: u16 = 0x18;
+
+/// The interrupt stack the double fault handler runs on.
+///
+/// It gets a stack of its own so a kernel stack overflow, which faults again
+/// while pushing the page fault, still ends up somewhere we can report it.
+pub const DOUBLE_FAULT_STACK: u8 = 1;
+
+/// The size of the interrupt stacks.
+const INTERRUPT_STACK_LEN: usize = 0x4000;
+
+/// An interrupt stack, aligned the way the System V ABI wants it.
+#[repr(C, align(16))]
+struct InterruptStack([u8; INTERRUPT_STACK_LEN]);
+
+static mut DOUBLE_FAULT: InterruptStack = InterruptStack([0; INTERRUPT_STACK_LEN]);
+
+static mut TASK_STATE: TaskStateSegment = TaskStateSegment::new();
+
+/// The table, with the TSS descriptor filled in by [`init`].
+static mut GDT: [Descriptor; 5] = [
+    Descriptor::NULL,
+    Descriptor::CODE_64,
+    Descriptor::DATA_64,
+    Descriptor::NULL,
+    Descriptor::NULL,
+];
+
+/// Loads the kernel's GDT and TSS, and reloads all segment registers.
+///
+/// # Safety
+///
+/// Must only be called once, before interrupts are enabled.
+pub unsafe fn init() {
+    unsafe {
+        let tss = &mut *addr_of_mut!(TASK_STATE);
+        tss.interrupt_stacks[DOUBLE_FAULT_STACK as usize - 1] =
+            addr_of!(DOUBLE_FAULT) as u64 + INTERRUPT_STACK_LEN as u64;
+
+        let gdt = &mut *addr_of_mut!(GDT);
+        [gdt[3], gdt[4]] = Descriptor::tss(addr_of!(TASK_STATE) as u64);
+
+        let pointer = Pointer64::new(gdt);
+
+        asm!(
+            "lgdt [{pointer}]",
+            // Far return to reload CS, there's no far jump to an immediate in long mode.
+            "push {code}",
+            "lea {tmp}, [rip + 2f]",
+            "push {tmp}",
+            "retfq",
+            "2:",
+            "mov ds, {data:x}",
+            "mov es, {data:x}",
+            "mov ss, {data:x}",
+            "xor {tmp:e}, {tmp:e}",
+            "mov fs, {tmp:x}",
+            "mov gs, {tmp:x}",
+            "ltr {tss:x}",
+            pointer = in(reg) addr_of!(pointer),
+            code = const CODE,
+            data = in(reg) DATA as u64,
+            tss = in(reg) TSS as u64,
+            tmp = out(reg) _,
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! The kernel heap, behind `alloc`.
+//!
+//! Pages come from a region of their own in the higher half, and get frames
+//! mapped in as the heap grows.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, NonNull},
+};
+use mrow_common::{
+    frame::FrameAllocator,
+    heap::{Heap, PageSource, Stats, PAGE_SIZE},
+    paging::{Mapper, PageSize},
+    warn,
+};
+
+use crate::{
+    memory::{self, FRAMES, PAGE_TABLES},
+    serial::Serial,
+    sync::SpinLock,
+};
+
+/// Where the heap starts, right after the largest direct map we can have.
+pub const HEAP_START: u64 = 0xffff_c000_0000_0000;
+
+/// How far the heap can grow.
+pub const HEAP_LEN: u64 = 0x10_0000_0000;
+
+#[global_allocator]
+static HEAP: KernelHeap = KernelHeap(SpinLock::new(Heap::new(KernelPages { next: HEAP_START })));
+
+/// Returns how much of the heap is in use.
+pub fn stats() -> Stats {
+    HEAP.0.lock().stats()
+}
+
+/// The global allocator, which works once [`memory::init_paging`] is done.
+struct KernelHeap(SpinLock<Heap<KernelPages>>);
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.0.lock().allocate(layout) {
+            Some(ptr) => ptr.as_ptr(),
+            None => {
+                // Callers usually panic next, but not all of them say why.
+                warn!(
+                    Serial,
+                    "Out of heap memory for ",
+                    layout.size(),
+                    " bytes aligned to ",
+                    layout.align()
+                );
+
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe {
+            self.0
+                .lock()
+                .deallocate(NonNull::new_unchecked(ptr), layout)
+        };
+    }
+}
+
+/// Hands out pages from the heap region, mapping frames in on demand.
+///
+/// Addresses only get reused when the last pages are freed, the region is big
+/// enough to not care otherwise.
+#[derive(Debug)]
+struct KernelPages {
+    next: u64,
+}
+
+unsafe impl PageSource for KernelPages {
+    fn allocate_pages(&mut self, count: usize) -> Option<NonNull<u8>> {
+        let start = self.next;
+        let len = (count * PAGE_SIZE) as u64;
+
+        if len > HEAP_START + HEAP_LEN - start {
+            return None;
+        }
+
+        let mut frames = FRAMES.lock();
+        let frames = frames.as_mut()?;
+        let mut tables = PAGE_TABLES.lock();
+        let tables = tables.as_mut()?;
+
+        for page in 0..count {
+            let virt = start + (page * PAGE_SIZE) as u64;
+
+            let Some(frame) = frames.allocate() else {
+                unsafe { unmap(tables, frames, start, page) };
+                return None;
+            };
+
+            match unsafe { tables.map(virt, frame, PageSize::Size4K, memory::data_flags(), frames) }
+            {
+                // Nothing can have cached a page that wasn't mapped.
+                Ok(flush) => flush.ignore(),
+                Err(_) => {
+                    frames.free(frame);
+                    unsafe { unmap(tables, frames, start, page) };
+                    return None;
+                }
+            }
+        }
+
+        self.next += len;
+
+        NonNull::new(start as *mut u8)
+    }
+
+    unsafe fn free_pages(&mut self, pages: NonNull<u8>, count: usize) {
+        let start = pages.as_ptr() as u64;
+        let mut frames = FRAMES.lock();
+        let mut tables = PAGE_TABLES.lock();
+
+        if let (Some(frames), Some(tables)) = (frames.as_mut(), tables.as_mut()) {
+            unsafe { unmap(tables, frames, start, count) };
+        }
+
+        if start + (count * PAGE_SIZE) as u64 == self.next {
+            self.next = start;
+        }
+    }
+}
+
+/// Unmaps `count` heap pages from `start` and frees their frames.
+///
+/// # Safety
+///
+/// Nothing may use the pages anymore.
+unsafe fn unmap(tables: &mut Mapper, frames: &mut FrameAllocator<'_>, start: u64, count: usize) {
+    for page in 0..count {
+        if let Ok((mapping, flush)) = unsafe { tables.unmap(start + (page * PAGE_SIZE) as u64) } {
+            flush.flush();
+            frames.free(mapping.address);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! The interrupt descriptor table, the exception handlers, and dispatching
+//! device interrupts.
+//!
+//! Every vector gets a small stub that pushes its number, and a 0 if the CPU
+//! doesn't push an error code, and then jumps to a common entry that saves the
+//! registers and calls [`handle`] with an [`InterruptFrame`].
+
+use core::{
+    arch::{asm, naked_asm},
+    mem,
+    ptr::{self, addr_of, addr_of_mut},
+    sync::atomic::{AtomicPtr, Ordering},
+};
+use mrow_common::{
+    error,
+    format::Int,
+    format_to,
+    idt::{
+        self, attributes, exception_name, page_fault, vectors, Gate, InterruptFrame, ERROR_CODES,
+        EXCEPTIONS,
+    },
+    log::Level,
+    trace, warn,
+};
+
+use crate::{apic, cpu, gdt, halt, serial::Serial};
+
+/// The vector of the local APIC timer.
+pub const TIMER: u8 = 0x20;
+
+/// The vector of ISA IRQ 0, the others follow.
+pub const ISA_IRQS: u8 = 0x30;
+
+/// The vector of the first IRQ of the masked 8259 PICs, which only ever send
+/// spurious ones.
+pub const LEGACY_PIC: u8 = 0xe0;
+
+/// The vector of spurious local APIC interrupts.
+pub const SPURIOUS: u8 = 0xff;
+
+/// The interrupt flag in RFLAGS.
+const INTERRUPT_FLAG: u64 = 1 << 9;
+
+/// How many vectors there are.
+const VECTORS: usize = 256;
+
+/// How far apart the entry stubs are.
+const STUB_LEN: usize = 16;
+
+/// Something handling a device interrupt.
+pub type Handler = fn(&mut InterruptFrame);
+
+static mut IDT: [Gate; VECTORS] = [Gate::MISSING; VECTORS];
+
+/// The handlers of the vectors above the exceptions, null where there's none.
+static HANDLERS: [AtomicPtr<()>; VECTORS] = [const { AtomicPtr::new(ptr::null_mut()) }; VECTORS];
+
+/// Fills in the IDT and loads it.
+///
+/// # Safety
+///
+/// Must only be called once, after [`gdt::init`].
+pub unsafe fn init() {
+    unsafe {
+        let idt = &mut *addr_of_mut!(IDT);
+
+        for (vector, gate) in idt.iter_mut().enumerate() {
+            let stack = if vector == vectors::DOUBLE_FAULT as usize {
+                gdt::DOUBLE_FAULT_STACK
+            } else {
+                0
+            };
+
+            *gate = Gate::new(
+                stubs as *const () as u64 + (vector * STUB_LEN) as u64,
+                gdt::CODE,
+                stack,
+                attributes::PRESENT | attributes::INTERRUPT,
+            );
+        }
+
+        let pointer = idt::pointer(&*addr_of!(IDT));
+
+        asm!("lidt [{}]", in(reg) addr_of!(pointer), options(readonly, nostack, preserves_flags));
+    }
+}
+
+/// The entry stubs, one every [`STUB_LEN`] bytes.
+#[unsafe(naked)]
+unsafe extern "sysv64" fn stubs() {
+    naked_asm!(
+        ".set mrow_vector, 0",
+        ".rept {count}",
+        "2:",
+        ".if (mrow_vector >= {exceptions}) || ((({error_codes} >> (mrow_vector & 31)) & 1) == 0)",
+        "push 0",
+        ".endif",
+        "push mrow_vector",
+        "jmp {entry}",
+        // Padding from the start of the stub, the function itself may not be aligned.
+        ".skip {stub_len} - (. - 2b), 0xcc",
+        ".set mrow_vector, mrow_vector + 1",
+        ".endr",
+        count = const VECTORS,
+        exceptions = const EXCEPTIONS,
+        stub_len = const STUB_LEN,
+        error_codes = const ERROR_CODES,
+        entry = sym entry,
+    )
+}
+
+/// Saves the registers the stubs didn't, calls [`handle`], and returns from the
+/// interrupt if it does.
+#[unsafe(naked)]
+unsafe extern "sysv64" fn entry() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        // The CPU aligns the stack before pushing its frame, and we pushed an even
+        // number of registers on top of that, so it's aligned for the call.
+        "mov rdi, rsp",
+        "cld",
+        "call {handle}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        // Drop the vector and error code.
+        "add rsp, 16",
+        "iretq",
+        handle = sym handle,
+    )
+}
+
+/// Makes `handler` handle interrupts on `vector`.
+///
+/// # Panics
+///
+/// Panics if `vector` belongs to an exception.
+pub fn set_handler(vector: u8, handler: Handler) {
+    assert!(
+        vector as usize >= EXCEPTIONS,
+        "exceptions can't have handlers"
+    );
+
+    HANDLERS[vector as usize].store(handler as *mut (), Ordering::Release);
+}
+
+/// Turns interrupts on.
+///
+/// # Safety
+///
+/// Nothing that's running may hold a lock a handler takes.
+pub unsafe fn enable() {
+    unsafe { asm!("sti", options(nomem, nostack)) };
+}
+
+/// Runs `f` with interrupts off, so it can take locks handlers take.
+///
+/// Interrupts are turned back on afterwards if they were on before.
+pub fn without<T>(f: impl FnOnce() -> T) -> T {
+    let rflags: u64;
+
+    // Neither may be reordered with the memory accesses of `f`.
+    unsafe { asm!("pushfq", "pop {}", "cli", out(reg) rflags) };
+
+    let result = f();
+
+    if rflags & INTERRUPT_FLAG != 0 {
+        unsafe { asm!("sti", options(nostack)) };
+    }
+
+    result
+}
+
+/// Handles the interrupt described by `frame`.
+///
+/// Breakpoints get logged and return, every other exception is fatal. Device
+/// interrupts go to their [`Handler`] and are acknowledged afterwards.
+extern "sysv64" fn handle(frame: &mut InterruptFrame) {
+    let vector = frame.vector as u8;
+
+    if vector == vectors::BREAKPOINT {
+        warn!(Serial, "Breakpoint at 0x", Int::hex(frame.rip));
+        return;
+    }
+
+    if (vector as usize) < EXCEPTIONS {
+        report(frame);
+        halt();
+    }
+
+    // Spurious interrupts must not be acknowledged.
+    if (vector == SPURIOUS) | (LEGACY_PIC..LEGACY_PIC + 16).contains(&vector) {
+        trace!(Serial, "Spurious interrupt ", vector);
+        return;
+    }
+
+    let handler = HANDLERS[vector as usize].load(Ordering::Acquire);
+
+    if handler.is_null() {
+        warn!(Serial, "Unexpected interrupt ", vector);
+    } else {
+        // Only `set_handler` stores anything, and it stores a `Handler`.
+        let handler = unsafe { mem::transmute::<*mut (), Handler>(handler) };
+        handler(frame);
+    }
+
+    apic::end_of_interrupt();
+}
+
+/// Logs an exception along with all registers.
+fn report(frame: &InterruptFrame) {
+    error!(
+        Serial,
+        "Exception ",
+        frame.vector,
+        " (",
+        exception_name(frame.vector as u8),
+        "), error code 0x",
+        Int::hex(frame.error_code)
+    );
+
+    if frame.vector == vectors::PAGE_FAULT as u64 {
+        let access = if frame.error_code & page_fault::INSTRUCTION_FETCH != 0 {
+            "executing"
+        } else if frame.error_code & page_fault::WRITE != 0 {
+            "writing"
+        } else {
+            "reading"
+        };
+
+        let page = if frame.error_code & page_fault::PRESENT != 0 {
+            "a protected page"
+        } else {
+            "a page that isn't mapped"
+        };
+
+        error!(
+            Serial,
+            "Page fault at 0x",
+            Int::hex(cpu::read_cr2()),
+            " while ",
+            access,
+            " ",
+            page
+        );
+    }
+
+    if !Level::Error.enabled() {
+        return;
+    }
+
+    let registers = [
+        ("rax", frame.rax),
+        ("rbx", frame.rbx),
+        ("rcx", frame.rcx),
+        ("rdx", frame.rdx),
+        ("rsi", frame.rsi),
+        ("rdi", frame.rdi),
+        ("rbp", frame.rbp),
+        ("rsp", frame.rsp),
+        ("r8 ", frame.r8),
+        ("r9 ", frame.r9),
+        ("r10", frame.r10),
+        ("r11", frame.r11),
+        ("r12", frame.r12),
+        ("r13", frame.r13),
+        ("r14", frame.r14),
+        ("r15", frame.r15),
+        ("rip", frame.rip),
+        ("rfl", frame.rflags),
+        ("cs ", frame.cs),
+        ("ss ", frame.ss),
+    ];
+
+    for row in registers.chunks(4) {
+        for (name, value) in row {
+            format_to!(Serial, "  ", name, " ", Int::hex(*value).width(16).zeroes());
+        }
+
+        format_to!(Serial, "\r\n");
+    }
+}
@@ -0,0 +1,195 @@
+//! The PS/2 keyboard on the first port of the 8042.
+//!
+//! The interrupt handler decodes scancodes as they come in and queues the
+//! characters they type for [`read`]. Lock keys update the keyboard's LEDs.
+
+use mrow_common::{
+    format::Fmt,
+    idt::InterruptFrame,
+    keyboard::{Keyboard, ScancodeSet},
+    port::inb,
+    trace, warn,
+};
+
+use crate::{
+    apic, interrupts,
+    ps2::{self, config, device},
+    serial::Serial,
+    sync::SpinLock,
+};
+
+/// The ISA IRQ of the first PS/2 port.
+const IRQ: u8 = 1;
+
+/// How many typed characters are kept until they're read.
+const INPUT_LEN: usize = 64;
+
+/// The keyboard, once [`init`] found one.
+///
+/// The interrupt handler takes this, so only take it with interrupts off.
+static DRIVER: SpinLock<Option<Driver>> = SpinLock::new(None);
+
+#[derive(Debug)]
+struct Driver {
+    keyboard: Keyboard,
+    /// The LEDs to send once the keyboard acknowledges the command.
+    leds: Option<u8>,
+    input: [char; INPUT_LEN],
+    start: usize,
+    len: usize,
+}
+
+impl Driver {
+    /// Handles a byte from the keyboard.
+    fn receive(&mut self, byte: u8) {
+        if byte == device::ACK {
+            if let Some(leds) = self.leds.take() {
+                // The keyboard just took a byte, so it's ready for the next.
+                if let Err(err) = unsafe { ps2::write(leds) } {
+                    warn!(Serial, "Can't set the keyboard LEDs: ", Fmt(err));
+                }
+            }
+
+            return;
+        }
+
+        if byte == device::RESEND {
+            self.leds = None;
+            return;
+        }
+
+        let leds = self.keyboard.modifiers().leds();
+
+        let Some((event, char)) = self.keyboard.feed(byte) else {
+            return;
+        };
+
+        trace!(
+            Serial,
+            "Key ",
+            Fmt(format_args!("{:?}", event.code)),
+            if event.pressed { " down" } else { " up" }
+        );
+
+        if self.keyboard.modifiers().leds() != leds {
+            self.leds = Some(self.keyboard.modifiers().leds());
+
+            if let Err(err) = unsafe { ps2::write(device::SET_LEDS) } {
+                warn!(Serial, "Can't set the keyboard LEDs: ", Fmt(err));
+                self.leds = None;
+            }
+        }
+
+        if let Some(char) = char {
+            if self.len == INPUT_LEN {
+                warn!(Serial, "Keyboard input is full, dropping a key");
+                return;
+            }
+
+            self.input[(self.start + self.len) % INPUT_LEN] = char;
+            self.len += 1;
+        }
+    }
+
+    /// Takes the oldest typed character.
+    fn pop(&mut self) -> Option<char> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let char = self.input[self.start];
+        self.start = (self.start + 1) % INPUT_LEN;
+        self.len -= 1;
+
+        Some(char)
+    }
+}
+
+/// Sets up the 8042 and resets the keyboard behind it, and starts handling its
+/// interrupts.
+///
+/// Returns the scancode set the keyboard sends.
+///
+/// # Safety
+///
+/// Must only be called once, after [`apic::init`], with interrupts off.
+pub unsafe fn init() -> Result<ScancodeSet, ps2::Error> {
+    let config = unsafe { ps2::init() }?;
+
+    unsafe { ps2::command(device::RESET) }?;
+
+    let answer = ps2::read()?;
+
+    if answer != device::SELF_TEST_PASSED {
+        return Err(ps2::Error::DeviceTest(answer));
+    }
+
+    // With translation off we see what the keyboard sends, so ask it which set
+    // that is, and switch to set 2 if it's one we can't decode.
+    unsafe { ps2::write_config(config & !config::TRANSLATION) }?;
+
+    let set = match unsafe { scancode_set() } {
+        Ok(1) => ScancodeSet::Set1,
+        Ok(2) => ScancodeSet::Set2,
+        _ => {
+            unsafe {
+                ps2::command(device::SCANCODE_SET)?;
+                ps2::command(2)?;
+            }
+
+            ScancodeSet::Set2
+        }
+    };
+
+    unsafe { ps2::command(device::ENABLE_SCANNING) }?;
+
+    interrupts::without(|| {
+        *DRIVER.lock() = Some(Driver {
+            keyboard: Keyboard::new(set),
+            leds: None,
+            input: ['\0'; INPUT_LEN],
+            start: 0,
+            len: 0,
+        });
+    });
+
+    interrupts::set_handler(interrupts::ISA_IRQS + IRQ, interrupt);
+
+    if !unsafe { apic::route_isa_irq(IRQ, interrupts::ISA_IRQS + IRQ) } {
+        warn!(Serial, "No I/O APIC for the keyboard's IRQ");
+    }
+
+    unsafe {
+        ps2::write_config((config & !config::TRANSLATION) | config::PORT_1_INTERRUPT)?;
+    }
+
+    Ok(set)
+}
+
+/// Asks the keyboard which scancode set it sends.
+///
+/// # Safety
+///
+/// Interrupts for the first port must be off.
+unsafe fn scancode_set() -> Result<u8, ps2::Error> {
+    unsafe {
+        ps2::command(device::SCANCODE_SET)?;
+        ps2::command(0)?;
+    }
+
+    ps2::read()
+}
+
+fn interrupt(_: &mut InterruptFrame) {
+    // Reading the byte is what lets the next one come in.
+    let byte = unsafe { inb(ps2::DATA) };
+
+    if let Some(driver) = DRIVER.lock().as_mut() {
+        driver.receive(byte);
+    }
+}
+
+/// Takes the oldest character typed, if there's any.
+pub fn read() -> Option<char> {
+    interrupts::without(|| DRIVER.lock().as_mut()?.pop())
+}
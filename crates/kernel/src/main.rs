@@ -0,0 +1,309 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use core::{
+    arch::{asm, naked_asm},
+    ptr::addr_of,
+    time::Duration,
+};
+use mrow_common::{
+    acpi::{Madt, MADT_SIGNATURE},
+    boot_info::{BootInfo, Reader},
+    debug, error,
+    format::{Fmt, Int},
+    format_to,
+    frame::FRAME_SIZE,
+    handoff::{KernelEntry, KERNEL_BASE},
+    header::StageHeader,
+    info,
+    keyboard::ScancodeSet,
+    paging::PHYSICAL_MAP_BASE,
+    warn,
+};
+use serial::Serial;
+
+mod acpi;
+mod apic;
+mod cpu;
+mod gdt;
+mod heap;
+mod interrupts;
+mod keyboard;
+mod memory;
+mod pic;
+mod ps2;
+mod serial;
+mod sync;
+mod timer;
+
+/// The size of the stack we switch to on entry.
+const STACK_LEN: usize = 0x10000;
+
+/// How often the timer interrupts.
+const TICK: Duration = Duration::from_millis(10);
+
+/// A stack with a guard page below it.
+///
+/// The guard page gets unmapped once we manage our own page tables, so an
+/// overflow faults instead of running into whatever comes before.
+#[repr(C, align(4096))]
+struct Stack {
+    guard: [u8; FRAME_SIZE as usize],
+    bytes: [u8; STACK_LEN],
+}
+
+/// The stack of the boot processor.
+///
+/// The one the loader leaves us with is in low memory we want to hand out later.
+static mut STACK: Stack = Stack {
+    guard: [0; FRAME_SIZE as usize],
+    bytes: [0; STACK_LEN],
+};
+
+/// The header stage 2 verifies before loading us.
+///
+/// The payload length and checksum are filled in by the host tool.
+#[used]
+#[no_mangle]
+#[link_section = ".header"]
+pub static HEADER: StageHeader = StageHeader::new();
+
+const _: KernelEntry = _start;
+
+/// The entry point stage 3 jumps to, right after [`HEADER`].
+///
+/// Switches to [`STACK`] and calls [`main`], as there's nothing else we can do
+/// without a stack of our own.
+///
+/// # Safety
+///
+/// `boot_info` must point to the boot information built by the loader, see
+/// [`KernelEntry`].
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".start"]
+pub unsafe extern "sysv64" fn _start(boot_info: *const BootInfo) -> ! {
+    naked_asm!(
+        "lea rsp, [rip + {stack} + {stack_size}]",
+        "xor ebp, ebp",
+        "call {main}",
+        "ud2",
+        stack = sym STACK,
+        stack_size = const size_of::<Stack>(),
+        main = sym main,
+    )
+}
+
+/// Where the kernel starts for real, on its own stack.
+///
+/// # Safety
+///
+/// See [`_start`].
+unsafe extern "sysv64" fn main(boot_info: *const BootInfo) -> ! {
+    unsafe {
+        Serial::init();
+        gdt::init();
+        interrupts::init();
+    }
+
+    info!(Serial, "mrow ", env!("CARGO_PKG_VERSION"));
+
+    let boot_info = match unsafe { Reader::from_ptr(boot_info) } {
+        Ok(boot_info) => boot_info,
+        Err(err) => {
+            error!(Serial, "Unusable boot information: ", Fmt(err));
+            halt();
+        }
+    };
+
+    info!(
+        Serial,
+        "Booted by ",
+        boot_info.loader_name(),
+        " ",
+        boot_info.loader_version()
+    );
+
+    if !boot_info.cmdline().is_empty() {
+        info!(Serial, "Command line: ", boot_info.cmdline());
+    }
+
+    let Some(frames) = (unsafe { memory::init(&boot_info) }) else {
+        error!(Serial, "No memory for the frame allocator");
+        halt();
+    };
+
+    info!(
+        Serial,
+        "Memory: ",
+        (frames.free as u64 * FRAME_SIZE) >> 20,
+        " MiB free"
+    );
+
+    let mapped = match unsafe { memory::init_paging(&boot_info) } {
+        Ok(mapped) => mapped,
+        Err(err) => {
+            error!(Serial, "Can't map physical memory: ", Fmt(err));
+            halt();
+        }
+    };
+
+    info!(
+        Serial,
+        "Mapped ",
+        mapped >> 20,
+        " MiB of physical memory at 0x",
+        Int::hex(PHYSICAL_MAP_BASE)
+    );
+
+    if let Err(err) = unsafe { memory::add_guard_page(addr_of!(STACK.guard) as u64) } {
+        warn!(Serial, "Can't protect the kernel stack: ", Fmt(err));
+    }
+
+    if let Some(kernel) = memory::PAGE_TABLES
+        .lock()
+        .as_ref()
+        .and_then(|tables| tables.translate_address(KERNEL_BASE))
+    {
+        debug!(Serial, "Kernel loaded at 0x", Int::hex(kernel));
+    }
+
+    let mut kinds = BTreeMap::new();
+
+    for region in boot_info.memory_map() {
+        *kinds.entry(region.kind).or_insert(0) += region.end - region.start;
+    }
+
+    for (kind, len) in kinds {
+        debug!(
+            Serial,
+            "Memory map: ",
+            Fmt(format_args!("{kind:?}")),
+            " ",
+            len >> 10,
+            " KiB"
+        );
+    }
+
+    debug!(
+        Serial,
+        "Heap at 0x",
+        Int::hex(heap::HEAP_START),
+        ", ",
+        heap::stats().allocated,
+        " bytes in use"
+    );
+
+    let Some(madt) = boot_info
+        .rsdp()
+        .and_then(|rsdp| unsafe { acpi::find_table(rsdp, MADT_SIGNATURE) })
+        .and_then(|table| Madt::parse(table).ok())
+    else {
+        error!(Serial, "No MADT, can't set up interrupts");
+        halt();
+    };
+
+    if madt.has_legacy_pics() {
+        unsafe { pic::disable() };
+    }
+
+    let apics = match unsafe { apic::init(&madt) } {
+        Ok(apics) => apics,
+        Err(err) => {
+            error!(Serial, "Can't map the APICs: ", Fmt(err));
+            halt();
+        }
+    };
+
+    info!(
+        Serial,
+        "Local APIC at 0x",
+        Int::hex(apics.local_apic),
+        ", ",
+        apics.processors,
+        " processors, ",
+        apics.io_apics,
+        " I/O APICs"
+    );
+
+    let frequency = unsafe { timer::init() };
+
+    info!(Serial, "APIC timer at ", frequency / 1000, " kHz");
+
+    // Make sure interrupts get through before relying on them.
+    debug!(Serial, "Waiting for a timer interrupt");
+    timer::start_one_shot(TICK);
+
+    while timer::ticks() == 0 {
+        // Interrupts only come in once `hlt` runs, so the one we wait for can't
+        // slip in between the check and sleeping.
+        unsafe { asm!("sti", "hlt", "cli", options(nomem, nostack)) };
+    }
+
+    timer::start_periodic(TICK);
+
+    match unsafe { keyboard::init() } {
+        Ok(set) => info!(
+            Serial,
+            "PS/2 keyboard on scancode set ",
+            match set {
+                ScancodeSet::Set1 => 1,
+                ScancodeSet::Set2 => 2,
+            }
+        ),
+        Err(err) => warn!(Serial, "No keyboard: ", Fmt(err)),
+    }
+
+    unsafe { interrupts::enable() };
+
+    echo();
+}
+
+/// Echoes what's typed on the keyboard to the serial port, forever.
+fn echo() -> ! {
+    loop {
+        unsafe { asm!("cli", options(nomem, nostack)) };
+
+        match keyboard::read() {
+            Some('\n') => {
+                unsafe { interrupts::enable() };
+                format_to!(Serial, "\r\n");
+            }
+            Some(char) => {
+                unsafe { interrupts::enable() };
+                format_to!(Serial, char);
+            }
+            // Interrupts only come in once `hlt` runs, so a key can't slip in
+            // between the check and sleeping.
+            None => unsafe { asm!("sti", "hlt", options(nomem, nostack)) },
+        }
+    }
+}
+
+/// Stops the processor for good.
+pub fn halt() -> ! {
+    loop {
+        unsafe { asm!("cli", "hlt", options(nomem, nostack)) };
+    }
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
+    match info.location() {
+        Some(location) => error!(
+            Serial,
+            "Panic at ",
+            location.file(),
+            ":",
+            location.line(),
+            ": ",
+            Fmt(info.message())
+        ),
+        None => error!(Serial, "Panic: ", Fmt(info.message())),
+    }
+
+    halt();
+}
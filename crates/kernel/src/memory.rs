@@ -0,0 +1,203 @@
+//! Physical and virtual memory management.
+
+use core::{
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use mrow_common::{
+    boot_info::Reader,
+    frame::{FrameAllocator, Stats, FRAME_SIZE, LOW_MEMORY_END},
+    memory_map::MemoryKind,
+    paging::{self, flags, FrameSource, Mapper, PageSize, ADDRESS_MASK, PHYSICAL_MAP_BASE},
+};
+
+use crate::{cpu, sync::SpinLock};
+
+/// The end of the memory the loader identity maps for us.
+const IDENTITY_END: u64 = 0x1_0000_0000;
+
+/// The allocator for physical frames, set up by [`init`].
+pub static FRAMES: SpinLock<Option<FrameAllocator<'static>>> = SpinLock::new(None);
+
+/// The kernel's page tables, set up by [`init_paging`].
+///
+/// They are edited through the direct map at [`PHYSICAL_MAP_BASE`].
+pub static PAGE_TABLES: SpinLock<Option<Mapper>> = SpinLock::new(None);
+
+/// Where device registers get mapped, after the heap.
+const MMIO_START: u64 = 0xffff_d000_0000_0000;
+
+/// The next free address for device registers.
+static MMIO_NEXT: AtomicU64 = AtomicU64::new(MMIO_START);
+
+/// The page flags for kernel data, which is never executable if we can help it.
+static DATA_FLAGS: AtomicU64 = AtomicU64::new(flags::WRITABLE | flags::GLOBAL);
+
+/// Returns the page flags for kernel data, set up by [`init_paging`].
+#[inline]
+pub fn data_flags() -> u64 {
+    DATA_FLAGS.load(Ordering::Relaxed)
+}
+
+/// Hands out frames that are identity mapped, for tables we edit before the
+/// direct map exists.
+struct IdentityFrames<'a, 'b>(&'a mut FrameAllocator<'b>);
+
+impl FrameSource for IdentityFrames<'_, '_> {
+    fn allocate_frame(&mut self) -> Option<u64> {
+        self.0.allocate_contiguous(1, FRAME_SIZE, IDENTITY_END)
+    }
+}
+
+/// Sets up [`FRAMES`] from the memory map in `boot_info`.
+///
+/// The bitmap goes into the first usable memory that's identity mapped and big
+/// enough. Returns `None` if there's no such memory.
+///
+/// # Safety
+///
+/// Must only be called once, and the memory map must be right about what's
+/// usable.
+pub unsafe fn init(boot_info: &Reader<'_>) -> Option<Stats> {
+    let memory_map = boot_info.memory_map();
+    let len = FrameAllocator::bitmap_len(memory_map);
+    let bytes = (len * size_of::<u64>()) as u64;
+
+    let bitmap_start = memory_map
+        .iter()
+        .filter(|region| region.kind == MemoryKind::Usable)
+        .find_map(|region| {
+            let start = region
+                .start
+                .max(LOW_MEMORY_END)
+                .next_multiple_of(FRAME_SIZE);
+
+            (start + bytes <= region.end.min(IDENTITY_END)).then_some(start)
+        })?;
+
+    let bitmap = unsafe { slice::from_raw_parts_mut(bitmap_start as *mut u64, len) };
+
+    // The loader marks the boot information as its own, but we're still reading it.
+    let boot_info_start = boot_info.as_bytes().as_ptr() as u64;
+    let boot_info_end = boot_info_start + boot_info.as_bytes().len() as u64;
+
+    let frames = FrameAllocator::new(
+        bitmap,
+        memory_map,
+        &[
+            bitmap_start..bitmap_start + bytes,
+            boot_info_start..boot_info_end,
+        ],
+    );
+    let stats = frames.stats();
+
+    *FRAMES.lock() = Some(frames);
+
+    Some(stats)
+}
+
+/// Maps all of physical memory at [`PHYSICAL_MAP_BASE`] and sets up [`PAGE_TABLES`].
+///
+/// At least the first 4 GiB get mapped, as that's where devices usually are.
+/// Returns how many bytes got mapped.
+///
+/// # Safety
+///
+/// Must only be called once, after [`init`], while the loader's identity
+/// mapping is still active.
+pub unsafe fn init_paging(boot_info: &Reader<'_>) -> Result<u64, paging::Error> {
+    let size = if mrow_common::cpu::has_huge_pages() {
+        PageSize::Size1G
+    } else {
+        PageSize::Size2M
+    };
+
+    let end = boot_info
+        .memory_map()
+        .iter()
+        .map(|region| region.end)
+        .fold(IDENTITY_END, u64::max)
+        .next_multiple_of(size.bytes());
+
+    let mut map_flags = flags::WRITABLE | flags::GLOBAL;
+
+    if cpu::enable_no_execute() {
+        map_flags |= flags::NO_EXECUTE;
+    }
+
+    DATA_FLAGS.store(map_flags, Ordering::Relaxed);
+
+    cpu::enable_global_pages();
+
+    let pml4 = cpu::read_cr3() & ADDRESS_MASK;
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().ok_or(paging::Error::OutOfFrames)?;
+
+    // The loader identity maps the first 4 GiB, so that's where tables are for now.
+    let mut identity = unsafe { Mapper::new(pml4, 0) };
+
+    unsafe {
+        identity.map_range(
+            PHYSICAL_MAP_BASE,
+            0,
+            end,
+            size,
+            map_flags,
+            &mut IdentityFrames(frames),
+        )?;
+    }
+
+    *PAGE_TABLES.lock() = Some(unsafe { Mapper::new(pml4, PHYSICAL_MAP_BASE) });
+
+    Ok(end)
+}
+
+/// Unmaps the page at `address`, so touching it faults.
+///
+/// This goes below stacks to catch overflows. The frame behind the page is lost,
+/// as it's usually part of the kernel image.
+///
+/// # Safety
+///
+/// Nothing may use the page anymore.
+pub unsafe fn add_guard_page(address: u64) -> Result<(), paging::Error> {
+    let mut tables = PAGE_TABLES.lock();
+    let tables = tables.as_mut().ok_or(paging::Error::NotMapped)?;
+
+    let (_, flush) = unsafe { tables.unmap(address)? };
+    flush.flush();
+
+    Ok(())
+}
+
+/// Maps `len` bytes of device registers at `address` uncached, and returns where.
+///
+/// The direct map is cached, which registers don't like.
+///
+/// # Safety
+///
+/// `address` must be device memory, or nothing else may rely on caching for it.
+pub unsafe fn map_mmio(address: u64, len: u64) -> Result<u64, paging::Error> {
+    let start = address & !(FRAME_SIZE - 1);
+    let end = (address + len).next_multiple_of(FRAME_SIZE);
+
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().ok_or(paging::Error::OutOfFrames)?;
+    let mut tables = PAGE_TABLES.lock();
+    let tables = tables.as_mut().ok_or(paging::Error::NotMapped)?;
+
+    let virt = MMIO_NEXT.fetch_add(end - start, Ordering::Relaxed);
+
+    unsafe {
+        tables.map_range(
+            virt,
+            start,
+            end - start,
+            PageSize::Size4K,
+            data_flags() | flags::NO_CACHE | flags::WRITE_THROUGH,
+            frames,
+        )?;
+    }
+
+    Ok(virt + (address - start))
+}
@@ -0,0 +1,52 @@
+//! The legacy 8259 PICs, which only get out of the way of the APICs.
+
+use mrow_common::port::{io_wait, outb};
+
+use crate::interrupts::LEGACY_PIC;
+
+const PRIMARY_COMMAND: u16 = 0x20;
+const PRIMARY_DATA: u16 = 0x21;
+const SECONDARY_COMMAND: u16 = 0xa0;
+const SECONDARY_DATA: u16 = 0xa1;
+
+/// Starts initialization, with a fourth control word to come.
+const ICW1_INIT: u8 = 0x11;
+
+/// Makes the PICs talk to an 8086 rather than an 8080.
+const ICW4_8086: u8 = 0x01;
+
+/// Remaps the PICs to [`LEGACY_PIC`] and masks all of their IRQs.
+///
+/// The BIOS leaves them on vectors that clash with exceptions, and even masked
+/// they can still raise spurious IRQs there.
+///
+/// # Safety
+///
+/// There must be PICs, which there are on anything PC compatible.
+pub unsafe fn disable() {
+    unsafe {
+        outb(PRIMARY_COMMAND, ICW1_INIT);
+        io_wait();
+        outb(SECONDARY_COMMAND, ICW1_INIT);
+        io_wait();
+
+        outb(PRIMARY_DATA, LEGACY_PIC);
+        io_wait();
+        outb(SECONDARY_DATA, LEGACY_PIC + 8);
+        io_wait();
+
+        // The secondary one is chained to IRQ 2 of the primary one.
+        outb(PRIMARY_DATA, 1 << 2);
+        io_wait();
+        outb(SECONDARY_DATA, 2);
+        io_wait();
+
+        outb(PRIMARY_DATA, ICW4_8086);
+        io_wait();
+        outb(SECONDARY_DATA, ICW4_8086);
+        io_wait();
+
+        outb(PRIMARY_DATA, 0xff);
+        outb(SECONDARY_DATA, 0xff);
+    }
+}
@@ -0,0 +1,246 @@
+//! The 8042 PS/2 controller, and talking to the device on its first port.
+
+use core::{fmt, hint};
+use mrow_common::port::{inb, outb};
+
+/// The port devices and the controller send their bytes on, and commands take
+/// their arguments from.
+pub const DATA: u16 = 0x60;
+
+/// The status port when read, the command port when written.
+const COMMAND: u16 = 0x64;
+
+/// Status bits.
+mod status {
+    /// There's a byte to read from [`DATA`](super::DATA).
+    pub const OUTPUT_FULL: u8 = 1 << 0;
+    /// The controller hasn't taken the last byte written yet.
+    pub const INPUT_FULL: u8 = 1 << 1;
+}
+
+/// Controller commands.
+mod command {
+    pub const READ_CONFIG: u8 = 0x20;
+    pub const WRITE_CONFIG: u8 = 0x60;
+    pub const DISABLE_PORT_2: u8 = 0xa7;
+    pub const SELF_TEST: u8 = 0xaa;
+    pub const TEST_PORT_1: u8 = 0xab;
+    pub const DISABLE_PORT_1: u8 = 0xad;
+    pub const ENABLE_PORT_1: u8 = 0xae;
+}
+
+/// Bits of the controller configuration byte.
+pub mod config {
+    /// Interrupts for bytes from the first port, on ISA IRQ 1.
+    pub const PORT_1_INTERRUPT: u8 = 1 << 0;
+    /// Interrupts for bytes from the second port, on ISA IRQ 12.
+    pub const PORT_2_INTERRUPT: u8 = 1 << 1;
+    /// Translation of the first port's bytes to scancode set 1.
+    pub const TRANSLATION: u8 = 1 << 6;
+}
+
+/// What the controller answers a passed self-test with.
+const SELF_TEST_PASSED: u8 = 0x55;
+
+/// Device commands and replies.
+pub mod device {
+    pub const SET_LEDS: u8 = 0xed;
+    pub const SCANCODE_SET: u8 = 0xf0;
+    pub const ENABLE_SCANNING: u8 = 0xf4;
+    pub const RESET: u8 = 0xff;
+    /// The device took the last byte.
+    pub const ACK: u8 = 0xfa;
+    /// The device wants the last byte again.
+    pub const RESEND: u8 = 0xfe;
+    /// What the device sends after a passed self-test.
+    pub const SELF_TEST_PASSED: u8 = 0xaa;
+}
+
+/// How often to poll the status before giving up on the controller or device.
+///
+/// Port reads take about a microsecond, and devices can take a few hundred
+/// milliseconds to reset.
+const POLLS: u32 = 1_000_000;
+
+/// How often to send a byte the device asks for again.
+const RETRIES: u32 = 3;
+
+/// Ways setting up the controller can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The controller or device didn't answer in time.
+    Timeout,
+    /// The controller failed its self-test, with its answer.
+    SelfTest(u8),
+    /// The first port failed its test, with the controller's answer.
+    PortTest(u8),
+    /// The device didn't acknowledge a byte, with its answer.
+    NoAck(u8),
+    /// The device failed its self-test after a reset, with its answer.
+    DeviceTest(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => f.write_str("PS/2 controller timed out"),
+            Self::SelfTest(answer) => {
+                write!(f, "PS/2 controller self-test failed with {answer:#x}")
+            }
+            Self::PortTest(answer) => write!(f, "PS/2 port test failed with {answer:#x}"),
+            Self::NoAck(answer) => write!(f, "PS/2 device answered {answer:#x}"),
+            Self::DeviceTest(answer) => write!(f, "PS/2 device self-test failed with {answer:#x}"),
+        }
+    }
+}
+
+/// Waits until `bit` of the status is `set`.
+fn wait(bit: u8, set: bool) -> Result<(), Error> {
+    for _ in 0..POLLS {
+        if (unsafe { inb(COMMAND) } & bit != 0) == set {
+            return Ok(());
+        }
+
+        hint::spin_loop();
+    }
+
+    Err(Error::Timeout)
+}
+
+/// Reads the next byte from the controller or the device.
+pub fn read() -> Result<u8, Error> {
+    wait(status::OUTPUT_FULL, true)?;
+
+    Ok(unsafe { inb(DATA) })
+}
+
+/// Writes a byte to the device on the first port.
+///
+/// # Safety
+///
+/// `byte` must make sense to the device.
+pub unsafe fn write(byte: u8) -> Result<(), Error> {
+    wait(status::INPUT_FULL, false)?;
+
+    unsafe { outb(DATA, byte) };
+
+    Ok(())
+}
+
+/// Sends a command to the controller.
+///
+/// # Safety
+///
+/// Commands can do anything up to resetting the machine.
+unsafe fn send(command: u8) -> Result<(), Error> {
+    wait(status::INPUT_FULL, false)?;
+
+    unsafe { outb(COMMAND, command) };
+
+    Ok(())
+}
+
+/// Sends `byte` to the device and waits for it to be acknowledged, sending it
+/// again if the device asks for that.
+///
+/// # Safety
+///
+/// See [`write`]. Interrupts for the first port must be off, or the handler
+/// gets the answer instead.
+pub unsafe fn command(byte: u8) -> Result<(), Error> {
+    let mut answer = 0;
+
+    for _ in 0..RETRIES {
+        unsafe { write(byte) }?;
+
+        answer = read()?;
+
+        if answer != device::RESEND {
+            break;
+        }
+    }
+
+    if answer == device::ACK {
+        Ok(())
+    } else {
+        Err(Error::NoAck(answer))
+    }
+}
+
+/// Throws away any bytes waiting to be read.
+fn flush() {
+    for _ in 0..POLLS {
+        if unsafe { inb(COMMAND) } & status::OUTPUT_FULL == 0 {
+            return;
+        }
+
+        let _ = unsafe { inb(DATA) };
+    }
+}
+
+/// Reads the configuration byte.
+fn read_config() -> Result<u8, Error> {
+    unsafe { send(command::READ_CONFIG) }?;
+
+    read()
+}
+
+/// Writes the configuration byte.
+///
+/// # Safety
+///
+/// See [`config`].
+pub unsafe fn write_config(value: u8) -> Result<(), Error> {
+    unsafe {
+        send(command::WRITE_CONFIG)?;
+        write(value)
+    }
+}
+
+/// Resets the controller to a known state and tests it and its first port,
+/// which is then enabled with its interrupt off.
+///
+/// Returns the configuration byte, with translation to set 1 as the firmware
+/// left it.
+///
+/// # Safety
+///
+/// There must be an 8042, or something emulating one, and nothing else may use
+/// it at the same time.
+pub unsafe fn init() -> Result<u8, Error> {
+    unsafe {
+        send(command::DISABLE_PORT_1)?;
+        send(command::DISABLE_PORT_2)?;
+    }
+
+    flush();
+
+    let config = read_config()? & !(config::PORT_1_INTERRUPT | config::PORT_2_INTERRUPT);
+
+    unsafe {
+        write_config(config)?;
+        send(command::SELF_TEST)?;
+    }
+
+    let answer = read()?;
+
+    if answer != SELF_TEST_PASSED {
+        return Err(Error::SelfTest(answer));
+    }
+
+    // Some controllers reset their configuration on a self-test.
+    unsafe {
+        write_config(config)?;
+        send(command::TEST_PORT_1)?;
+    }
+
+    let answer = read()?;
+
+    if answer != 0 {
+        return Err(Error::PortTest(answer));
+    }
+
+    unsafe { send(command::ENABLE_PORT_1) }?;
+
+    Ok(config)
+}
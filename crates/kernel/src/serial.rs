@@ -0,0 +1,39 @@
+//! Logging over the first serial port.
+
+use mrow_common::{
+    format::Sink,
+    serial::{self, SerialPort},
+};
+
+/// The first serial port, as a [`Sink`] for the log macros.
+///
+/// Writes do nothing if serial output was disabled at build time, see
+/// [`serial::ENABLED`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Serial;
+
+impl Serial {
+    /// Programs the port for [`serial::BAUD`].
+    ///
+    /// The loader did this already, but we don't want to depend on it.
+    ///
+    /// # Safety
+    ///
+    /// Must be called before anything else writes to the port.
+    pub unsafe fn init() {
+        if serial::ENABLED {
+            unsafe { SerialPort::com1().init(serial::BAUD) };
+        }
+    }
+}
+
+impl Sink for Serial {
+    #[inline]
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if serial::ENABLED {
+            // Nothing else touches the port, and the panic handler only runs
+            // after the write it interrupted.
+            unsafe { SerialPort::com1().write_bytes(bytes) };
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! The local APIC timer, calibrated against the PIT.
+
+use core::{
+    hint,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use mrow_common::{
+    apic::{local, lvt},
+    idt::InterruptFrame,
+    port::{inb, outb},
+};
+
+use crate::{apic, interrupts};
+
+/// How fast the PIT counts, in Hz.
+const PIT_FREQUENCY: u64 = 1_193_182;
+
+/// The data port of PIT channel 2, the one we can poll.
+const PIT_CHANNEL_2: u16 = 0x42;
+
+/// The PIT mode and command port.
+const PIT_COMMAND: u16 = 0x43;
+
+/// Channel 2, low then high byte, interrupt on terminal count.
+const PIT_ONE_SHOT: u8 = 0b1011_0000;
+
+/// Keyboard controller port B, which gates PIT channel 2 and shows its output.
+const PORT_B: u16 = 0x61;
+
+/// The bit of [`PORT_B`] that lets PIT channel 2 count.
+const GATE: u8 = 1 << 0;
+
+/// The bit of [`PORT_B`] that connects PIT channel 2 to the speaker.
+const SPEAKER: u8 = 1 << 1;
+
+/// The bit of [`PORT_B`] that mirrors the output of PIT channel 2.
+const OUTPUT: u8 = 1 << 5;
+
+/// How long to count for when calibrating.
+const CALIBRATION_MS: u64 = 10;
+
+/// How fast the timer counts down, in Hz, or 0 before [`init`].
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// How many timer interrupts there have been.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Measures how fast the timer counts, and installs its interrupt handler.
+///
+/// Returns the frequency in Hz.
+///
+/// # Safety
+///
+/// Must only be called once, after [`apic::init`], with interrupts off.
+pub unsafe fn init() -> u64 {
+    let count = PIT_FREQUENCY * CALIBRATION_MS / 1000;
+
+    let elapsed = unsafe {
+        let port_b = inb(PORT_B);
+        outb(PORT_B, (port_b & !SPEAKER) | GATE);
+
+        apic::write(local::TIMER_DIVIDE, local::DIVIDE_BY_16);
+        apic::write(local::LVT_TIMER, lvt::MASKED);
+
+        outb(PIT_COMMAND, PIT_ONE_SHOT);
+        outb(PIT_CHANNEL_2, count as u8);
+        outb(PIT_CHANNEL_2, (count >> 8) as u8);
+
+        // The PIT starts with the high byte, so start right after it.
+        apic::write(local::TIMER_INITIAL, u32::MAX);
+
+        while inb(PORT_B) & OUTPUT == 0 {
+            hint::spin_loop();
+        }
+
+        let elapsed = u32::MAX - apic::read(local::TIMER_CURRENT);
+
+        stop();
+        outb(PORT_B, port_b);
+
+        elapsed as u64
+    };
+
+    let frequency = elapsed * 1000 / CALIBRATION_MS;
+
+    FREQUENCY.store(frequency, Ordering::Relaxed);
+    interrupts::set_handler(interrupts::TIMER, tick);
+
+    frequency
+}
+
+fn tick(_: &mut InterruptFrame) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns how fast the timer counts down, in Hz.
+pub fn frequency() -> u64 {
+    FREQUENCY.load(Ordering::Relaxed)
+}
+
+/// Returns how many timer interrupts there have been.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Raises a timer interrupt every `interval`.
+///
+/// # Panics
+///
+/// Panics before [`init`].
+pub fn start_periodic(interval: Duration) {
+    start(interval, lvt::PERIODIC);
+}
+
+/// Raises a single timer interrupt after `delay`.
+///
+/// # Panics
+///
+/// Panics before [`init`].
+pub fn start_one_shot(delay: Duration) {
+    start(delay, 0);
+}
+
+/// Stops the timer, so no more interrupts come in.
+///
+/// # Panics
+///
+/// Panics before [`init`].
+pub fn stop() {
+    unsafe {
+        apic::write(local::TIMER_INITIAL, 0);
+        apic::write(local::LVT_TIMER, lvt::MASKED);
+    }
+}
+
+/// (Re)starts the timer for `duration`, in `mode`.
+fn start(duration: Duration, mode: u32) {
+    let frequency = frequency();
+    assert_ne!(frequency, 0, "the timer isn't calibrated");
+
+    // Counting down from 0 stops the timer rather than firing right away.
+    let count =
+        (frequency as u128 * duration.as_nanos() / 1_000_000_000).clamp(1, u32::MAX as u128);
+
+    unsafe {
+        apic::write(local::LVT_TIMER, interrupts::TIMER as u32 | mode);
+        apic::write(local::TIMER_INITIAL, count as u32);
+    }
+}
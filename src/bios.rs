@@ -1,9 +1,17 @@
-use std::path::PathBuf;
+use std::{
+    io::{Cursor, ErrorKind, Write},
+    path::PathBuf,
+};
 
 use anyhow::{anyhow, Context};
-use bytemuck::checked::try_from_bytes_mut;
+use bytemuck::{bytes_of, checked::try_from_bytes_mut, pod_read_unaligned};
 use cargo_metadata::camino::Utf8PathBuf;
-use mrow_common::mbr::MasterBootRecord;
+use fatfs::{FatType, FileSystem, FormatVolumeOptions, FsOptions};
+use mrow_common::{
+    config::{self, Config},
+    header::StageHeader,
+    mbr::MasterBootRecord,
+};
 use tokio::{
     fs::File,
     io::{AsyncWrite, AsyncWriteExt},
@@ -15,12 +23,28 @@ use crate::{
     util::{apply_context, Env, ObjCopy},
 };
 
+/// The partition type of the boot partition, FAT16 with LBA addressing.
+pub const BOOT_PARTITION_KIND: u8 = 0x0e;
+
+/// The boundary the boot partition starts on, in sectors.
+///
+/// This is what partitioning tools use, so the image can be repartitioned.
+pub const BOOT_PARTITION_ALIGN: usize = 2048;
+
+/// The smallest boot partition we create, in bytes.
+///
+/// FAT16 needs at least 4085 clusters.
+pub const BOOT_PARTITION_MIN_LEN: usize = 16 << 20;
+
 /// Struct for building a bios bootloader.
 pub struct BiosBuilder<'a> {
     pub env: &'a Env,
     pub profile: &'a str,
     pub code16_target: Utf8PathBuf,
     pub code16_pic_target: Utf8PathBuf,
+    pub i686_target: Utf8PathBuf,
+    /// The loader configuration that goes on the boot partition, see [`mrow_common::config`].
+    pub config: Utf8PathBuf,
 }
 
 impl<'a> BiosBuilder<'a> {
@@ -30,6 +54,8 @@ impl<'a> BiosBuilder<'a> {
             profile,
             code16_target: env.metadata.workspace_root.join("i386-code16.json"),
             code16_pic_target: env.metadata.workspace_root.join("i386-code16-pic.json"),
+            i686_target: env.metadata.workspace_root.join("i686-none.json"),
+            config: env.metadata.workspace_root.join("mrow.cfg"),
         }
     }
 
@@ -38,10 +64,11 @@ impl<'a> BiosBuilder<'a> {
         &self,
         stdout: &mut File,
         stderr: &mut File,
+        kernel: &[u8],
         path: &mut PathBuf,
     ) -> Result<(Vec<u8>, File), Vec<anyhow::Error>> {
         let bootloader = self
-            .build(stdout, stderr)
+            .build(stdout, stderr, kernel)
             .await
             .map_err(apply_context(|| "building bootloader"))?;
 
@@ -70,12 +97,23 @@ impl<'a> BiosBuilder<'a> {
         Ok((bootloader, file))
     }
 
-    /// Builds the bios bootloader.
+    /// Builds the bios bootloader, with `kernel` on its boot partition.
     pub async fn build(
         &self,
         stdout: &mut File,
         stderr: &mut File,
+        kernel: &[u8],
     ) -> Result<Vec<u8>, Vec<anyhow::Error>> {
+        // No point in building anything stage 2 would refuse to boot with.
+        let config = self
+            .load_config()
+            .await
+            .map_err(apply_context(|| "loading loader config"))?;
+
+        let boot_partition = build_boot_partition(kernel, config.as_deref())
+            .context("building boot partition")
+            .map_err(|err| vec![err])?;
+
         let stage_1 = {
             let mut stdout = stdout.try_clone().await.map_err(|err| vec![err.into()])?;
             let mut stderr = stderr.try_clone().await.map_err(|err| vec![err.into()])?;
@@ -87,21 +125,39 @@ impl<'a> BiosBuilder<'a> {
             }
         };
 
-        let stage_2 = async {
-            self.build_stage2(stdout, stderr)
-                .await
-                .map_err(apply_context(|| "building stage 2"))
-            // .context("building stage 2")
+        let stage_2 = {
+            let mut stdout = stdout.try_clone().await.map_err(|err| vec![err.into()])?;
+            let mut stderr = stderr.try_clone().await.map_err(|err| vec![err.into()])?;
+
+            async move {
+                self.build_stage2(&mut stdout, &mut stderr)
+                    .await
+                    .map_err(apply_context(|| "building stage 2"))
+            }
         };
 
-        let (mut stage_1, stage_2) = match join!(stage_1, stage_2) {
-            (Ok(stage_1), Ok(stage_2)) => (stage_1, stage_2),
-            (Ok(_), Err(err_2)) => return Err(err_2),
-            (Err(err_1), Ok(_)) => return Err(err_1),
-            (Err(mut err_1), Err(mut err_2)) => {
-                err_1.append(&mut err_2);
+        let stage_3 = async {
+            self.build_stage3(stdout, stderr)
+                .await
+                .map_err(apply_context(|| "building stage 3"))
+        };
 
-                return Err(err_1);
+        let (mut stage_1, stage_2, stage_3) = match join!(stage_1, stage_2, stage_3) {
+            (Ok(stage_1), Ok(stage_2), Ok(stage_3)) => (stage_1, stage_2, stage_3),
+            results => {
+                let mut errors = Vec::new();
+
+                if let Err(mut err) = results.0 {
+                    errors.append(&mut err);
+                }
+                if let Err(mut err) = results.1 {
+                    errors.append(&mut err);
+                }
+                if let Err(mut err) = results.2 {
+                    errors.append(&mut err);
+                }
+
+                return Err(errors);
             }
         };
 
@@ -123,18 +179,83 @@ impl<'a> BiosBuilder<'a> {
             .context("stage 2 loader sector size must fit in a u32")
             .map_err(|err| vec![err])?;
 
+        if stage_3.is_empty() {
+            return Err(vec![anyhow!("stage 3 loader must not be empty")]);
+        } else if stage_3.len() % 512 != 0 {
+            return Err(vec![anyhow!(
+                "stage 3 loader size must be a multiple of 512"
+            )]);
+        }
+
+        let stage_3_sectors = u32::try_from(stage_3.len() / 512)
+            .context("stage 3 loader sector size must fit in a u32")
+            .map_err(|err| vec![err])?;
+
         let bootloader_parition = &mut mbr.partition_table.entries[0];
 
         bootloader_parition.flags |= 0x80;
         bootloader_parition.set_start_lba(1);
         bootloader_parition.set_sector_len(stage_2_sectors);
 
+        // Stage 3 directly follows stage 2, stage 2 finds it through this entry.
+        let stage_3_partition = &mut mbr.partition_table.entries[1];
+
+        stage_3_partition.set_start_lba(1 + stage_2_sectors);
+        stage_3_partition.set_sector_len(stage_3_sectors);
+
+        // Stage 2 loads the kernel and configuration from the first FAT partition.
+        let loader_sectors = 1 + stage_2_sectors as usize + stage_3_sectors as usize;
+        let boot_partition_start = loader_sectors.next_multiple_of(BOOT_PARTITION_ALIGN);
+
+        let boot_partition_lba = u32::try_from(boot_partition_start)
+            .context("boot partition start must fit in a u32")
+            .map_err(|err| vec![err])?;
+        let boot_partition_sectors = u32::try_from(boot_partition.len() / 512)
+            .context("boot partition sector size must fit in a u32")
+            .map_err(|err| vec![err])?;
+
+        let boot_partition_entry = &mut mbr.partition_table.entries[2];
+
+        boot_partition_entry.partition_kind = BOOT_PARTITION_KIND;
+        boot_partition_entry.set_start_lba(boot_partition_lba);
+        boot_partition_entry.set_sector_len(boot_partition_sectors);
+
         let mut bootloader = stage_1;
         bootloader.extend_from_slice(&stage_2);
+        bootloader.extend_from_slice(&stage_3);
+        bootloader.resize(boot_partition_start * 512, 0);
+        bootloader.extend_from_slice(&boot_partition);
 
         Ok(bootloader)
     }
 
+    /// Reads and parses the loader configuration, if there is one.
+    ///
+    /// Stage 2 falls back to [`mrow_common::config::DEFAULT`] without it, so a missing
+    /// file is fine.
+    pub async fn load_config(&self) -> Result<Option<String>, Vec<anyhow::Error>> {
+        let text = match tokio::fs::read_to_string(&self.config).await {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(vec![anyhow::Error::new(err)
+                    .context(format!("reading loader config from {}", self.config))])
+            }
+        };
+
+        if let Err(err) = Config::parse(&text) {
+            return Err(vec![anyhow!(
+                "{}:{}:{}: {}",
+                self.config,
+                err.line,
+                err.column,
+                err.kind
+            )]);
+        }
+
+        Ok(Some(text))
+    }
+
     /// Builds the stage 2 loader.
     pub async fn build_stage2<Stdout, Stderr>(
         &self,
@@ -173,7 +294,7 @@ impl<'a> BiosBuilder<'a> {
         );
         let output = self.env.build_path(&package.name, Some("bin"));
 
-        let stage_2 = ObjCopy {
+        let mut stage_2 = ObjCopy {
             input: input.as_str(),
             output: output.as_str(),
             output_format: Some("binary"),
@@ -182,9 +303,67 @@ impl<'a> BiosBuilder<'a> {
         .run(stdout, stderr)
         .await?;
 
+        seal_header(&mut stage_2)
+            .context("sealing stage 2 header")
+            .map_err(|err| vec![err])?;
+
         Ok(stage_2)
     }
 
+    /// Builds the 32-bit stage 3 loader.
+    pub async fn build_stage3<Stdout, Stderr>(
+        &self,
+        stdout: &mut Stdout,
+        stderr: &mut Stderr,
+    ) -> Result<Vec<u8>, Vec<anyhow::Error>>
+    where
+        Stdout: AsyncWrite + ?Sized + Unpin,
+        Stderr: AsyncWrite + ?Sized + Unpin,
+    {
+        let package = self
+            .env
+            .metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "mrow-bios-stage-3")
+            .context("failed to find stage-3")
+            .map_err(|err| vec![err])?;
+
+        // Build it
+        CargoBuild {
+            package: &package.name,
+            target: self.i686_target.as_str(),
+            profile: self.profile,
+            build_std: Some(&["core", "compiler_builtins"]),
+            build_std_features: &["compiler-builtins-mem"],
+            ..self.env.cargo_build()
+        }
+        .run(&mut tokio::io::empty(), stdout, stderr)
+        .await?;
+
+        let input = self.env.target_path(
+            self.i686_target.file_stem(),
+            Some(self.profile),
+            Some(&package.name),
+        );
+        let output = self.env.build_path(&package.name, Some("bin"));
+
+        let mut stage_3 = ObjCopy {
+            input: input.as_str(),
+            output: output.as_str(),
+            output_format: Some("binary"),
+            ..self.env.objcopy()
+        }
+        .run(stdout, stderr)
+        .await?;
+
+        seal_header(&mut stage_3)
+            .context("sealing stage 3 header")
+            .map_err(|err| vec![err])?;
+
+        Ok(stage_3)
+    }
+
     /// Builds the stage 1 bios bootloader without modifying the master boot record.
     pub async fn build_stage1<Stdout, Stderr>(
         &self,
@@ -236,3 +415,72 @@ impl<'a> BiosBuilder<'a> {
         Ok(stage_1)
     }
 }
+
+/// Builds a FAT16 file system holding `kernel` at `/boot/kernel` and `config` at
+/// [`mrow_common::config::PATH`].
+pub fn build_boot_partition(kernel: &[u8], config: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    // Leave room for whatever else ends up on there, like an initrd.
+    let len = (kernel.len() + config.map_or(0, str::len) + (1 << 20))
+        .next_multiple_of(1 << 20)
+        .max(BOOT_PARTITION_MIN_LEN);
+
+    let mut partition = Cursor::new(vec![0; len]);
+
+    fatfs::format_volume(
+        &mut partition,
+        FormatVolumeOptions::new()
+            .fat_type(FatType::Fat16)
+            .volume_label(*b"MROW BOOT  "),
+    )
+    .context("formatting boot partition")?;
+
+    let fs = FileSystem::new(&mut partition, FsOptions::new()).context("opening boot partition")?;
+
+    {
+        let root = fs.root_dir();
+
+        root.create_dir("boot").context("creating /boot")?;
+
+        root.create_file("boot/kernel")
+            .and_then(|mut file| file.write_all(kernel))
+            .context("writing /boot/kernel")?;
+
+        if let Some(config) = config {
+            root.create_file(config::PATH.trim_start_matches('/'))
+                .and_then(|mut file| file.write_all(config.as_bytes()))
+                .context(format!("writing {}", config::PATH))?;
+        }
+    }
+
+    fs.unmount().context("unmounting boot partition")?;
+
+    Ok(partition.into_inner())
+}
+
+/// Fills in the payload length and checksum of the [`StageHeader`] at the start of `stage`.
+pub fn seal_header(stage: &mut [u8]) -> anyhow::Result<()> {
+    if stage.len() < StageHeader::SIZE {
+        return Err(anyhow!("stage is too small to contain a header"));
+    }
+
+    let (header, payload) = stage.split_at_mut(StageHeader::SIZE);
+    let unsealed = pod_read_unaligned::<StageHeader>(header);
+
+    if !unsealed.is_compatible() {
+        return Err(anyhow!(
+            "stage header has magic {:#010x} and version {}, expected {:#010x} and version {}",
+            unsealed.magic,
+            unsealed.version,
+            mrow_common::header::MAGIC,
+            mrow_common::header::VERSION,
+        ));
+    }
+
+    let sealed = unsealed
+        .seal(payload)
+        .context("stage payload must fit in a u32")?;
+
+    header.copy_from_slice(bytes_of(&sealed));
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use anyhow::Context;
+use cargo_metadata::camino::Utf8PathBuf;
+use tokio::io::AsyncWrite;
+
+use crate::{
+    bios::seal_header,
+    cargo::CargoBuild,
+    util::{Env, ObjCopy},
+};
+
+/// Struct for building the kernel.
+pub struct KernelBuilder<'a> {
+    pub env: &'a Env,
+    pub profile: &'a str,
+    pub x86_64_target: Utf8PathBuf,
+}
+
+impl<'a> KernelBuilder<'a> {
+    pub fn new(env: &'a Env, profile: &'a str) -> Self {
+        Self {
+            env,
+            profile,
+            x86_64_target: env.metadata.workspace_root.join("x86_64-none.json"),
+        }
+    }
+
+    /// Builds the kernel image stage 3 maps at [`mrow_common::handoff::KERNEL_BASE`].
+    pub async fn build<Stdout, Stderr>(
+        &self,
+        stdout: &mut Stdout,
+        stderr: &mut Stderr,
+    ) -> Result<Vec<u8>, Vec<anyhow::Error>>
+    where
+        Stdout: AsyncWrite + ?Sized + Unpin,
+        Stderr: AsyncWrite + ?Sized + Unpin,
+    {
+        let package = self
+            .env
+            .metadata
+            .packages
+            .iter()
+            .find(|p| p.name == "mrow-kernel")
+            .context("failed to find kernel")
+            .map_err(|err| vec![err])?;
+
+        // Build it
+        CargoBuild {
+            package: &package.name,
+            target: self.x86_64_target.as_str(),
+            profile: self.profile,
+            build_std: Some(&["core", "compiler_builtins", "alloc"]),
+            build_std_features: &["compiler-builtins-mem"],
+            ..self.env.cargo_build()
+        }
+        .run(&mut tokio::io::empty(), stdout, stderr)
+        .await?;
+
+        let input = self.env.target_path(
+            self.x86_64_target.file_stem(),
+            Some(self.profile),
+            Some(&package.name),
+        );
+        let output = self.env.build_path(&package.name, Some("bin"));
+
+        let mut kernel = ObjCopy {
+            input: input.as_str(),
+            output: output.as_str(),
+            output_format: Some("binary"),
+            ..self.env.objcopy()
+        }
+        .run(stdout, stderr)
+        .await?;
+
+        seal_header(&mut kernel)
+            .context("sealing kernel header")
+            .map_err(|err| vec![err])?;
+
+        Ok(kernel)
+    }
+}
@@ -2,6 +2,7 @@ use std::process::ExitCode;
 
 use anyhow::Context;
 use bios::BiosBuilder;
+use kernel::KernelBuilder;
 
 use tokio::{
     fs::File,
@@ -12,6 +13,7 @@ use util::apply_context;
 
 pub mod bios;
 pub mod cargo;
+pub mod kernel;
 pub mod util;
 
 fn main() -> ExitCode {
@@ -77,11 +79,16 @@ async fn run_inner(
         .context("writing environment loading output")
         .map_err(|err| vec![err])?;
 
+    let kernel = KernelBuilder::new(&env, "release")
+        .build(stdout, stderr)
+        .await
+        .map_err(apply_context(|| "building kernel"))?;
+
     let bios_builder = BiosBuilder::new(&env, "bios-release");
     let mut bootloader_path = Default::default();
 
     bios_builder
-        .build_and_save(stdout, stderr, &mut bootloader_path)
+        .build_and_save(stdout, stderr, &kernel, &mut bootloader_path)
         .await
         .map_err(apply_context(|| "building and saving bios bootloader"))?;
 